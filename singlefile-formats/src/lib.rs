@@ -5,8 +5,27 @@
 //! By default, no features are enabled.
 //!
 //! - `cbor-serde`: Enables the [`Cbor`][crate::cbor_serde::Cbor] file format for use with [`serde`] types.
-//! - `json-serde`: Enables the [`Json`][crate::json_serde::Json] file format for use with [`serde`] types.
-//! - `toml-serde`: Enables the [`Toml`][crate::toml_serde::Toml] file format for use with [`serde`] types.
+//! - `json-serde`: Enables the [`Json`][crate::json_serde::Json] and [`RuntimeJson`][crate::json_serde::RuntimeJson]
+//!   file formats and the [`JsonLines`][crate::json_serde::JsonLines] record format for use with [`serde`] types.
+//! - `toml-serde`: Enables the [`Toml`][crate::toml_serde::Toml] and [`RuntimeToml`][crate::toml_serde::RuntimeToml]
+//!   file formats for use with [`serde`] types.
+//! - `detect`: Enables the [`DetectFormat`][crate::detect::DetectFormat] file format, which selects among
+//!   the enabled `serde` formats by file extension or contents.
+//! - `path-to-error`: Enables the [`Tracked`][crate::tracked::Tracked] format wrapper, which makes
+//!   [`Json`][crate::json_serde::Json] and [`Toml`][crate::toml_serde::Toml] errors report the path of the offending field.
+//! - `interpolate`: Enables the [`Interpolated`][crate::interpolate::Interpolated] format wrapper for
+//!   expanding environment variables in text formats.
+//! - `checksum`: Enables the [`Checksummed`][crate::checksum::Checksummed] format wrapper, which detects truncated
+//!   or corrupted files with a CRC-32 or SHA-256 footer.
+//! - `encryption`: Enables the [`Encrypted`][crate::encryption::Encrypted] format wrapper, which encrypts contents
+//!   with any [`EncryptionFormat`][crate::encryption::EncryptionFormat] (AES-256-GCM or ChaCha20-Poly1305),
+//!   using a key or a passphrase.
+//! - `secret`: Enables the [`ContainerSecret`][crate::secret::ContainerSecret] preset for storing secrets,
+//!   encrypted with ChaCha20-Poly1305. Implies `encryption`.
+//! - `zeroize`: Wipes intermediate plaintext buffers used by [`Encrypted`][crate::encryption::Encrypted]
+//!   and [`Interpolated`][crate::interpolate::Interpolated] from memory after use.
+//! - `mlock`: Enables [`Encrypted::with_locked_memory`][crate::encryption::Encrypted::with_locked_memory] and
+//!   [`lock_memory`][crate::encryption::lock_memory] for keeping decrypted secrets out of swap. Implies `encryption`.
 //! - `bzip`: Enables the [`BZip2`][crate::bzip::BZip2] compression format. See [`CompressionFormat`] for more info.
 //! - `flate`: Enables the [`Deflate`][crate::flate::Deflate], [`Gz`][crate::flate::Gz],
 //!   and [`ZLib`][crate::flate::ZLib] compression formats. See [`CompressionFormat`] for more info.
@@ -30,6 +49,13 @@ use std::io::{Read, Write};
 
 /// Combines a [`FileFormat`] and a [`CompressionFormat`], making the contents emitted by
 /// the format compressed before writing to disk, and decompressed before parsing.
+///
+/// Compressing very small contents often makes them larger and slower to handle, so a minimum size can be set
+/// with [`Compressed::with_threshold`]. Contents smaller than the threshold are then stored uncompressed,
+/// and every file begins with a flag byte recording whether its contents were compressed.
+///
+/// When reading untrusted files, a limit on the size of the decompressed contents should be set with
+/// [`Compressed::with_limit`], so that a small file cannot decompress into an unbounded amount of data.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Compressed<C, F> {
   /// The [`FileFormat`] to be used.
@@ -38,14 +64,52 @@ pub struct Compressed<C, F> {
   pub compression: C,
   /// The level of compression to use.
   /// This value may have different meanings for different compression formats.
-  pub level: u32
+  pub level: u32,
+  threshold: Option<usize>,
+  limit: Option<u64>
 }
 
 impl<C, F> Compressed<C, F> {
   /// Create a new [`Compressed`], given a compression level.
   #[inline]
   pub const fn with_level(format: F, compression: C, level: u32) -> Self {
-    Compressed { format, compression, level }
+    Compressed { format, compression, level, threshold: None, limit: None }
+  }
+
+  /// Sets the minimum size in bytes that contents must reach to be compressed, storing smaller contents as-is.
+  ///
+  /// Contents smaller than the threshold are serialized twice when writing, since they are only
+  /// known to be small once the format has finished writing them.
+  #[inline]
+  pub const fn with_threshold(mut self, threshold: usize) -> Self {
+    self.threshold = Some(threshold);
+    self
+  }
+
+  /// Gets the minimum size in bytes that the contents emitted by the format must reach to be compressed.
+  ///
+  /// If this is `None`, contents are always compressed and no flag byte is written,
+  /// so files written with and without a threshold cannot be read interchangeably.
+  #[inline]
+  pub const fn threshold(&self) -> Option<usize> {
+    self.threshold
+  }
+
+  /// Sets the maximum size in bytes of the decompressed contents, protecting against decompression bombs.
+  ///
+  /// Reading contents that decompress into more than `limit` bytes fails with an I/O error
+  /// wrapping [`DecompressionLimitExceeded`], reported through the format's own error type.
+  #[inline]
+  pub const fn with_limit(mut self, limit: u64) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// Gets the maximum size in bytes of the decompressed contents that the format may read.
+  /// If this is `None`, the decompressed contents may be of any size.
+  #[inline]
+  pub const fn limit(&self) -> Option<u64> {
+    self.limit
   }
 }
 
@@ -82,11 +146,217 @@ where C: CompressionFormat, F: FileFormat<T> {
   type FormatError = F::FormatError;
 
   fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
-    self.format.from_reader(self.compression.decode_reader(reader))
+    match (self.threshold, self.limit) {
+      (Some(_), Some(limit)) => self.format.from_reader(LimitedReader::new(FlaggedReader::Flag(&self.compression, reader), limit)),
+      (Some(_), None) => self.format.from_reader(FlaggedReader::Flag(&self.compression, reader)),
+      (None, Some(limit)) => self.format.from_reader(LimitedReader::new(self.compression.decode_reader(reader), limit)),
+      (None, None) => self.format.from_reader(self.compression.decode_reader(reader))
+    }
   }
 
   fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
-    self.format.to_writer(self.compression.encode_writer(writer, self.level), value)
+    let threshold = match self.threshold {
+      Some(threshold) => threshold,
+      None => return self.format.to_writer(self.compression.encode_writer(writer, self.level), value)
+    };
+
+    let mut threshold_writer = ThresholdWriter {
+      state: ThresholdState::Buffering(writer),
+      buf: Vec::new(),
+      threshold,
+      level: self.level,
+      compression: &self.compression
+    };
+
+    self.format.to_writer(&mut threshold_writer, value)?;
+    match std::mem::replace(&mut threshold_writer.state, ThresholdState::Poisoned) {
+      // the contents never reached the threshold, so nothing has been written yet,
+      // and writing them again through the format lets it report any I/O errors
+      ThresholdState::Buffering(writer) => {
+        self.format.to_writer(FlaggedWriter { flag: Some(FLAG_STORED), writer }, value)
+      },
+      _ => Ok(())
+    }
+  }
+}
+
+/// The error wrapped by the I/O error returned when the decompressed contents read by a [`Compressed`]
+/// exceed its [limit][Compressed::with_limit].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("decompressed contents exceed the limit of {limit} bytes")]
+pub struct DecompressionLimitExceeded {
+  /// The limit that was exceeded.
+  pub limit: u64
+}
+
+impl DecompressionLimitExceeded {
+  /// Searches an error and its sources for a [`DecompressionLimitExceeded`],
+  /// looking inside of any I/O errors along the way.
+  ///
+  /// This is useful since formats wrap I/O errors differently, for example:
+  /// `DecompressionLimitExceeded::find(&err).is_some()`.
+  pub fn find<'e>(err: &'e (dyn std::error::Error + 'static)) -> Option<&'e Self> {
+    let mut current = Some(err);
+    while let Some(err) = current {
+      if let Some(found) = err.downcast_ref::<Self>() {
+        return Some(found);
+      };
+
+      current = match err.downcast_ref::<std::io::Error>().and_then(std::io::Error::get_ref) {
+        Some(inner) => Some(inner),
+        None => err.source()
+      };
+    };
+
+    None
+  }
+}
+
+/// Wraps [`DecompressionLimitExceeded`] as its source, since the source of an I/O error
+/// is the source of the error it wraps, which is what formats such as `serde_json` expose.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct LimitError(#[source] DecompressionLimitExceeded);
+
+/// A reader that fails with [`DecompressionLimitExceeded`] once more than `limit` bytes have been read.
+struct LimitedReader<R> {
+  reader: R,
+  remaining: u64,
+  limit: u64
+}
+
+impl<R: Read> LimitedReader<R> {
+  fn new(reader: R, limit: u64) -> Self {
+    LimitedReader { reader, remaining: limit, limit }
+  }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    };
+
+    // one byte past the limit is still read, so that contents of exactly the limit are accepted
+    let max = usize::try_from(self.remaining).map_or(buf.len(), |remaining| remaining.min(buf.len())).max(1);
+    let read = self.reader.read(&mut buf[..max])?;
+    match self.remaining.checked_sub(read as u64) {
+      Some(remaining) => {
+        self.remaining = remaining;
+        Ok(read)
+      },
+      None => Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        LimitError(DecompressionLimitExceeded { limit: self.limit })
+      ))
+    }
+  }
+}
+
+const FLAG_STORED: u8 = 0;
+const FLAG_COMPRESSED: u8 = 1;
+
+/// A reader that reads the flag byte written by [`Compressed`] with a threshold,
+/// then either passes through or decompresses the rest of the reader.
+///
+/// All I/O happens while the format reads, so errors are reported through the format's own error type.
+enum FlaggedReader<'c, C: CompressionFormat, R: Read> {
+  Flag(&'c C, R),
+  Stored(R),
+  Compressed(C::Decoder<R>),
+  Poisoned
+}
+
+impl<'c, C: CompressionFormat, R: Read> Read for FlaggedReader<'c, C, R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    loop {
+      match self {
+        FlaggedReader::Flag(_, reader) => {
+          let mut flag = [0];
+          if reader.read(&mut flag)? == 0 {
+            return Ok(0);
+          };
+
+          *self = match std::mem::replace(self, FlaggedReader::Poisoned) {
+            FlaggedReader::Flag(_, reader) if flag[0] == FLAG_STORED => FlaggedReader::Stored(reader),
+            FlaggedReader::Flag(compression, reader) if flag[0] == FLAG_COMPRESSED => {
+              FlaggedReader::Compressed(compression.decode_reader(reader))
+            },
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown compression flag"))
+          };
+        },
+        FlaggedReader::Stored(reader) => return reader.read(buf),
+        FlaggedReader::Compressed(decoder) => return decoder.read(buf),
+        FlaggedReader::Poisoned => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "unknown compression flag"))
+      };
+    }
+  }
+}
+
+/// A writer that buffers contents until they reach a threshold,
+/// then writes the compressed flag and starts compressing them.
+struct ThresholdWriter<'c, C: CompressionFormat, W: Write> {
+  state: ThresholdState<C::Encoder<W>, W>,
+  buf: Vec<u8>,
+  threshold: usize,
+  level: u32,
+  compression: &'c C
+}
+
+enum ThresholdState<E, W> {
+  Buffering(W),
+  Compressing(E),
+  Poisoned
+}
+
+impl<'c, C: CompressionFormat, W: Write> Write for ThresholdWriter<'c, C, W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    if let ThresholdState::Buffering(..) = self.state {
+      if self.buf.len() + buf.len() < self.threshold {
+        self.buf.extend_from_slice(buf);
+        return Ok(buf.len());
+      };
+
+      if let ThresholdState::Buffering(mut writer) = std::mem::replace(&mut self.state, ThresholdState::Poisoned) {
+        writer.write_all(&[FLAG_COMPRESSED])?;
+        let mut encoder = self.compression.encode_writer(writer, self.level);
+        encoder.write_all(&std::mem::take(&mut self.buf))?;
+        self.state = ThresholdState::Compressing(encoder);
+      };
+    };
+
+    match &mut self.state {
+      ThresholdState::Compressing(encoder) => encoder.write(buf),
+      _ => Err(std::io::Error::new(std::io::ErrorKind::Other, "compression failed to start"))
+    }
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    match &mut self.state {
+      ThresholdState::Compressing(encoder) => encoder.flush(),
+      _ => Ok(())
+    }
+  }
+}
+
+/// A writer that writes a flag byte before any other contents.
+struct FlaggedWriter<W> {
+  flag: Option<u8>,
+  writer: W
+}
+
+impl<W: Write> Write for FlaggedWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    if let Some(flag) = self.flag {
+      self.writer.write_all(&[flag])?;
+      self.flag = None;
+    };
+
+    self.writer.write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    self.writer.flush()
   }
 }
 
@@ -117,6 +387,20 @@ pub trait CompressionFormatLevels: CompressionFormat {
   const COMPRESSION_LEVEL_DEFAULT: u32;
 }
 
+/// Wraps a buffer that may hold sensitive plaintext, wiping it from memory when it is dropped.
+#[cfg(all(feature = "zeroize", any(feature = "encryption", feature = "interpolate")))]
+#[inline]
+fn sensitive<T: zeroize::Zeroize>(buf: T) -> zeroize::Zeroizing<T> {
+  zeroize::Zeroizing::new(buf)
+}
+
+/// Wraps a buffer that may hold sensitive plaintext, this does nothing without the `zeroize` feature.
+#[cfg(all(not(feature = "zeroize"), any(feature = "encryption", feature = "interpolate")))]
+#[inline(always)]
+fn sensitive<T>(buf: T) -> T {
+  buf
+}
+
 /// Defines a [`FileFormat`] that wraps data from another format in Base64.
 #[cfg_attr(docsrs, doc(cfg(feature = "base64")))]
 #[cfg(feature = "base64")]
@@ -243,6 +527,14 @@ pub mod cbor_serde {
     fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
       ciborium::ser::into_writer(value, writer).map_err(From::from)
     }
+
+    fn error_offset(&self, error: &Self::FormatError, _buf: &[u8]) -> Option<usize> {
+      match error {
+        CborError::DeserializeError(ciborium::de::Error::Syntax(offset)) => Some(*offset),
+        CborError::DeserializeError(ciborium::de::Error::Semantic(offset, _)) => *offset,
+        _ => None
+      }
+    }
   }
 
   /// A shortcut type to a [`Compressed`][crate::Compressed] [`Cbor`].
@@ -259,16 +551,18 @@ pub mod json_serde {
   use serde::ser::Serialize;
   use serde::de::DeserializeOwned;
   use singlefile::{FileFormat, FileFormatUtf8};
+  use singlefile::manager::format::RecordFormat;
 
   use std::io::{Read, Write};
 
-  /// An error that can occur while using [`Json`].
+  /// An error that can occur while using [`Json`] or [`JsonLines`].
   pub type JsonError = serde_json::Error;
 
   /// A [`FileFormat`] corresponding to the JSON data format.
   /// Implemented using the [`serde_json`] crate, only compatible with [`serde`] types.
   ///
   /// This type provides an optional constant generic parameter for configuring pretty-print.
+  /// See [`RuntimeJson`] for choosing whether to pretty-print at runtime instead.
   #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
   pub struct Json<const PRETTY: bool = true>;
 
@@ -276,47 +570,203 @@ pub mod json_serde {
   where T: Serialize + DeserializeOwned {
     type FormatError = JsonError;
 
+    #[inline]
+    fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      RuntimeJson::new(PRETTY).from_reader(reader)
+    }
+
+    #[inline]
+    fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      RuntimeJson::new(PRETTY).to_writer(writer, value)
+    }
+
+    #[inline]
+    fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+      RuntimeJson::new(PRETTY).to_buffer(value)
+    }
+
+    #[inline]
+    fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+      FileFormat::<T>::error_offset(&RuntimeJson::new(PRETTY), error, buf)
+    }
+  }
+
+  impl<T, const PRETTY: bool> FileFormatUtf8<T> for Json<PRETTY>
+  where T: Serialize + DeserializeOwned {
+    #[inline]
+    fn from_string_buffer(&self, buf: &str) -> Result<T, Self::FormatError> {
+      RuntimeJson::new(PRETTY).from_string_buffer(buf)
+    }
+
+    #[inline]
+    fn to_string_buffer(&self, value: &T) -> Result<String, Self::FormatError> {
+      RuntimeJson::new(PRETTY).to_string_buffer(value)
+    }
+  }
+
+  /// A shortcut type to a [`Json`] with pretty-print enabled.
+  pub type PrettyJson = Json<true>;
+  /// A shortcut type to a [`Json`] with pretty-print disabled.
+  pub type RegularJson = Json<false>;
+
+  /// A shortcut type to a [`Compressed`][crate::Compressed] [`Json`].
+  /// Provides parameters for compression format and pretty-print configuration (defaulting to off).
+  pub type CompressedJson<C, const PRETTY: bool = false> = crate::Compressed<C, Json<PRETTY>>;
+
+  /// A [`FileFormat`] corresponding to the JSON data format, identical to [`Json`] except that
+  /// whether output is pretty-printed is chosen at runtime with [`RuntimeJson::pretty`] or [`RuntimeJson::minified`],
+  /// so it can come from user settings without changing the type of a container. Defaults to pretty-printed.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct RuntimeJson {
+    pretty: bool
+  }
+
+  impl RuntimeJson {
+    /// Creates a new [`RuntimeJson`], pretty-printing output if `pretty` is `true`.
+    #[inline]
+    pub const fn new(pretty: bool) -> Self {
+      RuntimeJson { pretty }
+    }
+
+    /// Creates a new [`RuntimeJson`] that pretty-prints output.
+    #[inline]
+    pub const fn pretty() -> Self {
+      RuntimeJson::new(true)
+    }
+
+    /// Creates a new [`RuntimeJson`] that outputs minified JSON.
+    #[inline]
+    pub const fn minified() -> Self {
+      RuntimeJson::new(false)
+    }
+
+    /// Returns `true` if this [`RuntimeJson`] pretty-prints output.
+    #[inline]
+    pub const fn is_pretty(&self) -> bool {
+      self.pretty
+    }
+  }
+
+  impl Default for RuntimeJson {
+    #[inline]
+    fn default() -> Self {
+      RuntimeJson::pretty()
+    }
+  }
+
+  impl<const PRETTY: bool> From<Json<PRETTY>> for RuntimeJson {
+    #[inline]
+    fn from(_: Json<PRETTY>) -> Self {
+      RuntimeJson::new(PRETTY)
+    }
+  }
+
+  impl<T> FileFormat<T> for RuntimeJson
+  where T: Serialize + DeserializeOwned {
+    type FormatError = JsonError;
+
     fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
       serde_json::from_reader(reader)
     }
 
     fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
-      match PRETTY {
+      match self.pretty {
         true => serde_json::to_writer_pretty(writer, value),
         false => serde_json::to_writer(writer, value)
       }
     }
 
     fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
-      match PRETTY {
+      match self.pretty {
         true => serde_json::to_vec_pretty(value),
         false => serde_json::to_vec(value)
       }
     }
+
+    fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+      // `serde_json` reports one-based lines and columns, with a line of zero meaning unknown
+      let line = error.line().checked_sub(1)?;
+      let line_start = match line {
+        0 => 0,
+        line => buf.iter().enumerate()
+          .filter(|&(_, &b)| b == b'\n')
+          .nth(line - 1)?.0 + 1
+      };
+
+      Some(line_start + error.column().saturating_sub(1))
+    }
   }
 
-  impl<T, const PRETTY: bool> FileFormatUtf8<T> for Json<PRETTY>
+  impl<T> FileFormatUtf8<T> for RuntimeJson
   where T: Serialize + DeserializeOwned {
     fn from_string_buffer(&self, buf: &str) -> Result<T, Self::FormatError> {
       serde_json::from_str(buf)
     }
 
     fn to_string_buffer(&self, value: &T) -> Result<String, Self::FormatError> {
-      match PRETTY {
+      match self.pretty {
         true => serde_json::to_string_pretty(value),
         false => serde_json::to_string(value)
       }
     }
   }
 
-  /// A shortcut type to a [`Json`] with pretty-print enabled.
-  pub type PrettyJson = Json<true>;
-  /// A shortcut type to a [`Json`] with pretty-print disabled.
-  pub type RegularJson = Json<false>;
+  /// A [`RecordFormat`] corresponding to newline-delimited JSON (also known as NDJSON or JSON Lines),
+  /// where each line of a file holds a single JSON value.
+  /// Implemented using the [`serde_json`] crate, only compatible with [`serde`] types.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct JsonLines;
 
-  /// A shortcut type to a [`Compressed`][crate::Compressed] [`Json`].
-  /// Provides parameters for compression format and pretty-print configuration (defaulting to off).
-  pub type CompressedJson<C, const PRETTY: bool = false> = crate::Compressed<C, Json<PRETTY>>;
+  impl<T> RecordFormat<T> for JsonLines
+  where T: Serialize + DeserializeOwned {
+    type FormatError = JsonError;
+
+    fn from_record(&self, record: &[u8]) -> Result<T, Self::FormatError> {
+      serde_json::from_slice(record)
+    }
+
+    fn to_record<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      serde_json::to_writer(writer, value)
+    }
+  }
+
+  #[cfg(feature = "path-to-error")]
+  impl<const PRETTY: bool> crate::tracked::TrackedFormat for Json<PRETTY> {
+    type Error = JsonError;
+
+    #[inline]
+    fn from_reader_tracked<T, R>(&self, reader: R) -> Result<T, crate::tracked::PathError<Self::Error>>
+    where T: DeserializeOwned, R: Read {
+      RuntimeJson::new(PRETTY).from_reader_tracked(reader)
+    }
+
+    #[inline]
+    fn to_writer_tracked<T, W>(&self, writer: W, value: &T) -> Result<(), crate::tracked::PathError<Self::Error>>
+    where T: Serialize, W: Write {
+      RuntimeJson::new(PRETTY).to_writer_tracked(writer, value)
+    }
+  }
+
+  #[cfg(feature = "path-to-error")]
+  impl crate::tracked::TrackedFormat for RuntimeJson {
+    type Error = JsonError;
+
+    fn from_reader_tracked<T, R>(&self, reader: R) -> Result<T, crate::tracked::PathError<Self::Error>>
+    where T: DeserializeOwned, R: Read {
+      let mut deserializer = serde_json::Deserializer::from_reader(reader);
+      let value = serde_path_to_error::deserialize(&mut deserializer)?;
+      deserializer.end().map_err(crate::tracked::untracked)?;
+      Ok(value)
+    }
+
+    fn to_writer_tracked<T, W>(&self, writer: W, value: &T) -> Result<(), crate::tracked::PathError<Self::Error>>
+    where T: Serialize, W: Write {
+      match self.pretty {
+        true => serde_path_to_error::serialize(value, &mut serde_json::Serializer::pretty(writer)),
+        false => serde_path_to_error::serialize(value, &mut serde_json::Serializer::new(writer))
+      }
+    }
+  }
 }
 
 /// Defines a [`FileFormat`] using the TOML data format.
@@ -350,6 +800,7 @@ pub mod toml_serde {
   /// Implemented using the [`toml`] crate, only compatible with [`serde`] types.
   ///
   /// This type provides an optional constant generic parameter for configuring pretty-print.
+  /// See [`RuntimeToml`] for choosing whether to pretty-print at runtime instead.
   #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
   pub struct Toml<const PRETTY: bool = true>;
 
@@ -358,6 +809,114 @@ pub mod toml_serde {
   where T: Serialize + DeserializeOwned {
     type FormatError = TomlError;
 
+    #[inline]
+    fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      RuntimeToml::new(PRETTY).from_reader(reader)
+    }
+
+    #[inline]
+    fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      // no need to pass `reader` in with a `BufReader` as that would cause things to be buffered twice
+      self.from_reader(reader)
+    }
+
+    #[inline]
+    fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      RuntimeToml::new(PRETTY).to_writer(writer, value)
+    }
+
+    #[inline]
+    fn to_writer_buffered<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      // no need to pass `writer` in with a `BufWriter` as that would cause things to be buffered twice
+      self.to_writer(writer, value)
+    }
+
+    #[inline]
+    fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+      RuntimeToml::new(PRETTY).to_buffer(value)
+    }
+
+    #[inline]
+    fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+      FileFormat::<T>::error_offset(&RuntimeToml::new(PRETTY), error, buf)
+    }
+  }
+
+  impl<T, const PRETTY: bool> FileFormatUtf8<T> for Toml<PRETTY>
+  where T: Serialize + DeserializeOwned {
+    #[inline]
+    fn from_string_buffer(&self, buf: &str) -> Result<T, Self::FormatError> {
+      RuntimeToml::new(PRETTY).from_string_buffer(buf)
+    }
+
+    #[inline]
+    fn to_string_buffer(&self, value: &T) -> Result<String, Self::FormatError> {
+      RuntimeToml::new(PRETTY).to_string_buffer(value)
+    }
+  }
+
+  /// A shortcut type to a [`Toml`] with pretty-print enabled.
+  pub type PrettyToml = Toml<true>;
+  /// A shortcut type to a [`Toml`] with pretty-print disabled.
+  pub type RegularToml = Toml<false>;
+
+  /// A shortcut type to a [`Compressed`][crate::Compressed] [`Toml`].
+  /// Provides parameters for compression format and pretty-print configuration (defaulting to off).
+  pub type CompressedToml<C, const PRETTY: bool = false> = crate::Compressed<C, Toml<PRETTY>>;
+
+  /// A [`FileFormat`] corresponding to the TOML data format, identical to [`Toml`] except that
+  /// whether output is pretty-printed is chosen at runtime with [`RuntimeToml::pretty`] or [`RuntimeToml::minified`],
+  /// so it can come from user settings without changing the type of a container. Defaults to pretty-printed.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct RuntimeToml {
+    pretty: bool
+  }
+
+  impl RuntimeToml {
+    /// Creates a new [`RuntimeToml`], pretty-printing output if `pretty` is `true`.
+    #[inline]
+    pub const fn new(pretty: bool) -> Self {
+      RuntimeToml { pretty }
+    }
+
+    /// Creates a new [`RuntimeToml`] that pretty-prints output.
+    #[inline]
+    pub const fn pretty() -> Self {
+      RuntimeToml::new(true)
+    }
+
+    /// Creates a new [`RuntimeToml`] that does not pretty-print output, keeping arrays on a single line.
+    #[inline]
+    pub const fn minified() -> Self {
+      RuntimeToml::new(false)
+    }
+
+    /// Returns `true` if this [`RuntimeToml`] pretty-prints output.
+    #[inline]
+    pub const fn is_pretty(&self) -> bool {
+      self.pretty
+    }
+  }
+
+  impl Default for RuntimeToml {
+    #[inline]
+    fn default() -> Self {
+      RuntimeToml::pretty()
+    }
+  }
+
+  impl<const PRETTY: bool> From<Toml<PRETTY>> for RuntimeToml {
+    #[inline]
+    fn from(_: Toml<PRETTY>) -> Self {
+      RuntimeToml::new(PRETTY)
+    }
+  }
+
+  /// Since the [`toml`] crate exposes no writer-based operations, all operations within this implementation are buffered.
+  impl<T> FileFormat<T> for RuntimeToml
+  where T: Serialize + DeserializeOwned {
+    type FormatError = TomlError;
+
     fn from_reader<R: Read>(&self, mut reader: R) -> Result<T, Self::FormatError> {
       let mut buf = String::new();
       reader.read_to_string(&mut buf)?;
@@ -385,30 +944,1202 @@ pub mod toml_serde {
     fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
       self.to_string_buffer(value).map(String::into_bytes)
     }
+
+    fn error_offset(&self, error: &Self::FormatError, _buf: &[u8]) -> Option<usize> {
+      match error {
+        TomlError::DeserializeError(err) => err.span().map(|span| span.start),
+        _ => None
+      }
+    }
   }
 
-  impl<T, const PRETTY: bool> FileFormatUtf8<T> for Toml<PRETTY>
+  impl<T> FileFormatUtf8<T> for RuntimeToml
   where T: Serialize + DeserializeOwned {
     fn from_string_buffer(&self, buf: &str) -> Result<T, Self::FormatError> {
       Ok(toml::de::from_str(buf)?)
     }
 
     fn to_string_buffer(&self, value: &T) -> Result<String, Self::FormatError> {
-      Ok(match PRETTY {
+      Ok(match self.pretty {
         true => toml::ser::to_string_pretty(value),
         false => toml::ser::to_string(value)
       }?)
     }
   }
 
-  /// A shortcut type to a [`Toml`] with pretty-print enabled.
-  pub type PrettyToml = Toml<true>;
-  /// A shortcut type to a [`Toml`] with pretty-print disabled.
-  pub type RegularToml = Toml<false>;
+  #[cfg(feature = "path-to-error")]
+  impl<const PRETTY: bool> crate::tracked::TrackedFormat for Toml<PRETTY> {
+    type Error = TomlError;
 
-  /// A shortcut type to a [`Compressed`][crate::Compressed] [`Toml`].
-  /// Provides parameters for compression format and pretty-print configuration (defaulting to off).
-  pub type CompressedToml<C, const PRETTY: bool = false> = crate::Compressed<C, Toml<PRETTY>>;
+    #[inline]
+    fn from_reader_tracked<T, R>(&self, reader: R) -> Result<T, crate::tracked::PathError<Self::Error>>
+    where T: DeserializeOwned, R: Read {
+      RuntimeToml::new(PRETTY).from_reader_tracked(reader)
+    }
+
+    #[inline]
+    fn to_writer_tracked<T, W>(&self, writer: W, value: &T) -> Result<(), crate::tracked::PathError<Self::Error>>
+    where T: Serialize, W: Write {
+      RuntimeToml::new(PRETTY).to_writer_tracked(writer, value)
+    }
+  }
+
+  #[cfg(feature = "path-to-error")]
+  impl crate::tracked::TrackedFormat for RuntimeToml {
+    type Error = TomlError;
+
+    fn from_reader_tracked<T, R>(&self, mut reader: R) -> Result<T, crate::tracked::PathError<Self::Error>>
+    where T: DeserializeOwned, R: Read {
+      let mut buf = String::new();
+      reader.read_to_string(&mut buf).map_err(|err| crate::tracked::untracked(err.into()))?;
+      serde_path_to_error::deserialize(toml::Deserializer::new(&buf))
+        .map_err(|err| crate::tracked::map_inner(err, TomlError::from))
+    }
+
+    fn to_writer_tracked<T, W>(&self, mut writer: W, value: &T) -> Result<(), crate::tracked::PathError<Self::Error>>
+    where T: Serialize, W: Write {
+      let mut buf = String::new();
+      match self.pretty {
+        true => serde_path_to_error::serialize(value, toml::Serializer::pretty(&mut buf)),
+        false => serde_path_to_error::serialize(value, toml::Serializer::new(&mut buf))
+      }.map_err(|err| crate::tracked::map_inner(err, TomlError::from))?;
+      writer.write_all(buf.as_bytes()).map_err(|err| crate::tracked::untracked(err.into()))
+    }
+  }
+}
+
+/// Defines a [`FileFormat`] that detects which of the enabled `serde` formats a file uses.
+#[cfg_attr(docsrs, doc(cfg(feature = "detect")))]
+#[cfg(feature = "detect")]
+pub mod detect {
+  use serde::ser::Serialize;
+  use serde::de::DeserializeOwned;
+  use singlefile::FileFormat;
+  use thiserror::Error;
+
+  use std::io::{Read, Write};
+  use std::path::Path;
+  use std::sync::atomic::{AtomicU8, Ordering};
+
+  /// A format that [`DetectFormat`] can select.
+  /// Only the formats whose cargo features are enabled are available.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+  #[non_exhaustive]
+  pub enum FormatKind {
+    /// [`Cbor`][crate::cbor_serde::Cbor], for files with the `cbor` extension.
+    #[cfg(feature = "cbor-serde")]
+    Cbor,
+    /// [`Json`][crate::json_serde::Json], for files with the `json` extension.
+    #[cfg(feature = "json-serde")]
+    Json,
+    /// [`Toml`][crate::toml_serde::Toml], for files with the `toml` extension.
+    #[cfg(feature = "toml-serde")]
+    Toml
+  }
+
+  impl FormatKind {
+    /// Every available format.
+    pub const ALL: &'static [FormatKind] = &[
+      #[cfg(feature = "cbor-serde")]
+      FormatKind::Cbor,
+      #[cfg(feature = "json-serde")]
+      FormatKind::Json,
+      #[cfg(feature = "toml-serde")]
+      FormatKind::Toml
+    ];
+
+    /// Gets the file extension (without the leading dot) of files in this format.
+    pub const fn extension(self) -> &'static str {
+      match self {
+        #[cfg(feature = "cbor-serde")]
+        FormatKind::Cbor => "cbor",
+        #[cfg(feature = "json-serde")]
+        FormatKind::Json => "json",
+        #[cfg(feature = "toml-serde")]
+        FormatKind::Toml => "toml"
+      }
+    }
+
+    /// Selects the format with the given file extension (without the leading dot), ignoring case.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+      FormatKind::ALL.iter().copied().find(|kind| kind.extension().eq_ignore_ascii_case(extension))
+    }
+
+    /// Selects a format from the extension of the given path.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+      path.as_ref().extension()?.to_str().and_then(FormatKind::from_extension)
+    }
+
+    /// Returns the formats that the given contents may be in, from most to least likely,
+    /// judging by their leading bytes.
+    ///
+    /// Contents that begin with a byte that cannot begin a text file are binary, and so can only be CBOR.
+    /// Text that begins with `{` can only be JSON, while text that begins with `[` may be either
+    /// a JSON array or a TOML table header. Any other text is more likely to be TOML.
+    pub fn candidates(buf: &[u8]) -> Vec<Self> {
+      let buf = buf.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(buf);
+      let first = buf.iter().copied().find(|b| !b.is_ascii_whitespace());
+      let preferred: &[&str] = match first {
+        None => &[],
+        Some(b) if b >= 0x80 || b.is_ascii_control() => &["cbor"],
+        Some(b'{') => &["json"],
+        Some(b'[') => &["json", "toml"],
+        Some(_) => &["toml", "json"]
+      };
+
+      preferred.iter().filter_map(|extension| FormatKind::from_extension(extension)).collect()
+    }
+
+    fn to_u8(self) -> u8 {
+      FormatKind::ALL.iter().position(|&kind| kind == self).map_or(0, |i| i as u8 + 1)
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+      FormatKind::ALL.get(usize::from(value).checked_sub(1)?).copied()
+    }
+  }
+
+  /// An error that can occur while using [`DetectFormat`].
+  #[derive(Debug, Error)]
+  #[non_exhaustive]
+  pub enum DetectError {
+    /// An error occurred in [`Cbor`][crate::cbor_serde::Cbor].
+    #[cfg(feature = "cbor-serde")]
+    #[error(transparent)]
+    Cbor(#[from] crate::cbor_serde::CborError),
+    /// An error occurred in [`Json`][crate::json_serde::Json].
+    #[cfg(feature = "json-serde")]
+    #[error(transparent)]
+    Json(#[from] crate::json_serde::JsonError),
+    /// An error occurred in [`Toml`][crate::toml_serde::Toml].
+    #[cfg(feature = "toml-serde")]
+    #[error(transparent)]
+    Toml(#[from] crate::toml_serde::TomlError),
+    /// An error occurred while reading data to the buffer.
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    /// The contents are not in any of the available formats, or a value was written before a format was known.
+    #[error("could not detect the format of the file")]
+    Undetected
+  }
+
+  /// A [`FileFormat`] that selects among the enabled `serde` formats ([`Cbor`], [`Json`] and [`Toml`]),
+  /// so that a single container type can open files in any of them, such as `config.json` or `config.toml`.
+  ///
+  /// The format is either fixed up front, usually from the extension of the file with [`DetectFormat::for_path`],
+  /// or detected from the leading bytes of the file every time it is read (see [`FormatKind::candidates`]).
+  /// A detected format is remembered, so that the file is written back in the format it was read in.
+  /// Writing a value before any format is known fails with [`DetectError::Undetected`].
+  ///
+  /// ```no_run
+  /// # use singlefile_formats::detect::{DetectError, DetectFormat};
+  /// # #[derive(serde::Serialize, serde::Deserialize, Default)] struct Config;
+  /// use singlefile::container::ContainerWritable;
+  ///
+  /// let path = "config.toml";
+  /// let config = ContainerWritable::<Config, _>::create_or_default(path, DetectFormat::for_path(path))?;
+  /// # Ok::<(), singlefile::Error<DetectError>>(())
+  /// ```
+  ///
+  /// Since this is only implemented with `serde`, every enabled format must support the type being stored.
+  ///
+  /// [`Cbor`]: crate::cbor_serde::Cbor
+  /// [`Json`]: crate::json_serde::Json
+  /// [`Toml`]: crate::toml_serde::Toml
+  #[derive(Debug, Default)]
+  pub struct DetectFormat {
+    /// The [`RuntimeJson`][crate::json_serde::RuntimeJson] format used for JSON files.
+    #[cfg(feature = "json-serde")]
+    pub json: crate::json_serde::RuntimeJson,
+    /// The [`RuntimeToml`][crate::toml_serde::RuntimeToml] format used for TOML files.
+    #[cfg(feature = "toml-serde")]
+    pub toml: crate::toml_serde::RuntimeToml,
+    fixed: Option<FormatKind>,
+    detected: AtomicU8
+  }
+
+  impl DetectFormat {
+    /// Creates a new [`DetectFormat`] that detects the format of the file from its contents.
+    #[inline]
+    pub fn new() -> Self {
+      DetectFormat::default()
+    }
+
+    /// Creates a new [`DetectFormat`] that always uses the given format.
+    #[inline]
+    pub fn with_kind(kind: FormatKind) -> Self {
+      DetectFormat { fixed: Some(kind), ..DetectFormat::default() }
+    }
+
+    /// Creates a new [`DetectFormat`] that uses the format matching the extension of the given path,
+    /// or detects the format of the file from its contents if the extension is not recognized.
+    #[inline]
+    pub fn for_path<P: AsRef<Path>>(path: P) -> Self {
+      DetectFormat { fixed: FormatKind::from_path(path), ..DetectFormat::default() }
+    }
+
+    /// Gets the format that this [`DetectFormat`] writes with, if it is known yet.
+    pub fn kind(&self) -> Option<FormatKind> {
+      self.fixed.or_else(|| FormatKind::from_u8(self.detected.load(Ordering::Acquire)))
+    }
+
+    #[cfg_attr(not(any(feature = "cbor-serde", feature = "json-serde", feature = "toml-serde")), allow(unused_variables))]
+    fn read_as<T>(&self, kind: FormatKind, buf: &[u8]) -> Result<T, DetectError>
+    where T: Serialize + DeserializeOwned {
+      match kind {
+        #[cfg(feature = "cbor-serde")]
+        FormatKind::Cbor => crate::cbor_serde::Cbor.from_buffer(buf).map_err(From::from),
+        #[cfg(feature = "json-serde")]
+        FormatKind::Json => serde_json::from_slice(buf).map_err(From::from),
+        #[cfg(feature = "toml-serde")]
+        FormatKind::Toml => {
+          let buf = std::str::from_utf8(buf).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+          toml::de::from_str(buf).map_err(|err| crate::toml_serde::TomlError::from(err).into())
+        }
+      }
+    }
+  }
+
+  impl Clone for DetectFormat {
+    fn clone(&self) -> Self {
+      DetectFormat {
+        #[cfg(feature = "json-serde")]
+        json: self.json,
+        #[cfg(feature = "toml-serde")]
+        toml: self.toml,
+        fixed: self.fixed,
+        detected: AtomicU8::new(self.detected.load(Ordering::Acquire))
+      }
+    }
+  }
+
+  /// Since the format may have to be detected from the contents, all reads within this implementation are buffered.
+  impl<T> FileFormat<T> for DetectFormat
+  where T: Serialize + DeserializeOwned {
+    type FormatError = DetectError;
+
+    fn from_reader<R: Read>(&self, mut reader: R) -> Result<T, Self::FormatError> {
+      let mut buf = Vec::new();
+      reader.read_to_end(&mut buf)?;
+      self.from_buffer(&buf)
+    }
+
+    #[inline]
+    fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      // no need to pass `reader` in with a `BufReader` as that would cause things to be buffered twice
+      self.from_reader(reader)
+    }
+
+    fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
+      if let Some(kind) = self.fixed {
+        return self.read_as(kind, buf);
+      };
+
+      // the error of the most likely format is the most useful one to report
+      let mut first_error = None;
+      for kind in FormatKind::candidates(buf) {
+        match self.read_as(kind, buf) {
+          Ok(value) => {
+            self.detected.store(kind.to_u8(), Ordering::Release);
+            return Ok(value);
+          },
+          Err(err) => {
+            first_error.get_or_insert(err);
+          }
+        };
+      };
+
+      Err(first_error.unwrap_or(DetectError::Undetected))
+    }
+
+    #[cfg_attr(not(any(feature = "cbor-serde", feature = "json-serde", feature = "toml-serde")), allow(unused_variables))]
+    fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      match self.kind().ok_or(DetectError::Undetected)? {
+        #[cfg(feature = "cbor-serde")]
+        FormatKind::Cbor => crate::cbor_serde::Cbor.to_writer(writer, value).map_err(From::from),
+        #[cfg(feature = "json-serde")]
+        FormatKind::Json => self.json.to_writer(writer, value).map_err(From::from),
+        #[cfg(feature = "toml-serde")]
+        FormatKind::Toml => self.toml.to_writer(writer, value).map_err(From::from)
+      }
+    }
+
+    fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+      match error {
+        #[cfg(feature = "cbor-serde")]
+        DetectError::Cbor(error) => FileFormat::<T>::error_offset(&crate::cbor_serde::Cbor, error, buf),
+        #[cfg(feature = "json-serde")]
+        DetectError::Json(error) => FileFormat::<T>::error_offset(&self.json, error, buf),
+        #[cfg(feature = "toml-serde")]
+        DetectError::Toml(error) => FileFormat::<T>::error_offset(&self.toml, error, buf),
+        _ => None
+      }
+    }
+  }
+}
+
+/// Defines a [`FileFormat`] wrapper that reports the path of the field that caused an error in `serde` formats.
+#[cfg_attr(docsrs, doc(cfg(feature = "path-to-error")))]
+#[cfg(feature = "path-to-error")]
+pub mod tracked {
+  pub extern crate serde_path_to_error;
+
+  use serde::ser::Serialize;
+  use serde::de::DeserializeOwned;
+  use singlefile::FileFormat;
+
+  use std::io::{Read, Write};
+
+  /// An error that can occur while using [`Tracked`], holding the path of the field that caused it
+  /// (such as `settings.accounts[3].token`) along with the error from the wrapped format.
+  ///
+  /// Errors that are not caused by any particular field (such as I/O errors) have an empty path,
+  /// in which case the path is omitted from the error message.
+  pub type PathError<E> = serde_path_to_error::Error<E>;
+
+  /// A `serde` format that can report the path of the field that caused an error, for use with [`Tracked`].
+  ///
+  /// This is implemented for [`Json`][crate::json_serde::Json] and [`Toml`][crate::toml_serde::Toml]
+  /// when their respective features are enabled.
+  pub trait TrackedFormat {
+    /// The type of error returned by the format.
+    type Error: std::error::Error;
+
+    /// Deserialize a value from a `Read` stream, tracking the path of the field being deserialized.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_reader_tracked<T, R>(&self, reader: R) -> Result<T, PathError<Self::Error>>
+    where T: DeserializeOwned, R: Read;
+
+    /// Serialize a value into a `Write` stream, tracking the path of the field being serialized.
+    fn to_writer_tracked<T, W>(&self, writer: W, value: &T) -> Result<(), PathError<Self::Error>>
+    where T: Serialize, W: Write;
+  }
+
+  /// Wraps a [`TrackedFormat`], making its errors report the path of the field that caused them,
+  /// instead of only a position within the file.
+  ///
+  /// ```no_run
+  /// # use singlefile_formats::json_serde::Json;
+  /// # #[derive(serde::Serialize, serde::Deserialize)] struct Settings;
+  /// use singlefile::container::ContainerReadonly;
+  /// use singlefile_formats::tracked::Tracked;
+  ///
+  /// match ContainerReadonly::<Settings, Tracked<Json>>::open("settings.json", Tracked(Json)) {
+  ///   // `accounts[3].token: invalid type: integer `5`, expected a string at line 12 column 16`
+  ///   Err(singlefile::Error::Format(err)) => eprintln!("{err}"),
+  ///   _ => ()
+  /// };
+  /// ```
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct Tracked<F>(pub F);
+
+  impl<T, F> FileFormat<T> for Tracked<F>
+  where T: Serialize + DeserializeOwned, F: TrackedFormat {
+    type FormatError = PathError<F::Error>;
+
+    #[inline]
+    fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      self.0.from_reader_tracked(reader)
+    }
+
+    #[inline]
+    fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      self.0.to_writer_tracked(writer, value)
+    }
+  }
+
+  /// Creates a [`PathError`] with an empty path, for errors that are not caused by any particular field.
+  #[cfg(any(feature = "json-serde", feature = "toml-serde"))]
+  pub(crate) fn untracked<E>(err: E) -> PathError<E> {
+    PathError::new(serde_path_to_error::Track::new().path(), err)
+  }
+
+  /// Converts the error held by a [`PathError`], keeping its path.
+  #[cfg(feature = "toml-serde")]
+  pub(crate) fn map_inner<E, U>(err: PathError<E>, f: impl FnOnce(E) -> U) -> PathError<U> {
+    let path = err.path().clone();
+    PathError::new(path, f(err.into_inner()))
+  }
+}
+
+/// Defines a [`FileFormat`] wrapper that expands environment variable placeholders in text formats.
+#[cfg_attr(docsrs, doc(cfg(feature = "interpolate")))]
+#[cfg(feature = "interpolate")]
+pub mod interpolate {
+  use singlefile::{FileFormat, FileFormatUtf8};
+  use thiserror::Error;
+
+  use std::io::{self, Read, Write};
+
+  /// An error that can occur while using [`Interpolated`].
+  #[derive(Debug, Error)]
+  pub enum InterpolatedError<FE> {
+    /// An error occurred in the wrapped format.
+    #[error(transparent)]
+    Format(FE),
+    /// An error occurred while reading data to the buffer.
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    /// The contents are not valid UTF-8.
+    #[error("contents are not valid UTF-8")]
+    InvalidUtf8,
+    /// A placeholder was not closed with a `}`.
+    #[error("unterminated placeholder at byte {0}")]
+    Unterminated(usize),
+    /// A placeholder referred to an environment variable that is not set (or is not valid unicode).
+    #[error("environment variable {0:?} is not set")]
+    MissingVariable(String)
+  }
+
+  /// What [`Interpolated`] should do when a placeholder refers to an environment variable that is not set,
+  /// and the placeholder does not provide a default.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+  pub enum MissingVariable {
+    /// Fail with [`InterpolatedError::MissingVariable`].
+    #[default]
+    Error,
+    /// Replace the placeholder with an empty string.
+    Empty,
+    /// Leave the placeholder as-is.
+    Keep
+  }
+
+  /// Takes a [`FileFormatUtf8`], expanding `${VAR}` placeholders in the contents with the value of the
+  /// environment variable `VAR` before they are parsed by the format.
+  ///
+  /// - `${VAR:-default}` expands to `default` when `VAR` is not set.
+  /// - `$$` expands to a single `$`, so `$${VAR}` produces the literal text `${VAR}`.
+  /// - A `$` that is not followed by `{` or `$` is left as-is.
+  ///
+  /// With the `zeroize` feature, the expanded contents are wiped from memory once they have been parsed.
+  ///
+  /// Placeholders are only expanded on read, values are written by the wrapped format unchanged.
+  /// This means that committing a value read through this format writes the *expanded* values to disk,
+  /// so it is best used with read-only containers, especially when injecting secrets.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct Interpolated<F> {
+    /// The [`FileFormatUtf8`] to be used.
+    pub format: F,
+    /// What to do when a variable is not set.
+    pub missing: MissingVariable
+  }
+
+  impl<F> Interpolated<F> {
+    /// Creates a new [`Interpolated`] that fails when a variable is not set.
+    #[inline]
+    pub const fn new(format: F) -> Self {
+      Interpolated { format, missing: MissingVariable::Error }
+    }
+
+    /// Creates a new [`Interpolated`] with the given behavior for variables that are not set.
+    #[inline]
+    pub const fn with_missing(format: F, missing: MissingVariable) -> Self {
+      Interpolated { format, missing }
+    }
+  }
+
+  /// Since the whole contents must be expanded before parsing, reading is buffered.
+  impl<T, F> FileFormat<T> for Interpolated<F>
+  where F: FileFormatUtf8<T> {
+    type FormatError = InterpolatedError<F::FormatError>;
+
+    fn from_reader<R: Read>(&self, mut reader: R) -> Result<T, Self::FormatError> {
+      let mut buf = Vec::new();
+      reader.read_to_end(&mut buf)?;
+      self.from_buffer(&buf)
+    }
+
+    #[inline]
+    fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      // no need to pass `reader` in with a `BufReader` as that would cause things to be buffered twice
+      self.from_reader(reader)
+    }
+
+    fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
+      let buf = std::str::from_utf8(buf).map_err(|_| InterpolatedError::InvalidUtf8)?;
+      let expanded = crate::sensitive(expand(buf, self.missing)?);
+      self.format.from_string_buffer(&expanded).map_err(InterpolatedError::Format)
+    }
+
+    #[inline]
+    fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      self.format.to_writer(writer, value).map_err(InterpolatedError::Format)
+    }
+
+    #[inline]
+    fn to_writer_buffered<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      self.format.to_writer_buffered(writer, value).map_err(InterpolatedError::Format)
+    }
+
+    #[inline]
+    fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+      self.format.to_buffer(value).map_err(InterpolatedError::Format)
+    }
+  }
+
+  /// Expands every placeholder in `text`, as described by [`Interpolated`].
+  pub fn expand<FE>(text: &str, missing: MissingVariable) -> Result<String, InterpolatedError<FE>> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+      out.push_str(&rest[..i]);
+      let after = &rest[i + 1..];
+      if let Some(after) = after.strip_prefix('$') {
+        out.push('$');
+        rest = after;
+      } else if let Some(after) = after.strip_prefix('{') {
+        let offset = text.len() - rest.len() + i;
+        let end = after.find('}').ok_or(InterpolatedError::Unterminated(offset))?;
+        let placeholder = &after[..end];
+        let (name, default) = match placeholder.split_once(":-") {
+          Some((name, default)) => (name, Some(default)),
+          None => (placeholder, None)
+        };
+
+        match (std::env::var(name), default) {
+          (Ok(value), _) => out.push_str(&value),
+          (Err(_), Some(default)) => out.push_str(default),
+          (Err(_), None) => match missing {
+            MissingVariable::Error => return Err(InterpolatedError::MissingVariable(name.to_owned())),
+            MissingVariable::Empty => (),
+            MissingVariable::Keep => out.push_str(&rest[i..i + end + 3])
+          }
+        };
+
+        rest = &after[end + 1..];
+      } else {
+        out.push('$');
+        rest = after;
+      };
+    };
+
+    out.push_str(rest);
+    Ok(out)
+  }
+}
+
+/// Defines a [`FileFormat`] wrapper that appends a checksum to data from another format, and verifies it when reading.
+#[cfg_attr(docsrs, doc(cfg(feature = "checksum")))]
+#[cfg(feature = "checksum")]
+pub mod checksum {
+  pub extern crate crc32fast;
+  pub extern crate sha2;
+
+  use sha2::Digest;
+  use singlefile::FileFormat;
+  use thiserror::Error;
+
+  use std::io::{self, Read, Write};
+
+  /// An error that can occur while using [`Checksummed`].
+  #[derive(Debug, Error)]
+  pub enum ChecksummedError<FE> {
+    /// An error occurred in the wrapped format.
+    #[error(transparent)]
+    Format(FE),
+    /// An error occurred while reading data to the buffer.
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    /// The checksum stored in the file does not match its contents, or the file is too short to contain one.
+    /// This usually means that the file was truncated, or otherwise corrupted on disk.
+    #[error("checksum mismatch, the file may have been truncated or corrupted")]
+    Corrupted
+  }
+
+  /// A checksum algorithm that can be used by [`Checksummed`].
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+  pub enum ChecksumAlgorithm {
+    /// CRC-32 (IEEE), a 4 byte checksum that is very fast to compute, and catches truncation and accidental corruption.
+    /// Implemented using the [`crc32fast`] crate.
+    #[default]
+    Crc32,
+    /// SHA-256, a 32 byte cryptographic hash, which is slower to compute but practically never collides.
+    /// Note that a checksum cannot protect against deliberate tampering, since it can simply be recomputed.
+    /// Implemented using the [`sha2`] crate.
+    Sha256
+  }
+
+  impl ChecksumAlgorithm {
+    /// Returns the length in bytes of the checksums produced by this algorithm.
+    pub const fn output_len(self) -> usize {
+      match self {
+        ChecksumAlgorithm::Crc32 => 4,
+        ChecksumAlgorithm::Sha256 => 32
+      }
+    }
+
+    /// Computes the checksum of the given data with this algorithm.
+    pub fn checksum(self, data: &[u8]) -> Vec<u8> {
+      match self {
+        ChecksumAlgorithm::Crc32 => crc32fast::hash(data).to_be_bytes().to_vec(),
+        ChecksumAlgorithm::Sha256 => sha2::Sha256::digest(data).to_vec()
+      }
+    }
+  }
+
+  /// Wraps a [`FileFormat`], appending a checksum of the contents emitted by the format as a footer before
+  /// writing to disk, and verifying the checksum before the contents are parsed by the format.
+  ///
+  /// A file that was truncated or corrupted then fails with [`ChecksummedError::Corrupted`],
+  /// rather than with a confusing parse error, or worse, parsing successfully into the wrong value.
+  /// Files must be read with the same [`ChecksumAlgorithm`] that they were written with.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct Checksummed<F> {
+    /// The [`FileFormat`] to be used.
+    pub format: F,
+    /// The [`ChecksumAlgorithm`] to be used.
+    pub algorithm: ChecksumAlgorithm
+  }
+
+  impl<F> Checksummed<F> {
+    /// Creates a new [`Checksummed`], using the given format and checksum algorithm.
+    #[inline]
+    pub const fn new(format: F, algorithm: ChecksumAlgorithm) -> Self {
+      Checksummed { format, algorithm }
+    }
+
+    /// Creates a new [`Checksummed`], using the given format and a CRC-32 checksum.
+    #[inline]
+    pub const fn crc32(format: F) -> Self {
+      Checksummed::new(format, ChecksumAlgorithm::Crc32)
+    }
+
+    /// Creates a new [`Checksummed`], using the given format and a SHA-256 checksum.
+    #[inline]
+    pub const fn sha256(format: F) -> Self {
+      Checksummed::new(format, ChecksumAlgorithm::Sha256)
+    }
+  }
+
+  /// Since the checksum covers the contents as a whole, all operations within this implementation are buffered.
+  impl<T, F> FileFormat<T> for Checksummed<F>
+  where F: FileFormat<T> {
+    type FormatError = ChecksummedError<F::FormatError>;
+
+    fn from_reader<R: Read>(&self, mut reader: R) -> Result<T, Self::FormatError> {
+      let mut buf = Vec::new();
+      reader.read_to_end(&mut buf)?;
+      self.from_buffer(&buf)
+    }
+
+    #[inline]
+    fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      // no need to pass `reader` in with a `BufReader` as that would cause things to be buffered twice
+      self.from_reader(reader)
+    }
+
+    fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
+      let contents_len = buf.len().checked_sub(self.algorithm.output_len()).ok_or(ChecksummedError::Corrupted)?;
+      let (contents, checksum) = buf.split_at(contents_len);
+      if self.algorithm.checksum(contents) != checksum {
+        return Err(ChecksummedError::Corrupted);
+      };
+
+      self.format.from_buffer(contents).map_err(ChecksummedError::Format)
+    }
+
+    fn to_writer<W: Write>(&self, mut writer: W, value: &T) -> Result<(), Self::FormatError> {
+      let buf = self.to_buffer(value)?;
+      writer.write_all(&buf).map_err(From::from)
+    }
+
+    #[inline]
+    fn to_writer_buffered<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      // no need to pass `writer` in with a `BufWriter` as that would cause things to be buffered twice
+      self.to_writer(writer, value)
+    }
+
+    fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+      let mut buf = self.format.to_buffer(value).map_err(ChecksummedError::Format)?;
+      let checksum = self.algorithm.checksum(&buf);
+      buf.extend_from_slice(&checksum);
+      Ok(buf)
+    }
+  }
+}
+
+/// Defines a preset container for storing secrets, encrypted with [`Encrypted`] using ChaCha20-Poly1305.
+///
+/// [`Encrypted`]: crate::secret::Encrypted
+#[cfg_attr(docsrs, doc(cfg(feature = "secret")))]
+#[cfg(feature = "secret")]
+pub mod secret {
+  pub use crate::encryption::{ChaCha20Poly1305, EncryptedError};
+  #[cfg_attr(docsrs, doc(cfg(feature = "mlock")))]
+  #[cfg(feature = "mlock")]
+  pub use crate::encryption::{lock_memory, MemoryLock};
+
+  use singlefile::{Error, FileFormat};
+  use singlefile::container::Container;
+  use singlefile::manager::{Atomic, ExclusiveLock, FileManager};
+
+  use std::fs::{self, OpenOptions};
+  use std::io;
+  use std::path::Path;
+
+  /// A container preset for storing secrets such as API tokens.
+  ///
+  /// Contents are encrypted with [`Encrypted`], and the file is exclusively locked for as long as the container is open.
+  /// Commits are encrypted in full before the file is overwritten in place (see [`Atomic`]), so a failing format never
+  /// leaves a corrupted file. The file is not replaced by a rename, since a lock only covers the file it was taken on.
+  /// When created through [`create_or`] or [`create_or_default`], the file is only readable and writable
+  /// by its owner on Unix, and commits keep it that way.
+  pub type ContainerSecret<T, F> = Container<T, ManagerSecret<F>>;
+
+  /// The file manager used by [`ContainerSecret`].
+  pub type ManagerSecret<F> = FileManager<Encrypted<F>, ExclusiveLock, Atomic>;
+
+  /// The [`encryption::Encrypted`] format wrapper used by [`ContainerSecret`], which encrypts contents with ChaCha20-Poly1305.
+  ///
+  /// [`encryption::Encrypted`]: crate::encryption::Encrypted
+  pub type Encrypted<F> = crate::encryption::Encrypted<ChaCha20Poly1305, F>;
+
+  /// Opens a [`ContainerSecret`], returning an error if the file at the given path does not exist.
+  pub fn open<T, F, P>(path: P, format: Encrypted<F>) -> Result<ContainerSecret<T, F>, Error<EncryptedError<F::FormatError>>>
+  where F: FileFormat<T>, P: AsRef<Path> {
+    Container::open(path, format)
+  }
+
+  /// Opens a [`ContainerSecret`], writing the given value to the file if it does not exist.
+  ///
+  /// A file created by this function is only readable and writable by its owner on Unix.
+  pub fn create_or<T, F, P>(path: P, format: Encrypted<F>, value: T) -> Result<ContainerSecret<T, F>, Error<EncryptedError<F::FormatError>>>
+  where F: FileFormat<T>, P: AsRef<Path> {
+    let path = path.as_ref();
+    match create_restricted(path) {
+      Ok(()) => (),
+      Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return open(path, format),
+      Err(err) => return Err(err.into())
+    };
+
+    // the file is locked before anything is written to it, and removed if the initial value cannot be written,
+    // so that a failed creation does not leave behind an empty file that can never be opened
+    let result = ManagerSecret::open(path, format).map_err(Error::from)
+      .and_then(|manager| manager.write(&value).map(|()| manager));
+    match result {
+      Ok(manager) => Ok(Container::new(value, manager)),
+      Err(err) => {
+        let _ = fs::remove_file(path);
+        Err(err)
+      }
+    }
+  }
+
+  /// Opens a [`ContainerSecret`], writing the default value of `T` to the file if it does not exist.
+  ///
+  /// A file created by this function is only readable and writable by its owner on Unix.
+  pub fn create_or_default<T, F, P>(path: P, format: Encrypted<F>) -> Result<ContainerSecret<T, F>, Error<EncryptedError<F::FormatError>>>
+  where F: FileFormat<T>, P: AsRef<Path>, T: Default {
+    create_or(path, format, T::default())
+  }
+
+  fn create_restricted(path: &Path) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path).map(drop)
+  }
+}
+
+/// Defines a [`FileFormat`] wrapper that encrypts data from another format using an [`EncryptionFormat`],
+/// with keys that are either given directly or derived from a passphrase.
+///
+/// [`EncryptionFormat`]: crate::encryption::EncryptionFormat
+#[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+#[cfg(feature = "encryption")]
+pub mod encryption {
+  pub extern crate aes_gcm;
+  pub extern crate argon2;
+  pub extern crate chacha20poly1305;
+
+  use chacha20poly1305::aead::{self, Aead, KeyInit};
+  use chacha20poly1305::aead::rand_core::{OsRng, RngCore};
+  use singlefile::FileFormat;
+  use thiserror::Error;
+
+  use std::fmt;
+  use std::io::{self, Read, Write};
+  use std::sync::Mutex;
+
+  const SALT_LEN: usize = 16;
+
+  /// The Argon2id memory cost used to derive keys from passphrases, in KiB.
+  /// These parameters are part of the file format, since they are not stored in files.
+  const ARGON2_M_COST: u32 = 19 * 1024;
+  /// The Argon2id iteration count used to derive keys from passphrases.
+  const ARGON2_T_COST: u32 = 2;
+  /// The Argon2id parallelism used to derive keys from passphrases.
+  const ARGON2_P_COST: u32 = 1;
+
+  /// An error that can occur while using [`Encrypted`].
+  #[derive(Debug, Error)]
+  pub enum EncryptedError<FE> {
+    /// An error occurred in the wrapped format.
+    #[error(transparent)]
+    Format(FE),
+    /// An error occurred while reading data to the buffer.
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    /// The contents could not be decrypted, either the key is wrong or the contents have been tampered with.
+    #[error("failed to decrypt contents, the key may be incorrect or the contents may be corrupted")]
+    Decrypt,
+    /// The contents could not be encrypted.
+    #[error("failed to encrypt contents")]
+    Encrypt,
+    /// A key could not be derived from the passphrase.
+    #[error("failed to derive key from passphrase: {0}")]
+    KeyDerivation(argon2::Error)
+  }
+
+  /// Combines a [`FileFormat`] and an [`EncryptionFormat`], encrypting the contents emitted by the format before
+  /// writing to disk, and decrypting (and authenticating) contents before they are parsed by the format.
+  ///
+  /// A fresh random nonce is generated for every write and stored at the start of the file.
+  /// When the key is derived from a passphrase (see [`Encrypted::with_passphrase`]), a random salt is stored before
+  /// the nonce, and the key is derived with Argon2id. Deriving a key is deliberately slow, so the key derived for the
+  /// latest salt is kept in memory, and the salt is reused by later writes.
+  ///
+  /// With the `zeroize` feature, intermediate plaintext buffers are wiped from memory once they are no longer needed.
+  pub struct Encrypted<E, F> {
+    /// The [`FileFormat`] to be used.
+    pub format: F,
+    /// The [`EncryptionFormat`] to be used.
+    pub encryption: E,
+    key: KeySource,
+    #[cfg(feature = "mlock")]
+    lock_memory: bool
+  }
+
+  enum KeySource {
+    Key([u8; 32]),
+    Passphrase {
+      passphrase: Vec<u8>,
+      /// The salt last read or written, along with the key derived from it.
+      derived: Mutex<Option<([u8; SALT_LEN], [u8; 32])>>
+    }
+  }
+
+  impl<E, F> Encrypted<E, F> {
+    /// Creates a new [`Encrypted`] from a format, an encryption format and a 256-bit key.
+    pub const fn new(format: F, encryption: E, key: [u8; 32]) -> Self {
+      Encrypted {
+        format,
+        encryption,
+        key: KeySource::Key(key),
+        #[cfg(feature = "mlock")]
+        lock_memory: false
+      }
+    }
+
+    /// Creates a new [`Encrypted`] from a format, an encryption format and a passphrase that keys are derived from.
+    ///
+    /// Files written this way can only be read with a passphrase, and files written with a key can only be read with a key.
+    pub fn with_passphrase<P: AsRef<[u8]>>(format: F, encryption: E, passphrase: P) -> Self {
+      let passphrase = passphrase.as_ref().to_owned();
+      Encrypted {
+        format,
+        encryption,
+        key: KeySource::Passphrase { passphrase, derived: Mutex::new(None) },
+        #[cfg(feature = "mlock")]
+        lock_memory: false
+      }
+    }
+
+    /// Sets whether the pages holding intermediate plaintext buffers should be locked into RAM while they are in use,
+    /// preventing them from being written to swap. This is disabled by default.
+    ///
+    /// If a buffer cannot be locked (for example because the process has hit its locked memory limit),
+    /// it is used unlocked instead. See also [`lock_memory`] for locking the decrypted state of a container.
+    #[cfg_attr(docsrs, doc(cfg(feature = "mlock")))]
+    #[cfg(feature = "mlock")]
+    pub const fn with_locked_memory(mut self, lock_memory: bool) -> Self {
+      self.lock_memory = lock_memory;
+      self
+    }
+
+    /// Generates a new random 256-bit key, suitable for use with [`Encrypted::new`].
+    pub fn generate_key() -> [u8; 32] {
+      let mut key = [0; 32];
+      OsRng.fill_bytes(&mut key);
+      key
+    }
+
+    fn plaintext<B: AsRef<[u8]>>(&self, buf: B) -> Plaintext<B> {
+      #[cfg(feature = "mlock")]
+      let lock = match self.lock_memory {
+        true => lock_memory(buf.as_ref()),
+        false => None
+      };
+
+      Plaintext {
+        buf,
+        #[cfg(feature = "mlock")]
+        _lock: lock
+      }
+    }
+
+    /// Splits the salt (if any) off of the given buffer, returning the key to decrypt the rest with.
+    fn read_key<'b, FE>(&self, buf: &'b [u8]) -> Result<([u8; 32], &'b [u8]), EncryptedError<FE>> {
+      match &self.key {
+        KeySource::Key(key) => Ok((*key, buf)),
+        KeySource::Passphrase { passphrase, derived } => {
+          if buf.len() < SALT_LEN {
+            return Err(EncryptedError::Decrypt);
+          };
+
+          let (salt, buf) = buf.split_at(SALT_LEN);
+          let mut derived = derived.lock().unwrap_or_else(|err| err.into_inner());
+          match *derived {
+            Some((derived_salt, key)) if derived_salt == salt => Ok((key, buf)),
+            _ => {
+              let salt = <[u8; SALT_LEN]>::try_from(salt).expect("salt has the right length");
+              let key = derive_key(passphrase, &salt)?;
+              *derived = Some((salt, key));
+              Ok((key, buf))
+            }
+          }
+        }
+      }
+    }
+
+    /// Writes the salt (if any) to the given buffer, returning the key to encrypt the contents with.
+    fn write_key<FE>(&self, buf: &mut Vec<u8>) -> Result<[u8; 32], EncryptedError<FE>> {
+      match &self.key {
+        KeySource::Key(key) => Ok(*key),
+        KeySource::Passphrase { passphrase, derived } => {
+          let mut derived = derived.lock().unwrap_or_else(|err| err.into_inner());
+          let (salt, key) = match *derived {
+            Some(derived) => derived,
+            None => {
+              let mut salt = [0; SALT_LEN];
+              OsRng.fill_bytes(&mut salt);
+              (salt, derive_key(passphrase, &salt)?)
+            }
+          };
+
+          *derived = Some((salt, key));
+          buf.extend_from_slice(&salt);
+          Ok(key)
+        }
+      }
+    }
+  }
+
+  /// A buffer holding plaintext, which may be locked into RAM for as long as it lives.
+  struct Plaintext<B> {
+    // fields are dropped in declaration order, so the buffer is wiped (with `zeroize`) before it is unlocked
+    buf: B,
+    #[cfg(feature = "mlock")]
+    _lock: Option<MemoryLock>
+  }
+
+  impl<B: AsRef<[u8]>> Plaintext<B> {
+    fn as_slice(&self) -> &[u8] {
+      self.buf.as_ref()
+    }
+  }
+
+  /// A guard that keeps the pages holding a value locked into RAM until it is dropped, returned by [`lock_memory`].
+  #[cfg_attr(docsrs, doc(cfg(feature = "mlock")))]
+  #[cfg(feature = "mlock")]
+  pub struct MemoryLock(#[allow(dead_code)] region::LockGuard);
+
+  #[cfg(feature = "mlock")]
+  impl fmt::Debug for MemoryLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      f.debug_struct("MemoryLock").finish_non_exhaustive()
+    }
+  }
+
+  /// Locks the pages holding the given value into RAM (with `mlock` on Unix, or `VirtualLock` on Windows),
+  /// preventing them from being written to swap until the returned guard is dropped.
+  ///
+  /// Only the memory occupied by the value itself is locked, not any heap allocations that it owns,
+  /// so heap-allocated secrets should be locked directly (for example, `lock_memory(secret.as_bytes())`).
+  /// Locks apply to whole pages and are not reference counted by the operating system, so dropping a guard
+  /// also unlocks any other values sharing its pages. This makes locking a best-effort measure.
+  ///
+  /// Returns `None` if the memory could not be locked, for example because the process has hit its locked memory limit,
+  /// or if the value is zero-sized.
+  #[cfg_attr(docsrs, doc(cfg(feature = "mlock")))]
+  #[cfg(feature = "mlock")]
+  pub fn lock_memory<T: ?Sized>(value: &T) -> Option<MemoryLock> {
+    match std::mem::size_of_val(value) {
+      0 => None,
+      size => region::lock(value as *const T as *const u8, size).ok().map(MemoryLock)
+    }
+  }
+
+  fn derive_key<FE>(passphrase: &[u8], salt: &[u8; SALT_LEN]) -> Result<[u8; 32], EncryptedError<FE>> {
+    let params = argon2::Params::new(ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST, Some(32))
+      .map_err(EncryptedError::KeyDerivation)?;
+    let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut key = [0; 32];
+    argon2.hash_password_into(passphrase, salt, &mut key).map_err(EncryptedError::KeyDerivation)?;
+    Ok(key)
+  }
+
+  impl<E: Clone, F: Clone> Clone for Encrypted<E, F> {
+    fn clone(&self) -> Self {
+      let key = match &self.key {
+        KeySource::Key(key) => KeySource::Key(*key),
+        KeySource::Passphrase { passphrase, derived } => KeySource::Passphrase {
+          passphrase: passphrase.clone(),
+          derived: Mutex::new(*derived.lock().unwrap_or_else(|err| err.into_inner()))
+        }
+      };
+
+      Encrypted {
+        format: self.format.clone(),
+        encryption: self.encryption.clone(),
+        key,
+        #[cfg(feature = "mlock")]
+        lock_memory: self.lock_memory
+      }
+    }
+  }
+
+  impl<E: fmt::Debug, F: fmt::Debug> fmt::Debug for Encrypted<E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      let mut f = f.debug_struct("Encrypted");
+      f.field("format", &self.format);
+      f.field("encryption", &self.encryption);
+      f.field("key", &"<redacted>");
+      #[cfg(feature = "mlock")]
+      f.field("lock_memory", &self.lock_memory);
+      f.finish()
+    }
+  }
+
+  /// Since contents must be authenticated as a whole, all operations within this implementation are buffered.
+  impl<T, E, F> FileFormat<T> for Encrypted<E, F>
+  where E: EncryptionFormat, F: FileFormat<T> {
+    type FormatError = EncryptedError<F::FormatError>;
+
+    fn from_reader<R: Read>(&self, mut reader: R) -> Result<T, Self::FormatError> {
+      let mut buf = Vec::new();
+      reader.read_to_end(&mut buf)?;
+      self.from_buffer(&buf)
+    }
+
+    #[inline]
+    fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+      // no need to pass `reader` in with a `BufReader` as that would cause things to be buffered twice
+      self.from_reader(reader)
+    }
+
+    fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
+      let (key, buf) = self.read_key(buf)?;
+      if buf.len() < E::NONCE_LEN {
+        return Err(EncryptedError::Decrypt);
+      };
+
+      let (nonce, ciphertext) = buf.split_at(E::NONCE_LEN);
+      let plaintext = self.plaintext(crate::sensitive(self.encryption.decrypt(&key, nonce, ciphertext)
+        .map_err(|_| EncryptedError::Decrypt)?));
+      self.format.from_buffer(plaintext.as_slice()).map_err(EncryptedError::Format)
+    }
+
+    fn to_writer<W: Write>(&self, mut writer: W, value: &T) -> Result<(), Self::FormatError> {
+      let buf = self.to_buffer(value)?;
+      writer.write_all(&buf).map_err(From::from)
+    }
+
+    #[inline]
+    fn to_writer_buffered<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+      // no need to pass `writer` in with a `BufWriter` as that would cause things to be buffered twice
+      self.to_writer(writer, value)
+    }
+
+    fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+      let plaintext = self.plaintext(crate::sensitive(self.format.to_buffer(value).map_err(EncryptedError::Format)?));
+      let mut buf = Vec::new();
+      let key = self.write_key(&mut buf)?;
+      let nonce_start = buf.len();
+      buf.resize(nonce_start + E::NONCE_LEN, 0);
+      OsRng.fill_bytes(&mut buf[nonce_start..]);
+
+      let ciphertext = self.encryption.encrypt(&key, &buf[nonce_start..], plaintext.as_slice())
+        .map_err(|_| EncryptedError::Encrypt)?;
+      buf.extend_from_slice(&ciphertext);
+      Ok(buf)
+    }
+  }
+
+  /// Defines an authenticated encryption algorithm with 256-bit keys.
+  ///
+  /// In order to use an [`EncryptionFormat`], you may consider using the [`Encrypted`] struct,
+  /// which takes care of generating nonces and storing them alongside the contents.
+  pub trait EncryptionFormat {
+    /// The length of the nonces used by this algorithm, in bytes.
+    const NONCE_LEN: usize;
+
+    /// Encrypts and authenticates the given plaintext, returning the ciphertext.
+    /// The nonce is always [`NONCE_LEN`][EncryptionFormat::NONCE_LEN] bytes long, and must never be reused with the same key.
+    fn encrypt(&self, key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, aead::Error>;
+    /// Decrypts and authenticates the given ciphertext, returning the plaintext.
+    /// The nonce is always [`NONCE_LEN`][EncryptionFormat::NONCE_LEN] bytes long.
+    fn decrypt(&self, key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, aead::Error>;
+  }
+
+  /// An [`EncryptionFormat`] corresponding to AES-256 in Galois/Counter Mode.
+  ///
+  /// This is fastest on processors with AES instructions. Since nonces are only 96 bits long and randomly generated,
+  /// a single key should not be used for more than about 2<sup>32</sup> writes.
+  /// Implemented using the [`aes_gcm`] crate.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct Aes256Gcm;
+
+  impl EncryptionFormat for Aes256Gcm {
+    const NONCE_LEN: usize = 12;
+
+    fn encrypt(&self, key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      aes_gcm::Aes256Gcm::new(key.into()).encrypt(nonce.into(), plaintext)
+    }
+
+    fn decrypt(&self, key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      aes_gcm::Aes256Gcm::new(key.into()).decrypt(nonce.into(), ciphertext)
+    }
+  }
+
+  /// An [`EncryptionFormat`] corresponding to ChaCha20-Poly1305.
+  ///
+  /// This is fast on processors without AES instructions. Since nonces are only 96 bits long and randomly generated,
+  /// a single key should not be used for more than about 2<sup>32</sup> writes, see [`XChaCha20Poly1305`].
+  /// Implemented using the [`chacha20poly1305`] crate.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct ChaCha20Poly1305;
+
+  impl EncryptionFormat for ChaCha20Poly1305 {
+    const NONCE_LEN: usize = 12;
+
+    fn encrypt(&self, key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      chacha20poly1305::ChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), plaintext)
+    }
+
+    fn decrypt(&self, key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      chacha20poly1305::ChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), ciphertext)
+    }
+  }
+
+  /// An [`EncryptionFormat`] corresponding to XChaCha20-Poly1305, a variant of [`ChaCha20Poly1305`]
+  /// with 192-bit nonces, which are long enough to be randomly generated for any number of writes.
+  /// Implemented using the [`chacha20poly1305`] crate.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+  pub struct XChaCha20Poly1305;
+
+  impl EncryptionFormat for XChaCha20Poly1305 {
+    const NONCE_LEN: usize = 24;
+
+    fn encrypt(&self, key: &[u8; 32], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      chacha20poly1305::XChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), plaintext)
+    }
+
+    fn decrypt(&self, key: &[u8; 32], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, aead::Error> {
+      chacha20poly1305::XChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), ciphertext)
+    }
+  }
 }
 
 /// Defines a [`CompressionFormat`] for the bzip compression algorithm.
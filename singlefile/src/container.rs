@@ -1,13 +1,21 @@
 //! Container constructs providing single-ownership managed access to a file.
 
-use crate::error::Error;
-use crate::manager::lock::FileLock;
+use crate::error::{Conflict, Error, LockChangeError, UserError};
+use crate::manager::lock::{FileLock, Upgradable, Downgradable};
 use crate::manager::mode::FileMode;
 use crate::manager::*;
+use crate::utils::RecoveryReport;
 
-use std::io;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Type alias to a container that is read-only.
 pub type ContainerReadonly<T, Format> = Container<T, ManagerReadonly<Format>>;
@@ -16,6 +24,9 @@ pub type ContainerWritable<T, Format> = Container<T, ManagerWritable<Format>>;
 /// Type alias to a container that is readable and writable (with atomic writes).
 /// See [`Atomic`] for more information.
 pub type ContainerAtomic<T, Format> = Container<T, ManagerAtomic<Format>>;
+/// Type alias to a container that is readable and writable (with atomic writes through a temporary file).
+/// See [`AtomicRename`] for more information.
+pub type ContainerAtomicRename<T, Format> = Container<T, ManagerAtomicRename<Format>>;
 /// Type alias to a container that is read-only, and has a shared file lock.
 pub type ContainerReadonlyLocked<T, Format> = Container<T, ManagerReadonlyLocked<Format>>;
 /// Type alias to a container that is readable and writable, and has an exclusive file lock.
@@ -23,19 +34,37 @@ pub type ContainerWritableLocked<T, Format> = Container<T, ManagerWritableLocked
 /// Type alias to a container that is readable and writable (with atomic writes), and has an exclusive file lock.
 /// See [`Atomic`] for more information.
 pub type ContainerAtomicLocked<T, Format> = Container<T, ManagerAtomicLocked<Format>>;
+/// Type alias to a container that is readable and writable (splitting contents larger than `THRESHOLD` bytes
+/// across multiple files), and has an exclusive file lock. See [`Chunked`] for more information.
+pub type ContainerChunkedLocked<T, Format, const THRESHOLD: u64> = Container<T, ManagerChunkedLocked<Format, THRESHOLD>>;
+/// Type alias to a container that is readable and writable (keeping every version as a content-addressed object),
+/// and has an exclusive file lock. See [`ContentAddressed`] for more information.
+#[cfg_attr(docsrs, doc(cfg(feature = "cas")))]
+#[cfg(feature = "cas")]
+pub type ContainerContentAddressedLocked<T, Format> = Container<T, ManagerContentAddressedLocked<Format>>;
+
+/// Type alias to a container that is not backed by any file.
+/// Committing and refreshing are no-ops, which makes this useful as a drop-in for tests.
+pub type ContainerMemoryOnly<T> = Container<T, ()>;
+
+/// Type alias to a container that is read-only, and backed by static bytes instead of a file.
+/// See [`StaticManager`] for more information.
+pub type ContainerStatic<T, Format> = Container<T, StaticManager<Format>>;
 
 /// A basic owned container allowing managed access to some underlying file.
 #[derive(Debug)]
 pub struct Container<T, Manager> {
   pub(crate) value: T,
-  pub(crate) manager: Manager
+  pub(crate) manager: Manager,
+  pub(crate) stats: Stats,
+  pub(crate) hooks: CommitHooks<T>
 }
 
 impl<T, Manager> Container<T, Manager> {
   /// Create a new [`Container`] from the value and manager directly.
   #[inline(always)]
   pub const fn new(value: T, manager: Manager) -> Self {
-    Container { value, manager }
+    Container { value, manager, stats: Stats::new(), hooks: CommitHooks::new() }
   }
 
   /// Extract the contained state.
@@ -66,15 +95,70 @@ impl<T, Manager> Container<T, Manager> {
     &self.value
   }
 
-  /// Gets a mutable reference to the contained value.
+  /// Gets a mutable reference to the contained value, marking the state as dirty.
   ///
   /// You may also operate on the container directly with [`DerefMut`] instead.
   #[inline(always)]
   pub fn get_mut(&mut self) -> &mut T {
+    self.stats.dirty.store(true, Ordering::Release);
     &mut self.value
   }
+
+  /// Returns `true` if the state may have been modified since it was last committed or refreshed.
+  ///
+  /// Any mutable access to the state through this container counts as a modification, whether or not it changed anything.
+  /// Modifications made through interior mutability are not observed, use [`Container::mark_dirty`] for those.
+  #[inline]
+  pub fn is_dirty(&self) -> bool {
+    self.stats.dirty.load(Ordering::Acquire)
+  }
+
+  /// Marks the state as dirty, as if it had been mutably accessed.
+  #[inline]
+  pub fn mark_dirty(&self) {
+    self.stats.dirty.store(true, Ordering::Release);
+  }
+
+  /// Returns the number of successful commits (including overwrites) made through this container.
+  #[inline]
+  pub fn commit_count(&self) -> u64 {
+    self.stats.commit_count.load(Ordering::Acquire)
+  }
+
+  /// Returns the time of the last successful commit (or overwrite) made through this container, if any.
+  #[inline]
+  pub fn last_commit_at(&self) -> Option<SystemTime> {
+    Stats::load_time(&self.stats.last_commit_at)
+  }
+
+  /// Returns the time of the last successful refresh made through this container, if any.
+  #[inline]
+  pub fn last_refresh_at(&self) -> Option<SystemTime> {
+    Stats::load_time(&self.stats.last_refresh_at)
+  }
+
+  /// Registers a callback to be invoked with the state right before every commit (including overwrites)
+  /// made through this container, such as for logging or metrics. Callbacks are invoked in the order they were registered.
+  ///
+  /// Callbacks are invoked while the state is borrowed, so they must not attempt to access the container.
+  pub fn on_before_commit<F>(&mut self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    self.hooks.before.push(Box::new(callback));
+  }
+
+  /// Registers a callback to be invoked with the state after every successful commit (including overwrites)
+  /// made through this container, such as for cache invalidation. Callbacks are invoked in the order they were registered.
+  ///
+  /// Callbacks are invoked while the state is borrowed, so they must not attempt to access the container.
+  pub fn on_after_commit<F>(&mut self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    self.hooks.after.push(Box::new(callback));
+  }
 }
 
+type RecoverResult<C, FE> = Result<(C, Option<FE>), Error<FE>>;
+type ContainerWithLock<T, Format, Lock, Mode> = Container<T, FileManager<Format, Lock, Mode>>;
+
 impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
 where Format: FileFormat<T>, Lock: FileLock, Mode: FileMode {
   /// Opens a new [`Container`], returning an error if the file at the given path does not exist.
@@ -82,66 +166,745 @@ where Format: FileFormat<T>, Lock: FileLock, Mode: FileMode {
   where Mode: Reading {
     let manager = FileManager::open(path, format)?;
     let value = manager.read()?;
-    Ok(Container { value, manager })
+    Ok(Container::with_stamp(value, manager))
+  }
+
+  /// Opens a new [`Container`] from the first of the given paths at which a file exists, trying them in order.
+  /// The path that was used can be retrieved with [`FileManager::path`].
+  ///
+  /// Paths are used as given, so `~` is not expanded. Only paths that do not exist are skipped,
+  /// a file that exists but cannot be opened or parsed is returned as an error.
+  /// Returns an error of kind [`io::ErrorKind::NotFound`] if none of the paths exist.
+  ///
+  /// ```no_run
+  /// # use singlefile_formats::json_serde::{Json, JsonError};
+  /// # #[derive(serde::Serialize, serde::Deserialize)] struct Config {}
+  /// use singlefile::container::ContainerReadonly;
+  ///
+  /// let config = ContainerReadonly::<Config, Json>::open_first(["./config.json", "/etc/app/config.json"], Json)?;
+  /// println!("using {}", config.manager().path().display());
+  /// # Ok::<(), singlefile::Error<JsonError>>(())
+  /// ```
+  pub fn open_first<I, P>(paths: I, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where I: IntoIterator<Item = P>, P: AsRef<Path>, Mode: Reading {
+    for path in paths {
+      match fs::metadata(path.as_ref()) {
+        Ok(_) => return Self::open(path, format),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+        Err(err) => return Err(err.into())
+      };
+    };
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "none of the candidate paths exist").into())
+  }
+
+  /// Creates a new [`Container`] from a file handle that has already been opened, reading its value from the file.
+  /// See [`FileManager::from_file`] for more information.
+  ///
+  /// Changes to the file are detected through the handle, so [`Container::refresh_if_changed`] and
+  /// [`Container::commit_if_unchanged`] work even though the container has no path.
+  pub fn from_file(file: File, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
+    let manager = FileManager::from_file(file, format)?;
+    let value = manager.read()?;
+    Ok(Container::with_stamp(value, manager))
+  }
+
+  /// Opens a new [`Container`] like [`Container::open`], first recovering from any commit that was interrupted
+  /// by a crash, and reporting what was done. See [`recover`] for more information.
+  ///
+  /// [`recover`]: crate::utils::recover
+  pub fn open_with_recovery<P: AsRef<Path>>(path: P, format: Format) -> Result<(Self, RecoveryReport), Error<Format::FormatError>>
+  where Mode: Reading {
+    let report = crate::utils::recover::<T, _, _>(path.as_ref(), &format)?;
+    Ok((Self::open(path, format)?, report))
+  }
+
+  /// Opens a new [`Container`] like [`Container::open`], then rewrites the file if its contents differ from what
+  /// the configured format would write, returning whether the file was rewritten.
+  ///
+  /// This migrates files to new encoding settings (such as minified to pretty-printed) in a single step.
+  /// File modes that do not store the encoded contents directly in the file (such as [`Chunked`] above its threshold),
+  /// and formats whose output is not deterministic (such as encryption with random nonces), always rewrite the file.
+  /// To migrate files that the configured format cannot read at all, see [`Container::open_migrating`].
+  pub fn open_normalized<P: AsRef<Path>>(path: P, format: Format) -> Result<(Self, bool), Error<Format::FormatError>>
+  where Mode: Reading + Writing {
+    Self::open(path, format)?.normalize()
+  }
+
+  /// Opens a new [`Container`], reading the file with `new_format`, or with `old_format` if `new_format` fails to parse it,
+  /// in which case the file is rewritten in `new_format`, returning whether it was rewritten.
+  ///
+  /// This lets applications change the format of their files across releases without any action from their users.
+  /// The file is always replaced through a temporary file (see [`AtomicRename`]),
+  /// so an interrupted migration leaves the file in the old format.
+  /// If neither format can parse the file, the error from the new format is returned.
+  ///
+  /// With a file mode that replaces the file itself (such as [`AtomicRename`]), the file stays locked throughout the migration.
+  /// With other file modes, the lock is released while the file is replaced, and the new file is locked once it is in place.
+  ///
+  /// [`AtomicRename`]: crate::manager::mode::AtomicRename
+  pub fn open_migrating<P: AsRef<Path>, Old>(path: P, old_format: Old, new_format: Format) -> Result<(Self, bool), Error<Format::FormatError>>
+  where Old: FileFormat<T>, Mode: Reading + Writing {
+    let manager = FileManager::open(path, new_format)?;
+    let err = match manager.read() {
+      Ok(value) => return Ok((Container::with_stamp(value, manager), false)),
+      Err(Error::Format(err)) => err,
+      Err(err) => return Err(err)
+    };
+
+    let value = match Mode::read(&old_format, manager.file(), manager.path()) {
+      Ok(value) => value,
+      Err(_) => return Err(Error::Format(err))
+    };
+
+    if Mode::REPLACES_FILE {
+      manager.write(&value)?;
+      return Ok((Container::with_stamp(value, manager), true));
+    };
+
+    // the manager is closed before the file is replaced, since closing any handle to the old file would release
+    // the locks held on it anyway, and the manager would keep referring to the old file afterwards
+    let path = manager.path().to_owned();
+    let format = manager.into_inner()?;
+    crate::manager::mode::write_rename(&format, &path, &value, true)?;
+    let manager = FileManager::open(path, format)?;
+    Ok((Container::with_stamp(value, manager), true))
+  }
+
+  fn normalize(self) -> Result<(Self, bool), Error<Format::FormatError>>
+  where Mode: Writing {
+    use std::io::{Seek, SeekFrom};
+
+    let buf = self.manager.format().to_buffer(&self.value).map_err(Error::Format)?;
+    let mut file = self.manager.file();
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut contents)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let rewrite = contents != buf;
+    if rewrite {
+      self.commit()?;
+    };
+
+    Ok((self, rewrite))
   }
 
   /// Opens a new [`Container`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
   pub fn create_overwrite<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
     let (value, manager) = FileManager::create_overwrite(path, format, value)?;
-    Ok(Container { value, manager })
+    Ok(Container::with_stamp(value, manager))
   }
 
   /// Opens a new [`Container`], writing the given value to the file if it does not exist.
-  pub fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
+  pub fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
     let (value, manager) = FileManager::create_or(path, format, value)?;
-    Ok(Container { value, manager })
+    Ok(Container::with_stamp(value, manager))
+  }
+
+  /// Opens a new [`Container`] like [`Container::create_or`], but if the file exists and cannot be parsed,
+  /// it is moved aside (to the same path with `.corrupt-<unix timestamp>` appended) and replaced with `fallback`.
+  ///
+  /// Returns the format error that the corrupt file failed with alongside the container, so that it can be logged.
+  /// Errors other than format errors, such as I/O errors, are returned without touching the file.
+  pub fn create_or_recover<P: AsRef<Path>>(path: P, format: Format, fallback: T) -> RecoverResult<Self, Format::FormatError>
+  where Mode: Reading {
+    Self::create_or_else_lenient(path, format, || fallback, true)
+  }
+
+  /// Opens a new [`Container`], writing the default value of `T` to the file if it does not exist, or if it cannot be parsed.
+  ///
+  /// If `keep_corrupt` is `true`, a file that cannot be parsed is first moved aside as with [`Container::create_or_recover`],
+  /// otherwise it is overwritten. See [`Container::create_or_else_lenient`] for more information.
+  pub fn create_or_default_lenient<P: AsRef<Path>>(path: P, format: Format, keep_corrupt: bool) -> RecoverResult<Self, Format::FormatError>
+  where T: Default, Mode: Reading {
+    Self::create_or_else_lenient(path, format, T::default, keep_corrupt)
+  }
+
+  /// Opens a new [`Container`], writing the result of the given closure to the file if it does not exist,
+  /// or if it cannot be parsed, treating a format error the same as a missing file.
+  ///
+  /// If `keep_corrupt` is `true`, a file that cannot be parsed is first moved aside (to the same path with
+  /// `.corrupt-<unix timestamp>` appended), otherwise it is overwritten.
+  /// Returns the format error that the corrupt file failed with alongside the container, so that it can be logged.
+  /// Errors other than format errors, such as I/O errors, are returned without touching the file.
+  pub fn create_or_else_lenient<P: AsRef<Path>, C>(path: P, format: Format, closure: C, keep_corrupt: bool) -> RecoverResult<Self, Format::FormatError>
+  where C: FnOnce() -> T, Mode: Reading {
+    let path = path.as_ref();
+    let result = match fs::File::open(path) {
+      Ok(file) => Mode::read(&format, &file, path),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((Self::create_or_else(path, format, closure)?, None)),
+      Err(err) => return Err(err.into())
+    };
+
+    match result {
+      Ok(value) => Ok((Container::with_stamp(value, FileManager::open(path, format)?), None)),
+      Err(Error::Format(err)) => {
+        if keep_corrupt {
+          let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+          fs::rename(path, crate::utils::aside_path(path, &format!(".corrupt-{timestamp}")))?;
+        };
+
+        Ok((Self::create_overwrite(path, format, closure())?, Some(err)))
+      },
+      Err(err) => Err(err)
+    }
   }
 
   /// Opens a new [`Container`], writing the result of the given closure to the file if it does not exist.
   pub fn create_or_else<P: AsRef<Path>, C>(path: P, format: Format, closure: C) -> Result<Self, Error<Format::FormatError>>
-  where C: FnOnce() -> T {
+  where C: FnOnce() -> T, Mode: Reading {
     let (value, manager) = FileManager::create_or_else(path, format, closure)?;
-    Ok(Container { value, manager })
+    Ok(Container::with_stamp(value, manager))
   }
 
   /// Opens a new [`Container`], writing the default value of `T` to the file if it does not exist.
   pub fn create_or_default<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
-  where T: Default {
+  where T: Default, Mode: Reading {
     let (value, manager) = FileManager::create_or_default(path, format)?;
-    Ok(Container { value, manager })
+    Ok(Container::with_stamp(value, manager))
+  }
+
+  /// Opens a new [`Container`] like [`Container::create_or_default`], first creating the parent directories
+  /// of the file if they do not exist.
+  pub fn create_or_default_with_dirs<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    create_parent_dirs(path.as_ref())?;
+    Self::create_or_default(path, format)
+  }
+
+  /// Opens a new [`Container`] like [`Container::create_or_default`], for a file in the platform's configuration
+  /// directory for the given project, creating the directory if it does not exist. See [`Project::path`].
+  ///
+  /// [`Project::path`]: crate::paths::Project::path
+  #[cfg_attr(docsrs, doc(cfg(feature = "paths")))]
+  #[cfg(feature = "paths")]
+  pub fn create_or_default_in<P: AsRef<Path>>(project: &crate::paths::Project<'_>, file: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    let path = project.path(crate::paths::Directory::Config, file)?;
+    Self::create_or_default(path, format)
   }
 }
 
 impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
 where Format: FileFormat<T> {
+  /// Creates a new [`Container`], recording the current size and modification time of the managed file.
+  fn with_stamp(value: T, manager: FileManager<Format, Lock, Mode>) -> Self
+  where Mode: FileMode {
+    let container = Container::new(value, manager);
+    container.stats.record_stamp(&container.manager);
+    container
+  }
+
   /// Reads a value from the managed file, replacing the current state in memory.
   pub fn refresh(&mut self) -> Result<T, Error<Format::FormatError>>
   where Mode: Reading {
-    self.manager.read().map(|value| std::mem::replace(&mut self.value, value))
+    let value = self.manager.read()?;
+    self.stats.record_refresh();
+    self.stats.record_stamp(&self.manager);
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Reads a value from the managed file like [`Container::refresh`], but only if the size or modification time
+  /// of the file differ from when it was last read or written through this container.
+  ///
+  /// Returns the value of the previous state if the file was read, or `None` if it was unchanged.
+  /// This makes polling a file for changes cheap, since an unchanged file is neither read nor parsed.
+  /// Note that a change which keeps the size of the file the same can go unnoticed
+  /// if it happens within the resolution of the filesystem's modification times.
+  pub fn refresh_if_changed(&mut self) -> Result<Option<T>, Error<Format::FormatError>>
+  where Mode: Reading {
+    if self.stats.is_stamp_current(&self.manager)? {
+      return Ok(None);
+    };
+
+    self.refresh().map(Some)
   }
 
   /// Writes the current in-memory state to the managed file.
   pub fn commit(&self) -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
-    self.manager.write(&self.value)
+    self.hooks.before(&self.value);
+    self.manager.write(&self.value)?;
+    self.stats.record_commit();
+    self.stats.record_stamp(&self.manager);
+    self.hooks.after(&self.value);
+    Ok(())
+  }
+
+  /// Writes the current in-memory state to the managed file like [`Container::commit`], but only if the file has not been
+  /// changed by anyone else since it was last read or written through this container, failing with [`Error::Conflict`] otherwise.
+  /// After a conflict, the state should be refreshed (and any changes reapplied to it) before committing again.
+  ///
+  /// This lets multiple processes share a file without exclusive locks, as long as every one of them commits this way.
+  /// Changes are recognized by the size and modification time of the file like [`Container::refresh_if_changed`] does,
+  /// and a file whose modification time cannot be determined always conflicts.
+  /// Note that the file is checked right before it is written, so a write that races with the check can still be lost.
+  pub fn commit_if_unchanged(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    self.check_unchanged()?;
+    self.commit()
+  }
+
+  /// Fails with [`Error::Conflict`] if the managed file has changed since it was last read or written through this container.
+  pub(crate) fn check_unchanged(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: FileMode {
+    if self.stats.is_stamp_current(&self.manager)? {
+      Ok(())
+    } else {
+      Err(Conflict.into())
+    }
+  }
+
+  /// Writes the current in-memory state to the managed file like [`Container::commit`], but only if it is dirty.
+  ///
+  /// Returns `true` if the state was dirty, and has been committed. See [`Container::is_dirty`].
+  pub fn commit_if_dirty(&self) -> Result<bool, Error<Format::FormatError>>
+  where Mode: Writing {
+    if !self.is_dirty() {
+      return Ok(false);
+    };
+
+    self.commit().map(|()| true)
+  }
+
+  /// Writes the current in-memory state to the managed file, first copying its current contents to a new backup
+  /// according to the given [`BackupPolicy`]. The manager's own policy, if any, is not applied to this commit.
+  pub fn commit_with_backup(&self, backup_policy: &BackupPolicy) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    self.hooks.before(&self.value);
+    self.manager.write_with_backup(&self.value, backup_policy)?;
+    self.stats.record_commit();
+    self.stats.record_stamp(&self.manager);
+    self.hooks.after(&self.value);
+    Ok(())
+  }
+
+  /// Sets the [`BackupPolicy`] of the managed file, so that every commit first takes a backup of it.
+  /// See [`FileManager::with_backup_policy`].
+  pub fn with_backup_policy(self, backup_policy: BackupPolicy) -> Self
+  where Mode: FileMode {
+    Container { manager: self.manager.with_backup_policy(backup_policy), ..self }
+  }
+
+  /// Serializes the current in-memory state into a buffer without touching the managed file,
+  /// returning the number of bytes that the next commit would write.
+  ///
+  /// Any errors produced by the format (including wrapper formats, such as those that compress or encrypt)
+  /// are returned exactly as they would be by [`Container::commit`], making this useful as a pre-flight check.
+  pub fn commit_dry_run(&self) -> Result<usize, Error<Format::FormatError>> {
+    let buf = self.manager.format().to_buffer(&self.value).map_err(Error::Format)?;
+    Ok(buf.len())
   }
 
   /// Writes the given state to the managed file, replacing the in-memory state.
   pub fn overwrite(&mut self, value: T) -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
     self.value = value;
-    self.manager.write(&self.value)
+    self.commit()
+  }
+
+  /// Serializes the current in-memory state into the given writer, using the container's format.
+  ///
+  /// The managed file is not touched, this is intended for backups or sending the state elsewhere.
+  pub fn export_to<W: Write>(&self, writer: W) -> Result<(), Error<Format::FormatError>> {
+    self.manager.format().to_writer(writer, &self.value).map_err(Error::Format)
+  }
+
+  /// Deserializes a value from the given reader using the container's format, replacing the in-memory state
+  /// and returning the previous state.
+  ///
+  /// The managed file is not touched, call [`Container::commit`] to write the imported state to it.
+  pub fn import_from<R: Read>(&mut self, reader: R) -> Result<T, Error<Format::FormatError>> {
+    let value = self.manager.format().from_reader(reader).map_err(Error::Format)?;
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Writes the current in-memory state to a file at the given path, creating it if it does not exist
+  /// and overwriting its contents if it does, returning a new, independent [`Container`] that manages it.
+  ///
+  /// The new container acquires its own file lock, and changes to either container do not affect the other.
+  pub fn fork<P: AsRef<Path>>(&self, path: P) -> Result<Self, Error<Format::FormatError>>
+  where T: Clone, Format: Clone, Lock: FileLock, Mode: FileMode {
+    Container::create_overwrite(path, self.manager.format().clone(), self.value.clone())
+  }
+
+  /// Writes the current in-memory state to a file at the given path in a different format, creating it if it does not exist
+  /// and overwriting its contents if it does, returning a new, independent [`Container`] that manages it.
+  ///
+  /// This migrates a file to a new format, such as from JSON to a binary format, after which this container
+  /// can be dropped and the original file removed. Like [`Container::fork`], the new container acquires its own file lock,
+  /// so the path must not be the one managed by this container. Commit callbacks are not carried over.
+  /// To convert a file without opening it as a container, see [`transcode`].
+  ///
+  /// [`transcode`]: crate::utils::transcode
+  pub fn convert_format<P, NewFormat>(&self, new_path: P, new_format: NewFormat) -> Result<ContainerWithLock<T, NewFormat, Lock, Mode>, Error<NewFormat::FormatError>>
+  where P: AsRef<Path>, T: Clone, NewFormat: FileFormat<T>, Lock: FileLock, Mode: FileMode {
+    Container::create_overwrite(new_path, new_format, self.value.clone())
+  }
+
+  /// Takes a [`Snapshot`] of the current in-memory state, along with a hash of the current contents of the managed file,
+  /// which can later be restored with [`Container::rollback`].
+  ///
+  /// Since the state is cloned, snapshots are best suited to small states, such as application settings.
+  pub fn snapshot(&self) -> io::Result<Snapshot<T>>
+  where T: Clone, Mode: FileMode {
+    Ok(Snapshot {
+      value: self.value.clone(),
+      file_hash: Snapshot::<T>::hash_file(&self.manager)?,
+      dirty: self.is_dirty(),
+      taken_at: SystemTime::now()
+    })
+  }
+
+  /// Restores the in-memory state from the given [`Snapshot`], returning the state it replaces, and writes it
+  /// to the managed file if the contents of the file have changed since the snapshot was taken.
+  ///
+  /// If the state was dirty when the snapshot was taken, the file will contain the snapshotted state afterwards,
+  /// rather than what the file contained at the time, since only a hash of its contents is kept.
+  /// If the file is not written, the state is dirty again exactly when it was dirty at the time of the snapshot.
+  pub fn rollback(&mut self, snapshot: Snapshot<T>) -> Result<T, Error<Format::FormatError>>
+  where Mode: Writing {
+    let previous = std::mem::replace(&mut self.value, snapshot.value);
+    if Snapshot::<T>::hash_file(&self.manager)? != snapshot.file_hash {
+      self.commit()?;
+    } else if snapshot.dirty {
+      self.mark_dirty();
+    } else {
+      self.stats.dirty.store(false, Ordering::Release);
+    };
+
+    Ok(previous)
+  }
+
+  /// Runs the provided function or closure on a copy of the current in-memory state,
+  /// replacing the state with that copy and committing it if no error was returned.
+  ///
+  /// If the operation panics or returns an error, or if the commit fails, the state is left unchanged,
+  /// so neither the file nor the state in memory can ever reflect a partially applied operation.
+  pub fn transaction<F, R, U>(&mut self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let mut staged = self.value.clone();
+    let ret = operation(&mut staged).map_err(UserError::User)?;
+    let previous = std::mem::replace(&mut self.value, staged);
+    if let Err(err) = self.commit() {
+      self.value = previous;
+      return Err(err.into());
+    };
+
+    Ok(ret)
   }
 }
 
 impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
-where Lock: FileLock {
+where Lock: FileLock, Mode: FileMode {
   /// Unlocks and closes this [`Container`], returning the contained state.
   pub fn close(self) -> io::Result<T> {
     self.manager.close().map(|()| self.value)
   }
 }
 
+/// A copy of the state of a [`Container`] and a hash of the contents of its managed file at some point in time,
+/// taken by [`Container::snapshot`] and restored by [`Container::rollback`], for example to implement undo.
+///
+/// The hash is computed with [`DefaultHasher`], so it is only meaningful within the process that took the snapshot.
+#[derive(Debug, Clone)]
+pub struct Snapshot<T> {
+  value: T,
+  file_hash: u64,
+  dirty: bool,
+  taken_at: SystemTime
+}
+
+impl<T> Snapshot<T> {
+  /// Gets a reference to the snapshotted state.
+  #[inline]
+  pub const fn value(&self) -> &T {
+    &self.value
+  }
+
+  /// Extracts the snapshotted state.
+  #[inline]
+  pub fn into_value(self) -> T {
+    self.value
+  }
+
+  /// Returns the hash of the contents of the managed file when this snapshot was taken.
+  #[inline]
+  pub const fn file_hash(&self) -> u64 {
+    self.file_hash
+  }
+
+  /// Returns the time at which this snapshot was taken.
+  #[inline]
+  pub const fn taken_at(&self) -> SystemTime {
+    self.taken_at
+  }
+
+  fn hash_file<Format, Lock, Mode>(manager: &FileManager<Format, Lock, Mode>) -> io::Result<u64>
+  where Mode: FileMode {
+    use std::io::{Seek, SeekFrom};
+
+    let contents = match Mode::REPLACES_FILE {
+      // modes that replace the file read it by its path, so the handle may refer to an older file
+      true => fs::read(manager.path())?,
+      // read through the handle the file is locked with, since closing another handle to it would release the lock
+      false => {
+        let mut file = manager.file();
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        let result = file.read_to_end(&mut contents);
+        file.seek(SeekFrom::Start(0))?;
+        result?;
+        contents
+      }
+    };
+
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Ok(hasher.finish())
+  }
+}
+
+/// Describes how the state of a shared container has changed,
+/// sent to the subscribers of `ContainerShared::subscribe` and `ContainerSharedAsync::subscribe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeEvent {
+  /// The state was written to the managed file.
+  Commit,
+  /// The state was replaced, and the new state written to the managed file.
+  Overwrite,
+  /// The state was replaced by the contents of the managed file.
+  Refresh
+}
+
+/// Configures the lock mode, file mode, open behavior, permissions, backup policy and sync policy of a [`Container`] fluently,
+/// as an alternative to naming the [`Container`] type and picking one of its constructors.
+/// See [`FileManagerBuilder`] for the defaults.
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use singlefile::container::ContainerBuilder;
+/// use singlefile::manager::{AtomicRename, BackupPolicy, ExclusiveLock, OpenBehavior};
+///
+/// let mut container = ContainerBuilder::new("data.json", Json::<true>)
+///   .with_lock::<ExclusiveLock>()
+///   .with_mode::<AtomicRename>()
+///   .with_open_behavior(OpenBehavior::Open)
+///   .with_backup_policy(BackupPolicy::new(3))
+///   .build_or_default::<Vec<String>>()?;
+/// container.push("hello".to_owned());
+/// container.commit()?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContainerBuilder<Format, Lock = NoLock, Mode = Writable> {
+  inner: FileManagerBuilder<Format, Lock, Mode>
+}
+
+impl<Format> ContainerBuilder<Format> {
+  /// Creates a new [`ContainerBuilder`] for the file at the given path, using the given format.
+  #[inline]
+  pub fn new<P: AsRef<Path>>(path: P, format: Format) -> Self {
+    ContainerBuilder { inner: FileManagerBuilder::new(path, format) }
+  }
+}
+
+impl<Format, Lock, Mode> ContainerBuilder<Format, Lock, Mode> {
+  /// Sets the lock mode that the file is locked with, see [`manager::lock`][crate::manager::lock].
+  #[inline]
+  pub fn with_lock<NewLock>(self) -> ContainerBuilder<Format, NewLock, Mode> {
+    ContainerBuilder { inner: self.inner.with_lock() }
+  }
+
+  /// Sets the file mode that the file is read and written with, see [`manager::mode`][crate::manager::mode].
+  #[inline]
+  pub fn with_mode<NewMode>(self) -> ContainerBuilder<Format, Lock, NewMode> {
+    ContainerBuilder { inner: self.inner.with_mode() }
+  }
+
+  /// Sets what happens when the file is opened, depending on whether it exists.
+  #[inline]
+  pub fn with_open_behavior(self, open_behavior: OpenBehavior) -> Self {
+    ContainerBuilder { inner: self.inner.with_open_behavior(open_behavior) }
+  }
+
+  /// Sets the permissions of the file, see [`FileManagerBuilder::with_permissions`].
+  #[inline]
+  pub fn with_permissions(self, permissions: fs::Permissions) -> Self {
+    ContainerBuilder { inner: self.inner.with_permissions(permissions) }
+  }
+
+  /// Sets the [`CreateOptions`] that the file is created with, see [`FileManagerBuilder::with_create_options`].
+  #[inline]
+  pub fn with_create_options(self, create_options: CreateOptions) -> Self {
+    ContainerBuilder { inner: self.inner.with_create_options(create_options) }
+  }
+
+  /// Sets whether the parent directories of the file are created, see [`FileManagerBuilder::with_create_dirs`].
+  #[inline]
+  pub fn with_create_dirs(self, create_dirs: bool) -> Self {
+    ContainerBuilder { inner: self.inner.with_create_dirs(create_dirs) }
+  }
+
+  /// Sets the [`BackupPolicy`] of the managed file, so that every commit first takes a backup of it.
+  #[inline]
+  pub fn with_backup_policy(self, backup_policy: BackupPolicy) -> Self {
+    ContainerBuilder { inner: self.inner.with_backup_policy(backup_policy) }
+  }
+
+  /// Sets the [`SyncPolicy`] of the managed file, which determines when commits are synced to disk.
+  #[inline]
+  pub fn with_sync_policy(self, sync_policy: SyncPolicy) -> Self {
+    ContainerBuilder { inner: self.inner.with_sync_policy(sync_policy) }
+  }
+
+  /// Sets the maximum size of the managed file, so that reading a larger file fails with [`Error::TooLarge`].
+  /// See [`FileManager::with_max_size`] for more information.
+  #[inline]
+  pub fn with_max_size(self, max_size: u64) -> Self {
+    ContainerBuilder { inner: self.inner.with_max_size(max_size) }
+  }
+
+  /// Sets the [`Instrumentation`] of the managed file, which measures every read, write and lock wait.
+  /// See the [`metrics`] module for more information.
+  ///
+  /// [`Instrumentation`]: crate::metrics::Instrumentation
+  /// [`metrics`]: crate::metrics
+  #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+  #[cfg(feature = "metrics")]
+  #[inline]
+  pub fn with_instrumentation(self, instrumentation: std::sync::Arc<dyn crate::metrics::Instrumentation>) -> Self {
+    ContainerBuilder { inner: self.inner.with_instrumentation(instrumentation) }
+  }
+
+  /// Gets the [`FileManagerBuilder`] that this builder configures.
+  #[inline]
+  pub fn into_manager_builder(self) -> FileManagerBuilder<Format, Lock, Mode> {
+    self.inner
+  }
+}
+
+type BuildResult<T, Format, Lock, Mode> = Result<Container<T, FileManager<Format, Lock, Mode>>, Error<<Format as FileFormat<T>>::FormatError>>;
+
+impl<Format, Lock, Mode> ContainerBuilder<Format, Lock, Mode>
+where Lock: FileLock, Mode: Reading {
+  /// Opens the [`Container`], writing the given value to the file if the open behavior calls for it.
+  #[inline]
+  pub fn build_or<T>(self, value: T) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T> {
+    self.build_or_else(|| value)
+  }
+
+  /// Opens the [`Container`], writing the default value of `T` to the file if the open behavior calls for it.
+  #[inline]
+  pub fn build_or_default<T>(self) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T>, T: Default {
+    self.build_or_else(T::default)
+  }
+
+  /// Opens the [`Container`], writing the result of the given closure to the file if the open behavior calls for it.
+  pub fn build_or_else<T, C>(self, closure: C) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T>, C: FnOnce() -> T {
+    let (value, manager) = self.inner.build_or_else(closure)?;
+    Ok(Container::with_stamp(value, manager))
+  }
+}
+
+impl<T> Container<T, ()> {
+  /// Creates a new memory-only [`Container`] by deserializing a value from the given reader, such as standard input.
+  ///
+  /// Since there is no managed file, the returned container is effectively read-only,
+  /// its state can be written elsewhere with [`Container::attach_manager`].
+  /// See [`read_path_or_stdin`] for a helper that follows the `-` convention of command line tools.
+  ///
+  /// [`read_path_or_stdin`]: crate::utils::read_path_or_stdin
+  pub fn from_reader_only<R, Format>(reader: R, format: &Format) -> Result<Self, Error<Format::FormatError>>
+  where R: Read, Format: FileFormat<T> {
+    let value = format.from_reader_buffered(reader).map_err(Error::Format)?;
+    Ok(Container::from(value))
+  }
+
+  /// Attaches a manager to this memory-only [`Container`], returning a new [`Container`] that uses it.
+  ///
+  /// The in-memory state and commit callbacks are kept as-is, the state will not be written until the next commit.
+  #[inline]
+  pub fn attach_manager<Manager>(self, manager: Manager) -> Container<T, Manager> {
+    Container { hooks: self.hooks, ..Container::new(self.value, manager) }
+  }
+
+  /// Does nothing, since there is no managed file to read from.
+  ///
+  /// The in-memory state is left untouched and a clone of it is returned as the previous state.
+  pub fn refresh(&mut self) -> Result<T, Error<Infallible>>
+  where T: Clone {
+    self.stats.record_refresh();
+    Ok(self.value.clone())
+  }
+
+  /// Does nothing, since there is no managed file to write to.
+  #[inline]
+  pub fn commit(&self) -> Result<(), Error<Infallible>> {
+    self.hooks.before(&self.value);
+    self.stats.record_commit();
+    self.hooks.after(&self.value);
+    Ok(())
+  }
+
+  /// Replaces the in-memory state, there is no managed file to write to.
+  pub fn overwrite(&mut self, value: T) -> Result<(), Error<Infallible>> {
+    self.value = value;
+    self.commit()
+  }
+
+  /// Closes this [`Container`], returning the contained state.
+  #[inline]
+  pub fn close(self) -> io::Result<T> {
+    Ok(self.value)
+  }
+}
+
+impl<T, Format> Container<T, StaticManager<Format>>
+where Format: FileFormat<T> {
+  /// Creates a new [`Container`] by deserializing a value from the given static bytes, such as those embedded with `include_bytes!`.
+  pub fn from_static(bytes: &'static [u8], format: Format) -> Result<Self, Error<Format::FormatError>> {
+    let manager = StaticManager::new(bytes, format);
+    let value = manager.read()?;
+    Ok(Container::new(value, manager))
+  }
+
+  /// Deserializes the static bytes again, replacing the current state in memory.
+  ///
+  /// Since the bytes never change, this discards any modifications made to the state.
+  pub fn refresh(&mut self) -> Result<T, Error<Format::FormatError>> {
+    let value = self.manager.read()?;
+    self.stats.record_refresh();
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Closes this [`Container`], returning the contained state.
+  #[inline]
+  pub fn close(self) -> io::Result<T> {
+    Ok(self.value)
+  }
+}
+
+impl<T> From<T> for Container<T, ()> {
+  #[inline]
+  fn from(value: T) -> Self {
+    Container::new(value, ())
+  }
+}
+
+impl<T: Default> Default for Container<T, ()> {
+  #[inline]
+  fn default() -> Self {
+    Container::new(T::default(), ())
+  }
+}
+
 impl<T, Manager> Deref for Container<T, Manager> {
   type Target = T;
 
@@ -157,3 +920,202 @@ impl<T, Manager> DerefMut for Container<T, Manager> {
     self.get_mut()
   }
 }
+
+impl<T: PartialEq, Manager> PartialEq for Container<T, Manager> {
+  #[inline]
+  fn eq(&self, other: &Self) -> bool {
+    self.value == other.value
+  }
+}
+
+impl<T: Eq, Manager> Eq for Container<T, Manager> {}
+
+impl<T: Hash, Manager> Hash for Container<T, Manager> {
+  #[inline]
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.value.hash(state);
+  }
+}
+
+impl<T: fmt::Display, Manager> fmt::Display for Container<T, Manager> {
+  #[inline]
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    <T as fmt::Display>::fmt(&self.value, f)
+  }
+}
+
+impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
+where Lock: Upgradable {
+  /// Upgrades the shared lock held on the managed file to an exclusive lock, without reopening the file.
+  /// See [`FileManager::upgrade_lock`] for more information.
+  pub fn upgrade_lock(self) -> Result<ContainerWithLock<T, Format, Lock::Upgraded, Mode>, LockChangeError<Self>> {
+    let Container { value, manager, stats, hooks } = self;
+    match manager.upgrade_lock() {
+      Ok(manager) => Ok(Container { value, manager, stats, hooks }),
+      Err(err) => {
+        let (manager, err) = err.into_parts();
+        Err(LockChangeError::new(Container { value, manager, stats, hooks }, err))
+      }
+    }
+  }
+}
+
+impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
+where Lock: Downgradable {
+  /// Downgrades the exclusive lock held on the managed file to a shared lock, without reopening the file.
+  /// See [`FileManager::downgrade_lock`] for more information.
+  pub fn downgrade_lock(self) -> Result<ContainerWithLock<T, Format, Lock::Downgraded, Mode>, LockChangeError<Self>> {
+    let Container { value, manager, stats, hooks } = self;
+    match manager.downgrade_lock() {
+      Ok(manager) => Ok(Container { value, manager, stats, hooks }),
+      Err(err) => {
+        let (manager, err) = err.into_parts();
+        Err(LockChangeError::new(Container { value, manager, stats, hooks }, err))
+      }
+    }
+  }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "diff")))]
+#[cfg(feature = "diff")]
+impl<T, Format, Lock, Mode> Container<T, FileManager<Format, Lock, Mode>>
+where Format: FileFormat<T>, T: serde::Serialize {
+  /// Reads the value in the managed file, and computes a [`Diff`] from it to the current in-memory state,
+  /// describing what will be changed by the next commit (or, in reverse, what will be discarded by the next refresh).
+  ///
+  /// The in-memory state is not modified.
+  ///
+  /// [`Diff`]: crate::diff::Diff
+  pub fn diff(&self) -> Result<crate::diff::Diff, crate::diff::DiffError<Format::FormatError>>
+  where Mode: Reading {
+    let value = self.manager.read()?;
+    crate::diff::Diff::new(&value, &self.value).map_err(crate::error::UserError::User)
+  }
+}
+
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, Manager> serde::Serialize for Container<T, Manager> {
+  #[inline]
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    self.value.serialize(serializer)
+  }
+}
+
+type CommitHook<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+/// The callbacks registered with [`Container::on_before_commit`] and [`Container::on_after_commit`].
+pub(crate) struct CommitHooks<T> {
+  before: Vec<CommitHook<T>>,
+  after: Vec<CommitHook<T>>
+}
+
+impl<T> CommitHooks<T> {
+  const fn new() -> Self {
+    CommitHooks { before: Vec::new(), after: Vec::new() }
+  }
+
+  pub(crate) fn before(&self, value: &T) {
+    for hook in self.before.iter() {
+      hook(value);
+    };
+  }
+
+  pub(crate) fn after(&self, value: &T) {
+    for hook in self.after.iter() {
+      hook(value);
+    };
+  }
+}
+
+impl<T> fmt::Debug for CommitHooks<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CommitHooks")
+      .field("before", &self.before.len())
+      .field("after", &self.after.len())
+      .finish()
+  }
+}
+
+#[derive(Debug)]
+pub(crate) struct Stats {
+  commit_count: AtomicU64,
+  /// Nanoseconds since the unix epoch, zero if there has been no commit.
+  last_commit_at: AtomicU64,
+  /// Nanoseconds since the unix epoch, zero if there has been no refresh.
+  last_refresh_at: AtomicU64,
+  /// The size of the managed file when it was last read or written.
+  stamp_len: AtomicU64,
+  /// The modification time of the managed file when it was last read or written,
+  /// in nanoseconds since the unix epoch, zero if it is unknown.
+  stamp_modified: AtomicU64,
+  /// Whether the state has been mutably accessed since it was last committed or refreshed.
+  dirty: AtomicBool
+}
+
+impl Stats {
+  const fn new() -> Self {
+    Stats {
+      commit_count: AtomicU64::new(0),
+      last_commit_at: AtomicU64::new(0),
+      last_refresh_at: AtomicU64::new(0),
+      stamp_len: AtomicU64::new(0),
+      stamp_modified: AtomicU64::new(0),
+      dirty: AtomicBool::new(false)
+    }
+  }
+
+  /// Records a commit of the current state, which is no longer dirty afterwards.
+  pub(crate) fn record_commit(&self) {
+    self.record_write();
+    self.dirty.store(false, Ordering::Release);
+  }
+
+  /// Records a commit that did not necessarily write all of the current state, leaving it dirty.
+  pub(crate) fn record_write(&self) {
+    self.commit_count.fetch_add(1, Ordering::AcqRel);
+    Self::store_time(&self.last_commit_at);
+  }
+
+  pub(crate) fn record_refresh(&self) {
+    Self::store_time(&self.last_refresh_at);
+    self.dirty.store(false, Ordering::Release);
+  }
+
+  /// Records the size and modification time of the managed file, forgetting them if they cannot be determined.
+  pub(crate) fn record_stamp<Format, Lock, Mode>(&self, manager: &FileManager<Format, Lock, Mode>)
+  where Mode: FileMode {
+    let (len, modified) = Self::stamp(manager).unwrap_or((0, 0));
+    self.stamp_len.store(len, Ordering::Release);
+    self.stamp_modified.store(modified, Ordering::Release);
+  }
+
+  /// Returns `true` if the size and modification time of the managed file are known, and match those last recorded.
+  pub(crate) fn is_stamp_current<Format, Lock, Mode>(&self, manager: &FileManager<Format, Lock, Mode>) -> io::Result<bool>
+  where Mode: FileMode {
+    let (len, modified) = Self::stamp(manager)?;
+    let recorded_modified = self.stamp_modified.load(Ordering::Acquire);
+    Ok(recorded_modified != 0 && recorded_modified == modified && self.stamp_len.load(Ordering::Acquire) == len)
+  }
+
+  fn stamp<Format, Lock, Mode>(manager: &FileManager<Format, Lock, Mode>) -> io::Result<(u64, u64)>
+  where Mode: FileMode {
+    let metadata = manager.metadata()?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)
+      .map_or(0, |duration| duration.as_nanos() as u64);
+    Ok((metadata.len(), modified))
+  }
+
+  fn store_time(time: &AtomicU64) {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+      .map_or(1, |duration| duration.as_nanos() as u64);
+    time.store(nanos.max(1), Ordering::Release);
+  }
+
+  fn load_time(time: &AtomicU64) -> Option<SystemTime> {
+    match time.load(Ordering::Acquire) {
+      0 => None,
+      nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos))
+    }
+  }
+}
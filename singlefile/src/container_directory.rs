@@ -0,0 +1,510 @@
+//! Container constructs for managing a directory of files, one per entry of a collection.
+//!
+//! A [`ContainerDirectory`] stores each of its entries in its own file, named after the entry's key,
+//! so that collections too large to be kept in (or rewritten as) a single file can still be backed by disk.
+//! Entries are only read from disk when they are first accessed, and can be committed individually.
+//!
+//! Keys are converted to file names through [`Display`], and back through [`FromStr`], followed by the extension
+//! given when the container is opened. Files in the directory with other extensions (or names that
+//! fail to parse) are ignored. Entries are written atomically (see [`AtomicRename`]), but are not locked.
+//!
+//! Entries can instead be locked explicitly with [`ContainerDirectory::lock_many`] (or [`ContainerDirectory::lock_many_shared`]),
+//! which lock each entry independently, so that threads and processes working with different entries never contend.
+//! Because entry files are replaced on every commit, the locks are taken on separate lock files in a `.locks` subdirectory,
+//! which are never removed. Locking is advisory: nothing stops entries from being accessed without their locks.
+//!
+//! Bulk operations such as [`ContainerDirectory::commit_all`] and [`ContainerDirectory::refresh_all`] carry on past entries
+//! that fail, reporting every failure at once through a [`BulkError`]. The keys to operate on can be picked with a glob
+//! pattern through [`ContainerDirectory::select`].
+//!
+//! Collections too large to be loaded at once can be scanned with [`ContainerDirectory::scan`],
+//! which reads one file at a time as it is iterated, without loading anything into the container.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::container_directory::ContainerDirectory;
+//!
+//! let mut users = ContainerDirectory::<String, u64, Json>::open("users", "json", Json)?;
+//! // reads `users/alice.json` if it exists
+//! *users.get_or_insert_with("alice".to_owned(), || 0)? += 1;
+//! // writes only `users/alice.json`
+//! users.commit("alice")?;
+//!
+//! for key in users.keys()? {
+//!   println!("{key}");
+//! };
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`Display`]: std::fmt::Display
+//! [`FromStr`]: std::str::FromStr
+//! [`AtomicRename`]: crate::manager::mode::AtomicRename
+
+use crate::error::Error;
+use crate::manager::format::FileFormat;
+use crate::manager::lock::{ExclusiveLockBlocking, FileLock, SharedLockBlocking};
+use crate::manager::mode;
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fmt::{self, Debug, Display};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+const LOCKS_DIR: &str = ".locks";
+
+/// A collection persisted as a directory of files, one per entry.
+/// See the [module-level documentation][self] for more information.
+#[derive(Debug)]
+pub struct ContainerDirectory<K, T, Format> {
+  entries: BTreeMap<K, Entry<T>>,
+  removed: BTreeSet<K>,
+  dir: PathBuf,
+  extension: String,
+  format: Format
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+  value: T,
+  dirty: bool
+}
+
+impl<K, T, Format> ContainerDirectory<K, T, Format> {
+  /// Opens a new [`ContainerDirectory`] for the files with the given extension in the given directory,
+  /// creating the directory if it does not exist. No entries are read until they are accessed.
+  pub fn open<P: AsRef<Path>>(dir: P, extension: &str, format: Format) -> io::Result<Self> {
+    let dir = dir.as_ref().to_owned();
+    fs::create_dir_all(&dir)?;
+    let extension = extension.trim_start_matches('.').to_owned();
+    Ok(ContainerDirectory { entries: BTreeMap::new(), removed: BTreeSet::new(), dir, extension, format })
+  }
+
+  /// Gets the directory that entries are stored in.
+  #[inline]
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Gets the extension of the files that entries are stored in.
+  #[inline]
+  pub fn extension(&self) -> &str {
+    &self.extension
+  }
+
+  /// Gets a reference to the [`FileFormat`] used for every entry.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Returns `true` if any entry has been modified or removed since it was last loaded or committed.
+  pub fn is_dirty(&self) -> bool {
+    !self.removed.is_empty() || self.entries.values().any(|entry| entry.dirty)
+  }
+}
+
+impl<K: Ord, T, Format> ContainerDirectory<K, T, Format> {
+  /// Returns the path of the file that the entry with the given key is stored in.
+  ///
+  /// Returns an error if the key does not form a valid file name, such as when it contains a path separator.
+  pub fn path_of<Q>(&self, key: &Q) -> io::Result<PathBuf>
+  where K: Borrow<Q>, Q: Display + ?Sized {
+    self.file_name_of(key).map(|name| self.dir.join(name))
+  }
+
+  /// Locks the entries with the given keys for exclusive access, waiting for as long as it takes
+  /// for other threads and processes to release them. The entries are unlocked when the returned guard is dropped.
+  ///
+  /// Locks are always taken in the same order regardless of the order of the given keys,
+  /// so that two callers locking overlapping sets of entries can never deadlock each other.
+  /// Keys that do not have an entry can also be locked, and duplicate keys are locked once.
+  ///
+  /// Locks belong to the returned guard rather than to this container or to the process, so locking an entry
+  /// that is already locked through another guard (even on the same thread) waits until that guard is dropped.
+  pub fn lock_many<'k, Q, I>(&self, keys: I) -> io::Result<EntryLocks>
+  where K: Borrow<Q>, Q: Display + ?Sized + 'k, I: IntoIterator<Item = &'k Q> {
+    self.lock_entries::<Q, I, ExclusiveLockBlocking>(keys)
+  }
+
+  /// Locks the entries with the given keys for shared access, waiting for as long as it takes
+  /// for other threads and processes to release any exclusive locks on them.
+  /// The entries are unlocked when the returned guard is dropped.
+  ///
+  /// See [`ContainerDirectory::lock_many`] for more information.
+  pub fn lock_many_shared<'k, Q, I>(&self, keys: I) -> io::Result<EntryLocks>
+  where K: Borrow<Q>, Q: Display + ?Sized + 'k, I: IntoIterator<Item = &'k Q> {
+    self.lock_entries::<Q, I, SharedLockBlocking>(keys)
+  }
+
+  fn lock_entries<'k, Q, I, L>(&self, keys: I) -> io::Result<EntryLocks>
+  where K: Borrow<Q>, Q: Display + ?Sized + 'k, I: IntoIterator<Item = &'k Q>, L: FileLock {
+    // lock files are sorted by name, so the order is the same in every process
+    let names = keys.into_iter()
+      .map(|key| self.file_name_of(key))
+      .collect::<io::Result<BTreeSet<String>>>()?;
+    let locks_dir = self.dir.join(LOCKS_DIR);
+    fs::create_dir_all(&locks_dir)?;
+
+    let mut files = Vec::with_capacity(names.len());
+    for name in names {
+      let file = OpenOptions::new()
+        .read(true).write(true).create(true).truncate(false)
+        .open(locks_dir.join(name))?;
+      // locks taken so far are released when `files` is dropped
+      L::lock(&file)?;
+      files.push(file);
+    };
+
+    Ok(EntryLocks { files })
+  }
+
+  fn file_name_of<Q>(&self, key: &Q) -> io::Result<String>
+  where K: Borrow<Q>, Q: Display + ?Sized {
+    let name = format!("{key}.{}", self.extension);
+    let mut components = Path::new(&name).components();
+    match (components.next(), components.next()) {
+      (Some(Component::Normal(component)), None) if component == OsStr::new(&name) => Ok(name),
+      _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{name:?} is not a valid file name")))
+    }
+  }
+
+  /// Returns `true` if the entry with the given key is currently loaded into memory.
+  #[inline]
+  pub fn is_loaded<Q>(&self, key: &Q) -> bool
+  where K: Borrow<Q>, Q: Ord + ?Sized {
+    self.entries.contains_key(key)
+  }
+
+  /// Returns an iterator over the entries currently loaded into memory, ordered by key.
+  pub fn loaded(&self) -> impl Iterator<Item = (&K, &T)> {
+    self.entries.iter().map(|(key, entry)| (key, &entry.value))
+  }
+
+  /// Unloads the entry with the given key from memory, returning it.
+  /// Any modifications to it that have not been committed are lost.
+  pub fn unload<Q>(&mut self, key: &Q) -> Option<T>
+  where K: Borrow<Q>, Q: Ord + ?Sized {
+    self.entries.remove(key).map(|entry| entry.value)
+  }
+
+  /// Stores a value under the given key, marking it as modified.
+  /// Returns the value previously loaded under the key, which does not cause it to be read from disk.
+  pub fn insert(&mut self, key: K, value: T) -> Option<T> {
+    self.removed.remove(&key);
+    self.entries.insert(key, Entry { value, dirty: true }).map(|entry| entry.value)
+  }
+
+  /// Removes the entry with the given key, returning the value loaded under it, if any.
+  /// Its file is removed from disk when the key is next committed.
+  pub fn remove(&mut self, key: K) -> Option<T> {
+    let value = self.entries.remove(&key).map(|entry| entry.value);
+    self.removed.insert(key);
+    value
+  }
+
+  /// Closes this [`ContainerDirectory`], returning the entries that are currently loaded.
+  /// Any modifications that have not been committed are lost.
+  pub fn into_loaded(self) -> BTreeMap<K, T> {
+    self.entries.into_iter().map(|(key, entry)| (key, entry.value)).collect()
+  }
+}
+
+impl<K, T, Format> ContainerDirectory<K, T, Format>
+where K: Ord + Clone + Display + FromStr, Format: FileFormat<T> {
+  /// Returns the keys of every entry, those stored on disk as well as those only inserted in memory,
+  /// excluding those that have been removed.
+  pub fn keys(&self) -> io::Result<BTreeSet<K>> {
+    let mut keys = BTreeSet::new();
+    for dir_entry in fs::read_dir(&self.dir)? {
+      if let Some(key) = self.key_of(&dir_entry?)? {
+        keys.insert(key);
+      };
+    };
+
+    keys.extend(self.entries.keys().cloned());
+    keys.retain(|key| !self.removed.contains(key));
+    Ok(keys)
+  }
+
+  /// Returns an iterator over the entries stored on disk, which reads the file of each entry only once it is reached,
+  /// so that a directory of any size can be scanned without holding more than one entry in memory.
+  ///
+  /// The files are scanned as they are on disk, in no particular order. Entries are not loaded into this container,
+  /// and modifications to it that have not been committed (including insertions and removals) are not reflected.
+  /// Files that are removed while the directory is scanned may or may not be yielded.
+  pub fn scan(&self) -> io::Result<Scan<'_, K, T, Format>> {
+    Ok(Scan { container: self, read_dir: fs::read_dir(&self.dir)?, error: None })
+  }
+
+  /// Returns a reference to the entry with the given key, reading it from disk if it is not loaded yet.
+  /// Returns `None` if there is no such entry.
+  pub fn get(&mut self, key: &K) -> Result<Option<&T>, Error<Format::FormatError>> {
+    Ok(self.load(key)?.map(|entry| &entry.value))
+  }
+
+  /// Returns a mutable reference to the entry with the given key, reading it from disk if it is not loaded yet,
+  /// and marking it as modified. Returns `None` if there is no such entry.
+  pub fn get_mut(&mut self, key: &K) -> Result<Option<&mut T>, Error<Format::FormatError>> {
+    Ok(self.load(key)?.map(|entry| {
+      entry.dirty = true;
+      &mut entry.value
+    }))
+  }
+
+  /// Returns a mutable reference to the entry with the given key, reading it from disk if it is not loaded yet,
+  /// or inserting the value returned by the closure if there is no such entry, and marking it as modified.
+  pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> Result<&mut T, Error<Format::FormatError>>
+  where F: FnOnce() -> T {
+    if self.load(&key)?.is_none() {
+      self.insert(key.clone(), f());
+    };
+
+    let entry = self.entries.get_mut(&key).expect("entry was just loaded or inserted");
+    entry.dirty = true;
+    Ok(&mut entry.value)
+  }
+
+  /// Reads the entry with the given key from disk again, replacing it in memory and returning the previously loaded value.
+  /// Any modifications to it that have not been committed, including its removal, are lost.
+  pub fn refresh(&mut self, key: &K) -> Result<Option<T>, Error<Format::FormatError>> {
+    self.removed.remove(key);
+    let previous = self.unload(key);
+    self.load(key)?;
+    Ok(previous)
+  }
+
+  /// Writes the entry with the given key to disk if it has been modified since it was loaded or last committed,
+  /// or removes its file if the entry has been removed.
+  pub fn commit<Q>(&mut self, key: &Q) -> Result<(), Error<Format::FormatError>>
+  where K: Borrow<Q>, Q: Ord + Display + ?Sized {
+    if self.removed.contains(key) {
+      match fs::remove_file(self.path_of(key)?) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => ()
+      };
+
+      self.removed.remove(key);
+    } else if let Some(entry) = self.entries.get(key).filter(|entry| entry.dirty) {
+      mode::write_rename(&self.format, &self.path_of(key)?, &entry.value, true)?;
+      self.entries.get_mut(key).expect("entry exists").dirty = false;
+    };
+
+    Ok(())
+  }
+
+  /// Commits every entry that has been modified or removed since it was loaded or last committed.
+  ///
+  /// Entries that fail to be committed are left modified, and do not stop the other entries from being committed.
+  pub fn commit_all(&mut self) -> Result<(), BulkError<K, Format::FormatError>> {
+    let keys = self.removed.iter().chain(self.entries.iter().filter(|(_, entry)| entry.dirty).map(|(key, _)| key))
+      .cloned().collect::<Vec<K>>();
+    BulkError::collect(keys, |key| self.commit(key))
+  }
+
+  /// Reads every loaded or removed entry from disk again, like [`ContainerDirectory::refresh`].
+  /// Any modifications that have not been committed, including removals, are lost.
+  ///
+  /// Entries that fail to be read are left unloaded, and do not stop the other entries from being read.
+  pub fn refresh_all(&mut self) -> Result<(), BulkError<K, Format::FormatError>> {
+    let keys = self.removed.iter().chain(self.entries.keys()).cloned().collect::<BTreeSet<K>>();
+    BulkError::collect(keys, |key| self.refresh(key).map(drop))
+  }
+
+  /// Returns the keys of every entry (see [`ContainerDirectory::keys`]) whose key matches the given glob pattern.
+  ///
+  /// Patterns are matched against the whole key as it is converted to a file name, without the extension.
+  /// `*` matches any sequence of characters, `?` matches any single character, and every other character matches itself.
+  pub fn select(&self, pattern: &str) -> io::Result<BTreeSet<K>> {
+    let mut keys = self.keys()?;
+    keys.retain(|key| glob_match(pattern, &key.to_string()));
+    Ok(keys)
+  }
+
+  /// Parses the key of the entry stored in the given file, if it is the file of an entry.
+  fn key_of(&self, dir_entry: &fs::DirEntry) -> io::Result<Option<K>> {
+    if !dir_entry.file_type()?.is_file() {
+      return Ok(None);
+    };
+
+    let file_name = dir_entry.file_name();
+    Ok(file_name.to_str()
+      .and_then(|file_name| file_name.strip_suffix(self.extension.as_str()))
+      .and_then(|file_name| file_name.strip_suffix('.'))
+      .and_then(|file_name| file_name.parse::<K>().ok()))
+  }
+
+  fn load(&mut self, key: &K) -> Result<Option<&mut Entry<T>>, Error<Format::FormatError>> {
+    if self.removed.contains(key) {
+      return Ok(None);
+    };
+
+    if !self.entries.contains_key(key) {
+      let file = match File::open(self.path_of(key)?) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into())
+      };
+
+      let value = mode::read(&self.format, &file)?;
+      self.entries.insert(key.clone(), Entry { value, dirty: false });
+    };
+
+    Ok(self.entries.get_mut(key))
+  }
+}
+
+/// An iterator over the entries of a [`ContainerDirectory`] as they are stored on disk, yielding the key of each entry
+/// along with the result of reading it.
+///
+/// If listing the directory fails, iteration ends early, and the error can be retrieved with [`Scan::take_error`].
+/// This structure is created by [`ContainerDirectory::scan`].
+#[derive(Debug)]
+pub struct Scan<'a, K, T, Format> {
+  container: &'a ContainerDirectory<K, T, Format>,
+  read_dir: fs::ReadDir,
+  error: Option<io::Error>
+}
+
+impl<K, T, Format> Scan<'_, K, T, Format> {
+  /// Takes the error that ended the iteration early, if any.
+  pub fn take_error(&mut self) -> Option<io::Error> {
+    self.error.take()
+  }
+}
+
+impl<K, T, Format> Iterator for Scan<'_, K, T, Format>
+where K: Ord + Clone + Display + FromStr, Format: FileFormat<T> {
+  type Item = (K, Result<T, Error<Format::FormatError>>);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let entry = self.read_dir.next()?.and_then(|dir_entry| {
+        Ok(self.container.key_of(&dir_entry)?.map(|key| (key, dir_entry.path())))
+      });
+
+      let (key, path) = match entry {
+        Ok(Some(entry)) => entry,
+        Ok(None) => continue,
+        Err(err) => {
+          self.error = Some(err);
+          return None;
+        }
+      };
+
+      let value = match File::open(path) {
+        Ok(file) => mode::read(&self.container.format, &file),
+        // the file was removed after the directory was listed
+        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+        Err(err) => Err(err.into())
+      };
+
+      return Some((key, value));
+    };
+  }
+}
+
+/// A guard holding the locks on a set of entries of a [`ContainerDirectory`], which are released when it is dropped.
+/// This structure is created by [`ContainerDirectory::lock_many`] and [`ContainerDirectory::lock_many_shared`].
+#[derive(Debug)]
+pub struct EntryLocks {
+  files: Vec<File>
+}
+
+impl EntryLocks {
+  /// Returns the number of entries locked by this guard.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.files.len()
+  }
+
+  /// Returns `true` if this guard does not lock any entries.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.files.is_empty()
+  }
+}
+
+/// The error returned by the bulk operations of a [`ContainerDirectory`], holding the error of every entry that failed,
+/// along with its key, in the order the entries were operated on.
+pub struct BulkError<K, FE> {
+  errors: Vec<(K, Error<FE>)>
+}
+
+impl<K, FE> BulkError<K, FE> {
+  /// Applies the operation to every key, returning the errors of those it failed for.
+  fn collect<I, F>(keys: I, mut operation: F) -> Result<(), Self>
+  where I: IntoIterator<Item = K>, F: FnMut(&K) -> Result<(), Error<FE>> {
+    let errors = keys.into_iter()
+      .filter_map(|key| operation(&key).err().map(|err| (key, err)))
+      .collect::<Vec<(K, Error<FE>)>>();
+    if errors.is_empty() { Ok(()) } else { Err(BulkError { errors }) }
+  }
+
+  /// Gets the key and error of every entry that failed. This is never empty.
+  #[inline]
+  pub fn errors(&self) -> &[(K, Error<FE>)] {
+    &self.errors
+  }
+
+  /// Returns the key and error of every entry that failed. This is never empty.
+  #[inline]
+  pub fn into_errors(self) -> Vec<(K, Error<FE>)> {
+    self.errors
+  }
+}
+
+impl<K: Debug, FE: Debug> Debug for BulkError<K, FE> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("BulkError")
+      .field("errors", &self.errors)
+      .finish()
+  }
+}
+
+impl<K: Display, FE: Display> Display for BulkError<K, FE> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (key, err) = &self.errors[0];
+    match self.errors.len() {
+      1 => write!(f, "entry {key} failed: {err}"),
+      len => write!(f, "{len} entries failed, first entry {key} failed: {err}")
+    }
+  }
+}
+
+impl<K: Debug + Display, FE: std::error::Error> std::error::Error for BulkError<K, FE> {}
+
+/// Matches text against a glob pattern, where `*` matches any sequence of characters and `?` matches any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+  let pattern = pattern.chars().collect::<Vec<char>>();
+  let text = text.chars().collect::<Vec<char>>();
+  let (mut p, mut t) = (0, 0);
+  // the position of the last `*` in the pattern, and the position in the text that it was tried at
+  let mut backtrack = None;
+  while t < text.len() {
+    match pattern.get(p) {
+      Some('*') => {
+        backtrack = Some((p, t));
+        p += 1;
+      },
+      Some(&c) if c == '?' || c == text[t] => {
+        p += 1;
+        t += 1;
+      },
+      _ => match backtrack {
+        // let the last `*` match one more character, then try again
+        Some((star, star_t)) => {
+          backtrack = Some((star, star_t + 1));
+          p = star + 1;
+          t = star_t + 1;
+        },
+        None => return false
+      }
+    };
+  };
+
+  pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,416 @@
+//! Container constructs that persist state as an append-only log of events, instead of rewriting the whole file.
+//!
+//! An [`EventLogContainer`] holds some state that is only ever modified by applying events to it (see [`Apply`]).
+//! Committing appends the pending events to the log file as records of a [`RecordFormat`], which stays cheap
+//! no matter how large the state grows, and leaves a complete history of every change.
+//!
+//! Opening the container reads the latest snapshot of the state (stored next to the log as `<file name>.snapshot`)
+//! and replays every event appended since onto it. Taking a snapshot with [`EventLogContainer::snapshot_now`]
+//! (or automatically, see [`Retention`]) writes out the current state and empties the log, keeping replays short.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonLines};
+//! use singlefile::container_event_log::{Apply, EventLogContainer};
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! enum Event { Deposit(u64), Withdraw(u64) }
+//!
+//! #[derive(Serialize, Deserialize, Default)]
+//! struct Account { balance: u64 }
+//!
+//! impl Apply<Event> for Account {
+//!   fn apply(&mut self, event: &Event) {
+//!     match event {
+//!       Event::Deposit(amount) => self.balance += amount,
+//!       Event::Withdraw(amount) => self.balance -= amount
+//!     }
+//!   }
+//! }
+//!
+//! let mut account = EventLogContainer::<Event, Account, _, Json>::open("account.log", JsonLines, Json)?;
+//! account.push(Event::Deposit(100));
+//! account.push(Event::Withdraw(30));
+//! println!("balance: {}", account.balance);
+//! account.commit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`RecordFormat`]: crate::manager::format::RecordFormat
+
+use crate::manager::format::{FileFormat, RecordFormat};
+use crate::manager::format::record::trim_record;
+use crate::manager::lock::{ExclusiveLock, FileLock};
+
+use thiserror::Error;
+
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+const LOG_HEADER: &str = "sfevents";
+
+/// Describes state that is modified by applying events of type `E` to it.
+pub trait Apply<E> {
+  /// Applies a single event to this state.
+  ///
+  /// Applying the same sequence of events to the same initial state must always produce the same state,
+  /// since events are replayed every time the container is opened.
+  fn apply(&mut self, event: &E);
+}
+
+/// Controls when an [`EventLogContainer`] takes snapshots, and what happens to the events they replace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Retention {
+  /// Automatically take a snapshot when committing, once at least this many events have been
+  /// appended to the log since the last snapshot. If `None`, snapshots are only taken manually.
+  pub snapshot_every: Option<u64>,
+  /// The number of log segments to keep as an audit trail after they are replaced by a snapshot.
+  /// Segments are stored next to the log as `<file name>.segment-<sequence>`, where `<sequence>`
+  /// is the sequence number of the first event in the segment. If `0`, replaced events are discarded.
+  pub keep_segments: usize
+}
+
+/// An error that can occur while using an [`EventLogContainer`].
+#[derive(Debug, Error)]
+pub enum EventLogError<LE, SE> {
+  /// An error caused by the log format while handling the event with the given sequence number.
+  #[error("log format error at event {0}: {1}")]
+  Log(u64, LE),
+  /// An error caused by the snapshot format.
+  #[error("snapshot format error: {0}")]
+  Snapshot(SE),
+  /// The log or snapshot is malformed, or they do not belong together.
+  #[error("invalid event log: {0}")]
+  Invalid(&'static str),
+  /// An error caused by the filesystem.
+  #[error(transparent)]
+  Io(#[from] io::Error)
+}
+
+/// A container persisting state as a log of events, replayed onto the latest snapshot when opened.
+/// See the [module-level documentation][self] for more information.
+///
+/// The log file is exclusively locked for as long as the container is open.
+/// An event left incomplete by an interrupted commit is discarded when the log is next opened.
+#[derive(Debug)]
+pub struct EventLogContainer<E, S, LogFormat, SnapshotFormat> {
+  state: S,
+  pending: Vec<E>,
+  sequence: u64,
+  log_base: u64,
+  retention: Retention,
+  log_format: LogFormat,
+  snapshot_format: SnapshotFormat,
+  file: File,
+  path: PathBuf,
+  phantom: PhantomData<fn(E)>
+}
+
+impl<E, S, LogFormat, SnapshotFormat> EventLogContainer<E, S, LogFormat, SnapshotFormat>
+where
+  S: Apply<E> + Default,
+  LogFormat: RecordFormat<E>,
+  SnapshotFormat: FileFormat<S>
+{
+  /// Opens a new [`EventLogContainer`], creating an empty log at the given path if it does not exist.
+  ///
+  /// The state starts from the latest snapshot, or from the default value of `S` if there is no snapshot,
+  /// and every event in the log is then applied to it.
+  pub fn open<P: AsRef<Path>>(path: P, log_format: LogFormat, snapshot_format: SnapshotFormat)
+  -> Result<Self, EventLogError<LogFormat::FormatError, SnapshotFormat::FormatError>> {
+    let path = path.as_ref().to_owned();
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    ExclusiveLock::lock(&file)?;
+
+    let (mut sequence, mut state) = match fs::read(snapshot_path(&path)) {
+      Ok(buf) => read_snapshot(&snapshot_format, &buf)?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => (0, S::default()),
+      Err(err) => return Err(err.into())
+    };
+
+    let (log_base, events) = read_log(&log_format, &file, sequence)?;
+    // events before the snapshot are still present if a snapshot was taken, but the log was not yet emptied
+    let skip = sequence.checked_sub(log_base)
+      .filter(|&skip| skip <= events.len() as u64)
+      .ok_or(EventLogError::Invalid("snapshot does not match log"))?;
+    for event in &events[skip as usize..] {
+      state.apply(event);
+      sequence += 1;
+    };
+
+    Ok(EventLogContainer {
+      state,
+      pending: Vec::new(),
+      sequence,
+      log_base,
+      retention: Retention::default(),
+      log_format,
+      snapshot_format,
+      file,
+      path,
+      phantom: PhantomData
+    })
+  }
+
+  /// Appends every pending event to the log, taking a snapshot afterwards if the [`Retention`] calls for one.
+  pub fn commit(&mut self) -> Result<(), EventLogError<LogFormat::FormatError, SnapshotFormat::FormatError>> {
+    self.append_pending()?;
+    if let Some(every) = self.retention.snapshot_every {
+      if self.sequence - self.log_base >= every.max(1) {
+        self.snapshot_now()?;
+      };
+    };
+
+    Ok(())
+  }
+
+  /// Commits every pending event, then writes the current state as a new snapshot, emptying the log.
+  ///
+  /// The events in the log are moved to a new segment if [`Retention::keep_segments`] is not `0`,
+  /// and the oldest segments are removed so that only that many remain.
+  pub fn snapshot_now(&mut self) -> Result<(), EventLogError<LogFormat::FormatError, SnapshotFormat::FormatError>> {
+    self.append_pending()?;
+    if self.sequence == self.log_base {
+      return Ok(());
+    };
+
+    let mut buf = self.sequence.to_le_bytes().to_vec();
+    self.snapshot_format.to_writer(&mut buf, &self.state).map_err(EventLogError::Snapshot)?;
+    let snapshot_path = snapshot_path(&self.path);
+    let temp_path = crate::utils::temp_path(&snapshot_path);
+    let result = write_synced(&temp_path, &buf).and_then(|()| fs::rename(&temp_path, &snapshot_path));
+    if result.is_err() {
+      let _ = fs::remove_file(&temp_path);
+    };
+
+    result?;
+    // the rename must be durable before the log is emptied, or a crash could lose both the snapshot and the events
+    crate::manager::mode::sync_dir(&snapshot_path)?;
+
+    // the snapshot is in place, so an interruption from here on only leaves events that will be skipped
+    if self.retention.keep_segments > 0 {
+      // the log is read through the locked handle, since locks on windows prevent it from being opened again
+      let mut segment = Vec::new();
+      self.file.seek(SeekFrom::Start(0))?;
+      self.file.read_to_end(&mut segment)?;
+      fs::write(with_suffix(&self.path, &format!(".segment-{}", self.log_base)), segment)?;
+    };
+
+    self.file.set_len(0)?;
+    self.file.sync_all()?;
+    self.log_base = self.sequence;
+    prune_segments(&self.path, self.retention.keep_segments)?;
+    Ok(())
+  }
+
+  fn append_pending(&mut self) -> Result<(), EventLogError<LogFormat::FormatError, SnapshotFormat::FormatError>> {
+    if self.pending.is_empty() {
+      return Ok(());
+    };
+
+    let mut buf = Vec::new();
+    let len = self.file.seek(SeekFrom::End(0))?;
+    if len == 0 {
+      self.log_base = self.sequence;
+      writeln!(buf, "{LOG_HEADER} {}", self.log_base)?;
+    };
+
+    for (i, event) in self.pending.iter().enumerate() {
+      self.log_format.to_record(&mut buf, event)
+        .map_err(|err| EventLogError::Log(self.sequence + i as u64, err))?;
+      buf.push(b'\n');
+    };
+
+    if let Err(err) = self.file.write_all(&buf).and_then(|()| self.file.sync_all()) {
+      // a partially written event would corrupt every event appended after it, so it is cut off again
+      let _ = self.file.set_len(len);
+      return Err(err.into());
+    };
+
+    self.sequence += self.pending.len() as u64;
+    self.pending.clear();
+    Ok(())
+  }
+}
+
+impl<E, S: Apply<E>, LogFormat, SnapshotFormat> EventLogContainer<E, S, LogFormat, SnapshotFormat> {
+  /// Applies an event to the state, queueing it to be appended to the log on the next commit.
+  pub fn push(&mut self, event: E) {
+    self.state.apply(&event);
+    self.pending.push(event);
+  }
+
+  /// Applies every event to the state, queueing them to be appended to the log on the next commit.
+  pub fn extend<I: IntoIterator<Item = E>>(&mut self, events: I) {
+    for event in events {
+      self.push(event);
+    };
+  }
+}
+
+impl<E, S, LogFormat, SnapshotFormat> EventLogContainer<E, S, LogFormat, SnapshotFormat> {
+  /// Gets a reference to the current state, including the effects of pending events.
+  ///
+  /// You may also operate on the container directly with [`Deref`] instead.
+  #[inline]
+  pub const fn state(&self) -> &S {
+    &self.state
+  }
+
+  /// Gets the events that have been pushed, but not yet committed.
+  #[inline]
+  pub fn pending(&self) -> &[E] {
+    &self.pending
+  }
+
+  /// Gets the number of events that have been committed over the whole lifetime of the log,
+  /// including those replaced by snapshots.
+  #[inline]
+  pub const fn sequence(&self) -> u64 {
+    self.sequence
+  }
+
+  /// Gets the number of committed events in the log since the last snapshot.
+  #[inline]
+  pub const fn log_len(&self) -> u64 {
+    self.sequence - self.log_base
+  }
+
+  /// Gets the [`Retention`] used by this container.
+  #[inline]
+  pub const fn retention(&self) -> Retention {
+    self.retention
+  }
+
+  /// Sets the [`Retention`] used by this container, which is not persisted.
+  /// Containers are opened with the default retention, which only takes snapshots manually and discards replaced events.
+  #[inline]
+  pub fn set_retention(&mut self, retention: Retention) {
+    self.retention = retention;
+  }
+
+  /// Gets the path of the log file.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Unlocks and closes this [`EventLogContainer`], returning the current state.
+  /// Any events that have not been committed are not appended to the log, but their effects remain in the returned state.
+  pub fn close(self) -> io::Result<S> {
+    ExclusiveLock::unlock(&self.file)?;
+    self.file.sync_all()?;
+    Ok(self.state)
+  }
+}
+
+impl<E, S, LogFormat, SnapshotFormat> Deref for EventLogContainer<E, S, LogFormat, SnapshotFormat> {
+  type Target = S;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.state
+  }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(suffix);
+  path.with_file_name(name)
+}
+
+fn snapshot_path(path: &Path) -> PathBuf {
+  with_suffix(path, ".snapshot")
+}
+
+fn write_synced(path: &Path, buf: &[u8]) -> io::Result<()> {
+  let mut file = File::create(path)?;
+  file.write_all(buf)?;
+  file.sync_all()
+}
+
+fn read_snapshot<S, LE, SnapshotFormat>(format: &SnapshotFormat, buf: &[u8]) -> Result<(u64, S), EventLogError<LE, SnapshotFormat::FormatError>>
+where SnapshotFormat: FileFormat<S> {
+  if buf.len() < 8 {
+    return Err(EventLogError::Invalid("snapshot is truncated"));
+  };
+
+  let (sequence, buf) = buf.split_at(8);
+  let sequence = u64::from_le_bytes(sequence.try_into().unwrap());
+  let state = format.from_buffer(buf).map_err(EventLogError::Snapshot)?;
+  Ok((sequence, state))
+}
+
+/// Reads every complete event in the log, returning them along with the sequence number of the first one.
+/// An incomplete event at the end of the log is removed from the file.
+fn read_log<E, LogFormat, SE>(format: &LogFormat, file: &File, sequence: u64) -> Result<(u64, Vec<E>), EventLogError<LogFormat::FormatError, SE>>
+where LogFormat: RecordFormat<E> {
+  let mut reader = BufReader::new(file);
+  reader.seek(SeekFrom::Start(0))?;
+
+  let mut line = Vec::new();
+  if reader.read_until(b'\n', &mut line)? == 0 || !line.ends_with(b"\n") {
+    // the log is empty, or was interrupted while writing its header
+    file.set_len(0)?;
+    return Ok((sequence, Vec::new()));
+  };
+
+  let log_base = std::str::from_utf8(trim_record(&line).unwrap_or_default()).ok()
+    .and_then(|header| header.strip_prefix(LOG_HEADER))
+    .and_then(|base| base.trim().parse::<u64>().ok())
+    .ok_or(EventLogError::Invalid("missing log header"))?;
+
+  let mut events = Vec::new();
+  let mut position = line.len() as u64;
+  loop {
+    line.clear();
+    match reader.read_until(b'\n', &mut line)? {
+      0 => break,
+      _ if !line.ends_with(b"\n") => {
+        file.set_len(position)?;
+        break;
+      },
+      len => position += len as u64
+    };
+
+    if let Some(record) = trim_record(&line) {
+      let event = format.from_record(record)
+        .map_err(|err| EventLogError::Log(log_base + events.len() as u64, err))?;
+      events.push(event);
+    };
+  };
+
+  Ok((log_base, events))
+}
+
+fn prune_segments(path: &Path, keep: usize) -> io::Result<()> {
+  let dir = match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new(".")
+  };
+
+  let prefix = with_suffix(Path::new(path.file_name().unwrap_or_default()), ".segment-");
+  let prefix = prefix.to_string_lossy();
+  let mut segments = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    let name = entry.file_name();
+    let base = name.to_str()
+      .and_then(|name| name.strip_prefix(&*prefix))
+      .and_then(|base| base.parse::<u64>().ok());
+    if let Some(base) = base {
+      segments.push((base, entry.path()));
+    };
+  };
+
+  segments.sort_unstable();
+  for (_, path) in &segments[..segments.len().saturating_sub(keep)] {
+    fs::remove_file(path)?;
+  };
+
+  Ok(())
+}
@@ -0,0 +1,182 @@
+//! Container constructs for using a single file as a small embedded key-value store.
+//!
+//! A [`ContainerKv`] stores its entries as one map, serialized with any [`FileFormat`] that can store a [`BTreeMap`].
+//! The file is exclusively locked for as long as the container is open, and written atomically (see [`Atomic`]).
+//!
+//! Every key inserted or removed since the last commit is tracked, so committing
+//! a container that has not been modified does not touch the file at all.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::container_kv::ContainerKv;
+//!
+//! let mut store = ContainerKv::<String, u64, Json>::open("store.json", Json)?;
+//! *store.get_or_insert_with("visits".to_owned(), || 0) += 1;
+//! store.remove("stale");
+//!
+//! for (key, value) in store.iter() {
+//!   println!("{key}: {value}");
+//! };
+//!
+//! store.commit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`FileFormat`]: crate::manager::format::FileFormat
+//! [`Atomic`]: crate::manager::mode::Atomic
+
+use crate::error::Error;
+use crate::manager::format::FileFormat;
+use crate::manager::{FileManager, ManagerAtomicLocked};
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Iter;
+use std::io;
+use std::path::Path;
+
+/// A key-value store persisted as a single map file.
+/// See the [module-level documentation][self] for more information.
+#[derive(Debug)]
+pub struct ContainerKv<K, V, Format> {
+  entries: BTreeMap<K, V>,
+  dirty: BTreeSet<K>,
+  manager: ManagerAtomicLocked<Format>
+}
+
+impl<K, V, Format> ContainerKv<K, V, Format>
+where K: Ord, Format: FileFormat<BTreeMap<K, V>> {
+  /// Opens a new [`ContainerKv`], creating an empty store at the given path if it does not exist.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>> {
+    let (entries, manager) = FileManager::create_or(path, format, BTreeMap::new())?;
+    Ok(ContainerKv { entries, dirty: BTreeSet::new(), manager })
+  }
+
+  /// Reads the store from disk again, replacing the entries in memory and returning the previous entries.
+  /// Any modifications that have not been committed are lost.
+  pub fn refresh(&mut self) -> Result<BTreeMap<K, V>, Error<Format::FormatError>> {
+    let entries = self.manager.read()?;
+    self.dirty.clear();
+    Ok(std::mem::replace(&mut self.entries, entries))
+  }
+
+  /// Writes the store to disk, if any key has been modified since it was last opened, refreshed or committed.
+  pub fn commit(&mut self) -> Result<(), Error<Format::FormatError>> {
+    if !self.dirty.is_empty() {
+      self.manager.write(&self.entries)?;
+      self.dirty.clear();
+    };
+
+    Ok(())
+  }
+}
+
+impl<K: Ord, V, Format> ContainerKv<K, V, Format> {
+  /// Returns a reference to the value stored under the given key.
+  #[inline]
+  pub fn get<Q>(&self, key: &Q) -> Option<&V>
+  where K: Borrow<Q>, Q: Ord + ?Sized {
+    self.entries.get(key)
+  }
+
+  /// Returns a mutable reference to the value stored under the given key, marking the key as modified.
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+  where K: Borrow<Q> + Clone, Q: Ord + ?Sized {
+    let (owned_key, _) = self.entries.get_key_value(key)?;
+    self.dirty.insert(owned_key.clone());
+    self.entries.get_mut(key)
+  }
+
+  /// Returns a mutable reference to the value stored under the given key, inserting the value
+  /// returned by the closure if the key is not present, and marking the key as modified.
+  pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+  where K: Clone, F: FnOnce() -> V {
+    self.dirty.insert(key.clone());
+    self.entries.entry(key).or_insert_with(f)
+  }
+
+  /// Returns `true` if a value is stored under the given key.
+  #[inline]
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where K: Borrow<Q>, Q: Ord + ?Sized {
+    self.entries.contains_key(key)
+  }
+
+  /// Stores a value under the given key, returning the value previously stored under it.
+  pub fn insert(&mut self, key: K, value: V) -> Option<V>
+  where K: Clone {
+    self.dirty.insert(key.clone());
+    self.entries.insert(key, value)
+  }
+
+  /// Removes the value stored under the given key, returning it.
+  /// The key is only marked as modified if a value was removed.
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+  where K: Borrow<Q>, Q: Ord + ?Sized {
+    let (key, value) = self.entries.remove_entry(key)?;
+    self.dirty.insert(key);
+    Some(value)
+  }
+
+  /// Returns an iterator over the entries of the store, ordered by key.
+  #[inline]
+  pub fn iter(&self) -> Iter<'_, K, V> {
+    self.entries.iter()
+  }
+
+  /// Returns the number of entries in the store.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Returns `true` if the store has no entries.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Returns `true` if any key has been modified since the store was last opened, refreshed or committed.
+  #[inline]
+  pub fn is_dirty(&self) -> bool {
+    !self.dirty.is_empty()
+  }
+
+  /// Returns an iterator over every key modified since the store was last opened, refreshed or committed,
+  /// including keys that have since been removed.
+  #[inline]
+  pub fn dirty_keys(&self) -> impl Iterator<Item = &K> {
+    self.dirty.iter()
+  }
+}
+
+impl<K, V, Format> ContainerKv<K, V, Format> {
+  /// Gets a reference to the entries of the store.
+  #[inline]
+  pub const fn entries(&self) -> &BTreeMap<K, V> {
+    &self.entries
+  }
+
+  /// Gets a reference to the contained file manager.
+  ///
+  /// It is inadvisable to manipulate the manager manually.
+  #[inline]
+  pub const fn manager(&self) -> &ManagerAtomicLocked<Format> {
+    &self.manager
+  }
+
+  /// Closes this [`ContainerKv`], returning its entries. Any modifications that have not been committed are lost.
+  pub fn close(self) -> io::Result<BTreeMap<K, V>> {
+    self.manager.close().map(|()| self.entries)
+  }
+}
+
+impl<'a, K, V, Format> IntoIterator for &'a ContainerKv<K, V, Format> {
+  type Item = (&'a K, &'a V);
+  type IntoIter = Iter<'a, K, V>;
+
+  #[inline]
+  fn into_iter(self) -> Self::IntoIter {
+    self.entries.iter()
+  }
+}
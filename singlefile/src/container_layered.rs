@@ -0,0 +1,587 @@
+//! Container constructs serving embedded defaults until a file overriding them exists.
+//!
+//! Applications can embed a default configuration with `include_bytes!` and ship working defaults,
+//! without ever writing them to disk. If a file exists at the container's path, it is read instead.
+//! For configuration that also needs to be modified and overridden by environment variables, see `ContainerLayered`
+//! (enabled with the `layered` cargo feature).
+//! For configuration spread across several files (such as system-wide and per-user files), see [`ContainerMerged`].
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! # #[derive(serde::Serialize, serde::Deserialize)] struct Settings { volume: u8 }
+//! use singlefile::container_layered::{ContainerLayeredReadonly, Layer};
+//!
+//! // static DEFAULTS: &[u8] = include_bytes!("defaults.json");
+//! static DEFAULTS: &[u8] = br#"{ "volume": 50 }"#;
+//!
+//! let mut settings = ContainerLayeredReadonly::<Settings, Json>::open("settings.json", Json, DEFAULTS)?;
+//! println!("volume: {}", settings.volume);
+//!
+//! // Later, after the user may have created `settings.json`
+//! settings.refresh()?;
+//! if settings.layer() == Layer::File {
+//!   println!("using settings from disk");
+//! };
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::error::Error;
+#[cfg(feature = "layered")]
+use crate::error::UserError;
+use crate::manager::format::FileFormat;
+#[cfg(feature = "layered")]
+use crate::manager::{FileManager, ManagerWritable};
+
+#[cfg(feature = "layered")]
+use serde::{Serialize, de::DeserializeOwned};
+#[cfg(feature = "layered")]
+use serde_json::{Map, Value};
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+#[cfg(feature = "layered")]
+use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
+
+/// The layer that the value of a [`ContainerLayeredReadonly`] was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+  /// The value was read from the embedded defaults, because no file existed.
+  Defaults,
+  /// The value was read from the file on disk.
+  File
+}
+
+/// A read-only container that reads from a file if it exists, and from embedded defaults otherwise.
+///
+/// The file is not held open, every [`refresh`] checks for it anew, so it may be created
+/// or removed at any time, switching the container between layers.
+///
+/// [`refresh`]: ContainerLayeredReadonly::refresh
+#[derive(Debug)]
+pub struct ContainerLayeredReadonly<T, Format> {
+  value: T,
+  layer: Layer,
+  path: PathBuf,
+  format: Format,
+  defaults: &'static [u8]
+}
+
+impl<T, Format> ContainerLayeredReadonly<T, Format>
+where Format: FileFormat<T> {
+  /// Opens a new [`ContainerLayeredReadonly`], reading the file at the given path if it exists,
+  /// and deserializing `defaults` otherwise.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format, defaults: &'static [u8]) -> Result<Self, Error<Format::FormatError>> {
+    let path = path.as_ref().to_owned();
+    let (value, layer) = read_layered(&path, &format, defaults)?;
+    Ok(ContainerLayeredReadonly { value, layer, path, format, defaults })
+  }
+
+  /// Reads the value again from whichever layer is currently present, replacing the current state in memory,
+  /// and returning the previous state.
+  pub fn refresh(&mut self) -> Result<T, Error<Format::FormatError>> {
+    let (value, layer) = read_layered(&self.path, &self.format, self.defaults)?;
+    self.layer = layer;
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Deserializes the embedded defaults, regardless of whether the file exists.
+  pub fn read_defaults(&self) -> Result<T, Error<Format::FormatError>> {
+    self.format.from_buffer(self.defaults).map_err(Error::Format)
+  }
+}
+
+impl<T, Format> ContainerLayeredReadonly<T, Format> {
+  /// Returns the layer that the current value was read from.
+  #[inline]
+  pub const fn layer(&self) -> Layer {
+    self.layer
+  }
+
+  /// Gets the path of the file that overrides the embedded defaults.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Gets a reference to the [`FileFormat`] used by this container.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets a reference to the contained value.
+  ///
+  /// You may also operate on the container directly with [`Deref`] instead.
+  #[inline]
+  pub const fn get(&self) -> &T {
+    &self.value
+  }
+
+  /// Extract the contained state.
+  #[inline]
+  pub fn into_value(self) -> T {
+    self.value
+  }
+}
+
+impl<T, Format> Deref for ContainerLayeredReadonly<T, Format> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.value
+  }
+}
+
+fn read_layered<T, Format>(path: &Path, format: &Format, defaults: &[u8]) -> Result<(T, Layer), Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  match File::open(path) {
+    Ok(file) => Ok((crate::manager::mode::read(format, &file)?, Layer::File)),
+    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+      let value = format.from_buffer(defaults).map_err(Error::Format)?;
+      Ok((value, Layer::Defaults))
+    },
+    Err(err) => Err(err.into())
+  }
+}
+
+/// Describes values that can be layered on top of each other, such as configuration read from several files.
+///
+/// Layers are usually made of optional fields, so that each one only overrides what it specifies.
+pub trait Merge {
+  /// Merges a layer with higher precedence into this one, letting the values it specifies override those in this one.
+  fn merge(&mut self, overlay: &Self);
+}
+
+impl<T: Clone> Merge for Option<T> {
+  #[inline]
+  fn merge(&mut self, overlay: &Self) {
+    if overlay.is_some() {
+      self.clone_from(overlay);
+    };
+  }
+}
+
+/// A configuration container reading several files (such as system-wide and per-user configuration),
+/// and merging them on top of a default value with [`Merge`], each overriding the last.
+///
+/// Files that do not exist are skipped. Only the last file, which has the highest precedence, is writable:
+/// it is modified through [`writable_layer_mut`] and written with [`commit`], so that values from the other layers
+/// are never copied into it. The writable file is only created once it is committed to.
+///
+/// The files are not held open, every [`refresh`] reads them anew.
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use singlefile::container_layered::{ContainerMerged, Merge};
+/// use serde::{Serialize, Deserialize};
+///
+/// #[derive(Clone, Default, Serialize, Deserialize)]
+/// struct Settings { port: Option<u16>, verbose: Option<bool> }
+///
+/// impl Merge for Settings {
+///   fn merge(&mut self, overlay: &Self) {
+///     self.port.merge(&overlay.port);
+///     self.verbose.merge(&overlay.verbose);
+///   }
+/// }
+///
+/// let defaults = Settings { port: Some(80), verbose: Some(false) };
+/// let layers = ["/etc/app/settings.json", "settings.json"];
+/// let mut settings = ContainerMerged::<Settings, Json>::open(layers, Json, defaults)?;
+/// println!("port: {:?}", settings.port);
+///
+/// // Only `verbose` is written to `settings.json`
+/// settings.writable_layer_mut().verbose = Some(true);
+/// settings.commit()?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+///
+/// [`writable_layer_mut`]: ContainerMerged::writable_layer_mut
+/// [`commit`]: ContainerMerged::commit
+/// [`refresh`]: ContainerMerged::refresh
+#[derive(Debug)]
+pub struct ContainerMerged<T, Format> {
+  value: T,
+  defaults: T,
+  layers: Vec<(PathBuf, Option<T>)>,
+  format: Format
+}
+
+impl<T, Format> ContainerMerged<T, Format>
+where T: Merge + Clone, Format: FileFormat<T> {
+  /// Opens a new [`ContainerMerged`], reading every file that exists out of the given paths,
+  /// which are ordered from lowest to highest precedence. The last path is the writable layer.
+  ///
+  /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if no paths are given.
+  pub fn open<I, P>(paths: I, format: Format, defaults: T) -> Result<Self, Error<Format::FormatError>>
+  where I: IntoIterator<Item = P>, P: AsRef<Path> {
+    let layers = paths.into_iter()
+      .map(|path| {
+        let path = path.as_ref().to_owned();
+        read_layer(&path, &format).map(|layer| (path, layer))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    if layers.is_empty() {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "no layers were given").into());
+    };
+
+    let value = merge_all(&defaults, &layers);
+    Ok(ContainerMerged { value, defaults, layers, format })
+  }
+
+  /// Reads every file again, replacing the current state in memory, and returning the previous state.
+  /// Any modifications to the writable layer that have not been committed are lost.
+  pub fn refresh(&mut self) -> Result<T, Error<Format::FormatError>> {
+    for (path, layer) in &mut self.layers {
+      *layer = read_layer(path, &self.format)?;
+    };
+
+    let value = merge_all(&self.defaults, &self.layers);
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Writes the writable layer to its file, creating it if it does not exist, and merges the layers again.
+  /// Nothing is written if the writable layer does not exist and has not been modified.
+  pub fn commit(&mut self) -> Result<(), Error<Format::FormatError>> {
+    if let Some((path, Some(layer))) = self.layers.last() {
+      crate::manager::mode::write_rename(&self.format, path, layer, true)?;
+    };
+
+    self.value = merge_all(&self.defaults, &self.layers);
+    Ok(())
+  }
+
+  /// Gets a mutable reference to the writable layer, which is the default value of `T` if its file does not exist.
+  ///
+  /// Modifications are only reflected in the merged value once they are committed.
+  pub fn writable_layer_mut(&mut self) -> &mut T
+  where T: Default {
+    let (_, layer) = self.layers.last_mut().expect("there is always at least one layer");
+    layer.get_or_insert_with(T::default)
+  }
+}
+
+impl<T, Format> ContainerMerged<T, Format> {
+  /// Gets the layer read from the file at the given index (in the order the paths were given),
+  /// or `None` if the index is out of bounds or the file did not exist.
+  #[inline]
+  pub fn layer(&self, index: usize) -> Option<&T> {
+    self.layers.get(index).and_then(|(_, layer)| layer.as_ref())
+  }
+
+  /// Gets the writable layer, or `None` if its file does not exist and it has not been modified.
+  #[inline]
+  pub fn writable_layer(&self) -> Option<&T> {
+    self.layers.last().and_then(|(_, layer)| layer.as_ref())
+  }
+
+  /// Returns an iterator over the paths of every layer, from lowest to highest precedence.
+  pub fn paths(&self) -> impl Iterator<Item = &Path> + '_ {
+    self.layers.iter().map(|(path, _)| path.as_path())
+  }
+
+  /// Gets the path of the writable layer.
+  #[inline]
+  pub fn writable_path(&self) -> &Path {
+    &self.layers[self.layers.len() - 1].0
+  }
+
+  /// Gets the default value that the layers are merged on top of.
+  #[inline]
+  pub const fn defaults(&self) -> &T {
+    &self.defaults
+  }
+
+  /// Gets a reference to the [`FileFormat`] used by this container.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets a reference to the merged value.
+  ///
+  /// You may also operate on the container directly with [`Deref`] instead.
+  #[inline]
+  pub const fn get(&self) -> &T {
+    &self.value
+  }
+
+  /// Extract the merged state.
+  #[inline]
+  pub fn into_value(self) -> T {
+    self.value
+  }
+}
+
+impl<T, Format> Deref for ContainerMerged<T, Format> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.value
+  }
+}
+
+fn read_layer<T, Format>(path: &Path, format: &Format) -> Result<Option<T>, Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  match File::open(path) {
+    Ok(file) => crate::manager::mode::read(format, &file).map(Some),
+    Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(err.into())
+  }
+}
+
+fn merge_all<T: Merge + Clone>(defaults: &T, layers: &[(PathBuf, Option<T>)]) -> T {
+  let mut merged = defaults.clone();
+  for layer in layers.iter().filter_map(|(_, layer)| layer.as_ref()) {
+    merged.merge(layer);
+  };
+
+  merged
+}
+
+
+
+/// The error type returned by [`ContainerLayered`], where a layer failing to convert to or from
+/// a [`serde_json::Value`] is a [`UserError::User`].
+#[cfg_attr(docsrs, doc(cfg(feature = "layered")))]
+#[cfg(feature = "layered")]
+pub type LayeredError<FE> = UserError<FE, serde_json::Error>;
+
+/// A configuration container merging three layers into the exposed value, each overriding the last:
+/// a default value, the file on disk, and environment variables.
+///
+/// The file only holds the values that have been changed from the defaults, and committing only writes
+/// the values that were modified through this container. Values overridden by environment variables are never
+/// written to the file unless they were modified, so temporary overrides do not leak into it.
+///
+/// Environment variables are named `<prefix>_<key>`, with nested keys separated by double underscores
+/// and matched case-insensitively (e.g. `APP_SERVER__PORT=8080` overrides `server.port` for the prefix `APP`).
+/// Their values are parsed as JSON, falling back to a plain string if they cannot be parsed
+/// or if the value they override is a string.
+///
+/// Values are merged by converting them into [`serde_json::Value`]s, so the format used must be able to
+/// store a [`serde_json::Value`], as all of the `serde` formats in `singlefile-formats` can.
+///
+/// This container is enabled with the `layered` cargo feature.
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// # use singlefile::container_layered::LayeredError;
+/// # fn main() -> Result<(), LayeredError<JsonError>> {
+/// use singlefile::container_layered::ContainerLayered;
+/// use serde::{Serialize, Deserialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Settings { port: u16, verbose: bool }
+///
+/// let defaults = Settings { port: 80, verbose: false };
+/// let mut settings = ContainerLayered::<Settings, Json>::open("settings.json", Json, defaults, Some("APP"))?;
+///
+/// // Only `verbose` is written to the file, `port` may still have been overridden by `APP_PORT`
+/// settings.verbose = true;
+/// settings.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "layered")))]
+#[cfg(feature = "layered")]
+#[derive(Debug)]
+pub struct ContainerLayered<T, Format> {
+  value: T,
+  exposed: Value,
+  defaults: Value,
+  file_layer: Value,
+  env_prefix: Option<String>,
+  manager: ManagerWritable<Format>
+}
+
+#[cfg(feature = "layered")]
+impl<T, Format> ContainerLayered<T, Format>
+where T: Serialize + DeserializeOwned, Format: FileFormat<Value> {
+  /// Opens a new [`ContainerLayered`], creating an empty file at the given path if it does not exist.
+  ///
+  /// If `env_prefix` is `None`, environment variables are not read.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format, defaults: T, env_prefix: Option<&str>) -> Result<Self, LayeredError<Format::FormatError>> {
+    let defaults = serde_json::to_value(defaults).map_err(UserError::User)?;
+    let (file_layer, manager) = FileManager::create_or(path, format, Value::Object(Map::new()))?;
+    let env_prefix = env_prefix.map(str::to_owned);
+    let exposed = merge_layers(&defaults, &file_layer, env_prefix.as_deref());
+    let value = serde_json::from_value(exposed.clone()).map_err(UserError::User)?;
+    Ok(ContainerLayered { value, exposed, defaults, file_layer, env_prefix, manager })
+  }
+
+  /// Reads the file and environment variables again, replacing the current state in memory,
+  /// and returning the previous state. Any modifications that have not been committed are lost.
+  pub fn refresh(&mut self) -> Result<T, LayeredError<Format::FormatError>> {
+    let file_layer: Value = self.manager.read()?;
+    let exposed = merge_layers(&self.defaults, &file_layer, self.env_prefix.as_deref());
+    let value = serde_json::from_value(exposed.clone()).map_err(UserError::User)?;
+    self.file_layer = file_layer;
+    self.exposed = exposed;
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Writes every value modified through this container since it was last opened, refreshed or committed to the file,
+  /// leaving values that were not modified as they were in the file.
+  pub fn commit(&mut self) -> Result<(), LayeredError<Format::FormatError>> {
+    let modified = serde_json::to_value(&self.value).map_err(UserError::User)?;
+    let mut file_layer = self.file_layer.clone();
+    update_layer(&mut file_layer, &self.exposed, &modified);
+    self.manager.write(&file_layer)?;
+    self.file_layer = file_layer;
+    self.exposed = modified;
+    Ok(())
+  }
+}
+
+#[cfg(feature = "layered")]
+impl<T, Format> ContainerLayered<T, Format> {
+  /// Gets the layer currently stored in the file, holding only the values that override the defaults.
+  #[inline]
+  pub const fn file_layer(&self) -> &Value {
+    &self.file_layer
+  }
+
+  /// Gets a reference to the contained file manager.
+  ///
+  /// It is inadvisable to manipulate the manager manually.
+  #[inline]
+  pub const fn manager(&self) -> &ManagerWritable<Format> {
+    &self.manager
+  }
+
+  /// Gets a reference to the contained value.
+  ///
+  /// You may also operate on the container directly with [`Deref`] instead.
+  #[inline]
+  pub const fn get(&self) -> &T {
+    &self.value
+  }
+
+  /// Gets a mutable reference to the contained value.
+  ///
+  /// You may also operate on the container directly with [`DerefMut`] instead.
+  #[inline]
+  pub fn get_mut(&mut self) -> &mut T {
+    &mut self.value
+  }
+
+  /// Closes this [`ContainerLayered`], returning the contained state.
+  pub fn close(self) -> io::Result<T> {
+    self.manager.close().map(|()| self.value)
+  }
+}
+
+#[cfg(feature = "layered")]
+impl<T, Format> Deref for ContainerLayered<T, Format> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.value
+  }
+}
+
+#[cfg(feature = "layered")]
+impl<T, Format> DerefMut for ContainerLayered<T, Format> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.value
+  }
+}
+
+#[cfg(feature = "layered")]
+fn merge_layers(defaults: &Value, file_layer: &Value, env_prefix: Option<&str>) -> Value {
+  let mut merged = defaults.clone();
+  merge(&mut merged, file_layer);
+  if let Some(prefix) = env_prefix {
+    let prefix = format!("{}_", prefix.to_ascii_uppercase());
+    for (key, raw) in std::env::vars_os() {
+      let (key, raw) = match (key.into_string(), raw.into_string()) {
+        (Ok(key), Ok(raw)) => (key, raw),
+        _ => continue
+      };
+
+      if let Some(key) = key.to_ascii_uppercase().strip_prefix(&prefix) {
+        let segments = key.split("__").map(str::to_ascii_lowercase).collect::<Vec<String>>();
+        set_env_override(&mut merged, &segments, raw);
+      };
+    };
+  };
+
+  merged
+}
+
+#[cfg(feature = "layered")]
+fn merge(base: &mut Value, overlay: &Value) {
+  match (base, overlay) {
+    (Value::Object(base), Value::Object(overlay)) => {
+      for (key, overlay) in overlay {
+        match base.get_mut(key) {
+          Some(base) => merge(base, overlay),
+          None => { base.insert(key.clone(), overlay.clone()); }
+        };
+      };
+    },
+    (base, overlay) => *base = overlay.clone()
+  };
+}
+
+#[cfg(feature = "layered")]
+fn set_env_override(value: &mut Value, segments: &[String], raw: String) {
+  let (segment, rest) = match segments.split_first() {
+    Some(split) => split,
+    None => {
+      *value = match value {
+        Value::String(_) => Value::String(raw),
+        _ => serde_json::from_str(&raw).unwrap_or(Value::String(raw))
+      };
+
+      return;
+    }
+  };
+
+  if !value.is_object() {
+    *value = Value::Object(Map::new());
+  };
+
+  if let Value::Object(map) = value {
+    // keys are matched case-insensitively, since environment variables are conventionally uppercase
+    let key = map.keys().find(|key| key.eq_ignore_ascii_case(segment))
+      .cloned().unwrap_or_else(|| segment.clone());
+    set_env_override(map.entry(key).or_insert(Value::Null), rest, raw);
+  };
+}
+
+/// Records every difference between `before` and `after` into `layer`.
+#[cfg(feature = "layered")]
+fn update_layer(layer: &mut Value, before: &Value, after: &Value) {
+  match (before, after) {
+    (Value::Object(before), Value::Object(after)) => {
+      if !layer.is_object() {
+        *layer = Value::Object(Map::new());
+      };
+
+      if let Value::Object(layer) = layer {
+        for (key, after) in after {
+          match before.get(key) {
+            Some(before) if before == after => (),
+            Some(before) => update_layer(layer.entry(key.clone()).or_insert(Value::Null), before, after),
+            None => { layer.insert(key.clone(), after.clone()); }
+          };
+        };
+
+        for key in before.keys().filter(|key| !after.contains_key(*key)) {
+          layer.remove(key);
+        };
+      };
+    },
+    (before, after) => if before != after {
+      *layer = after.clone();
+    }
+  };
+}
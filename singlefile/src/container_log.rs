@@ -0,0 +1,344 @@
+//! Container constructs for durable, append-only logs of records.
+//!
+//! A [`ContainerLog`] never holds its records in memory. Each call to [`ContainerLog::append`] serializes a single
+//! record with a [`FileFormat`] and appends it to the end of the file, prefixed by its length, so appending stays
+//! cheap no matter how long the log grows. Records are read back lazily with [`ContainerLog::iter`],
+//! and the log can be rewritten without the records that are no longer needed with [`ContainerLog::compact`].
+//!
+//! Since every record is framed by its length, any [`FileFormat`] can be used, including binary ones.
+//! A record left incomplete by an interrupted append is discarded when the log is next opened.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, RegularJson};
+//! use singlefile::container_log::ContainerLog;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Visit { page: String, millis: u64 }
+//!
+//! let mut visits = ContainerLog::<Visit, RegularJson>::open("visits.log", Json::<false>)?;
+//! visits.append(&Visit { page: "/".to_owned(), millis: 12 })?;
+//! for visit in visits.iter() {
+//!   println!("{}", visit?.page);
+//! }
+//!
+//! // keep only the slow visits
+//! visits.compact(|visit| visit.millis >= 100)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`FileFormat`]: crate::manager::format::FileFormat
+
+use crate::error::Error;
+use crate::manager::format::FileFormat;
+use crate::manager::lock::{ExclusiveLock, FileLock};
+use crate::manager::mode::sync_dir;
+
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+const LOG_MAGIC: &[u8; 8] = b"sflog\0\0\0";
+
+/// A log file that records of type `T` can only be appended to, read back in order, or compacted.
+/// See the [module-level documentation][self] for more information.
+///
+/// The log file is exclusively locked for as long as the container is open.
+#[derive(Debug)]
+pub struct ContainerLog<T, Format> {
+  format: Format,
+  file: File,
+  path: PathBuf,
+  len: u64,
+  end: u64,
+  phantom: PhantomData<fn(T) -> T>
+}
+
+impl<T, Format> ContainerLog<T, Format>
+where Format: FileFormat<T> {
+  /// Opens a new [`ContainerLog`], creating an empty log at the given path if it does not exist.
+  ///
+  /// Every record is scanned (but not deserialized) to count them, and an incomplete record at the end of the log is removed.
+  /// Returns an error if the file is not a log.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>> {
+    let path = path.as_ref().to_owned();
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    ExclusiveLock::lock(&file)?;
+    let (len, end) = scan_log(&file)?;
+    Ok(ContainerLog { format, file, path, len, end, phantom: PhantomData })
+  }
+
+  /// Serializes a record and appends it to the end of the log, syncing it to disk before returning.
+  pub fn append(&mut self, record: &T) -> Result<(), Error<Format::FormatError>> {
+    self.extend(std::iter::once(record))
+  }
+
+  /// Serializes every record and appends them to the end of the log, syncing them to disk once before returning.
+  ///
+  /// If any record fails to serialize, or the records cannot be written, none of them are appended.
+  pub fn extend<'r, I>(&mut self, records: I) -> Result<(), Error<Format::FormatError>>
+  where I: IntoIterator<Item = &'r T>, T: 'r {
+    let mut buf = Vec::new();
+    let mut count = 0;
+    for record in records {
+      write_frame(&mut buf, &self.format, record)?;
+      count += 1;
+    };
+
+    if count == 0 {
+      return Ok(());
+    };
+
+    // records are written at the known end of the log, and cut off again if writing them fails,
+    // so that a partially written record can never shift the frames of the records appended after it
+    let result = self.file.seek(SeekFrom::Start(self.end))
+      .and_then(|_| self.file.write_all(&buf))
+      .and_then(|()| self.file.sync_all());
+    if let Err(err) = result {
+      let _ = self.file.set_len(self.end);
+      return Err(err.into());
+    };
+
+    self.len += count;
+    self.end += buf.len() as u64;
+    Ok(())
+  }
+
+  /// Returns an iterator that reads and deserializes the records in the log one at a time, from oldest to newest.
+  ///
+  /// The log is read through a buffer, so only one record is held in memory at a time.
+  pub fn iter(&self) -> Iter<'_, T, Format> {
+    Iter {
+      format: &self.format,
+      reader: None,
+      file: &self.file,
+      remaining: self.len,
+      phantom: PhantomData
+    }
+  }
+
+  /// Rewrites the log, keeping only the records for which `keep` returns `true`, and returns the number of records removed.
+  ///
+  /// The kept records are copied as-is to a temporary file, which then replaces the log, so an interrupted compaction
+  /// leaves the log untouched. If any record fails to deserialize, an error is returned and the log is left as it was.
+  pub fn compact<F>(&mut self, mut keep: F) -> Result<u64, Error<Format::FormatError>>
+  where F: FnMut(&T) -> bool {
+    let temp_path = crate::utils::temp_path(&self.path);
+    let result = self.compact_into(&temp_path, &mut keep);
+    if result.is_err() {
+      // the temporary file is useless if it was not moved into place, and it may not exist at all
+      let _ = fs::remove_file(&temp_path);
+    };
+
+    result
+  }
+
+  fn compact_into(&mut self, temp_path: &Path, keep: &mut dyn FnMut(&T) -> bool) -> Result<u64, Error<Format::FormatError>> {
+    let temp_file = OpenOptions::new().read(true).write(true).create_new(true).open(temp_path)?;
+    ExclusiveLock::lock(&temp_file)?;
+    let mut writer = BufWriter::new(&temp_file);
+    writer.write_all(LOG_MAGIC)?;
+
+    let mut reader = log_reader(&self.file)?;
+    let mut buf = Vec::new();
+    let mut kept = 0;
+    let mut end = LOG_MAGIC.len() as u64;
+    for _ in 0..self.len {
+      read_frame(&mut reader, &mut buf)?;
+      let record = self.format.from_buffer(&buf).map_err(Error::Format)?;
+      if keep(&record) {
+        writer.write_all(&(buf.len() as u64).to_le_bytes())?;
+        writer.write_all(&buf)?;
+        kept += 1;
+        end += 8 + buf.len() as u64;
+      };
+    };
+
+    writer.flush()?;
+    drop(writer);
+    temp_file.sync_all()?;
+    fs::rename(temp_path, &self.path)?;
+    sync_dir(&self.path)?;
+
+    // the new log was locked before anything was written to it, so it never appears unlocked at this path
+    let removed = self.len - kept;
+    let old_file = std::mem::replace(&mut self.file, temp_file);
+    let _ = ExclusiveLock::unlock(&old_file);
+    self.len = kept;
+    self.end = end;
+    Ok(removed)
+  }
+}
+
+impl<T, Format> ContainerLog<T, Format> {
+  /// Gets the number of records in the log.
+  #[inline]
+  pub const fn len(&self) -> u64 {
+    self.len
+  }
+
+  /// Returns `true` if the log contains no records.
+  #[inline]
+  pub const fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Gets a reference to the format used by this container.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets the path of the log file.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Unlocks and closes this [`ContainerLog`].
+  pub fn close(self) -> io::Result<()> {
+    ExclusiveLock::unlock(&self.file)?;
+    self.file.sync_all()
+  }
+}
+
+impl<'a, T, Format> IntoIterator for &'a ContainerLog<T, Format>
+where Format: FileFormat<T> {
+  type Item = Result<T, Error<Format::FormatError>>;
+  type IntoIter = Iter<'a, T, Format>;
+
+  #[inline]
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+/// An iterator over the records of a [`ContainerLog`], deserializing them as they are read.
+///
+/// After an error has been yielded, the iterator is exhausted.
+/// This structure is created by [`ContainerLog::iter`].
+#[derive(Debug)]
+pub struct Iter<'a, T, Format> {
+  format: &'a Format,
+  reader: Option<BufReader<&'a File>>,
+  file: &'a File,
+  remaining: u64,
+  phantom: PhantomData<fn() -> T>
+}
+
+impl<'a, T, Format> Iter<'a, T, Format>
+where Format: FileFormat<T> {
+  fn read_next(&mut self) -> Result<T, Error<Format::FormatError>> {
+    let reader = match &mut self.reader {
+      Some(reader) => reader,
+      // the log is read through the locked handle, since locks on windows prevent it from being opened again
+      None => self.reader.insert(log_reader(self.file)?)
+    };
+
+    let mut buf = Vec::new();
+    read_frame(reader, &mut buf)?;
+    self.format.from_buffer(&buf).map_err(Error::Format)
+  }
+}
+
+impl<'a, T, Format> Iterator for Iter<'a, T, Format>
+where Format: FileFormat<T> {
+  type Item = Result<T, Error<Format::FormatError>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.remaining == 0 {
+      return None;
+    };
+
+    let result = self.read_next();
+    self.remaining = if result.is_ok() { self.remaining - 1 } else { 0 };
+    Some(result)
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let remaining = self.remaining.try_into().unwrap_or(usize::MAX);
+    (remaining, Some(remaining))
+  }
+}
+
+fn write_frame<T, Format>(buf: &mut Vec<u8>, format: &Format, record: &T) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let start = buf.len();
+  buf.extend_from_slice(&[0; 8]);
+  format.to_writer(&mut *buf, record).map_err(Error::Format)?;
+  let len = (buf.len() - start - 8) as u64;
+  buf[start..start + 8].copy_from_slice(&len.to_le_bytes());
+  Ok(())
+}
+
+fn read_frame<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+  let mut len = [0; 8];
+  reader.read_exact(&mut len)?;
+  let len = u64::from_le_bytes(len);
+  buf.clear();
+  reader.take(len).read_to_end(buf)?;
+  if (buf.len() as u64) < len {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  };
+
+  Ok(())
+}
+
+/// Returns a reader positioned at the first record of the log.
+fn log_reader(file: &File) -> io::Result<BufReader<&File>> {
+  let mut reader = BufReader::new(file);
+  reader.seek(SeekFrom::Start(LOG_MAGIC.len() as u64))?;
+  Ok(reader)
+}
+
+/// Counts the complete records in the log and finds where they end, writing the header to an empty log.
+/// An incomplete record at the end of the log is removed from the file.
+fn scan_log(mut file: &File) -> io::Result<(u64, u64)> {
+  let size = file.metadata()?.len();
+  let mut header = [0; 8];
+  file.seek(SeekFrom::Start(0))?;
+  let header_len = file.read(&mut header)?;
+  if size < LOG_MAGIC.len() as u64 && header[..header_len] == LOG_MAGIC[..header_len] {
+    // the log is empty, or was interrupted while writing its header
+    file.set_len(0)?;
+    file.write_all(LOG_MAGIC)?;
+    file.sync_all()?;
+    return Ok((0, LOG_MAGIC.len() as u64));
+  };
+
+  file.seek(SeekFrom::Start(0))?;
+  file.read_exact(&mut header)?;
+  if &header != LOG_MAGIC {
+    return Err(io::Error::new(io::ErrorKind::InvalidData, "missing log header"));
+  };
+
+  let mut position = LOG_MAGIC.len() as u64;
+  let mut count = 0;
+  let mut len = [0; 8];
+  loop {
+    let remaining = size - position;
+    if remaining < 8 {
+      break;
+    };
+
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    if remaining - 8 < len {
+      break;
+    };
+
+    position += 8 + len;
+    count += 1;
+  };
+
+  if position < size {
+    file.set_len(position)?;
+    file.sync_all()?;
+  };
+
+  Ok((count, position))
+}
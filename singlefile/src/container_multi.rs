@@ -0,0 +1,313 @@
+//! Container constructs for storing several independently-typed sections in a single file.
+//!
+//! Sections are stored as a tuple, each element being serialized separately with the same [`FileFormat`].
+//! The file begins with a small manifest describing where each section lives, followed by the sections themselves.
+//! Since a [`ContainerMulti`] is just a [`Container`] holding a tuple, each section can be accessed
+//! directly through its tuple field (e.g. `container.0`, `container.1`).
+//!
+//! Each section is given some spare capacity when the whole file is written, so that a single section
+//! can later be committed on its own with [`Container::commit_section`], rewriting it in place as long as it still fits.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::container_multi::{ContainerMulti, MultiFormat};
+//!
+//! let mut container = ContainerMulti::<(Vec<String>, u32), Json>::create_or_default("state.bin", MultiFormat(Json))?;
+//! container.0.push("hello".to_owned());
+//! container.1 += 1;
+//! container.commit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::container::Container;
+use crate::error::Error;
+use crate::manager::*;
+
+use thiserror::Error;
+
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// Type alias to a container that stores multiple sections in a single readable and writable file.
+pub type ContainerMulti<S, Format> = Container<S, ManagerWritable<MultiFormat<Format>>>;
+/// Type alias to a container that stores multiple sections in a single readable and writable file,
+/// and has an exclusive file lock.
+pub type ContainerMultiLocked<S, Format> = Container<S, ManagerWritableLocked<MultiFormat<Format>>>;
+
+const MAGIC: [u8; 8] = *b"sfmulti\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+const ENTRY_LEN: usize = 24;
+const SLACK_MIN: u64 = 16;
+
+/// A [`FileFormat`] that stores a tuple of sections in a single file,
+/// serializing each section individually with the contained format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MultiFormat<Format>(pub Format);
+
+/// An error that can occur while using [`MultiFormat`].
+#[derive(Debug, Error)]
+pub enum MultiFormatError<FE> {
+  /// An error caused by the inner format while handling a given section.
+  #[error("format error in section {0}: {1}")]
+  Section(usize, FE),
+  /// The manifest at the start of the file is missing or malformed.
+  #[error("invalid section manifest: {0}")]
+  Manifest(&'static str),
+  /// An error caused by the reader or writer.
+  #[error(transparent)]
+  Io(#[from] io::Error)
+}
+
+/// Describes a tuple of values that can be stored as sections by a [`MultiFormat`].
+///
+/// This is implemented for tuples of up to 8 elements, as long as the format
+/// produces the same error type for every element.
+pub trait Sections<Format>: Sized {
+  /// The error type produced by `Format` for each of the sections.
+  type FormatError;
+  /// The number of sections in this tuple.
+  const COUNT: usize;
+
+  /// Serializes the section at the given index into a buffer.
+  ///
+  /// # Panics
+  /// Panics if `index` is not less than [`Sections::COUNT`].
+  fn section_to_buffer(&self, format: &Format, index: usize) -> Result<Vec<u8>, Self::FormatError>;
+
+  /// Deserializes every section from its buffer, returning the index of the section that failed if any.
+  ///
+  /// `buffers` must contain exactly [`Sections::COUNT`] elements.
+  fn from_section_buffers(format: &Format, buffers: &[&[u8]]) -> Result<Self, (usize, Self::FormatError)>;
+}
+
+macro_rules! impl_sections {
+  ($count:literal; $First:ident $first:tt $(, $Type:ident $index:tt)*) => (
+    impl<Format, $First $(, $Type)*> Sections<Format> for ($First, $($Type,)*)
+    where Format: FileFormat<$First> $(+ FileFormat<$Type, FormatError = <Format as FileFormat<$First>>::FormatError>)* {
+      type FormatError = <Format as FileFormat<$First>>::FormatError;
+      const COUNT: usize = $count;
+
+      fn section_to_buffer(&self, format: &Format, index: usize) -> Result<Vec<u8>, Self::FormatError> {
+        match index {
+          $first => FileFormat::<$First>::to_buffer(format, &self.$first),
+          $($index => FileFormat::<$Type>::to_buffer(format, &self.$index),)*
+          _ => panic!("section index {index} out of bounds for {} sections", $count)
+        }
+      }
+
+      fn from_section_buffers(format: &Format, buffers: &[&[u8]]) -> Result<Self, (usize, Self::FormatError)> {
+        Ok((
+          FileFormat::<$First>::from_buffer(format, buffers[$first]).map_err(|err| ($first, err))?,
+          $(FileFormat::<$Type>::from_buffer(format, buffers[$index]).map_err(|err| ($index, err))?,)*
+        ))
+      }
+    }
+  );
+}
+
+impl_sections!(1; A 0);
+impl_sections!(2; A 0, B 1);
+impl_sections!(3; A 0, B 1, C 2);
+impl_sections!(4; A 0, B 1, C 2, D 3);
+impl_sections!(5; A 0, B 1, C 2, D 3, E 4);
+impl_sections!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+impl_sections!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_sections!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+impl<S, Format> FileFormat<S> for MultiFormat<Format>
+where S: Sections<Format>, S::FormatError: std::error::Error {
+  type FormatError = MultiFormatError<S::FormatError>;
+
+  fn from_reader<R: Read>(&self, mut reader: R) -> Result<S, Self::FormatError> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    self.from_buffer(&buf)
+  }
+
+  #[inline]
+  fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<S, Self::FormatError> {
+    self.from_reader(reader)
+  }
+
+  fn from_buffer(&self, buf: &[u8]) -> Result<S, Self::FormatError> {
+    let manifest = Manifest::parse(buf, S::COUNT, buf.len() as u64)?;
+    let buffers = manifest.entries.iter()
+      .map(|entry| &buf[entry.offset as usize..][..entry.length as usize])
+      .collect::<Vec<&[u8]>>();
+    S::from_section_buffers(&self.0, &buffers)
+      .map_err(|(index, err)| MultiFormatError::Section(index, err))
+  }
+
+  fn to_writer<W: Write>(&self, mut writer: W, value: &S) -> Result<(), Self::FormatError> {
+    let buf = self.to_buffer(value)?;
+    writer.write_all(&buf).map_err(From::from)
+  }
+
+  #[inline]
+  fn to_writer_buffered<W: Write>(&self, writer: W, value: &S) -> Result<(), Self::FormatError> {
+    self.to_writer(writer, value)
+  }
+
+  fn to_buffer(&self, value: &S) -> Result<Vec<u8>, Self::FormatError> {
+    let sections = (0..S::COUNT)
+      .map(|index| value.section_to_buffer(&self.0, index).map_err(|err| MultiFormatError::Section(index, err)))
+      .collect::<Result<Vec<Vec<u8>>, _>>()?;
+    let manifest = Manifest::new(sections.iter().map(|section| section.len() as u64));
+    let mut buf = manifest.to_bytes();
+    for (section, entry) in sections.into_iter().zip(manifest.entries.iter()) {
+      buf.extend_from_slice(&section);
+      buf.resize(buf.len() + (entry.capacity - entry.length) as usize, 0);
+    };
+
+    Ok(buf)
+  }
+}
+
+impl<Format, Lock> FileManager<MultiFormat<Format>, Lock, Writable> {
+  /// Writes a single section of the given value to the file in place, leaving the other sections untouched.
+  ///
+  /// Returns `false` without writing anything if the section no longer fits in the space reserved for it,
+  /// or if the file does not contain a valid manifest, in which case the whole value must be written instead.
+  ///
+  /// # Panics
+  /// Panics if `index` is not less than [`Sections::COUNT`].
+  pub fn write_section<S>(&self, value: &S, index: usize) -> Result<bool, Error<MultiFormatError<S::FormatError>>>
+  where S: Sections<Format>, S::FormatError: std::error::Error {
+    let buf = value.section_to_buffer(&self.format().0, index)
+      .map_err(|err| Error::Format(MultiFormatError::Section(index, err)))?;
+
+    let mut file = self.file();
+    let mut table = vec![0; HEADER_LEN + S::COUNT * ENTRY_LEN];
+    file.seek(SeekFrom::Start(0))?;
+    let manifest = match file.read_exact(&mut table) {
+      Ok(()) => Manifest::parse::<S::FormatError>(&table, S::COUNT, file.metadata()?.len()).ok(),
+      Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+      Err(err) => return Err(err.into())
+    };
+
+    let entry = match manifest {
+      Some(manifest) if buf.len() as u64 <= manifest.entries[index].capacity => manifest.entries[index],
+      _ => {
+        file.seek(SeekFrom::Start(0))?;
+        return Ok(false);
+      }
+    };
+
+    file.seek(SeekFrom::Start(entry.offset))?;
+    file.write_all(&buf)?;
+    file.seek(SeekFrom::Start((HEADER_LEN + index * ENTRY_LEN + 16) as u64))?;
+    file.write_all(&(buf.len() as u64).to_le_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    file.sync_all()?;
+    Ok(true)
+  }
+}
+
+impl<S, Format, Lock> Container<S, FileManager<MultiFormat<Format>, Lock, Writable>>
+where S: Sections<Format>, S::FormatError: std::error::Error {
+  /// Writes only the section at the given index to the managed file.
+  ///
+  /// The section is rewritten in place if it fits within the space reserved for it,
+  /// otherwise this falls back to writing the entire value. Either way, the commit hooks
+  /// registered with [`Container::on_before_commit`] and [`Container::on_after_commit`] are invoked once.
+  ///
+  /// # Panics
+  /// Panics if `index` is not less than [`Sections::COUNT`].
+  pub fn commit_section(&self, index: usize) -> Result<(), Error<MultiFormatError<S::FormatError>>> {
+    self.hooks.before(&self.value);
+    if self.manager.write_section(&self.value, index)? {
+      // other sections may still hold changes that have not been written
+      self.stats.record_write();
+    } else {
+      self.manager.write(&self.value)?;
+      self.stats.record_commit();
+    };
+
+    self.stats.record_stamp(&self.manager);
+    self.hooks.after(&self.value);
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ManifestEntry {
+  offset: u64,
+  capacity: u64,
+  length: u64
+}
+
+/// The table at the start of a multi-section file, describing where each section is located.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+  entries: Vec<ManifestEntry>
+}
+
+impl Manifest {
+  /// Lays out sections of the given lengths back to back, reserving an extra quarter
+  /// of each section's length so that it may grow without the whole file being rewritten.
+  fn new(lengths: impl ExactSizeIterator<Item = u64>) -> Self {
+    let mut offset = (HEADER_LEN + lengths.len() * ENTRY_LEN) as u64;
+    let entries = lengths.map(|length| {
+      let capacity = length + length / 4 + SLACK_MIN;
+      let entry = ManifestEntry { offset, capacity, length };
+      offset += capacity;
+      entry
+    }).collect();
+    Manifest { entries }
+  }
+
+  /// Parses the manifest at the start of `buf`, checking that every section lies within `file_len` bytes.
+  fn parse<FE>(buf: &[u8], count: usize, file_len: u64) -> Result<Self, MultiFormatError<FE>> {
+    if buf.len() < HEADER_LEN || buf[..8] != MAGIC {
+      return Err(MultiFormatError::Manifest("missing header"));
+    };
+
+    if read_u32(&buf[8..]) != VERSION {
+      return Err(MultiFormatError::Manifest("unsupported version"));
+    };
+
+    if read_u32(&buf[12..]) as usize != count {
+      return Err(MultiFormatError::Manifest("section count mismatch"));
+    };
+
+    let table = buf.get(HEADER_LEN..HEADER_LEN + count * ENTRY_LEN)
+      .ok_or(MultiFormatError::Manifest("truncated section table"))?;
+    let entries = table.chunks_exact(ENTRY_LEN).map(|entry| ManifestEntry {
+      offset: read_u64(&entry[0..]),
+      capacity: read_u64(&entry[8..]),
+      length: read_u64(&entry[16..])
+    }).collect::<Vec<ManifestEntry>>();
+
+    for entry in entries.iter() {
+      let end = entry.offset.checked_add(entry.capacity);
+      if entry.length > entry.capacity || end.map_or(true, |end| end > file_len) {
+        return Err(MultiFormatError::Manifest("section out of bounds"));
+      };
+    };
+
+    Ok(Manifest { entries })
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + self.entries.len() * ENTRY_LEN);
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+    for entry in self.entries.iter() {
+      buf.extend_from_slice(&entry.offset.to_le_bytes());
+      buf.extend_from_slice(&entry.capacity.to_le_bytes());
+      buf.extend_from_slice(&entry.length.to_le_bytes());
+    };
+
+    buf
+  }
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+  u32::from_le_bytes(buf[..4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8]) -> u64 {
+  u64::from_le_bytes(buf[..8].try_into().unwrap())
+}
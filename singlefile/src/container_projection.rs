@@ -0,0 +1,215 @@
+//! Container constructs operating on a single part of a larger document.
+//!
+//! A [`PartialContainer`] only deserializes the value found at a [JSON pointer] within its file into a `T`,
+//! so that small parts of a large state file can be read and modified without defining (or deserializing into)
+//! a type for the whole document. Committing writes the modified part back into the document,
+//! leaving everything around it as it is in the file.
+//!
+//! The document is read into a [`serde_json::Value`] and dropped as soon as the part has been taken out of it,
+//! so the format used must be able to store a [`serde_json::Value`], as all of the `serde` formats in
+//! `singlefile-formats` can. Since the whole document is still parsed, this saves the cost of building
+//! a typed value for the parts that are not needed, and of holding the document in memory, but not of parsing it.
+//!
+//! This module can be enabled with the `projection` cargo feature.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! # use singlefile::container_projection::ProjectionError;
+//! # fn main() -> Result<(), ProjectionError<JsonError>> {
+//! use singlefile::container_projection::PartialContainer;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Window { width: u32, height: u32 }
+//!
+//! // only `ui.window` is deserialized, the rest of `state.json` is left untouched
+//! let mut window = PartialContainer::<Window, Json>::open("state.json", Json, "/ui/window")?;
+//! window.width = 1280;
+//! window.commit()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [JSON pointer]: https://datatracker.ietf.org/doc/html/rfc6901
+
+use crate::error::UserError;
+use crate::manager::format::FileFormat;
+use crate::manager::{FileManager, ManagerWritable};
+
+use serde::{Serialize, de::DeserializeOwned};
+use serde::de::Error as _;
+use serde_json::{Map, Value};
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// The error type returned by [`PartialContainer`], where the part failing to convert to or from
+/// a [`serde_json::Value`], or the pointer not being usable with the document, is a [`UserError::User`].
+pub type ProjectionError<FE> = UserError<FE, serde_json::Error>;
+
+/// A container holding the value found at a [JSON pointer] within its file, such as `/ui/window`.
+/// See the [module-level documentation][self] for more information.
+///
+/// The pointer `""` refers to the whole document. Each token of the pointer either names a key in an object,
+/// or an index in an array, with `~1` and `~0` escaping `/` and `~` respectively.
+///
+/// [JSON pointer]: https://datatracker.ietf.org/doc/html/rfc6901
+#[derive(Debug)]
+pub struct PartialContainer<T, Format> {
+  value: T,
+  pointer: String,
+  manager: ManagerWritable<Format>
+}
+
+impl<T, Format> PartialContainer<T, Format>
+where T: Serialize + DeserializeOwned, Format: FileFormat<Value> {
+  /// Opens a new [`PartialContainer`], reading the value at the given pointer from the file at the given path.
+  ///
+  /// Returns an error if the file does not exist, or if nothing is found at the pointer.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format, pointer: &str) -> Result<Self, ProjectionError<Format::FormatError>> {
+    let pointer = validate_pointer(pointer)?;
+    let manager = FileManager::open(path, format)?;
+    let value = read_part(&manager, &pointer)?;
+    Ok(PartialContainer { value, pointer, manager })
+  }
+
+  /// Opens a new [`PartialContainer`] like [`PartialContainer::open`], but if the file does not exist it is created
+  /// holding an empty object, and if nothing is found at the pointer, the default value of `T` is used.
+  ///
+  /// The default value is not written until the next commit.
+  pub fn open_or_default<P: AsRef<Path>>(path: P, format: Format, pointer: &str) -> Result<Self, ProjectionError<Format::FormatError>>
+  where T: Default {
+    let pointer = validate_pointer(pointer)?;
+    let (mut document, manager) = FileManager::create_or(path, format, Value::Object(Map::new()))?;
+    let value = match document.pointer_mut(&pointer).map(Value::take) {
+      Some(part) => serde_json::from_value(part).map_err(UserError::User)?,
+      None => T::default()
+    };
+
+    Ok(PartialContainer { value, pointer, manager })
+  }
+
+  /// Reads the value at the pointer from the file again, replacing the current state in memory,
+  /// and returning the previous state.
+  pub fn refresh(&mut self) -> Result<T, ProjectionError<Format::FormatError>> {
+    let value = read_part(&self.manager, &self.pointer)?;
+    Ok(std::mem::replace(&mut self.value, value))
+  }
+
+  /// Writes the current state to the file at the pointer, leaving the rest of the document as it is currently in the file.
+  ///
+  /// Objects are created along the pointer for any keys that do not exist yet. Returns an error if the pointer
+  /// passes through a value that is not an object or an array, or refers to an index past the end of an array.
+  pub fn commit(&self) -> Result<(), ProjectionError<Format::FormatError>> {
+    let part = serde_json::to_value(&self.value).map_err(UserError::User)?;
+    let mut document: Value = self.manager.read()?;
+    *pointer_entry(&mut document, &self.pointer).map_err(UserError::User)? = part;
+    self.manager.write(&document)?;
+    Ok(())
+  }
+}
+
+impl<T, Format> PartialContainer<T, Format> {
+  /// Gets the pointer to the value held by this container.
+  #[inline]
+  pub fn pointer(&self) -> &str {
+    &self.pointer
+  }
+
+  /// Gets a reference to the contained file manager.
+  ///
+  /// It is inadvisable to manipulate the manager manually.
+  #[inline]
+  pub const fn manager(&self) -> &ManagerWritable<Format> {
+    &self.manager
+  }
+
+  /// Gets a reference to the contained value.
+  ///
+  /// You may also operate on the container directly with [`Deref`] instead.
+  #[inline]
+  pub const fn get(&self) -> &T {
+    &self.value
+  }
+
+  /// Gets a mutable reference to the contained value.
+  ///
+  /// You may also operate on the container directly with [`DerefMut`] instead.
+  #[inline]
+  pub fn get_mut(&mut self) -> &mut T {
+    &mut self.value
+  }
+
+  /// Closes this [`PartialContainer`], returning the contained state.
+  pub fn close(self) -> io::Result<T> {
+    self.manager.close().map(|()| self.value)
+  }
+}
+
+impl<T, Format> Deref for PartialContainer<T, Format> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.value
+  }
+}
+
+impl<T, Format> DerefMut for PartialContainer<T, Format> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.value
+  }
+}
+
+/// Reads the value at the given pointer from the file, without keeping the rest of the document or a container around.
+///
+/// Returns `None` if nothing is found at the pointer. See [`PartialContainer`] for the syntax of pointers.
+pub fn read_projection<T, P, Format>(path: P, format: &Format, pointer: &str) -> Result<Option<T>, ProjectionError<Format::FormatError>>
+where T: DeserializeOwned, P: AsRef<Path>, Format: FileFormat<Value> {
+  let pointer = validate_pointer(pointer)?;
+  let file = std::fs::File::open(path)?;
+  let mut document: Value = crate::manager::mode::read(format, &file)?;
+  match document.pointer_mut(&pointer).map(Value::take) {
+    Some(part) => serde_json::from_value(part).map(Some).map_err(UserError::User),
+    None => Ok(None)
+  }
+}
+
+fn read_part<T, Format>(manager: &ManagerWritable<Format>, pointer: &str) -> Result<T, ProjectionError<Format::FormatError>>
+where T: DeserializeOwned, Format: FileFormat<Value> {
+  let mut document: Value = manager.read()?;
+  let part = document.pointer_mut(pointer).map(Value::take)
+    .ok_or_else(|| UserError::User(serde_json::Error::custom(format!("no value found at pointer `{pointer}`"))))?;
+  serde_json::from_value(part).map_err(UserError::User)
+}
+
+fn validate_pointer<FE>(pointer: &str) -> Result<String, ProjectionError<FE>> {
+  match pointer.is_empty() || pointer.starts_with('/') {
+    true => Ok(pointer.to_owned()),
+    false => Err(UserError::User(serde_json::Error::custom(format!("pointer `{pointer}` does not start with `/`"))))
+  }
+}
+
+/// Gets a mutable reference to the value at the given pointer, inserting objects along the way for keys that do not exist.
+fn pointer_entry<'v>(document: &'v mut Value, pointer: &str) -> Result<&'v mut Value, serde_json::Error> {
+  let mut value = document;
+  // the first token is always empty, since the pointer starts with `/`
+  for token in pointer.split('/').skip(1) {
+    let token = token.replace("~1", "/").replace("~0", "~");
+    value = match value {
+      Value::Object(map) => map.entry(token).or_insert_with(|| Value::Object(Map::new())),
+      Value::Array(array) => {
+        let len = array.len();
+        match token.parse::<usize>() {
+          Ok(index) if index < len => &mut array[index],
+          _ => return Err(serde_json::Error::custom(format!("`{token}` is not an index within an array of length {len}")))
+        }
+      },
+      _ => return Err(serde_json::Error::custom(format!("`{token}` cannot be looked up in a value that is not an object or an array")))
+    };
+  };
+
+  Ok(value)
+}
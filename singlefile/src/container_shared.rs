@@ -2,25 +2,48 @@
 //!
 //! This module can be enabled with the `shared` cargo feature.
 
+mod autosave;
+mod cached;
+mod config;
 mod guards;
+mod refresher;
+mod writer;
 
 use crate::error::{Error, UserError};
 use crate::container::*;
 use crate::manager::lock::FileLock;
 use crate::manager::mode::FileMode;
 use crate::manager::*;
+use crate::slow::{self, Operation};
 
+pub use self::cached::ContainerCached;
+pub use self::config::{Config, ValidationError};
 pub use self::guards::{
   AccessGuard,
   AccessGuardMut,
+  MappedAccessGuard,
+  MappedAccessGuardMut,
   OwnedAccessGuard,
   OwnedAccessGuardMut
 };
+pub use crate::panic_policy::PanicPolicy;
+pub use self::refresher::AutoRefresh;
+pub use self::writer::{BackgroundWriter, WriteOrder};
 
-use parking_lot::RwLock;
+use self::autosave::Autosave;
+use crate::panic_policy::Panics;
+#[cfg(feature = "watch")]
+use crate::container_watcher::{ContainerWatcher, Resolution};
 
+use parking_lot::{Condvar, Mutex, RwLock};
+
+use std::convert::Infallible;
+use std::fs::File;
+use std::io;
+use std::mem;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
 
 /// Type alias to a shared, thread-safe container that is read-only.
 pub type ContainerSharedReadonly<T, Format> = ContainerShared<T, ManagerReadonly<Format>>;
@@ -37,13 +60,28 @@ pub type ContainerSharedWritableLocked<T, Format> = ContainerShared<T, ManagerWr
 /// See [`Atomic`] for more information.
 pub type ContainerSharedAtomicLocked<T, Format> = ContainerShared<T, ManagerAtomicLocked<Format>>;
 
+/// Type alias to a shared, thread-safe container that is not backed by any file.
+/// See [`ContainerMemoryOnly`] for more information.
+pub type ContainerSharedMemoryOnly<T> = ContainerShared<T, ()>;
+
+/// Type alias to a shared, thread-safe container that is read-only, and backed by static bytes instead of a file.
+/// See [`ContainerStatic`] for more information.
+pub type ContainerSharedStatic<T, Format> = ContainerShared<T, StaticManager<Format>>;
+
 /// A container that allows synchronous atomic reference-counted, mutable access (gated by an [`RwLock`]) to the
 /// underlying file and contents. Cloning this container will not clone the underlying contents, it will clone the
 /// underlying pointer, allowing multiple-access.
-#[repr(transparent)]
+///
+/// The underlying [`RwLock`] is eventually fair: once a thread is waiting for mutable access,
+/// new readers will queue up behind it, so a steady stream of readers cannot starve a writer.
+///
+/// What happens to the state when an operation panics is determined by the container's [`PanicPolicy`].
 #[derive(Debug)]
 pub struct ContainerShared<T, Manager> {
-  ptr: Arc<RwLock<Container<T, Manager>>>
+  ptr: Arc<RwLock<Container<T, Manager>>>,
+  changes: Arc<Changes>,
+  panics: Arc<Panics<T>>,
+  autosave: Arc<Mutex<Option<Autosave>>>
 }
 
 impl<T, Manager> ContainerShared<T, Manager> {
@@ -57,7 +95,7 @@ impl<T, Manager> ContainerShared<T, Manager> {
   pub fn try_unwrap(self) -> Result<Container<T, Manager>, Self> {
     match Arc::try_unwrap(self.ptr) {
       Ok(inner) => Ok(RwLock::into_inner(inner)),
-      Err(ptr) => Err(ContainerShared { ptr })
+      Err(ptr) => Err(ContainerShared { ptr, changes: self.changes, panics: self.panics, autosave: self.autosave })
     }
   }
 
@@ -69,25 +107,25 @@ impl<T, Manager> ContainerShared<T, Manager> {
   /// Gets immutable access to the underlying container and value `T`.
   #[inline]
   pub fn access(&self) -> AccessGuard<'_, T, Manager> {
-    AccessGuard::new(self.ptr.read())
+    AccessGuard::new(slow::measure(Operation::LockWait, None, || self.ptr.read()))
   }
 
   /// Gets mutable access to the underlying container and value `T`.
   #[inline]
   pub fn access_mut(&self) -> AccessGuardMut<'_, T, Manager> {
-    AccessGuardMut::new(self.ptr.write())
+    AccessGuardMut::new(slow::measure(Operation::LockWait, None, || self.ptr.write()))
   }
 
   /// Gets owned immutable access to the underlying container and value `T`.
   #[inline]
   pub fn access_owned(&self) -> OwnedAccessGuard<T, Manager> {
-    OwnedAccessGuard::new(self.ptr.read_arc())
+    OwnedAccessGuard::new(slow::measure(Operation::LockWait, None, || self.ptr.read_arc()))
   }
 
   /// Gets owned mutable access to the underlying container and value `T`.
   #[inline]
   pub fn access_owned_mut(&self) -> OwnedAccessGuardMut<T, Manager> {
-    OwnedAccessGuardMut::new(self.ptr.write_arc())
+    OwnedAccessGuardMut::new(slow::measure(Operation::LockWait, None, || self.ptr.write_arc()))
   }
 
   /// Tries to get immutable access to the underlying container and value `T` without blocking.
@@ -114,6 +152,92 @@ impl<T, Manager> ContainerShared<T, Manager> {
     self.ptr.try_write_arc().map(OwnedAccessGuardMut::new)
   }
 
+  /// Returns the number of successful commits (including overwrites) made through this container.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub fn commit_count(&self) -> u64 {
+    self.access().container().commit_count()
+  }
+
+  /// Returns the time of the last successful commit (or overwrite) made through this container, if any.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub fn last_commit_at(&self) -> Option<SystemTime> {
+    self.access().container().last_commit_at()
+  }
+
+  /// Returns the time of the last successful refresh made through this container, if any.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub fn last_refresh_at(&self) -> Option<SystemTime> {
+    self.access().container().last_refresh_at()
+  }
+
+  /// Blocks the current thread until the state is committed, overwritten or refreshed
+  /// through another handle to this container, or until the timeout elapses.
+  ///
+  /// Returns `true` if a change occurred, or `false` if the timeout elapsed first.
+  /// Only changes made through [`ContainerShared`]'s own methods are observed,
+  /// committing through an access guard or the underlying [`Container`] will not wake waiters.
+  ///
+  /// This function does not acquire any lock on the shared state.
+  pub fn wait_for_change(&self, timeout: Duration) -> bool {
+    self.changes.wait(timeout)
+  }
+
+  /// Returns a receiver that is sent a [`ChangeEvent`] whenever the state is committed, overwritten or refreshed.
+  ///
+  /// Like [`ContainerShared::wait_for_change`], only changes made through [`ContainerShared`]'s own methods are observed.
+  /// Events are buffered until they are received, and the receiver is forgotten once it has been dropped.
+  ///
+  /// This function does not acquire any lock on the shared state.
+  pub fn subscribe(&self) -> mpsc::Receiver<ChangeEvent> {
+    self.changes.subscribe()
+  }
+
+  /// Registers a callback to be invoked with the state right before every commit made through any handle to this container.
+  /// See [`Container::on_before_commit`].
+  ///
+  /// Callbacks are invoked while a lock is held on the shared state, so they must not attempt to access the container.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn on_before_commit<F>(&self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    AccessGuardMut::container_mut(&mut self.access_mut()).on_before_commit(callback);
+  }
+
+  /// Registers a callback to be invoked with the state after every successful commit made through any handle to this container.
+  /// See [`Container::on_after_commit`].
+  ///
+  /// Callbacks are invoked while a lock is held on the shared state, so they must not attempt to access the container.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn on_after_commit<F>(&self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    AccessGuardMut::container_mut(&mut self.access_mut()).on_after_commit(callback);
+  }
+
+  /// Returns the [`PanicPolicy`] of this container.
+  pub fn panic_policy(&self) -> PanicPolicy<T> {
+    self.panics.policy()
+  }
+
+  /// Sets the [`PanicPolicy`] of this container, affecting every handle to it.
+  pub fn set_panic_policy(&self, policy: PanicPolicy<T>) {
+    self.panics.set_policy(policy);
+  }
+
+  /// Returns `true` if an operation panicked under [`PanicPolicy::Poison`],
+  /// and the state has not been replaced or explicitly cleared since.
+  pub fn is_poisoned(&self) -> bool {
+    self.panics.is_poisoned()
+  }
+
+  /// Clears the poison of this container, allowing the current state to be committed again.
+  pub fn clear_poison(&self) {
+    self.panics.clear();
+  }
+
   /// Grants the caller immutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   ///
@@ -126,10 +250,12 @@ impl<T, Manager> ContainerShared<T, Manager> {
   /// Grants the caller mutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   ///
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the panic is propagated.
+  ///
   /// This function acquires a mutable lock on the shared state.
   pub fn operate_mut<F, R>(&self, operation: F) -> R
   where F: FnOnce(&mut T) -> R {
-    operation(&mut *self.access_mut())
+    self.panics.operate(&mut self.access_mut(), operation)
   }
 }
 
@@ -145,27 +271,58 @@ where
     Container::<T, _>::open(path, format).map(From::from)
   }
 
+  /// Opens a new [`ContainerShared`] from the first of the given paths at which a file exists, trying them in order.
+  /// See [`Container::open_first`] for more information.
+  pub fn open_first<I, P>(paths: I, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where I: IntoIterator<Item = P>, P: AsRef<Path>, Mode: Reading {
+    Container::<T, _>::open_first(paths, format).map(From::from)
+  }
+
+  /// Creates a new [`ContainerShared`] from a file handle that has already been opened, reading its value from the file.
+  /// See [`FileManager::from_file`] for more information.
+  pub fn from_file(file: File, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
+    Container::<T, _>::from_file(file, format).map(From::from)
+  }
+
   /// Opens a new [`ContainerShared`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
   pub fn create_overwrite<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
     Container::<T, _>::create_overwrite(path, format, value).map(From::from)
   }
 
   /// Opens a new [`ContainerShared`], writing the given value to the file if it does not exist.
-  pub fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
+  pub fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
     Container::<T, _>::create_or(path, format, value).map(From::from)
   }
 
   /// Opens a new [`ContainerShared`], writing the result of the given closure to the file if it does not exist.
   pub fn create_or_else<P: AsRef<Path>, C>(path: P, format: Format, closure: C) -> Result<Self, Error<Format::FormatError>>
-  where C: FnOnce() -> T {
+  where C: FnOnce() -> T, Mode: Reading {
     Container::<T, _>::create_or_else(path, format, closure).map(From::from)
   }
 
   /// Opens a new [`ContainerShared`], writing the default value of `T` to the file if it does not exist.
   pub fn create_or_default<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
-  where T: Default {
+  where T: Default, Mode: Reading {
     Container::<T, _>::create_or_default(path, format).map(From::from)
   }
+
+  /// Opens a new [`ContainerShared`] like [`ContainerShared::create_or_default`], first creating the parent directories
+  /// of the file if they do not exist.
+  pub fn create_or_default_with_dirs<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    Container::<T, _>::create_or_default_with_dirs(path, format).map(From::from)
+  }
+
+  /// Opens a new [`ContainerShared`] like [`ContainerShared::create_or_default`], for a file in the platform's
+  /// configuration directory for the given project. See [`Container::create_or_default_in`].
+  #[cfg_attr(docsrs, doc(cfg(feature = "paths")))]
+  #[cfg(feature = "paths")]
+  pub fn create_or_default_in<P: AsRef<Path>>(project: &crate::paths::Project<'_>, file: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    Container::<T, _>::create_or_default_in(project, file, format).map(From::from)
+  }
 }
 
 impl<T, Format, Lock, Mode> ContainerShared<T, FileManager<Format, Lock, Mode>>
@@ -181,6 +338,8 @@ where Format: FileFormat<T> {
   where Mode: Reading, F: FnOnce(&T, T) -> R {
     let mut guard = self.access_mut();
     let old_value = guard.container_mut().refresh()?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Refresh);
     let guard = AccessGuardMut::downgrade(guard);
     Ok(operation(&guard, old_value))
   }
@@ -189,15 +348,78 @@ where Format: FileFormat<T> {
   /// but only for the duration of the provided function or closure,
   /// immediately committing any changes made.
   ///
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the panic is propagated.
+  /// Fails with [`UserError::Poisoned`] without running the operation if the container is poisoned.
+  ///
   /// This function acquires a mutable lock on the shared state.
   pub fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
   where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
     let mut guard = self.access_mut();
-    let ret = operation(&mut guard).map_err(UserError::User)?;
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
     self.commit_guard(AccessGuardMut::downgrade(guard))?;
     Ok(ret)
   }
 
+  /// Grants the caller mutable access to a copy of the underlying value `T`,
+  /// but only for the duration of the provided function or closure,
+  /// replacing the state with that copy and committing it if no error was returned.
+  ///
+  /// If the operation panics or returns an error, or if the commit fails, the state is left unchanged,
+  /// so neither the file nor the state in memory can ever reflect a partially applied operation.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn operate_mut_commit_staged<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_mut();
+    self.panics.check()?;
+    let mut staged = T::clone(&guard);
+    let ret = operation(&mut staged).map_err(UserError::User)?;
+    let previous = mem::replace(&mut *guard, staged);
+    if let Err(err) = guard.commit() {
+      *guard = previous;
+      return Err(err.into());
+    };
+
+    self.changes.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
+  /// Runs a transaction on the state, identical to [`ContainerShared::operate_mut_commit_staged`].
+  /// See [`Container::transaction`].
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  #[inline]
+  pub fn transaction<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    self.operate_mut_commit_staged(operation)
+  }
+
+  /// Grants the caller mutable access to a copy of the underlying value `T` like [`ContainerShared::operate_mut_commit_staged`],
+  /// but only commits it if the managed file has not been changed by anyone else since it was last read or written
+  /// through this container. See [`Container::commit_if_unchanged`].
+  ///
+  /// Fails with [`UserError::Conflict`] if the file has been changed, either without running the operation,
+  /// or (if the file changed while it ran) leaving the state unchanged. The state should be refreshed before retrying.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn operate_mut_cas<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_mut();
+    self.panics.check()?;
+    guard.container().check_unchanged()?;
+    let mut staged = T::clone(&guard);
+    let ret = operation(&mut staged).map_err(UserError::User)?;
+    let previous = mem::replace(&mut *guard, staged);
+    if let Err(err) = guard.container().commit_if_unchanged() {
+      *guard = previous;
+      return Err(err.into());
+    };
+
+    self.changes.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
   /// Reads a value from the managed file, replacing the current state in memory.
   ///
   /// Returns the value of the previous state if the operation succeeded.
@@ -205,7 +427,26 @@ where Format: FileFormat<T> {
   /// This function acquires a mutable lock on the shared state.
   pub fn refresh(&self) -> Result<T, Error<Format::FormatError>>
   where Mode: Reading {
-    AccessGuardMut::container_mut(&mut self.access_mut()).refresh()
+    let old_value = AccessGuardMut::container_mut(&mut self.access_mut()).refresh()?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Refresh);
+    Ok(old_value)
+  }
+
+  /// Reads a value from the managed file like [`ContainerShared::refresh`], but only if the file has changed
+  /// since it was last read or written, returning `None` otherwise.
+  /// See [`Container::refresh_if_changed`] for more information.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn refresh_if_changed(&self) -> Result<Option<T>, Error<Format::FormatError>>
+  where Mode: Reading {
+    let old_value = AccessGuardMut::container_mut(&mut self.access_mut()).refresh_if_changed()?;
+    if old_value.is_some() {
+      self.panics.clear();
+      self.changes.notify(ChangeEvent::Refresh);
+    };
+
+    Ok(old_value)
   }
 
   /// Writes the current in-memory state to the managed file.
@@ -214,33 +455,395 @@ where Format: FileFormat<T> {
   /// Don't call this if you currently have an access guard, use [`ContainerShared::commit_guard`] instead.
   pub fn commit(&self) -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
-    AccessGuard::container(&self.access()).commit()
+    self.commit_guard(self.access())
   }
 
   /// Writes to the managed file given an access guard.
+  ///
+  /// Fails with [`Error::Poisoned`] if the container is poisoned.
   pub fn commit_guard(&self, guard: AccessGuard<'_, T, FileManager<Format, Lock, Mode>>)
   -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
-    AccessGuard::container(&guard).commit()
+    self.panics.check()?;
+    AccessGuard::container(&guard).commit()?;
+    self.changes.notify(ChangeEvent::Commit);
+    Ok(())
   }
 
   /// Writes the given state to the managed file, replacing the in-memory state.
   pub fn overwrite(&self, value: T) -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
-    AccessGuardMut::container_mut(&mut self.access_mut()).overwrite(value)
+    AccessGuardMut::container_mut(&mut self.access_mut()).overwrite(value)?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Overwrite);
+    Ok(())
+  }
+
+  /// Spawns a [`BackgroundWriter`] thread for this container, through which commits can be made without blocking on file I/O.
+  ///
+  /// `T` must implement [`Clone`] so that [`WriteOrder::Ordered`] can snapshot the state.
+  pub fn spawn_writer(&self, order: WriteOrder) -> io::Result<BackgroundWriter<T, Format, Lock, Mode>>
+  where
+    T: Clone + Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Writing + Send + Sync + 'static
+  {
+    BackgroundWriter::spawn(self.clone(), order)
+  }
+
+  /// Starts a background thread that commits the state whenever it is dirty, at most once per `interval`,
+  /// replacing any autosave that was already enabled for this container.
+  ///
+  /// The state is dirty after any mutable access to it, see [`Container::is_dirty`].
+  /// Failed commits are retried after the next interval, use [`ContainerShared::flush`] to observe errors.
+  /// When the last handle to this container is dropped, the state is committed one final time if it is dirty.
+  ///
+  /// The autosave thread holds its own handle to this container until autosave is disabled,
+  /// so [`ContainerShared::try_unwrap`] and [`ContainerShared::get_mut`] fail until then.
+  pub fn enable_autosave(&self, interval: Duration) -> io::Result<()>
+  where
+    T: Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Writing + Send + Sync + 'static
+  {
+    let handle = ContainerShared {
+      ptr: Arc::clone(&self.ptr),
+      changes: Arc::clone(&self.changes),
+      panics: Arc::clone(&self.panics),
+      autosave: Arc::new(Mutex::new(None))
+    };
+
+    let autosave = Autosave::spawn(handle, interval)?;
+    let previous = self.autosave.lock().replace(autosave);
+    if let Some(previous) = previous {
+      previous.disable();
+    };
+
+    Ok(())
+  }
+
+  /// Stops the autosave started by [`ContainerShared::enable_autosave`], if any,
+  /// blocking the current thread until its background thread has exited.
+  ///
+  /// The state is not committed, even if it is dirty.
+  pub fn disable_autosave(&self) {
+    let autosave = self.autosave.lock().take();
+    if let Some(autosave) = autosave {
+      autosave.disable();
+    };
+  }
+
+  /// Returns `true` if autosave has been enabled for this container with [`ContainerShared::enable_autosave`].
+  pub fn is_autosave_enabled(&self) -> bool {
+    self.autosave.lock().is_some()
+  }
+
+  /// Starts a background thread that checks the managed file once per `interval`, refreshing the state
+  /// whenever the file has changed on disk, until the returned [`AutoRefresh`] is dropped.
+  ///
+  /// This is a polling alternative to watching the file, suited to files edited by hand or by other processes.
+  /// Each refresh wakes the waiters of [`ContainerShared::wait_for_change`]. Refreshing replaces the in-memory state,
+  /// discarding any changes to it that have not been committed.
+  ///
+  /// The thread holds its own handle to this container until the returned [`AutoRefresh`] is dropped,
+  /// so [`ContainerShared::try_unwrap`] and [`ContainerShared::get_mut`] fail until then.
+  pub fn spawn_auto_refresh(&self, interval: Duration) -> io::Result<AutoRefresh<Format::FormatError>>
+  where
+    T: Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Reading + Send + Sync + 'static
+  {
+    AutoRefresh::spawn(self.clone(), interval)
+  }
+
+  /// Writes the current in-memory state to the managed file if it is dirty, see [`Container::commit_if_dirty`].
+  ///
+  /// Returns `true` if the state was dirty, and has been committed.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub fn flush(&self) -> Result<bool, Error<Format::FormatError>>
+  where Mode: Writing {
+    self.commit_dirty(AccessGuard::container(&self.access()))
+  }
+
+  fn commit_dirty(&self, container: &Container<T, FileManager<Format, Lock, Mode>>) -> Result<bool, Error<Format::FormatError>>
+  where Mode: Writing {
+    if !container.is_dirty() {
+      return Ok(false);
+    };
+
+    self.panics.check()?;
+    container.commit()?;
+    self.changes.notify(ChangeEvent::Commit);
+    Ok(true)
+  }
+
+  /// Writes the current in-memory state to a file at the given path, creating it if it does not exist
+  /// and overwriting its contents if it does, returning a new, independent [`ContainerShared`] that manages it.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub fn fork<P: AsRef<Path>>(&self, path: P) -> Result<Self, Error<Format::FormatError>>
+  where T: Clone, Format: Clone, Lock: FileLock, Mode: FileMode {
+    AccessGuard::container(&self.access()).fork(path).map(From::from)
+  }
+
+  /// Starts watching the managed file, refreshing this container whenever the file changes on disk,
+  /// until the returned [`ContainerWatcher`] is dropped.
+  ///
+  /// See [`ContainerWatcher`] for more information.
+  #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+  #[cfg(feature = "watch")]
+  pub fn watch(&self) -> io::Result<ContainerWatcher<T, Format, Lock, Mode>>
+  where
+    T: Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Reading + Send + Sync + 'static
+  {
+    ContainerWatcher::spawn(self.clone(), |_, _| Resolution::Refresh, |_| Ok(()))
+  }
+
+  /// Starts watching the managed file like [`ContainerShared::watch`], calling the provided closure
+  /// whenever the file changes on disk to decide what to do with its new contents.
+  ///
+  /// The provided closure takes (1) a reference to the current state, and (2) a reference to the contents of the file,
+  /// and is called while holding a mutable lock on the shared state.
+  #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+  #[cfg(feature = "watch")]
+  pub fn watch_with<R>(&self, resolve: R) -> io::Result<ContainerWatcher<T, Format, Lock, Mode>>
+  where
+    T: Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Reading + Writing + Send + Sync + 'static,
+    R: FnMut(&T, &T) -> Resolution + Send + 'static
+  {
+    ContainerWatcher::spawn(self.clone(), resolve, |container| container.commit())
+  }
+
+  /// Reads the managed file if it has changed since it was last read or written, handing its contents to `resolve`
+  /// and applying the returned [`Resolution`], apart from committing the state for [`Resolution::Overwrite`].
+  ///
+  /// Returns `None` if the file was unchanged.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  #[cfg(feature = "watch")]
+  pub(crate) fn resolve_external_change<R>(&self, resolve: R) -> Result<Option<Resolution>, Error<Format::FormatError>>
+  where Mode: Reading, R: FnOnce(&T, &T) -> Resolution {
+    let mut guard = self.access_mut();
+    let container = AccessGuardMut::container_mut(&mut guard);
+    if container.stats.is_stamp_current(&container.manager)? {
+      return Ok(None);
+    };
+
+    let value = container.manager.read()?;
+    let resolution = resolve(&container.value, &value);
+    container.stats.record_stamp(&container.manager);
+    if resolution == Resolution::Refresh {
+      container.value = value;
+      container.stats.record_refresh();
+      drop(guard);
+      self.panics.clear();
+      self.changes.notify(ChangeEvent::Refresh);
+    };
+
+    Ok(Some(resolution))
+  }
+}
+
+impl<T, Format> ContainerShared<T, StaticManager<Format>>
+where Format: FileFormat<T> {
+  /// Creates a new [`ContainerShared`] by deserializing a value from the given static bytes, such as those embedded with `include_bytes!`.
+  pub fn from_static(bytes: &'static [u8], format: Format) -> Result<Self, Error<Format::FormatError>> {
+    Container::<T, _>::from_static(bytes, format).map(From::from)
+  }
+
+  /// Deserializes the static bytes again, replacing the current state in memory,
+  /// immediately granting the caller immutable access to that state
+  /// for the duration of the provided function or closure.
+  ///
+  /// The provided closure takes (1) a reference to the new state, and (2) the old state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn operate_refresh<F, R>(&self, operation: F) -> Result<R, Error<Format::FormatError>>
+  where F: FnOnce(&T, T) -> R {
+    let mut guard = self.access_mut();
+    let old_value = guard.container_mut().refresh()?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Refresh);
+    let guard = AccessGuardMut::downgrade(guard);
+    Ok(operation(&guard, old_value))
+  }
+
+  /// Deserializes the static bytes again, replacing the current state in memory.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  #[inline]
+  pub fn refresh(&self) -> Result<T, Error<Format::FormatError>> {
+    self.operate_refresh(|_, old_value| old_value)
+  }
+}
+
+impl<T> ContainerShared<T, ()> {
+  /// Attaches a manager to this memory-only [`ContainerShared`], returning a new [`ContainerShared`] that uses it.
+  /// See [`Container::attach_manager`].
+  ///
+  /// Subscribers, the panic policy and the poison status are kept. Fails, returning this container
+  /// and the manager back, if there are other existing pointers to this container.
+  pub fn attach_manager<Manager>(self, manager: Manager) -> Result<ContainerShared<T, Manager>, (Self, Manager)> {
+    match Arc::try_unwrap(self.ptr) {
+      Ok(inner) => Ok(ContainerShared {
+        ptr: Arc::new(RwLock::new(RwLock::into_inner(inner).attach_manager(manager))),
+        changes: self.changes,
+        panics: self.panics,
+        autosave: Arc::new(Mutex::new(None))
+      }),
+      Err(ptr) => Err((ContainerShared { ptr, changes: self.changes, panics: self.panics, autosave: self.autosave }, manager))
+    }
+  }
+
+  /// Does nothing, since there is no managed file to read from,
+  /// immediately granting the caller immutable access to the current state
+  /// for the duration of the provided function or closure.
+  ///
+  /// The provided closure takes (1) a reference to the state, and (2) a clone of that same state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn operate_refresh<F, R>(&self, operation: F) -> Result<R, Error<Infallible>>
+  where T: Clone, F: FnOnce(&T, T) -> R {
+    let mut guard = self.access_mut();
+    let old_value = guard.container_mut().refresh()?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Refresh);
+    let guard = AccessGuardMut::downgrade(guard);
+    Ok(operation(&guard, old_value))
+  }
+
+  /// Grants the caller mutable access to the underlying value `T`,
+  /// but only for the duration of the provided function or closure.
+  /// There is no managed file, so nothing is committed.
+  ///
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the panic is propagated.
+  /// Fails with [`UserError::Poisoned`] without running the operation if the container is poisoned.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<R, UserError<Infallible, U>>
+  where F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_mut();
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
+    self.commit_guard(AccessGuardMut::downgrade(guard))?;
+    Ok(ret)
+  }
+
+  /// Does nothing, since there is no managed file to read from.
+  /// Returns a clone of the current state as the previous state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub fn refresh(&self) -> Result<T, Error<Infallible>>
+  where T: Clone {
+    let old_value = AccessGuardMut::container_mut(&mut self.access_mut()).refresh()?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Refresh);
+    Ok(old_value)
+  }
+
+  /// Does nothing, since there is no managed file to write to.
+  #[inline]
+  pub fn commit(&self) -> Result<(), Error<Infallible>> {
+    self.commit_guard(self.access())
+  }
+
+  /// Does nothing, since there is no managed file to write to.
+  ///
+  /// Fails with [`Error::Poisoned`] if the container is poisoned.
+  pub fn commit_guard(&self, guard: AccessGuard<'_, T, ()>) -> Result<(), Error<Infallible>> {
+    self.panics.check()?;
+    AccessGuard::container(&guard).commit()?;
+    self.changes.notify(ChangeEvent::Commit);
+    Ok(())
+  }
+
+  /// Replaces the in-memory state, there is no managed file to write to.
+  pub fn overwrite(&self, value: T) -> Result<(), Error<Infallible>> {
+    AccessGuardMut::container_mut(&mut self.access_mut()).overwrite(value)?;
+    self.panics.clear();
+    self.changes.notify(ChangeEvent::Overwrite);
+    Ok(())
   }
 }
 
 impl<T, Manager> Clone for ContainerShared<T, Manager> {
   #[inline]
   fn clone(&self) -> Self {
-    ContainerShared { ptr: Arc::clone(&self.ptr) }
+    ContainerShared {
+      ptr: Arc::clone(&self.ptr),
+      changes: Arc::clone(&self.changes),
+      panics: Arc::clone(&self.panics),
+      autosave: Arc::clone(&self.autosave)
+    }
+  }
+}
+
+impl<T> From<T> for ContainerShared<T, ()> {
+  #[inline]
+  fn from(value: T) -> Self {
+    ContainerShared::new(value, ())
+  }
+}
+
+impl<T: Default> Default for ContainerShared<T, ()> {
+  #[inline]
+  fn default() -> Self {
+    ContainerShared::new(T::default(), ())
   }
 }
 
 impl<T, Manager> From<Container<T, Manager>> for ContainerShared<T, Manager> {
   #[inline]
   fn from(container: Container<T, Manager>) -> Self {
-    ContainerShared { ptr: Arc::new(RwLock::new(container)) }
+    ContainerShared {
+      ptr: Arc::new(RwLock::new(container)),
+      changes: Arc::new(Changes::default()),
+      panics: Arc::new(Panics::default()),
+      autosave: Arc::new(Mutex::new(None))
+    }
+  }
+}
+
+/// Tracks changes to the state of a [`ContainerShared`], allowing threads to wait for or subscribe to them.
+#[derive(Debug, Default)]
+struct Changes {
+  generation: Mutex<u64>,
+  condvar: Condvar,
+  subscribers: Mutex<Vec<mpsc::Sender<ChangeEvent>>>
+}
+
+impl Changes {
+  fn notify(&self, event: ChangeEvent) {
+    *self.generation.lock() += 1;
+    self.condvar.notify_all();
+    // subscribers whose receivers have been dropped are forgotten
+    self.subscribers.lock().retain(|subscriber| subscriber.send(event).is_ok());
+  }
+
+  fn subscribe(&self) -> mpsc::Receiver<ChangeEvent> {
+    let (sender, receiver) = mpsc::channel();
+    self.subscribers.lock().push(sender);
+    receiver
+  }
+
+  fn wait(&self, timeout: Duration) -> bool {
+    let mut generation = self.generation.lock();
+    let start = *generation;
+    !self.condvar.wait_while_for(&mut generation, |generation| *generation == start, timeout).timed_out()
   }
 }
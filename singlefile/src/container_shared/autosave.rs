@@ -0,0 +1,103 @@
+use super::ContainerShared;
+use crate::manager::{FileFormat, FileManager, Writing};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A thread that commits the state of a [`ContainerShared`] whenever it is dirty, at most once per interval.
+///
+/// Dropping this structure stops the thread, committing the state one final time if it is dirty.
+/// This structure is created by [`ContainerShared::enable_autosave`].
+#[derive(Debug)]
+pub(super) struct Autosave {
+  shared: Arc<Shared>,
+  thread: Option<JoinHandle<()>>
+}
+
+impl Autosave {
+  pub(super) fn spawn<T, Format, Lock, Mode>(
+    container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+    interval: Duration
+  ) -> io::Result<Self>
+  where
+    T: Send + Sync + 'static,
+    Format: FileFormat<T> + Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Writing + Send + Sync + 'static
+  {
+    let shared = Arc::new(Shared::default());
+    let thread = thread::Builder::new()
+      .name("singlefile-autosave".to_owned())
+      .spawn({
+        let shared = Arc::clone(&shared);
+        move || run(container, shared, interval)
+      })?;
+    Ok(Autosave { shared, thread: Some(thread) })
+  }
+
+  /// Stops the thread without committing the state one final time.
+  pub(super) fn disable(self) {
+    self.shared.state.lock().flush_on_stop = false;
+  }
+}
+
+impl Drop for Autosave {
+  fn drop(&mut self) {
+    self.shared.state.lock().stop = true;
+    self.shared.condvar.notify_all();
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    };
+  }
+}
+
+#[derive(Debug)]
+struct Shared {
+  state: Mutex<State>,
+  condvar: Condvar
+}
+
+#[derive(Debug)]
+struct State {
+  stop: bool,
+  flush_on_stop: bool
+}
+
+impl Default for Shared {
+  fn default() -> Self {
+    Shared {
+      state: Mutex::new(State { stop: false, flush_on_stop: true }),
+      condvar: Condvar::new()
+    }
+  }
+}
+
+fn run<T, Format, Lock, Mode>(
+  container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  shared: Arc<Shared>,
+  interval: Duration
+)
+where Format: FileFormat<T>, Mode: Writing {
+  let mut state = shared.state.lock();
+  loop {
+    shared.condvar.wait_while_for(&mut state, |state| !state.stop, interval);
+    let stop = state.stop;
+    if stop && !state.flush_on_stop { break };
+
+    MutexGuard::unlocked(&mut state, || {
+      // access is never waited on indefinitely, since the last handle to the container
+      // may be dropped (stopping this thread) while an owned access guard is still held
+      if let Some(guard) = container.ptr.try_read_for(interval) {
+        // errors are not reported, the state stays dirty and is committed again after the next interval
+        let _ = container.commit_dirty(&guard);
+      };
+    });
+
+    if stop { break };
+  };
+}
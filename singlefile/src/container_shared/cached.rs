@@ -0,0 +1,114 @@
+use super::{ContainerShared, ContainerSharedReadonly, AccessGuard};
+use crate::error::Error;
+use crate::manager::{FileFormat, ManagerReadonly};
+
+use parking_lot::Mutex;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A read-only container that serves its in-memory copy of a file, until that copy is older than a given time-to-live.
+///
+/// The first access after the time-to-live has elapsed reads the file again (if it has changed on disk,
+/// see [`Container::refresh_if_changed`]) before being granted, and restarts the time-to-live.
+/// Concurrent accesses wait for that read instead of reading the file themselves.
+/// If reading the file fails, the error is returned and the next access tries again.
+///
+/// This suits configuration that is read often from many threads, but rarely edited by hand or by other processes.
+/// A [`ContainerCached`] is not [`Clone`], it can be shared between threads by wrapping it in an [`Arc`].
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use singlefile::container_shared::ContainerCached;
+/// use std::time::Duration;
+///
+/// let settings = ContainerCached::<Vec<String>, Json>::open("settings.json", Json, Duration::from_secs(30))?;
+/// // reads `settings.json` again at most once every 30 seconds
+/// let len = settings.operate(|settings| settings.len())?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+///
+/// [`Container::refresh_if_changed`]: crate::container::Container::refresh_if_changed
+/// [`Arc`]: std::sync::Arc
+#[derive(Debug)]
+pub struct ContainerCached<T, Format> {
+  container: ContainerSharedReadonly<T, Format>,
+  ttl: Duration,
+  loaded_at: Mutex<Instant>
+}
+
+impl<T, Format> ContainerCached<T, Format>
+where Format: FileFormat<T> {
+  /// Opens a new [`ContainerCached`] with the given time-to-live, returning an error if the file at the given path does not exist.
+  pub fn open<P: AsRef<Path>>(path: P, format: Format, ttl: Duration) -> Result<Self, Error<Format::FormatError>> {
+    ContainerShared::open(path, format).map(|container| ContainerCached::new(container, ttl))
+  }
+
+  /// Gets immutable access to the cached value, reading the file again first if the time-to-live has elapsed.
+  ///
+  /// Don't call this if you currently have an access guard, since reading the file requires a mutable lock.
+  pub fn access(&self) -> Result<AccessGuard<'_, T, ManagerReadonly<Format>>, Error<Format::FormatError>> {
+    self.refresh_if_expired()?;
+    Ok(self.container.access())
+  }
+
+  /// Grants the caller immutable access to the cached value, but only for the duration of the provided function or closure,
+  /// reading the file again first if the time-to-live has elapsed.
+  pub fn operate<F, R>(&self, operation: F) -> Result<R, Error<Format::FormatError>>
+  where F: FnOnce(&T) -> R {
+    self.access().map(|guard| operation(&guard))
+  }
+
+  /// Reads the file again, regardless of whether the time-to-live has elapsed, and restarts it.
+  pub fn refresh(&self) -> Result<(), Error<Format::FormatError>> {
+    let mut loaded_at = self.loaded_at.lock();
+    self.container.refresh()?;
+    *loaded_at = Instant::now();
+    Ok(())
+  }
+
+  fn refresh_if_expired(&self) -> Result<(), Error<Format::FormatError>> {
+    let mut loaded_at = self.loaded_at.lock();
+    if loaded_at.elapsed() >= self.ttl {
+      self.container.refresh_if_changed()?;
+      *loaded_at = Instant::now();
+    };
+
+    Ok(())
+  }
+}
+
+impl<T, Format> ContainerCached<T, Format> {
+  /// Creates a new [`ContainerCached`] from an existing container, treating its current state as freshly read.
+  pub fn new(container: ContainerSharedReadonly<T, Format>, ttl: Duration) -> Self {
+    ContainerCached { container, ttl, loaded_at: Mutex::new(Instant::now()) }
+  }
+
+  /// Gets the time-to-live of the cached value.
+  #[inline]
+  pub const fn ttl(&self) -> Duration {
+    self.ttl
+  }
+
+  /// Returns the instant that the cached value was last read from the file, or when this container was created.
+  pub fn loaded_at(&self) -> Instant {
+    *self.loaded_at.lock()
+  }
+
+  /// Returns `true` if the time-to-live has elapsed, so that the next access reads the file again.
+  pub fn is_expired(&self) -> bool {
+    self.loaded_at.lock().elapsed() >= self.ttl
+  }
+
+  /// Gets the underlying [`ContainerShared`], accessing it directly never reads the file again.
+  #[inline]
+  pub fn container(&self) -> &ContainerSharedReadonly<T, Format> {
+    &self.container
+  }
+
+  /// Closes this [`ContainerCached`], returning the underlying [`ContainerShared`].
+  #[inline]
+  pub fn into_container(self) -> ContainerSharedReadonly<T, Format> {
+    self.container
+  }
+}
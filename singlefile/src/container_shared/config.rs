@@ -0,0 +1,175 @@
+use super::{ContainerShared, ContainerSharedReadonly, AccessGuard};
+use crate::container::ChangeEvent;
+use crate::error::UserError;
+use crate::manager::{FileFormat, ManagerReadonly};
+
+use parking_lot::Mutex;
+#[cfg(feature = "watch")]
+use notify::RecommendedWatcher;
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The error type returned by a [`Config`] validator when rejecting a value.
+pub type ValidationError = Box<dyn std::error::Error + Send + Sync>;
+
+type Validator<T> = Box<dyn Fn(&T) -> Result<(), ValidationError> + Send + Sync>;
+type Callback<T> = Box<dyn Fn(&T, &T) + Send + Sync>;
+
+/// A read-only, validated configuration file that can be reloaded from disk while in use.
+///
+/// Every value read from disk is first passed to a validator. A value that fails validation
+/// (or fails to parse) is rejected, leaving the current value in place, so consumers never observe invalid state.
+/// Once a new value has been swapped in, every callback registered with [`on_change`] is invoked
+/// with the old and new values.
+///
+/// A configuration opened with [`Config::open`] is only reloaded when [`reload`] is called,
+/// while one opened with [`Config::watch`] (which requires the `watch` cargo feature)
+/// is reloaded automatically whenever its file changes on disk.
+///
+/// [`on_change`]: Config::on_change
+/// [`reload`]: Config::reload
+pub struct Config<T, Format> {
+  inner: Arc<ConfigInner<T, Format>>,
+  #[cfg(feature = "watch")]
+  watcher: Option<RecommendedWatcher>
+}
+
+struct ConfigInner<T, Format> {
+  container: ContainerSharedReadonly<T, Format>,
+  validator: Validator<T>,
+  callbacks: Mutex<Vec<Callback<T>>>
+}
+
+impl<T, Format> Config<T, Format>
+where Format: FileFormat<T> {
+  /// Opens a configuration file, returning an error if it does not exist, cannot be parsed, or fails validation.
+  pub fn open<P, V>(path: P, format: Format, validator: V) -> Result<Self, UserError<Format::FormatError, ValidationError>>
+  where P: AsRef<Path>, V: Fn(&T) -> Result<(), ValidationError> + Send + Sync + 'static {
+    let container = ContainerShared::open(path, format)?;
+    validator(&container.access()).map_err(UserError::User)?;
+    Ok(Config {
+      inner: Arc::new(ConfigInner {
+        container,
+        validator: Box::new(validator),
+        callbacks: Mutex::new(Vec::new())
+      }),
+      #[cfg(feature = "watch")]
+      watcher: None
+    })
+  }
+
+  /// Opens a configuration file like [`Config::open`], then watches it for changes
+  /// until the returned [`Config`] is dropped, reloading it like [`Config::reload`] whenever it changes on disk.
+  ///
+  /// Reloads happen on a thread owned by `notify`, see [`ContainerWatcher`] for how the file is watched.
+  /// Reloads that fail or are rejected by the validator keep the current value in place, and are logged
+  /// as warnings when the `log` cargo feature is enabled. Contents that were rejected by the validator
+  /// are not read again until the file changes once more.
+  ///
+  /// [`ContainerWatcher`]: crate::container_watcher::ContainerWatcher
+  #[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+  #[cfg(feature = "watch")]
+  pub fn watch<P, V>(path: P, format: Format, validator: V) -> Result<Self, UserError<Format::FormatError, ValidationError>>
+  where
+    T: Send + Sync + 'static,
+    Format: Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    P: AsRef<Path>,
+    V: Fn(&T) -> Result<(), ValidationError> + Send + Sync + 'static
+  {
+    let mut config = Config::open(path, format, validator)?;
+    let path = config.inner.container.access().manager().path().to_owned();
+    let watcher = crate::container_watcher::watch_file(&path, {
+      let inner = Arc::clone(&config.inner);
+      move |result| {
+        let result = result.map_err(UserError::from).and_then(|()| inner.reload_if_changed());
+        #[cfg(feature = "log")]
+        if let Err(err) = result {
+          log::warn!(target: "singlefile", "failed to reload {}: {err}", inner.container.access().manager().path().display());
+        };
+
+        #[cfg(not(feature = "log"))]
+        let _ = result;
+      }
+    })?;
+
+    config.watcher = Some(watcher);
+    Ok(config)
+  }
+
+  /// Reads the file from disk again, swapping in its contents if they pass validation.
+  ///
+  /// If the contents fail to parse or are rejected by the validator, an error is returned and the current value is kept.
+  pub fn reload(&self) -> Result<(), UserError<Format::FormatError, ValidationError>> {
+    self.inner.reload()
+  }
+}
+
+impl<T, Format> ConfigInner<T, Format>
+where Format: FileFormat<T> {
+  fn reload(&self) -> Result<(), UserError<Format::FormatError, ValidationError>> {
+    let mut guard = self.container.access_mut();
+    let value: T = guard.manager().read()?;
+    if let Err(err) = (self.validator)(&value) {
+      // rejected contents are complete, so there is no use in reading them again until they change
+      guard.container().stats.record_stamp(guard.manager());
+      return Err(UserError::User(err));
+    };
+
+    let old = std::mem::replace(&mut *guard, value);
+    guard.container().stats.record_refresh();
+    guard.container().stats.record_stamp(guard.manager());
+    let guard = guard.downgrade();
+    for callback in self.callbacks.lock().iter() {
+      callback(&old, &guard);
+    };
+
+    drop(guard);
+    self.container.changes.notify(ChangeEvent::Refresh);
+    Ok(())
+  }
+
+  #[cfg(feature = "watch")]
+  fn reload_if_changed(&self) -> Result<(), UserError<Format::FormatError, ValidationError>> {
+    let guard = self.container.access();
+    if guard.container().stats.is_stamp_current(guard.manager())? {
+      return Ok(());
+    };
+
+    drop(guard);
+    self.reload()
+  }
+}
+
+impl<T, Format> Config<T, Format> {
+  /// Gets immutable access to the current configuration value.
+  #[inline]
+  pub fn get(&self) -> AccessGuard<'_, T, ManagerReadonly<Format>> {
+    self.inner.container.access()
+  }
+
+  /// Registers a callback to be invoked with the old and new values after every successful reload.
+  ///
+  /// Callbacks are invoked while a read lock is held on the configuration, so they must not attempt to reload it.
+  pub fn on_change<F>(&self, callback: F)
+  where F: Fn(&T, &T) + Send + Sync + 'static {
+    self.inner.callbacks.lock().push(Box::new(callback));
+  }
+
+  /// Gets the underlying [`ContainerShared`], which can be cloned and shared with consumers of the configuration.
+  #[inline]
+  pub fn container(&self) -> &ContainerSharedReadonly<T, Format> {
+    &self.inner.container
+  }
+}
+
+impl<T: fmt::Debug, Format: fmt::Debug> fmt::Debug for Config<T, Format> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Config")
+      .field("container", &self.inner.container)
+      .field("callbacks", &self.inner.callbacks.lock().len())
+      .finish_non_exhaustive()
+  }
+}
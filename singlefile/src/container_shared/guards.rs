@@ -1,4 +1,6 @@
 use crate::container::Container;
+use crate::error::Error;
+use crate::manager::{FileManager, FileFormat, Writing};
 
 use std::fmt;
 use std::ops::{Deref, DerefMut};
@@ -7,6 +9,8 @@ type RwLockReadGuard<'a, T> = parking_lot::lock_api::RwLockReadGuard<'a, parking
 type RwLockWriteGuard<'a, T> = parking_lot::lock_api::RwLockWriteGuard<'a, parking_lot::RawRwLock, T>;
 type ArcRwLockReadGuard<T> = parking_lot::lock_api::ArcRwLockReadGuard<parking_lot::RawRwLock, T>;
 type ArcRwLockWriteGuard<T> = parking_lot::lock_api::ArcRwLockWriteGuard<parking_lot::RawRwLock, T>;
+type MappedRwLockReadGuard<'a, T> = parking_lot::lock_api::MappedRwLockReadGuard<'a, parking_lot::RawRwLock, T>;
+type MappedRwLockWriteGuard<'a, T> = parking_lot::lock_api::MappedRwLockWriteGuard<'a, parking_lot::RawRwLock, T>;
 
 
 
@@ -39,6 +43,13 @@ impl<'a, T, Manager> AccessGuard<'a, T, Manager> {
   pub fn container(&self) -> &Container<T, Manager> {
     &self.inner
   }
+
+  /// Projects this guard onto a component of the underlying value `T`, such as a field.
+  #[inline]
+  pub fn map<U: ?Sized, F>(self, f: F) -> MappedAccessGuard<'a, U>
+  where F: FnOnce(&T) -> &U {
+    MappedAccessGuard { inner: RwLockReadGuard::map(self.inner, |container| f(Container::get(container))) }
+  }
 }
 
 impl<'a, T, Manager> Deref for AccessGuard<'a, T, Manager> {
@@ -100,6 +111,23 @@ impl<'a, T, Manager> AccessGuardMut<'a, T, Manager> {
   pub fn downgrade(self) -> AccessGuard<'a, T, Manager> {
     AccessGuard { inner: RwLockWriteGuard::downgrade(self.inner) }
   }
+
+  /// Projects this guard onto a component of the underlying value `T`, such as a field.
+  #[inline]
+  pub fn map<U: ?Sized, F>(self, f: F) -> MappedAccessGuardMut<'a, U>
+  where F: FnOnce(&mut T) -> &mut U {
+    MappedAccessGuardMut { inner: RwLockWriteGuard::map(self.inner, |container| f(Container::get_mut(container))) }
+  }
+}
+
+impl<'a, T, Format, Lock, Mode> AccessGuardMut<'a, T, FileManager<Format, Lock, Mode>>
+where Format: FileFormat<T> {
+  /// Writes the current in-memory state to the managed file.
+  #[inline]
+  pub fn commit(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    self.inner.commit()
+  }
 }
 
 impl<'a, T, Manager> Deref for AccessGuardMut<'a, T, Manager> {
@@ -219,6 +247,16 @@ impl<T, Manager> OwnedAccessGuardMut<T, Manager> {
   }
 }
 
+impl<T, Format, Lock, Mode> OwnedAccessGuardMut<T, FileManager<Format, Lock, Mode>>
+where Format: FileFormat<T> {
+  /// Writes the current in-memory state to the managed file.
+  #[inline]
+  pub fn commit(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    self.inner.commit()
+  }
+}
+
 impl<T, Manager> Deref for OwnedAccessGuardMut<T, Manager> {
   type Target = T;
 
@@ -241,3 +279,88 @@ impl<T: fmt::Display, Manager> fmt::Display for OwnedAccessGuardMut<T, Manager>
     <T as fmt::Display>::fmt(self, f)
   }
 }
+
+
+
+/// A lifetime-bound, read-only access permit into a component of the value in a [`ContainerShared`].
+///
+/// This structure is created by the [`map`] method on [`AccessGuard`].
+///
+/// [`ContainerShared`]: crate::container_shared::ContainerShared
+/// [`map`]: AccessGuard::map
+#[must_use = "if unused the lock will immediately unlock"]
+#[derive(Debug)]
+pub struct MappedAccessGuard<'a, T: ?Sized> {
+  inner: MappedRwLockReadGuard<'a, T>
+}
+
+impl<'a, T: ?Sized> MappedAccessGuard<'a, T> {
+  /// Projects this guard further onto a component of the mapped value.
+  #[inline]
+  pub fn map<U: ?Sized, F>(self, f: F) -> MappedAccessGuard<'a, U>
+  where F: FnOnce(&T) -> &U {
+    MappedAccessGuard { inner: MappedRwLockReadGuard::map(self.inner, f) }
+  }
+}
+
+impl<'a, T: ?Sized> Deref for MappedAccessGuard<'a, T> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<'a, T: fmt::Display + ?Sized> fmt::Display for MappedAccessGuard<'a, T> {
+  #[inline]
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    <T as fmt::Display>::fmt(self, f)
+  }
+}
+
+
+
+/// A lifetime-bound, mutable access permit into a component of the value in a [`ContainerShared`].
+///
+/// This structure is created by the [`map`] method on [`AccessGuardMut`].
+///
+/// [`ContainerShared`]: crate::container_shared::ContainerShared
+/// [`map`]: AccessGuardMut::map
+#[must_use = "if unused the lock will immediately unlock"]
+#[derive(Debug)]
+pub struct MappedAccessGuardMut<'a, T: ?Sized> {
+  inner: MappedRwLockWriteGuard<'a, T>
+}
+
+impl<'a, T: ?Sized> MappedAccessGuardMut<'a, T> {
+  /// Projects this guard further onto a component of the mapped value.
+  #[inline]
+  pub fn map<U: ?Sized, F>(self, f: F) -> MappedAccessGuardMut<'a, U>
+  where F: FnOnce(&mut T) -> &mut U {
+    MappedAccessGuardMut { inner: MappedRwLockWriteGuard::map(self.inner, f) }
+  }
+}
+
+impl<'a, T: ?Sized> Deref for MappedAccessGuardMut<'a, T> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<'a, T: ?Sized> DerefMut for MappedAccessGuardMut<'a, T> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.inner
+  }
+}
+
+impl<'a, T: fmt::Display + ?Sized> fmt::Display for MappedAccessGuardMut<'a, T> {
+  #[inline]
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    <T as fmt::Display>::fmt(self, f)
+  }
+}
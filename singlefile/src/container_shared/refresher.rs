@@ -0,0 +1,116 @@
+use super::ContainerShared;
+use crate::container::ChangeEvent;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Reading};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A thread that refreshes a [`ContainerShared`] whenever its file has changed on disk, checking once per interval.
+///
+/// Changes are recognized by the size and modification time of the file, see [`Container::refresh_if_changed`].
+/// Each refresh wakes the waiters of [`ContainerShared::wait_for_change`].
+/// Errors are not reported as they happen, the latest one can be retrieved with [`AutoRefresh::take_error`].
+///
+/// Dropping this structure stops the thread, blocking the current thread until it has exited.
+/// This structure is created by [`ContainerShared::spawn_auto_refresh`].
+///
+/// [`Container::refresh_if_changed`]: crate::container::Container::refresh_if_changed
+pub struct AutoRefresh<FE> {
+  shared: Arc<Shared>,
+  last_error: Arc<Mutex<Option<Error<FE>>>>,
+  thread: Option<JoinHandle<()>>
+}
+
+impl<FE> AutoRefresh<FE> {
+  pub(super) fn spawn<T, Format, Lock, Mode>(
+    container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+    interval: Duration
+  ) -> io::Result<Self>
+  where
+    T: Send + Sync + 'static,
+    Format: FileFormat<T, FormatError = FE> + Send + Sync + 'static,
+    FE: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Reading + Send + Sync + 'static
+  {
+    let shared = Arc::new(Shared { stop: Mutex::new(false), condvar: Condvar::new() });
+    let last_error = Arc::new(Mutex::new(None));
+    let thread = thread::Builder::new()
+      .name("singlefile-refresh".to_owned())
+      .spawn({
+        let shared = Arc::clone(&shared);
+        let last_error = Arc::clone(&last_error);
+        move || run(container, shared, last_error, interval)
+      })?;
+    Ok(AutoRefresh { shared, last_error, thread: Some(thread) })
+  }
+
+  /// Takes the latest error that occurred while refreshing the container, if any.
+  pub fn take_error(&self) -> Option<Error<FE>> {
+    self.last_error.lock().take()
+  }
+}
+
+impl<FE> Drop for AutoRefresh<FE> {
+  fn drop(&mut self) {
+    *self.shared.stop.lock() = true;
+    self.shared.condvar.notify_all();
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    };
+  }
+}
+
+impl<FE> fmt::Debug for AutoRefresh<FE> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AutoRefresh")
+      .field("thread", &self.thread)
+      .finish_non_exhaustive()
+  }
+}
+
+#[derive(Debug)]
+struct Shared {
+  stop: Mutex<bool>,
+  condvar: Condvar
+}
+
+fn run<T, Format, Lock, Mode>(
+  container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  shared: Arc<Shared>,
+  last_error: Arc<Mutex<Option<Error<Format::FormatError>>>>,
+  interval: Duration
+)
+where Format: FileFormat<T>, Mode: Reading {
+  let mut stop = shared.stop.lock();
+  loop {
+    shared.condvar.wait_while_for(&mut stop, |stop| !*stop, interval);
+    if *stop { break };
+
+    MutexGuard::unlocked(&mut stop, || {
+      // access is never waited on indefinitely, since this thread is joined when the handle is dropped,
+      // which may happen while the dropping thread holds an access guard
+      let mut guard = match container.ptr.try_write_for(interval) {
+        Some(guard) => guard,
+        None => return
+      };
+
+      let result = guard.refresh_if_changed();
+      drop(guard);
+      match result {
+        Ok(Some(_)) => {
+          container.panics.clear();
+          container.changes.notify(ChangeEvent::Refresh);
+        },
+        Ok(None) => (),
+        Err(err) => *last_error.lock() = Some(err)
+      };
+    });
+  };
+}
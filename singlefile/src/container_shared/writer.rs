@@ -0,0 +1,263 @@
+use super::ContainerShared;
+use crate::container::ChangeEvent;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Writing};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Determines which states a [`BackgroundWriter`] writes to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WriteOrder {
+  /// Every commit writes the latest state at the time the writer gets to it,
+  /// so commits made while the writer is busy are coalesced into a single write.
+  ///
+  /// This is the default.
+  #[default]
+  LatestWins,
+  /// Every commit snapshots the state at the time it was made,
+  /// and every snapshot is written to disk in the order it was committed.
+  Ordered
+}
+
+/// Funnels the commits of a [`ContainerShared`] through one dedicated background thread,
+/// so that committing never blocks on file I/O.
+///
+/// Commits are requested with [`BackgroundWriter::commit`], which returns immediately with the version of that commit.
+/// [`BackgroundWriter::flush`] blocks until every commit requested so far has been written,
+/// and reports any error that occurred while writing, while [`BackgroundWriter::wait_durable`]
+/// only blocks until a specific version has been written.
+///
+/// Versions start at 1 and increase by 1 with every commit. Since every write contains the changes of every
+/// commit before it, a version is durable once it or any later version has been successfully written.
+///
+/// Dropping the writer waits for every pending commit to be written, discarding any errors.
+/// This structure is created by [`ContainerShared::spawn_writer`].
+pub struct BackgroundWriter<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  shared: Arc<Shared<T, Format::FormatError>>,
+  order: WriteOrder,
+  thread: Option<JoinHandle<()>>
+}
+
+impl<T, Format, Lock, Mode> BackgroundWriter<T, Format, Lock, Mode>
+where
+  T: Clone + Send + Sync + 'static,
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + 'static,
+  Lock: Send + Sync + 'static,
+  Mode: Writing + Send + Sync + 'static
+{
+  pub(super) fn spawn(
+    container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+    order: WriteOrder
+  ) -> io::Result<Self> {
+    let shared = Arc::new(Shared::default());
+    let thread = thread::Builder::new()
+      .name("singlefile-writer".to_owned())
+      .spawn({
+        let container = container.clone();
+        let shared = Arc::clone(&shared);
+        move || run(container, shared, order)
+      })?;
+    Ok(BackgroundWriter { container, shared, order, thread: Some(thread) })
+  }
+
+  /// Requests that the state be committed, without waiting for it to be written, returning the version of this commit.
+  ///
+  /// With [`WriteOrder::Ordered`], this function acquires an immutable lock on the shared state to snapshot it.
+  pub fn commit(&self) -> u64 {
+    // the snapshot is queued before access is released, so that snapshots are queued in the order they were taken
+    let guard = (self.order == WriteOrder::Ordered).then(|| self.container.access());
+    let mut state = self.shared.state.lock();
+    state.pending.extend(guard.as_deref().cloned());
+    state.requested += 1;
+    self.shared.condvar.notify_all();
+    state.requested
+  }
+
+  /// Blocks the current thread until the given version has been written to disk.
+  ///
+  /// Returns the error that occurred while writing the given version, if it failed.
+  /// If that error has already been returned by [`BackgroundWriter::flush`], an [`io::ErrorKind::Other`] error is returned instead.
+  pub fn wait_durable(&self, version: u64) -> Result<(), Error<Format::FormatError>> {
+    let mut state = self.shared.state.lock();
+    let version = version.min(state.requested);
+    while state.written < version {
+      self.shared.condvar.wait(&mut state);
+    };
+
+    if state.durable >= version {
+      return Ok(());
+    };
+
+    match state.errors.iter().position(|&(target, _)| target >= version) {
+      Some(index) => Err(state.errors.remove(index).unwrap().1),
+      None => Err(io::Error::new(io::ErrorKind::Other, "write failed, error was already reported by flush").into())
+    }
+  }
+
+  /// Blocks the current thread until every commit requested so far has been written to disk.
+  ///
+  /// Returns the first error that occurred while writing since the last flush, if any.
+  pub fn flush(&self) -> Result<(), Error<Format::FormatError>> {
+    let mut state = self.shared.state.lock();
+    let target = state.requested;
+    while state.written < target {
+      self.shared.condvar.wait(&mut state);
+    };
+
+    let mut errors = std::mem::take(&mut state.errors);
+    errors.pop_front().map_or(Ok(()), |(_, err)| Err(err))
+  }
+
+  /// Flushes any pending commits and stops the writer thread.
+  pub fn close(mut self) -> Result<(), Error<Format::FormatError>> {
+    let result = self.flush();
+    self.stop();
+    result
+  }
+}
+
+impl<T, Format, Lock, Mode> BackgroundWriter<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  /// Gets a reference to the container that this writer commits.
+  #[inline]
+  pub fn container(&self) -> &ContainerShared<T, FileManager<Format, Lock, Mode>> {
+    &self.container
+  }
+
+  /// Returns the [`WriteOrder`] of this writer.
+  #[inline]
+  pub fn order(&self) -> WriteOrder {
+    self.order
+  }
+
+  /// Returns the number of commits that have been requested but not yet written.
+  pub fn pending(&self) -> u64 {
+    let state = self.shared.state.lock();
+    state.requested - state.written
+  }
+
+  /// Returns the latest version that has been successfully written to disk, or 0 if there is none.
+  pub fn durable_version(&self) -> u64 {
+    self.shared.state.lock().durable
+  }
+
+  fn stop(&mut self) {
+    self.shared.state.lock().shutdown = true;
+    self.shared.condvar.notify_all();
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    };
+  }
+}
+
+impl<T, Format, Lock, Mode> Drop for BackgroundWriter<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+impl<T, Format, Lock, Mode> fmt::Debug for BackgroundWriter<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("BackgroundWriter")
+      .field("order", &self.order)
+      .field("pending", &self.pending())
+      .finish_non_exhaustive()
+  }
+}
+
+struct Shared<T, FE> {
+  state: Mutex<State<T, FE>>,
+  condvar: Condvar
+}
+
+struct State<T, FE> {
+  /// Snapshots waiting to be written, only used by [`WriteOrder::Ordered`].
+  pending: VecDeque<T>,
+  /// The latest version that has been requested.
+  requested: u64,
+  /// The latest version that has been written, successfully or not.
+  written: u64,
+  /// The latest version that has been written successfully.
+  durable: u64,
+  /// The errors of failed writes that have not been reported yet, along with the version that was being written.
+  errors: VecDeque<(u64, Error<FE>)>,
+  shutdown: bool
+}
+
+impl<T, FE> Default for Shared<T, FE> {
+  fn default() -> Self {
+    Shared {
+      state: Mutex::new(State {
+        pending: VecDeque::new(),
+        requested: 0,
+        written: 0,
+        durable: 0,
+        errors: VecDeque::new(),
+        shutdown: false
+      }),
+      condvar: Condvar::new()
+    }
+  }
+}
+
+fn run<T, Format, Lock, Mode>(
+  container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  shared: Arc<Shared<T, Format::FormatError>>,
+  order: WriteOrder
+)
+where Format: FileFormat<T>, Mode: Writing {
+  let mut state = shared.state.lock();
+  loop {
+    if state.written == state.requested {
+      if state.shutdown { break };
+      shared.condvar.wait(&mut state);
+      continue;
+    };
+
+    let (snapshot, target) = match order {
+      WriteOrder::LatestWins => (None, state.requested),
+      WriteOrder::Ordered => (state.pending.pop_front(), state.written + 1)
+    };
+
+    let result = MutexGuard::unlocked(&mut state, || match snapshot {
+      Some(value) => write_snapshot(&container, &value),
+      None => container.commit()
+    });
+
+    state.written = target;
+    match result {
+      Ok(()) => state.durable = target,
+      Err(err) => state.errors.push_back((target, err))
+    };
+
+    shared.condvar.notify_all();
+  };
+}
+
+fn write_snapshot<T, Format, Lock, Mode>(
+  container: &ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  value: &T
+) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T>, Mode: Writing {
+  container.panics.check()?;
+  let guard = container.access();
+  guard.container().hooks.before(value);
+  guard.container().manager.write(value)?;
+  // the snapshot may be older than the current state, so the current state is left dirty
+  guard.container().stats.record_write();
+  guard.container().stats.record_stamp(guard.manager());
+  guard.container().hooks.after(value);
+  container.changes.notify(ChangeEvent::Commit);
+  Ok(())
+}
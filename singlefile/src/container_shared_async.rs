@@ -1,14 +1,35 @@
 //! Container constructs allowing multiple-ownership, asynchronous, managed access to a file.
 //!
 //! This module can be enabled with the `shared-async` cargo feature.
+//!
+//! Containers are designed around Tokio, but their blocking work can be handed to other async runtimes
+//! with a [`Spawner`], see its documentation for what still requires a Tokio runtime.
+//!
+//! On Linux, the `io-uring` cargo feature additionally enables [`ContainerSharedAsync::commit_uring`] and
+//! [`ContainerSharedAsync::refresh_uring`], which perform their I/O through io_uring instead of blocking tasks.
+
+macro_rules! spawn_blocking {
+  ($expr:expr) => (tokio::task::spawn_blocking(move || $expr).await.map_err(crate::error::TaskFailed::from));
+  ($pool:expr, $expr:expr) => ($pool.spawn_blocking(move || $expr).await);
+}
 
+mod autosave;
+mod coalescer;
 mod guards;
+mod pool;
+mod refresher;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
 
-use crate::error::{Error, UserError};
+use crate::error::{Error, UserError, TaskFailed, TimedOut};
 use crate::container::*;
 use crate::manager::lock::FileLock;
 use crate::manager::mode::FileMode;
 use crate::manager::*;
+use crate::manager::async_manager::{AsyncFileManager, AsyncMode};
+use crate::manager::format::async_io::AsyncFileFormat;
+use crate::panic_policy::Panics;
+use crate::slow::{Operation, Stopwatch};
 
 pub use self::guards::{
   AccessGuard,
@@ -16,11 +37,26 @@ pub use self::guards::{
   OwnedAccessGuard,
   OwnedAccessGuardMut
 };
+pub use self::pool::{BlockingHandle, BlockingPool, Spawner};
+pub use self::refresher::AutoRefresh;
+pub use self::coalescer::{CommitCoalescer, CommitHandle};
+pub use crate::panic_policy::PanicPolicy;
 
-use tokio::sync::RwLock;
+use self::autosave::Autosave;
+#[cfg_attr(docsrs, doc(cfg(all(target_os = "linux", feature = "io-uring"))))]
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use self::uring::UringMode;
 
+use tokio::sync::{broadcast, RwLock};
+
+use std::convert::Infallible;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 /// Type alias to a shared, asynchronous, thread-safe container that is read-only.
 pub type ContainerSharedAsyncReadonly<T, Format> = ContainerSharedAsync<T, ManagerReadonly<Format>>;
@@ -37,17 +73,31 @@ pub type ContainerSharedAsyncWritableLocked<T, Format> = ContainerSharedAsync<T,
 /// See [`Atomic`] for more information.
 pub type ContainerSharedAsyncAtomicLocked<T, Format> = ContainerSharedAsync<T, ManagerAtomicLocked<Format>>;
 
-macro_rules! spawn_blocking {
-  ($expr:expr) => (tokio::task::spawn_blocking(move || $expr).await.expect("blocking task failed"));
-}
+/// Type alias to a shared, asynchronous, thread-safe container that is not backed by any file.
+/// See [`ContainerMemoryOnly`] for more information.
+pub type ContainerSharedAsyncMemoryOnly<T> = ContainerSharedAsync<T, ()>;
 
 /// A container that allows asynchronous atomic reference-counted, mutable access (gated by an [`RwLock`]) to the
 /// underlying file and contents. Cloning this container will not clone the underlying contents, it will clone the
 /// underlying pointer, allowing multiple-access.
-#[repr(transparent)]
+///
+/// The underlying [`RwLock`] is fair: access is granted in the order it was requested, so once a task
+/// is waiting for mutable access, new readers will queue up behind it and cannot starve it.
+/// Note that [`commit`][ContainerSharedAsync::commit] only takes immutable access, so it is
+/// never blocked by other readers, only by writers that requested access before it.
+///
+/// Readers can additionally be gated by creating the container with [`ContainerSharedAsync::with_max_readers`],
+/// which bounds how many tasks may hold immutable access at once, so that a burst of long-lived readers
+/// cannot hold up a pending writer for longer than it takes the admitted readers to finish.
+///
+/// Blocking work is run on the container's [`BlockingPool`], which is tokio's global blocking pool by default.
 #[derive(Debug)]
 pub struct ContainerSharedAsync<T, Manager> {
-  ptr: Arc<RwLock<Container<T, Manager>>>
+  ptr: Arc<RwLock<Container<T, Manager>>>,
+  pool: BlockingPool,
+  changes: broadcast::Sender<ChangeEvent>,
+  autosave: Arc<Mutex<Option<Autosave>>>,
+  panics: Arc<Panics<T>>
 }
 
 impl<T, Manager> ContainerSharedAsync<T, Manager> {
@@ -56,12 +106,29 @@ impl<T, Manager> ContainerSharedAsync<T, Manager> {
     ContainerSharedAsync::from(Container::new(value, manager))
   }
 
+  /// Create a new [`ContainerSharedAsync`] from a [`Container`], allowing at most `max_readers` tasks
+  /// to hold immutable access at the same time. Further readers wait in line, behind any pending writers.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `max_readers` is zero or greater than `u32::MAX >> 3`.
+  pub fn with_max_readers(container: Container<T, Manager>, max_readers: u32) -> Self {
+    assert!(max_readers > 0, "max_readers must be greater than zero");
+    ContainerSharedAsync {
+      ptr: Arc::new(RwLock::with_max_readers(container, max_readers)),
+      pool: BlockingPool::global(),
+      changes: broadcast::channel(64).0,
+      autosave: Arc::new(Mutex::new(None)),
+      panics: Arc::new(Panics::default())
+    }
+  }
+
   /// Returns the inner owned [`Container`], as long as there are no other existing pointers.
   /// Otherwise, the same [`ContainerSharedAsync`] is returned back.
   pub fn try_unwrap(self) -> Result<Container<T, Manager>, Self> {
     match Arc::try_unwrap(self.ptr) {
       Ok(inner) => Ok(RwLock::into_inner(inner)),
-      Err(ptr) => Err(ContainerSharedAsync { ptr })
+      Err(ptr) => Err(ContainerSharedAsync { ptr, pool: self.pool, changes: self.changes, autosave: self.autosave, panics: self.panics })
     }
   }
 
@@ -70,28 +137,115 @@ impl<T, Manager> ContainerSharedAsync<T, Manager> {
     Arc::get_mut(&mut self.ptr).map(RwLock::get_mut)
   }
 
+  /// Runs the blocking work of this handle on the given [`BlockingPool`].
+  ///
+  /// The pool is inherited by clones made from this handle afterwards, but not by existing clones.
+  /// Opening a container and committing through an [`OwnedAccessGuardMut`] always use tokio's global blocking pool.
+  #[inline]
+  pub fn with_blocking_pool(self, pool: BlockingPool) -> Self {
+    ContainerSharedAsync { pool, ..self }
+  }
+
+  /// Returns the [`BlockingPool`] that this handle runs its blocking work on.
+  #[inline]
+  pub fn blocking_pool(&self) -> &BlockingPool {
+    &self.pool
+  }
+
+  /// Returns a receiver that is sent a [`ChangeEvent`] whenever the state is committed, overwritten or refreshed.
+  ///
+  /// Only changes made through [`ContainerSharedAsync`]'s own methods are observed, committing through
+  /// an access guard or the underlying [`Container`] will not notify subscribers.
+  /// At most 64 events are buffered for each receiver, after which the oldest are skipped
+  /// (see [`broadcast::error::RecvError::Lagged`]).
+  ///
+  /// This function does not acquire any lock on the shared state.
+  pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+    self.changes.subscribe()
+  }
+
+  /// Registers a callback to be invoked with the state right before every commit made through any handle to this container.
+  /// See [`Container::on_before_commit`].
+  ///
+  /// Callbacks are invoked while a lock is held on the shared state, so they must not attempt to access the container.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn on_before_commit<F>(&self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    AccessGuardMut::container_mut(&mut self.access_mut().await).on_before_commit(callback);
+  }
+
+  /// Registers a callback to be invoked with the state after every successful commit made through any handle to this container.
+  /// See [`Container::on_after_commit`].
+  ///
+  /// Callbacks are invoked while a lock is held on the shared state, so they must not attempt to access the container.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn on_after_commit<F>(&self, callback: F)
+  where F: Fn(&T) + Send + Sync + 'static {
+    AccessGuardMut::container_mut(&mut self.access_mut().await).on_after_commit(callback);
+  }
+
+  fn notify(&self, event: ChangeEvent) {
+    // sending only fails if there are no subscribers
+    let _ = self.changes.send(event);
+  }
+
+  /// Returns the [`PanicPolicy`] of this container.
+  pub fn panic_policy(&self) -> PanicPolicy<T> {
+    self.panics.policy()
+  }
+
+  /// Sets the [`PanicPolicy`] of this container, affecting every handle to it.
+  pub fn set_panic_policy(&self, policy: PanicPolicy<T>) {
+    self.panics.set_policy(policy);
+  }
+
+  /// Returns `true` if an operation panicked under [`PanicPolicy::Poison`],
+  /// and the state has not been replaced or explicitly cleared since.
+  pub fn is_poisoned(&self) -> bool {
+    self.panics.is_poisoned()
+  }
+
+  /// Clears the poison of this container, allowing the current state to be committed again.
+  pub fn clear_poison(&self) {
+    self.panics.clear();
+  }
+
   /// Gets immutable access to the underlying container and value `T`.
   #[inline]
   pub async fn access(&self) -> AccessGuard<'_, T, Manager> {
-    AccessGuard::new(self.ptr.read().await)
+    let stopwatch = Stopwatch::start();
+    let guard = self.ptr.read().await;
+    stopwatch.finish(Operation::LockWait, None);
+    AccessGuard::new(guard)
   }
 
   /// Gets mutable access to the underlying container and value `T`.
   #[inline]
   pub async fn access_mut(&self) -> AccessGuardMut<'_, T, Manager> {
-    AccessGuardMut::new(self.ptr.write().await)
+    let stopwatch = Stopwatch::start();
+    let guard = self.ptr.write().await;
+    stopwatch.finish(Operation::LockWait, None);
+    AccessGuardMut::new(guard)
   }
 
   /// Gets owned immutable access to the underlying container and value `T`.
   #[inline]
   pub async fn access_owned(&self) -> OwnedAccessGuard<T, Manager> {
-    OwnedAccessGuard::new(self.ptr.clone().read_owned().await)
+    let stopwatch = Stopwatch::start();
+    let guard = self.ptr.clone().read_owned().await;
+    stopwatch.finish(Operation::LockWait, None);
+    OwnedAccessGuard::new(guard)
   }
 
   /// Gets owned mutable access to the underlying container and value `T`.
   #[inline]
   pub async fn access_owned_mut(&self) -> OwnedAccessGuardMut<T, Manager> {
-    OwnedAccessGuardMut::new(self.ptr.clone().write_owned().await)
+    let stopwatch = Stopwatch::start();
+    let guard = self.ptr.clone().write_owned().await;
+    stopwatch.finish(Operation::LockWait, None);
+    OwnedAccessGuardMut::new(guard)
   }
 
   /// Tries to get immutable access to the underlying container and value `T` without blocking.
@@ -118,6 +272,51 @@ impl<T, Manager> ContainerSharedAsync<T, Manager> {
     self.ptr.clone().try_write_owned().map(OwnedAccessGuardMut::new).ok()
   }
 
+  /// Gets immutable access to the underlying container and value `T`,
+  /// giving up if access could not be acquired within the given timeout.
+  pub async fn access_timeout(&self, timeout: Duration) -> Result<AccessGuard<'_, T, Manager>, TimedOut> {
+    tokio::time::timeout(timeout, self.access()).await.map_err(|_| TimedOut)
+  }
+
+  /// Gets mutable access to the underlying container and value `T`,
+  /// giving up if access could not be acquired within the given timeout.
+  pub async fn access_mut_timeout(&self, timeout: Duration) -> Result<AccessGuardMut<'_, T, Manager>, TimedOut> {
+    tokio::time::timeout(timeout, self.access_mut()).await.map_err(|_| TimedOut)
+  }
+
+  /// Gets owned immutable access to the underlying container and value `T`,
+  /// giving up if access could not be acquired within the given timeout.
+  pub async fn access_owned_timeout(&self, timeout: Duration) -> Result<OwnedAccessGuard<T, Manager>, TimedOut> {
+    tokio::time::timeout(timeout, self.access_owned()).await.map_err(|_| TimedOut)
+  }
+
+  /// Gets owned mutable access to the underlying container and value `T`,
+  /// giving up if access could not be acquired within the given timeout.
+  pub async fn access_owned_mut_timeout(&self, timeout: Duration) -> Result<OwnedAccessGuardMut<T, Manager>, TimedOut> {
+    tokio::time::timeout(timeout, self.access_owned_mut()).await.map_err(|_| TimedOut)
+  }
+
+  /// Returns the number of successful commits (including overwrites) made through this container.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn commit_count(&self) -> u64 {
+    self.access().await.container().commit_count()
+  }
+
+  /// Returns the time of the last successful commit (or overwrite) made through this container, if any.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn last_commit_at(&self) -> Option<SystemTime> {
+    self.access().await.container().last_commit_at()
+  }
+
+  /// Returns the time of the last successful refresh made through this container, if any.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn last_refresh_at(&self) -> Option<SystemTime> {
+    self.access().await.container().last_refresh_at()
+  }
+
   /// Grants the caller immutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   ///
@@ -130,10 +329,26 @@ impl<T, Manager> ContainerSharedAsync<T, Manager> {
   /// Grants the caller mutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   ///
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the panic is propagated.
+  ///
   /// This function acquires a mutable lock on the shared state.
   pub async fn operate_mut<F, R>(&self, operation: F) -> R
   where F: FnOnce(&mut T) -> R {
-    operation(&mut *self.access_mut().await)
+    self.panics.operate(&mut *self.access_mut().await, operation)
+  }
+
+  /// Identical to [`ContainerSharedAsync::operate`], however gives up with [`TimedOut`]
+  /// if the lock could not be acquired within the given timeout.
+  pub async fn operate_timeout<F, R>(&self, timeout: Duration, operation: F) -> Result<R, TimedOut>
+  where F: FnOnce(&T) -> R {
+    Ok(operation(&*self.access_timeout(timeout).await?))
+  }
+
+  /// Identical to [`ContainerSharedAsync::operate_mut`], however gives up with [`TimedOut`]
+  /// if the lock could not be acquired within the given timeout.
+  pub async fn operate_mut_timeout<F, R>(&self, timeout: Duration, operation: F) -> Result<R, TimedOut>
+  where F: FnOnce(&mut T) -> R {
+    Ok(self.panics.operate(&mut *self.access_mut_timeout(timeout).await?, operation))
   }
 }
 
@@ -149,33 +364,49 @@ where
   pub async fn open<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
   where Mode: Reading {
     let path = path.as_ref().to_owned();
-    spawn_blocking!(Container::<T, _>::open(path, format)).map(From::from)
+    spawn_blocking!(Container::<T, _>::open(path, format))?.map(From::from)
+  }
+
+  /// Creates a new [`ContainerSharedAsync`] from a file handle that has already been opened, reading its value from the file.
+  /// See [`FileManager::from_file`] for more information.
+  pub async fn from_file(file: File, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
+    spawn_blocking!(Container::<T, _>::from_file(file, format))?.map(From::from)
   }
 
   /// Opens a new [`ContainerSharedAsync`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
   pub async fn create_overwrite<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
     let path = path.as_ref().to_owned();
-    spawn_blocking!(Container::<T, _>::create_overwrite(path, format, value)).map(From::from)
+    spawn_blocking!(Container::<T, _>::create_overwrite(path, format, value))?.map(From::from)
   }
 
   /// Opens a new [`ContainerSharedAsync`], writing the given value to the file if it does not exist.
-  pub async fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
+  pub async fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
     let path = path.as_ref().to_owned();
-    spawn_blocking!(Container::<T, _>::create_or(path, format, value)).map(From::from)
+    spawn_blocking!(Container::<T, _>::create_or(path, format, value))?.map(From::from)
   }
 
   /// Opens a new [`ContainerSharedAsync`], writing the result of the given closure to the file if it does not exist.
   pub async fn create_or_else<P: AsRef<Path>, C>(path: P, format: Format, closure: C) -> Result<Self, Error<Format::FormatError>>
-  where C: FnOnce() -> T + Send + 'static {
+  where C: FnOnce() -> T + Send + 'static, Mode: Reading {
     let path = path.as_ref().to_owned();
-    spawn_blocking!(Container::<T, _>::create_or_else(path, format, closure)).map(From::from)
+    spawn_blocking!(Container::<T, _>::create_or_else(path, format, closure))?.map(From::from)
   }
 
   /// Opens a new [`ContainerSharedAsync`], writing the default value of `T` to the file if it does not exist.
   pub async fn create_or_default<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
-  where T: Default {
+  where T: Default, Mode: Reading {
+    let path = path.as_ref().to_owned();
+    spawn_blocking!(Container::<T, _>::create_or_default(path, format))?.map(From::from)
+  }
+
+  /// Opens a new [`ContainerSharedAsync`] like [`ContainerSharedAsync::create_or_default`], first creating the parent directories
+  /// of the file if they do not exist.
+  pub async fn create_or_default_with_dirs<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
     let path = path.as_ref().to_owned();
-    spawn_blocking!(Container::<T, _>::create_or_default(path, format)).map(From::from)
+    spawn_blocking!(Container::<T, _>::create_or_default_with_dirs(path, format))?.map(From::from)
   }
 }
 
@@ -190,21 +421,27 @@ where
   /// Grants the caller immutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   /// The contents of `operation` will be treated as if they will block,
-  /// and will be called through [`tokio::task::spawn_blocking`].
-  pub async fn operate_nonblocking<F, R>(&self, operation: F) -> R
+  /// and will be called on the container's [`BlockingPool`].
+  ///
+  /// Returns an error if `operation` panicked, or if the task was cancelled because the runtime is shutting down.
+  pub async fn operate_nonblocking<F, R>(&self, operation: F) -> Result<R, TaskFailed>
   where F: FnOnce(&T) -> R + Send + 'static, R: Send + 'static {
     let guard = self.access_owned().await;
-    spawn_blocking!(operation(&guard))
+    spawn_blocking!(self.pool, operation(&guard))
   }
 
   /// Grants the caller mutable access to the underlying value `T`,
   /// but only for the duration of the provided function or closure.
   /// The contents of `operation` will be treated as if they will block,
-  /// and will be called through [`tokio::task::spawn_blocking`].
-  pub async fn operate_mut_nonblocking<F, R>(&self, operation: F) -> R
+  /// and will be called on the container's [`BlockingPool`].
+  ///
+  /// Returns an error if `operation` panicked, or if the task was cancelled because the runtime is shutting down.
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the error is returned.
+  pub async fn operate_mut_nonblocking<F, R>(&self, operation: F) -> Result<R, TaskFailed>
   where F: FnOnce(&mut T) -> R + Send + 'static, R: Send + 'static {
     let mut guard = self.access_owned_mut().await;
-    spawn_blocking!(operation(&mut guard))
+    let panics = Arc::clone(&self.panics);
+    spawn_blocking!(self.pool, panics.operate(&mut guard, operation))
   }
 
   /// Reads a value from the managed file, replacing the current state in memory,
@@ -217,7 +454,9 @@ where
   pub async fn operate_refresh<F, R>(&self, operation: F) -> Result<R, Error<Format::FormatError>>
   where Mode: Reading, F: FnOnce(&T, T) -> R {
     let mut guard = self.access_owned_mut().await;
-    let (old_value, guard) = spawn_blocking!(guard.container_mut().refresh().map(|t| (t, guard)))?;
+    let (old_value, guard) = spawn_blocking!(self.pool, guard.container_mut().refresh().map(|t| (t, guard)))??;
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
     let guard = OwnedAccessGuardMut::downgrade(guard);
     Ok(operation(&guard, old_value))
   }
@@ -226,11 +465,74 @@ where
   /// but only for the duration of the provided function or closure,
   /// immediately committing any changes made as long as no error was returned.
   ///
+  /// Fails with [`UserError::Poisoned`] without running the operation if the container is poisoned.
+  /// If the operation panics, the container's [`PanicPolicy`] is applied before the panic is propagated.
+  ///
   /// This function acquires a mutable lock on the shared state.
   pub async fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
   where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
     let mut guard = self.access_owned_mut().await;
-    let ret = operation(&mut guard).map_err(UserError::User)?;
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
+    self.commit_guard(OwnedAccessGuardMut::downgrade(guard)).await?;
+    Ok(ret)
+  }
+
+  /// Grants the caller mutable access to a copy of the underlying value `T`,
+  /// but only for the duration of the provided function or closure,
+  /// replacing the state with that copy and committing it if no error was returned.
+  ///
+  /// If the operation panics or returns an error, or if the commit fails, the state is left unchanged,
+  /// so neither the file nor the state in memory can ever reflect a partially applied operation.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn operate_mut_commit_staged<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_owned_mut().await;
+    self.panics.check()?;
+    let mut staged = T::clone(&guard);
+    let ret = operation(&mut staged).map_err(UserError::User)?;
+    let previous = mem::replace(&mut *guard, staged);
+    let (result, mut guard) = spawn_blocking!(self.pool, {
+      let result = panic::catch_unwind(AssertUnwindSafe(|| guard.container().commit()));
+      (result, guard)
+    })?;
+
+    match result {
+      Ok(Ok(())) => (),
+      Ok(Err(err)) => {
+        *guard = previous;
+        return Err(err.into());
+      },
+      Err(payload) => {
+        *guard = previous;
+        return Err(TaskFailed::panicked(payload).into());
+      }
+    };
+
+    self.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
+  /// Runs a transaction on the state, identical to [`ContainerSharedAsync::operate_mut_commit_staged`].
+  /// See [`Container::transaction`].
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  #[inline]
+  pub async fn transaction<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where T: Clone, Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    self.operate_mut_commit_staged(operation).await
+  }
+
+  /// Identical to [`ContainerSharedAsync::operate_mut_commit`], however gives up with
+  /// [`UserError::TimedOut`] if the lock could not be acquired within the given timeout.
+  ///
+  /// The timeout only applies to acquiring the lock, not to the operation or the commit.
+  pub async fn operate_mut_commit_timeout<F, R, U>(&self, timeout: Duration, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_owned_mut_timeout(timeout).await?;
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
     self.commit_guard(OwnedAccessGuardMut::downgrade(guard)).await?;
     Ok(ret)
   }
@@ -243,7 +545,27 @@ where
   pub async fn refresh(&self) -> Result<T, Error<Format::FormatError>>
   where Mode: Reading {
     let mut guard = self.access_owned_mut().await;
-    spawn_blocking!(guard.container_mut().refresh())
+    let old_value = spawn_blocking!(self.pool, guard.container_mut().refresh())??;
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
+    Ok(old_value)
+  }
+
+  /// Reads a value from the managed file like [`ContainerSharedAsync::refresh`], but only if the file has changed
+  /// since it was last read or written, returning `None` otherwise.
+  /// See [`Container::refresh_if_changed`] for more information.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn refresh_if_changed(&self) -> Result<Option<T>, Error<Format::FormatError>>
+  where Mode: Reading {
+    let mut guard = self.access_owned_mut().await;
+    let old_value = spawn_blocking!(self.pool, guard.container_mut().refresh_if_changed())??;
+    if old_value.is_some() {
+      self.panics.clear();
+      self.notify(ChangeEvent::Refresh);
+    };
+
+    Ok(old_value)
   }
 
   /// Writes the current in-memory state to the managed file.
@@ -257,30 +579,469 @@ where
   }
 
   /// Writes to the managed file given an access guard.
+  ///
+  /// Fails with [`Error::Poisoned`] if the container is poisoned.
   pub async fn commit_guard(&self, guard: OwnedAccessGuard<T, FileManager<Format, Lock, Mode>>)
   -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
-    spawn_blocking!(guard.container().commit())
+    self.panics.check()?;
+    spawn_blocking!(self.pool, guard.container().commit())??;
+    self.notify(ChangeEvent::Commit);
+    Ok(())
   }
 
   /// Writes the given state to the managed file, replacing the in-memory state.
   pub async fn overwrite(&self, value: T) -> Result<(), Error<Format::FormatError>>
   where Mode: Writing {
     let mut guard = self.access_owned_mut().await;
-    spawn_blocking!(guard.container_mut().overwrite(value))
+    spawn_blocking!(self.pool, guard.container_mut().overwrite(value))??;
+    self.panics.clear();
+    self.notify(ChangeEvent::Overwrite);
+    Ok(())
+  }
+
+  /// Writes the current in-memory state to a file at the given path, creating it if it does not exist
+  /// and overwriting its contents if it does, returning a new, independent [`ContainerSharedAsync`] that manages it.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn fork<P: AsRef<Path>>(&self, path: P) -> Result<Self, Error<Format::FormatError>>
+  where T: Clone, Format: Clone, Lock: FileLock, Mode: FileMode {
+    let path = path.as_ref().to_owned();
+    let guard = self.access_owned().await;
+    let pool = self.pool.clone();
+    spawn_blocking!(self.pool, guard.container().fork(path))?
+      .map(|container| ContainerSharedAsync::from(container).with_blocking_pool(pool))
+  }
+
+  /// Starts a task that commits the state whenever it is dirty, at most once per `interval`,
+  /// replacing any autosave that was already enabled for this container.
+  ///
+  /// The state is dirty after any mutable access to it, see [`Container::is_dirty`].
+  /// Failed commits are retried after the next interval, use [`ContainerSharedAsync::flush`] to observe errors.
+  /// When the last handle to this container is dropped, the task commits the state one final time if it is dirty,
+  /// without the dropping task waiting for it.
+  ///
+  /// The autosave task holds its own handle to this container until autosave is disabled,
+  /// so [`ContainerSharedAsync::try_unwrap`] and [`ContainerSharedAsync::get_mut`] fail until then.
+  ///
+  /// # Panics
+  /// Panics if called from outside of a Tokio runtime.
+  pub fn enable_autosave(&self, interval: Duration)
+  where Lock: Send + Sync, Mode: Writing + Send + Sync {
+    let handle = ContainerSharedAsync {
+      ptr: Arc::clone(&self.ptr),
+      pool: self.pool.clone(),
+      changes: self.changes.clone(),
+      autosave: Arc::new(Mutex::new(None)),
+      panics: Arc::clone(&self.panics)
+    };
+
+    let autosave = Autosave::spawn(handle, interval);
+    let previous = self.autosave.lock().unwrap_or_else(|err| err.into_inner()).replace(autosave);
+    if let Some(previous) = previous {
+      // the previous task is stopped in the background
+      tokio::spawn(previous.disable());
+    };
+  }
+
+  /// Stops the autosave started by [`ContainerSharedAsync::enable_autosave`], if any,
+  /// waiting for its task to finish.
+  ///
+  /// The state is not committed, even if it is dirty.
+  pub async fn disable_autosave(&self) {
+    let autosave = self.autosave.lock().unwrap_or_else(|err| err.into_inner()).take();
+    if let Some(autosave) = autosave {
+      autosave.disable().await;
+    };
+  }
+
+  /// Stops the autosave started by [`ContainerSharedAsync::enable_autosave`], if any,
+  /// waiting for its task to commit the state one final time if it is dirty and then finish.
+  ///
+  /// Since the autosave task does not report errors, the state is flushed once more afterwards,
+  /// returning the error if the final commit of the task failed.
+  /// Unlike [`ContainerSharedAsync::into_inner_graceful`], this does not consume the container.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn shutdown_autosave(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    let autosave = self.autosave.lock().unwrap_or_else(|err| err.into_inner()).take();
+    if let Some(autosave) = autosave {
+      autosave.shutdown().await;
+    };
+
+    self.flush().await.map(|_| ())
+  }
+
+  /// Returns `true` if autosave has been enabled for this container with [`ContainerSharedAsync::enable_autosave`].
+  pub fn is_autosave_enabled(&self) -> bool {
+    self.autosave.lock().unwrap_or_else(|err| err.into_inner()).is_some()
+  }
+
+  /// Starts a task that checks the managed file once per `interval`, refreshing the state
+  /// whenever the file has changed on disk, until the returned [`AutoRefresh`] is dropped.
+  ///
+  /// This is a polling alternative to watching the file, suited to files edited by hand or by other processes.
+  /// Each refresh is announced to the receivers returned by [`AutoRefresh::subscribe`]. Refreshing replaces
+  /// the in-memory state, discarding any changes to it that have not been committed.
+  ///
+  /// The task holds its own handle to this container until the returned [`AutoRefresh`] is dropped,
+  /// so [`ContainerSharedAsync::try_unwrap`] and [`ContainerSharedAsync::get_mut`] fail until then.
+  ///
+  /// # Panics
+  /// Panics if called from outside of a Tokio runtime.
+  pub fn spawn_auto_refresh(&self, interval: Duration) -> AutoRefresh<Format::FormatError>
+  where Lock: Send + Sync, Mode: Reading + Send + Sync {
+    AutoRefresh::spawn(self.clone(), interval)
+  }
+
+  /// Creates a [`CommitCoalescer`] for this container, which collects changes for `window`
+  /// before writing them to disk with a single commit.
+  ///
+  /// The coalescer holds its own handle to this container, as do its pending commits.
+  pub fn commit_coalescer(&self, window: Duration) -> CommitCoalescer<T, Format, Lock, Mode>
+  where Format::FormatError: Sync, Lock: Send + Sync, Mode: Writing + Send + Sync {
+    CommitCoalescer::new(self.clone(), window)
+  }
+
+  /// Writes the current in-memory state to the managed file if it is dirty, see [`Container::commit_if_dirty`].
+  ///
+  /// Returns `true` if the state was dirty, and has been committed.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn flush(&self) -> Result<bool, Error<Format::FormatError>>
+  where Mode: Writing {
+    let guard = self.access_owned().await;
+    self.commit_dirty(guard).await
+  }
+
+  async fn commit_dirty(&self, guard: OwnedAccessGuard<T, FileManager<Format, Lock, Mode>>)
+  -> Result<bool, Error<Format::FormatError>>
+  where Mode: Writing {
+    if !guard.container().is_dirty() {
+      return Ok(false);
+    };
+
+    self.commit_guard(guard).await.map(|()| true)
+  }
+
+  /// Shuts this container down gracefully, returning the final state.
+  ///
+  /// This waits for every in-flight operation to finish (including commits whose futures have been dropped,
+  /// but whose blocking tasks are still running), commits the current state, then unlocks and closes the managed file.
+  ///
+  /// Every other handle to this container must have been dropped by the time in-flight operations have finished.
+  /// Otherwise, an [`io::ErrorKind::Other`] error is returned after the state has been committed,
+  /// and the file is left open for the remaining handles. Autosave is disabled beforehand, for every handle.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn into_inner_graceful(self) -> Result<T, Error<Format::FormatError>>
+  where Lock: FileLock, Mode: Writing {
+    self.disable_autosave().await;
+    let guard = self.access_owned_mut().await;
+    self.panics.check()?;
+    spawn_blocking!(self.pool, guard.container().commit())??;
+    let pool = self.pool.clone();
+    let container = self.try_unwrap().map_err(|_| {
+      io::Error::new(io::ErrorKind::Other, "container is still shared by other handles")
+    })?;
+
+    spawn_blocking!(pool, container.close())?.map_err(Error::from)
+  }
+}
+
+impl<T, Format, Lock, Mode> ContainerSharedAsync<T, AsyncFileManager<Format, Lock, Mode>>
+where Format: AsyncFileFormat<T>, Lock: FileLock, Mode: AsyncMode {
+  /// Opens a new [`ContainerSharedAsync`], returning an error if the file at the given path does not exist.
+  pub async fn open<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
+    let manager = AsyncFileManager::open(path, format).await?;
+    let value = manager.read().await?;
+    Ok(ContainerSharedAsync::new(value, manager))
+  }
+
+  /// Opens a new [`ContainerSharedAsync`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
+  pub async fn create_overwrite<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>> {
+    let (value, manager) = AsyncFileManager::create_overwrite(path, format, value).await?;
+    Ok(ContainerSharedAsync::new(value, manager))
+  }
+
+  /// Opens a new [`ContainerSharedAsync`], writing the given value to the file if it does not exist.
+  pub async fn create_or<P: AsRef<Path>>(path: P, format: Format, value: T) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Reading {
+    let (value, manager) = AsyncFileManager::create_or(path, format, value).await?;
+    Ok(ContainerSharedAsync::new(value, manager))
+  }
+
+  /// Opens a new [`ContainerSharedAsync`], writing the result of the given closure to the file if it does not exist.
+  pub async fn create_or_else<P: AsRef<Path>, C>(path: P, format: Format, closure: C) -> Result<Self, Error<Format::FormatError>>
+  where C: FnOnce() -> T, Mode: Reading {
+    let (value, manager) = AsyncFileManager::create_or_else(path, format, closure).await?;
+    Ok(ContainerSharedAsync::new(value, manager))
+  }
+
+  /// Opens a new [`ContainerSharedAsync`], writing the default value of `T` to the file if it does not exist.
+  pub async fn create_or_default<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    let (value, manager) = AsyncFileManager::create_or_default(path, format).await?;
+    Ok(ContainerSharedAsync::new(value, manager))
+  }
+
+  /// Opens a new [`ContainerSharedAsync`] like [`ContainerSharedAsync::create_or_default`], first creating the parent directories
+  /// of the file if they do not exist.
+  pub async fn create_or_default_with_dirs<P: AsRef<Path>>(path: P, format: Format) -> Result<Self, Error<Format::FormatError>>
+  where T: Default, Mode: Reading {
+    if let Some(parent) = path.as_ref().parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    };
+
+    Self::create_or_default(path, format).await
+  }
+
+  /// Grants the caller mutable access to the underlying value `T`,
+  /// but only for the duration of the provided function or closure,
+  /// immediately committing any changes made as long as no error was returned.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let guard = self.access_mut().await;
+    self.operate_mut_commit_guard(guard, operation).await
+  }
+
+  /// Identical to [`ContainerSharedAsync::operate_mut_commit`], however gives up with
+  /// [`UserError::TimedOut`] if the lock could not be acquired within the given timeout.
+  ///
+  /// The timeout only applies to acquiring the lock, not to the operation or the commit.
+  pub async fn operate_mut_commit_timeout<F, R, U>(&self, timeout: Duration, operation: F) -> Result<R, UserError<Format::FormatError, U>>
+  where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    let guard = self.access_mut_timeout(timeout).await?;
+    self.operate_mut_commit_guard(guard, operation).await
+  }
+
+  async fn operate_mut_commit_guard<F, R, U>(
+    &self,
+    mut guard: AccessGuardMut<'_, T, AsyncFileManager<Format, Lock, Mode>>,
+    operation: F
+  ) -> Result<R, UserError<Format::FormatError, U>>
+  where Mode: Writing, F: FnOnce(&mut T) -> Result<R, U> {
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
+    let container = AccessGuardMut::container_mut(&mut guard);
+    container.hooks.before(&container.value);
+    container.manager.write(&container.value).await?;
+    container.stats.record_commit();
+    container.hooks.after(&container.value);
+    self.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
+  /// Reads a value from the managed file, replacing the current state in memory.
+  ///
+  /// Returns the value of the previous state if the operation succeeded.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn refresh(&self) -> Result<T, Error<Format::FormatError>>
+  where Mode: Reading {
+    let mut guard = self.access_mut().await;
+    let container = AccessGuardMut::container_mut(&mut guard);
+    let value = container.manager.read().await?;
+    container.stats.record_refresh();
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
+    Ok(mem::replace(&mut container.value, value))
+  }
+
+  /// Writes the current in-memory state to the managed file.
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn commit(&self) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    let guard = self.access().await;
+    self.panics.check()?;
+    let container = AccessGuard::container(&guard);
+    container.hooks.before(&container.value);
+    container.manager.write(&container.value).await?;
+    container.stats.record_commit();
+    container.hooks.after(&container.value);
+    self.notify(ChangeEvent::Commit);
+    Ok(())
+  }
+
+  /// Writes the given state to the managed file, replacing the in-memory state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn overwrite(&self, value: T) -> Result<(), Error<Format::FormatError>>
+  where Mode: Writing {
+    let mut guard = self.access_mut().await;
+    let container = AccessGuardMut::container_mut(&mut guard);
+    container.hooks.before(&value);
+    container.manager.write(&value).await?;
+    container.value = value;
+    container.stats.record_commit();
+    container.hooks.after(&container.value);
+    self.panics.clear();
+    self.notify(ChangeEvent::Overwrite);
+    Ok(())
+  }
+}
+
+impl<T> ContainerSharedAsync<T, ()> {
+  /// Attaches a manager to this memory-only [`ContainerSharedAsync`], returning a new [`ContainerSharedAsync`] that uses it.
+  /// See [`Container::attach_manager`].
+  ///
+  /// Subscribers, the blocking pool, the panic policy and the poison status are kept. Fails, returning this container
+  /// and the manager back, if there are other existing pointers to this container.
+  pub fn attach_manager<Manager>(self, manager: Manager) -> Result<ContainerSharedAsync<T, Manager>, (Self, Manager)> {
+    match Arc::try_unwrap(self.ptr) {
+      Ok(inner) => Ok(ContainerSharedAsync {
+        ptr: Arc::new(RwLock::new(RwLock::into_inner(inner).attach_manager(manager))),
+        pool: self.pool,
+        changes: self.changes,
+        autosave: Arc::new(Mutex::new(None)),
+        panics: self.panics
+      }),
+      Err(ptr) => {
+        let container = ContainerSharedAsync { ptr, pool: self.pool, changes: self.changes, autosave: self.autosave, panics: self.panics };
+        Err((container, manager))
+      }
+    }
+  }
+
+  /// Does nothing, since there is no managed file to read from,
+  /// immediately granting the caller immutable access to the current state
+  /// for the duration of the provided function or closure.
+  ///
+  /// The provided closure takes (1) a reference to the state, and (2) a clone of that same state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn operate_refresh<F, R>(&self, operation: F) -> Result<R, Error<Infallible>>
+  where T: Clone, F: FnOnce(&T, T) -> R {
+    let mut guard = self.access_mut().await;
+    let old_value = guard.container_mut().refresh()?;
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
+    let guard = AccessGuardMut::downgrade(guard);
+    Ok(operation(&guard, old_value))
+  }
+
+  /// Grants the caller mutable access to the underlying value `T`,
+  /// but only for the duration of the provided function or closure.
+  /// There is no managed file, so nothing is committed.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<R, UserError<Infallible, U>>
+  where F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_mut().await;
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
+    AccessGuardMut::container(&guard).commit()?;
+    self.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
+  /// Identical to [`ContainerSharedAsync::operate_mut_commit`], however gives up with
+  /// [`UserError::TimedOut`] if the lock could not be acquired within the given timeout.
+  pub async fn operate_mut_commit_timeout<F, R, U>(&self, timeout: Duration, operation: F) -> Result<R, UserError<Infallible, U>>
+  where F: FnOnce(&mut T) -> Result<R, U> {
+    let mut guard = self.access_mut_timeout(timeout).await?;
+    self.panics.check()?;
+    let ret = self.panics.operate(&mut guard, operation).map_err(UserError::User)?;
+    AccessGuardMut::container(&guard).commit()?;
+    self.notify(ChangeEvent::Commit);
+    Ok(ret)
+  }
+
+  /// Does nothing, since there is no managed file to read from.
+  /// Returns a clone of the current state as the previous state.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn refresh(&self) -> Result<T, Error<Infallible>>
+  where T: Clone {
+    let old_value = AccessGuardMut::container_mut(&mut self.access_mut().await).refresh()?;
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
+    Ok(old_value)
+  }
+
+  /// Does nothing, since there is no managed file to write to.
+  ///
+  /// Fails with [`Error::Poisoned`] if the container is poisoned.
+  pub async fn commit(&self) -> Result<(), Error<Infallible>> {
+    self.panics.check()?;
+    AccessGuard::container(&self.access().await).commit()?;
+    self.notify(ChangeEvent::Commit);
+    Ok(())
+  }
+
+  /// Does nothing, since there is no managed file to write to.
+  ///
+  /// Fails with [`Error::Poisoned`] if the container is poisoned.
+  pub async fn commit_guard(&self, guard: OwnedAccessGuard<T, ()>) -> Result<(), Error<Infallible>> {
+    self.panics.check()?;
+    OwnedAccessGuard::container(&guard).commit()?;
+    self.notify(ChangeEvent::Commit);
+    Ok(())
+  }
+
+  /// Replaces the in-memory state, there is no managed file to write to.
+  pub async fn overwrite(&self, value: T) -> Result<(), Error<Infallible>> {
+    AccessGuardMut::container_mut(&mut self.access_mut().await).overwrite(value)?;
+    self.panics.clear();
+    self.notify(ChangeEvent::Overwrite);
+    Ok(())
+  }
+
+  /// Waits for every in-flight operation to finish, returning the final state.
+  ///
+  /// Every other handle to this container must have been dropped by the time in-flight operations have finished,
+  /// otherwise an [`io::ErrorKind::Other`] error is returned.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn into_inner_graceful(self) -> Result<T, Error<Infallible>> {
+    drop(self.access_mut().await);
+    self.try_unwrap()
+      .map(Container::into_value)
+      .map_err(|_| io::Error::new(io::ErrorKind::Other, "container is still shared by other handles").into())
   }
 }
 
 impl<T, Manager> Clone for ContainerSharedAsync<T, Manager> {
   #[inline]
   fn clone(&self) -> Self {
-    ContainerSharedAsync { ptr: Arc::clone(&self.ptr) }
+    ContainerSharedAsync {
+      ptr: Arc::clone(&self.ptr),
+      pool: self.pool.clone(),
+      changes: self.changes.clone(),
+      autosave: Arc::clone(&self.autosave),
+      panics: Arc::clone(&self.panics)
+    }
+  }
+}
+
+impl<T> From<T> for ContainerSharedAsync<T, ()> {
+  #[inline]
+  fn from(value: T) -> Self {
+    ContainerSharedAsync::new(value, ())
+  }
+}
+
+impl<T: Default> Default for ContainerSharedAsync<T, ()> {
+  #[inline]
+  fn default() -> Self {
+    ContainerSharedAsync::new(T::default(), ())
   }
 }
 
 impl<T, Manager> From<Container<T, Manager>> for ContainerSharedAsync<T, Manager> {
   #[inline]
   fn from(container: Container<T, Manager>) -> Self {
-    ContainerSharedAsync { ptr: Arc::new(RwLock::new(container)) }
+    ContainerSharedAsync {
+      ptr: Arc::new(RwLock::new(container)),
+      pool: BlockingPool::global(),
+      changes: broadcast::channel(64).0,
+      autosave: Arc::new(Mutex::new(None)),
+      panics: Arc::new(Panics::default())
+    }
   }
 }
@@ -0,0 +1,95 @@
+use super::ContainerSharedAsync;
+use crate::manager::{FileFormat, FileManager, Writing};
+
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// A task that commits the state of a [`ContainerSharedAsync`] whenever it is dirty, at most once per interval.
+///
+/// Dropping this structure stops the task, which commits the state one final time if it is dirty.
+/// This structure is created by [`ContainerSharedAsync::enable_autosave`].
+#[derive(Debug)]
+pub(super) struct Autosave {
+  shared: Arc<Shared>,
+  task: Option<JoinHandle<()>>
+}
+
+impl Autosave {
+  pub(super) fn spawn<T, Format, Lock, Mode>(
+    container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>,
+    interval: Duration
+  ) -> Self
+  where
+    T: Send + Sync + 'static,
+    Format: FileFormat<T> + Send + Sync + 'static,
+    Format::FormatError: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Writing + Send + Sync + 'static
+  {
+    let shared = Arc::new(Shared { stop: Notify::new(), flush_on_stop: AtomicBool::new(true) });
+    let task = tokio::spawn(run(container, Arc::clone(&shared), interval));
+    Autosave { shared, task: Some(task) }
+  }
+
+  /// Stops the task without committing the state one final time, waiting for it to finish.
+  pub(super) async fn disable(self) {
+    self.stop(false).await;
+  }
+
+  /// Stops the task after it has committed the state one final time if it is dirty, waiting for it to finish.
+  pub(super) async fn shutdown(self) {
+    self.stop(true).await;
+  }
+
+  async fn stop(mut self, flush: bool) {
+    self.shared.flush_on_stop.store(flush, Ordering::Release);
+    self.shared.stop.notify_one();
+    if let Some(task) = self.task.take() {
+      let _ = task.await;
+    };
+  }
+}
+
+impl Drop for Autosave {
+  fn drop(&mut self) {
+    // the task cannot be waited on here, it finishes (and releases its handle to the container) on its own
+    self.shared.stop.notify_one();
+  }
+}
+
+#[derive(Debug)]
+struct Shared {
+  stop: Notify,
+  flush_on_stop: AtomicBool
+}
+
+async fn run<T, Format, Lock, Mode>(
+  container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>,
+  shared: Arc<Shared>,
+  interval: Duration
+)
+where
+  T: Send + Sync + 'static,
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + 'static,
+  Lock: Send + Sync + 'static,
+  Mode: Writing + Send + Sync + 'static
+{
+  loop {
+    let stop = tokio::time::timeout(interval, shared.stop.notified()).await.is_ok();
+    if stop && !shared.flush_on_stop.load(Ordering::Acquire) { break };
+
+    // access is never waited on indefinitely, since the last handle to the container
+    // may be dropped (stopping this task) while an owned access guard is still held
+    if let Ok(guard) = tokio::time::timeout(interval, container.access_owned()).await {
+      // errors are not reported, the state stays dirty and is committed again after the next interval
+      let _ = container.commit_dirty(guard).await;
+    };
+
+    if stop { break };
+  };
+}
@@ -0,0 +1,251 @@
+use super::ContainerSharedAsync;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Writing};
+
+use tokio::sync::{watch, Notify};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+type BatchResult<FE> = Option<Result<(), Arc<Error<FE>>>>;
+
+/// Coalesces commits to a [`ContainerSharedAsync`], so that every change made within a window of time
+/// is written to disk by a single commit.
+///
+/// The first change made through [`CommitCoalescer::operate_mut_commit`] opens a batch, which is committed once
+/// the window has elapsed. Every change made until then joins that batch, and receives a [`CommitHandle`]
+/// that resolves once the batch has been written. This suits state that is updated at a high frequency,
+/// where writing the whole file on every update would be wasteful.
+///
+/// Every change is also given a version, see [`CommitHandle::version`]. Versions start at 1 and increase by 1
+/// with every change. Since every commit contains every change made before it, a version is durable once it
+/// or any later version has been successfully written, which can be waited for with [`CommitCoalescer::await_durable`].
+///
+/// Cloning a [`CommitCoalescer`] shares its batches, so clones can be handed out to every task making changes.
+/// This structure is created by [`ContainerSharedAsync::commit_coalescer`].
+pub struct CommitCoalescer<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>,
+  window: Duration,
+  batches: Arc<Mutex<Batches<Format::FormatError>>>
+}
+
+impl<T, Format, Lock, Mode> CommitCoalescer<T, Format, Lock, Mode>
+where
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + Sync + 'static,
+  Lock: Send + Sync + 'static,
+  Mode: Writing + Send + Sync + 'static,
+  T: Send + Sync + 'static
+{
+  pub(super) fn new(container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>, window: Duration) -> Self {
+    CommitCoalescer { container, window, batches: Arc::new(Mutex::new(Batches::default())) }
+  }
+
+  /// Grants the caller mutable access to the underlying value `T`, but only for the duration of the provided function
+  /// or closure, then schedules the change to be committed with the current batch (opening one if there is none).
+  ///
+  /// If the operation returns an error, nothing is scheduled, but changes it made to the state are kept in memory
+  /// and are written by the next commit. The returned [`CommitHandle`] may be dropped if the caller does not need
+  /// to wait for the change to be written.
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  ///
+  /// # Panics
+  /// Panics if called from outside of a Tokio runtime.
+  pub async fn operate_mut_commit<F, R, U>(&self, operation: F) -> Result<(R, CommitHandle<Format::FormatError>), U>
+  where F: FnOnce(&mut T) -> Result<R, U> {
+    let ret = self.container.operate_mut(operation).await?;
+    Ok((ret, self.join_batch()))
+  }
+
+  fn join_batch(&self) -> CommitHandle<Format::FormatError> {
+    let mut batches = lock(&self.batches);
+    batches.requested += 1;
+    let version = batches.requested;
+    if let Some(open) = &batches.open {
+      return CommitHandle { receiver: open.receiver.clone(), version };
+    };
+
+    let (sender, receiver) = watch::channel(None);
+    let flush = Arc::new(Notify::new());
+    batches.open = Some(OpenBatch { receiver: receiver.clone(), flush: Arc::clone(&flush) });
+    batches.unwritten.insert(version, receiver.clone());
+    drop(batches);
+
+    let container = self.container.clone();
+    let batches = Arc::clone(&self.batches);
+    let window = self.window;
+    tokio::spawn(async move {
+      // the batch is committed early if the coalescer is shut down
+      let _ = tokio::time::timeout(window, flush.notified()).await;
+      // changes made from here on open a new batch, even if they make it into this commit
+      let target = {
+        let mut batches = lock(&batches);
+        batches.open = None;
+        batches.requested
+      };
+
+      let result = container.commit().await.map_err(Arc::new);
+      if result.is_ok() {
+        let mut batches = lock(&batches);
+        batches.durable = batches.durable.max(target);
+        batches.unwritten = batches.unwritten.split_off(&(target + 1));
+      };
+
+      let _ = sender.send(Some(result));
+    });
+
+    CommitHandle { receiver, version }
+  }
+}
+
+impl<T, Format, Lock, Mode> CommitCoalescer<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  /// Gets the window of time that changes are collected for before they are committed.
+  #[inline]
+  pub const fn window(&self) -> Duration {
+    self.window
+  }
+
+  /// Gets the [`ContainerSharedAsync`] that this coalescer commits to.
+  #[inline]
+  pub const fn container(&self) -> &ContainerSharedAsync<T, FileManager<Format, Lock, Mode>> {
+    &self.container
+  }
+
+  /// Returns the latest version that has been successfully written to disk, or 0 if there is none.
+  pub fn durable_version(&self) -> u64 {
+    lock(&self.batches).durable
+  }
+
+  /// Waits until the given version has been written to disk.
+  ///
+  /// Returns the error that the commit of the batch containing the given version failed with, if it failed
+  /// and no later batch has been written successfully since. Versions that have not been handed out yet
+  /// are treated as the latest version that has been.
+  pub async fn await_durable(&self, version: u64) -> Result<(), Arc<Error<Format::FormatError>>> {
+    let receiver = {
+      let batches = lock(&self.batches);
+      let version = version.min(batches.requested);
+      if batches.durable >= version {
+        return Ok(());
+      };
+
+      // batches are only forgotten once a batch at least as recent has been written
+      batches.unwritten.range(..=version).next_back()
+        .map(|(_, receiver)| receiver.clone())
+        .expect("unwritten version has no batch")
+    };
+
+    wait_for(receiver).await
+  }
+
+  /// Commits the open batch right away instead of waiting for its window to elapse,
+  /// then waits until every change made so far has been written to disk.
+  ///
+  /// Returns the error that the last commit failed with, if every change has not been written successfully.
+  /// This does not consume the coalescer or its container, changes made afterwards open new batches as usual.
+  pub async fn shutdown(&self) -> Result<(), Arc<Error<Format::FormatError>>> {
+    let version = {
+      let batches = lock(&self.batches);
+      if let Some(open) = &batches.open {
+        open.flush.notify_one();
+      };
+
+      batches.requested
+    };
+
+    self.await_durable(version).await
+  }
+}
+
+impl<T, Format, Lock, Mode> Clone for CommitCoalescer<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  fn clone(&self) -> Self {
+    CommitCoalescer {
+      container: self.container.clone(),
+      window: self.window,
+      batches: Arc::clone(&self.batches)
+    }
+  }
+}
+
+impl<T, Format, Lock, Mode> fmt::Debug for CommitCoalescer<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CommitCoalescer")
+      .field("window", &self.window)
+      .finish_non_exhaustive()
+  }
+}
+
+/// A handle to a batch of changes scheduled by a [`CommitCoalescer`], which resolves once the batch has been written.
+///
+/// Since every change in a batch shares the outcome of the same commit, errors are shared behind an [`Arc`].
+#[derive(Debug)]
+pub struct CommitHandle<FE> {
+  receiver: watch::Receiver<BatchResult<FE>>,
+  version: u64
+}
+
+impl<FE> CommitHandle<FE> {
+  /// Returns the version of the change that this handle was returned for.
+  /// See [`CommitCoalescer::await_durable`].
+  #[inline]
+  pub const fn version(&self) -> u64 {
+    self.version
+  }
+
+  /// Waits until the batch has been committed, returning the error that the commit failed with, if any.
+  pub async fn wait(self) -> Result<(), Arc<Error<FE>>> {
+    wait_for(self.receiver).await
+  }
+
+  /// Returns `true` if the batch has been committed, whether or not the commit succeeded.
+  pub fn is_committed(&self) -> bool {
+    self.receiver.borrow().is_some()
+  }
+}
+
+struct Batches<FE> {
+  /// The batch that changes are currently joining, if any.
+  open: Option<OpenBatch<FE>>,
+  /// The latest version that has been requested.
+  requested: u64,
+  /// The latest version that has been written successfully.
+  durable: u64,
+  /// The batches that have not been written successfully, by the first version that joined them.
+  unwritten: BTreeMap<u64, watch::Receiver<BatchResult<FE>>>
+}
+
+struct OpenBatch<FE> {
+  receiver: watch::Receiver<BatchResult<FE>>,
+  /// Wakes the task of the batch to commit it before its window has elapsed.
+  flush: Arc<Notify>
+}
+
+impl<FE> Default for Batches<FE> {
+  fn default() -> Self {
+    Batches { open: None, requested: 0, durable: 0, unwritten: BTreeMap::new() }
+  }
+}
+
+fn lock<FE>(batches: &Mutex<Batches<FE>>) -> MutexGuard<'_, Batches<FE>> {
+  batches.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+async fn wait_for<FE>(mut receiver: watch::Receiver<BatchResult<FE>>) -> Result<(), Arc<Error<FE>>> {
+  loop {
+    if let Some(result) = &*receiver.borrow() {
+      return result.clone();
+    };
+
+    if receiver.changed().await.is_err() {
+      // the task was dropped without sending a result, so the runtime is shutting down
+      return Err(Arc::new(std::io::Error::new(std::io::ErrorKind::Other, "the commit was cancelled").into()));
+    };
+  };
+}
@@ -1,4 +1,6 @@
 use crate::container::Container;
+use crate::error::Error;
+use crate::manager::{FileManager, FileFormat, Writing};
 
 use std::fmt;
 use std::ops::{Deref, DerefMut};
@@ -221,6 +223,22 @@ impl<T, Manager> OwnedAccessGuardMut<T, Manager> {
   }
 }
 
+impl<T, Format, Lock, Mode> OwnedAccessGuardMut<T, FileManager<Format, Lock, Mode>>
+where
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + 'static,
+  Lock: 'static,
+  Mode: 'static,
+  T: Send + Sync + 'static
+{
+  /// Writes the current in-memory state to the managed file,
+  /// handing this guard back once the write has completed.
+  pub async fn commit(self) -> Result<Self, Error<Format::FormatError>>
+  where Mode: Writing {
+    spawn_blocking!(self.inner.commit().map(|()| self))?
+  }
+}
+
 impl<T, Manager> Deref for OwnedAccessGuardMut<T, Manager> {
   type Target = T;
 
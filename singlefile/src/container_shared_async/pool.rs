@@ -0,0 +1,185 @@
+use crate::error::TaskFailed;
+
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+/// The thread pool that a [`ContainerSharedAsync`] runs its blocking work (reading, writing and locking files) on.
+///
+/// By default, blocking work is spawned onto the blocking pool of the current Tokio runtime with
+/// [`tokio::task::spawn_blocking`], which is shared with every other user of that function.
+/// Heavy commits can be isolated from other blocking work by running them on the blocking pool
+/// of another runtime with [`BlockingPool::from_handle`], or on a [dedicated][BlockingPool::dedicated] pool.
+/// Blocking work can also be handed to another async runtime entirely with [`BlockingPool::from_spawner`].
+///
+/// See [`ContainerSharedAsync::with_blocking_pool`].
+///
+/// [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+/// [`ContainerSharedAsync::with_blocking_pool`]: crate::container_shared_async::ContainerSharedAsync::with_blocking_pool
+#[derive(Debug, Clone, Default)]
+pub struct BlockingPool {
+  inner: Option<PoolInner>
+}
+
+#[derive(Clone)]
+enum PoolInner {
+  Tokio {
+    handle: Handle,
+    _runtime: Option<Arc<DedicatedRuntime>>
+  },
+  Spawner(Arc<dyn Spawner>)
+}
+
+impl fmt::Debug for PoolInner {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PoolInner::Tokio { handle, _runtime } => f.debug_struct("Tokio")
+        .field("handle", handle)
+        .field("dedicated", &_runtime.is_some())
+        .finish(),
+      PoolInner::Spawner(..) => f.write_str("Spawner")
+    }
+  }
+}
+
+impl BlockingPool {
+  /// Returns the blocking pool of the current Tokio runtime, this is the default.
+  #[inline]
+  pub const fn global() -> Self {
+    BlockingPool { inner: None }
+  }
+
+  /// Returns the blocking pool of the Tokio runtime behind the given handle.
+  #[inline]
+  pub fn from_handle(handle: Handle) -> Self {
+    BlockingPool { inner: Some(PoolInner::Tokio { handle, _runtime: None }) }
+  }
+
+  /// Creates a new pool with at most `max_threads` threads, dedicated to `singlefile`.
+  ///
+  /// The pool is shut down once every clone of it has been dropped, without waiting for running work to complete.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `max_threads` is zero.
+  pub fn dedicated(max_threads: usize) -> io::Result<Self> {
+    let runtime = Builder::new_current_thread()
+      .max_blocking_threads(max_threads)
+      .thread_name("singlefile-io")
+      .build()?;
+    let handle = runtime.handle().clone();
+    let runtime = Some(Arc::new(DedicatedRuntime(Some(runtime))));
+    Ok(BlockingPool { inner: Some(PoolInner::Tokio { handle, _runtime: runtime }) })
+  }
+
+  /// Returns a pool that hands blocking work to the given [`Spawner`], such as one backed by another async runtime.
+  #[inline]
+  pub fn from_spawner<S: Spawner>(spawner: S) -> Self {
+    BlockingPool { inner: Some(PoolInner::Spawner(Arc::new(spawner))) }
+  }
+
+  /// Returns `true` if this is the blocking pool of the current Tokio runtime.
+  #[inline]
+  pub const fn is_global(&self) -> bool {
+    self.inner.is_none()
+  }
+
+  /// Runs the provided function or closure on this pool, returning a handle that can be awaited for its result.
+  pub fn spawn_blocking<F, R>(&self, f: F) -> BlockingHandle<R>
+  where F: FnOnce() -> R + Send + 'static, R: Send + 'static {
+    BlockingHandle(match &self.inner {
+      Some(PoolInner::Tokio { handle, .. }) => HandleInner::Tokio(handle.spawn_blocking(f)),
+      Some(PoolInner::Spawner(spawner)) => {
+        let (sender, receiver) = oneshot::channel();
+        spawner.spawn_blocking(Box::new(move || {
+          let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        }));
+
+        HandleInner::Spawner(receiver)
+      },
+      None => HandleInner::Tokio(tokio::task::spawn_blocking(f))
+    })
+  }
+}
+
+/// Runs blocking work on behalf of a [`BlockingPool`] created with [`BlockingPool::from_spawner`],
+/// allowing containers to be used alongside async runtimes other than Tokio.
+///
+/// The lock guarding the state of a container comes from [`tokio::sync`], which does not depend on the Tokio runtime,
+/// so a container whose blocking work goes through a [`Spawner`] can be used from any executor.
+/// Constructors, [`OwnedAccessGuardMut::commit`], timeouts and autosaving still require a Tokio runtime,
+/// a container can instead be created from a [`Container`] with [`From`] and [`with_blocking_pool`].
+///
+/// ```
+/// use singlefile::container_shared_async::{BlockingPool, Spawner};
+///
+/// /// Runs every piece of blocking work on a new thread, with `async-std`, this could call `async_std::task::spawn_blocking`
+/// /// and with `smol`, this could call `smol::unblock(task).detach()`.
+/// struct ThreadSpawner;
+///
+/// impl Spawner for ThreadSpawner {
+///   fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send + 'static>) {
+///     std::thread::spawn(task);
+///   }
+/// }
+///
+/// let pool = BlockingPool::from_spawner(ThreadSpawner);
+/// ```
+///
+/// [`OwnedAccessGuardMut::commit`]: crate::container_shared_async::OwnedAccessGuardMut::commit
+/// [`Container`]: crate::container::Container
+/// [`with_blocking_pool`]: crate::container_shared_async::ContainerSharedAsync::with_blocking_pool
+pub trait Spawner: Send + Sync + 'static {
+  /// Runs the given task on a thread where blocking is acceptable.
+  ///
+  /// The task should eventually be run, if it is dropped instead, the task awaiting its result receives a [`TaskFailed`] error.
+  fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send + 'static>);
+}
+
+/// A handle to blocking work spawned with [`BlockingPool::spawn_blocking`], which can be awaited for its result.
+#[derive(Debug)]
+#[must_use = "blocking work is run whether or not its handle is awaited"]
+pub struct BlockingHandle<R>(HandleInner<R>);
+
+#[derive(Debug)]
+enum HandleInner<R> {
+  Tokio(JoinHandle<R>),
+  Spawner(oneshot::Receiver<thread::Result<R>>)
+}
+
+impl<R> Future for BlockingHandle<R> {
+  type Output = Result<R, TaskFailed>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    match &mut self.get_mut().0 {
+      HandleInner::Tokio(handle) => Pin::new(handle).poll(cx).map_err(TaskFailed::from),
+      HandleInner::Spawner(receiver) => Pin::new(receiver).poll(cx).map(|result| match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(payload)) => Err(TaskFailed::panicked(payload)),
+        Err(..) => Err(TaskFailed::cancelled())
+      })
+    }
+  }
+}
+
+/// Owns the runtime behind a dedicated pool, shutting it down without blocking when dropped,
+/// since dropping a runtime from within an asynchronous context would otherwise panic.
+#[derive(Debug)]
+struct DedicatedRuntime(Option<Runtime>);
+
+impl Drop for DedicatedRuntime {
+  fn drop(&mut self) {
+    if let Some(runtime) = self.0.take() {
+      runtime.shutdown_background();
+    };
+  }
+}
@@ -0,0 +1,112 @@
+use super::ContainerSharedAsync;
+use crate::container::ChangeEvent;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Reading};
+
+use tokio::sync::{watch, Notify};
+use tokio::task::JoinHandle;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A task that refreshes a [`ContainerSharedAsync`] whenever its file has changed on disk, checking once per interval.
+///
+/// Changes are recognized by the size and modification time of the file, see [`Container::refresh_if_changed`].
+/// Each refresh is announced to the receivers returned by [`AutoRefresh::subscribe`].
+/// Errors are not reported as they happen, the latest one can be retrieved with [`AutoRefresh::take_error`].
+///
+/// Dropping this structure stops the task, without waiting for it to finish.
+/// This structure is created by [`ContainerSharedAsync::spawn_auto_refresh`].
+///
+/// [`Container::refresh_if_changed`]: crate::container::Container::refresh_if_changed
+pub struct AutoRefresh<FE> {
+  stop: Arc<Notify>,
+  last_error: Arc<Mutex<Option<Error<FE>>>>,
+  refreshes: watch::Receiver<u64>,
+  task: JoinHandle<()>
+}
+
+impl<FE> AutoRefresh<FE> {
+  pub(super) fn spawn<T, Format, Lock, Mode>(
+    container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>,
+    interval: Duration
+  ) -> Self
+  where
+    T: Send + Sync + 'static,
+    Format: FileFormat<T, FormatError = FE> + Send + Sync + 'static,
+    FE: Send + 'static,
+    Lock: Send + Sync + 'static,
+    Mode: Reading + Send + Sync + 'static
+  {
+    let stop = Arc::new(Notify::new());
+    let last_error = Arc::new(Mutex::new(None));
+    let (sender, refreshes) = watch::channel(0);
+    let task = tokio::spawn(run(container, Arc::clone(&stop), Arc::clone(&last_error), sender, interval));
+    AutoRefresh { stop, last_error, refreshes, task }
+  }
+
+  /// Returns a receiver that is notified each time the container is refreshed,
+  /// holding the number of refreshes made so far.
+  pub fn subscribe(&self) -> watch::Receiver<u64> {
+    self.refreshes.clone()
+  }
+
+  /// Takes the latest error that occurred while refreshing the container, if any.
+  pub fn take_error(&self) -> Option<Error<FE>> {
+    self.last_error.lock().unwrap_or_else(|err| err.into_inner()).take()
+  }
+}
+
+impl<FE> Drop for AutoRefresh<FE> {
+  fn drop(&mut self) {
+    // the task cannot be waited on here, it finishes (and releases its handle to the container) on its own
+    self.stop.notify_one();
+  }
+}
+
+impl<FE> fmt::Debug for AutoRefresh<FE> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AutoRefresh")
+      .field("refreshes", &*self.refreshes.borrow())
+      .field("task", &self.task)
+      .finish_non_exhaustive()
+  }
+}
+
+async fn run<T, Format, Lock, Mode>(
+  container: ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>,
+  stop: Arc<Notify>,
+  last_error: Arc<Mutex<Option<Error<Format::FormatError>>>>,
+  refreshes: watch::Sender<u64>,
+  interval: Duration
+)
+where
+  T: Send + Sync + 'static,
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + 'static,
+  Lock: Send + Sync + 'static,
+  Mode: Reading + Send + Sync + 'static
+{
+  loop {
+    if tokio::time::timeout(interval, stop.notified()).await.is_ok() { break };
+
+    // access is never waited on indefinitely, since the handle may be dropped
+    // (stopping this task) while an owned access guard is still held
+    if let Ok(mut guard) = tokio::time::timeout(interval, container.access_owned_mut()).await {
+      let result = match spawn_blocking!(container.pool, guard.container_mut().refresh_if_changed()) {
+        Ok(result) => result.map(|old_value| old_value.is_some()),
+        Err(err) => Err(err.into())
+      };
+
+      match result {
+        Ok(true) => {
+          container.notify(ChangeEvent::Refresh);
+          refreshes.send_modify(|refreshes| *refreshes += 1);
+        },
+        Ok(false) => (),
+        Err(err) => *last_error.lock().unwrap_or_else(|err| err.into_inner()) = Some(err)
+      };
+    };
+  };
+}
@@ -0,0 +1,114 @@
+use super::ContainerSharedAsync;
+use crate::container::ChangeEvent;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Reading, Writing};
+use crate::manager::mode::{Atomic, Writable};
+use crate::sealed::Sealed;
+use crate::slow::{Operation, Stopwatch};
+
+use tokio_uring::buf::IoBuf;
+
+use std::fs::File;
+use std::io;
+
+/// A file mode whose writes are compatible with [`ContainerSharedAsync::commit_uring`],
+/// because it reads and writes the entire file in place.
+///
+/// This trait is sealed, it is implemented for [`Writable`] and [`Atomic`].
+pub trait UringMode: Writing + Reading + Sealed {}
+
+impl UringMode for Writable {}
+
+impl UringMode for Atomic {}
+
+impl<T, Format, Lock, Mode> ContainerSharedAsync<T, FileManager<Format, Lock, Mode>>
+where Format: FileFormat<T>, Mode: UringMode {
+  /// Writes the current in-memory state to the managed file through io_uring,
+  /// instead of spawning a blocking task.
+  ///
+  /// The state is serialized into a buffer on the current task before being written, as with [`Atomic`].
+  ///
+  /// This function must be called from within a `tokio-uring` runtime (see [`tokio_uring::start`]).
+  ///
+  /// This function acquires an immutable lock on the shared state.
+  pub async fn commit_uring(&self) -> Result<(), Error<Format::FormatError>> {
+    let guard = self.access().await;
+    self.panics.check()?;
+    let container = guard.container();
+    container.hooks.before(&container.value);
+    let stopwatch = Stopwatch::start();
+    let buf = container.manager.format().to_buffer(&container.value)
+      .map_err(Error::Format)?;
+    write(container.manager.file(), buf).await?;
+    stopwatch.finish(Operation::Commit, Some(container.manager.path()));
+    container.stats.record_commit();
+    container.stats.record_stamp(&container.manager);
+    container.hooks.after(&container.value);
+    self.notify(ChangeEvent::Commit);
+    Ok(())
+  }
+
+  /// Reads a value from the managed file through io_uring, instead of spawning a blocking task,
+  /// replacing the current state in memory.
+  ///
+  /// Returns the value of the previous state if the operation succeeded.
+  ///
+  /// This function must be called from within a `tokio-uring` runtime (see [`tokio_uring::start`]).
+  ///
+  /// This function acquires a mutable lock on the shared state.
+  pub async fn refresh_uring(&self) -> Result<T, Error<Format::FormatError>> {
+    let mut guard = self.access_mut().await;
+    let container = guard.container_mut();
+    let stopwatch = Stopwatch::start();
+    let buf = read(container.manager.file()).await?;
+    let value = container.manager.format().from_buffer(&buf)
+      .map_err(Error::Format)?;
+    stopwatch.finish(Operation::Read, Some(container.manager.path()));
+    container.stats.record_refresh();
+    container.stats.record_stamp(&container.manager);
+    self.panics.clear();
+    self.notify(ChangeEvent::Refresh);
+    Ok(std::mem::replace(&mut container.value, value))
+  }
+}
+
+/// Duplicates the handle to the file, since `tokio-uring` closes the files that it is given.
+/// The duplicate shares the lock held by the original handle.
+fn duplicate(file: &File) -> io::Result<tokio_uring::fs::File> {
+  file.try_clone().map(tokio_uring::fs::File::from_std)
+}
+
+async fn write(std_file: &File, mut buf: Vec<u8>) -> io::Result<()> {
+  let file = duplicate(std_file)?;
+  let len = buf.len();
+  let mut pos = 0;
+  while pos < len {
+    let (result, slice) = file.write_at(buf.slice(pos..), pos as u64).await;
+    buf = slice.into_inner();
+    match result? {
+      0 => return Err(io::ErrorKind::WriteZero.into()),
+      n => pos += n
+    };
+  };
+
+  std_file.set_len(len as u64)?;
+  file.sync_all().await?;
+  file.close().await
+}
+
+async fn read(std_file: &File) -> io::Result<Vec<u8>> {
+  let file = duplicate(std_file)?;
+  let len = std_file.metadata()?.len() as usize;
+  let mut buf = Vec::with_capacity(len);
+  while buf.len() < len {
+    let pos = buf.len();
+    let (result, slice) = file.read_at(buf.slice(pos..len), pos as u64).await;
+    buf = slice.into_inner();
+    if result? == 0 {
+      break;
+    };
+  };
+
+  file.close().await?;
+  Ok(buf)
+}
@@ -0,0 +1,179 @@
+//! Watching the files of shared containers, refreshing them automatically when they change on disk.
+//!
+//! This module can be enabled with the `watch` cargo feature.
+//!
+//! A [`ContainerWatcher`] observes the file behind a [`ContainerShared`] using the `notify` crate,
+//! which lets multiple processes coordinate through the same file without polling it.
+//! Changes made through the container itself are recognized by the size and modification time of the file,
+//! and do not cause it to be read again.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::container_shared::ContainerSharedReadonly;
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Settings {
+//!   verbose: bool
+//! }
+//!
+//! let settings = ContainerSharedReadonly::<Settings, Json>::open("settings.json", Json)?;
+//! // The container is refreshed whenever another process changes `settings.json`, until the watcher is dropped
+//! let watcher = settings.watch()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [`ContainerShared`]: crate::container_shared::ContainerShared
+
+use crate::container_shared::ContainerShared;
+use crate::error::Error;
+use crate::manager::{FileFormat, FileManager, Reading};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// What a [`ContainerWatcher`] does when the file behind its container has changed on disk.
+///
+/// See [`ContainerShared::watch_with`].
+///
+/// [`ContainerShared::watch_with`]: crate::container_shared::ContainerShared::watch_with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Resolution {
+  /// Replaces the in-memory state with the contents of the file.
+  ///
+  /// This is the default.
+  #[default]
+  Refresh,
+  /// Keeps the in-memory state, leaving the file as it is.
+  Ignore,
+  /// Keeps the in-memory state, and commits it over the contents of the file.
+  Overwrite
+}
+
+/// Watches the file behind a [`ContainerShared`], refreshing the container when the file changes on disk.
+///
+/// The file's directory is watched rather than the file itself, so that files which are replaced
+/// (such as by [`AtomicRename`] or by text editors) keep being watched.
+/// Events are handled on a thread owned by `notify`, and watching stops once this structure is dropped.
+///
+/// Files that are written in place (rather than replaced) may be observed while they are only partially written,
+/// in which case reading them fails, and the file is read again once the write has finished.
+/// Errors are not reported as they happen, the latest one can be retrieved with [`ContainerWatcher::take_error`].
+///
+/// This structure is created by [`ContainerShared::watch`] and [`ContainerShared::watch_with`].
+///
+/// [`ContainerShared`]: crate::container_shared::ContainerShared
+/// [`ContainerShared::watch`]: crate::container_shared::ContainerShared::watch
+/// [`ContainerShared::watch_with`]: crate::container_shared::ContainerShared::watch_with
+/// [`AtomicRename`]: crate::manager::mode::AtomicRename
+pub struct ContainerWatcher<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+  last_error: Arc<Mutex<Option<Error<Format::FormatError>>>>,
+  _watcher: RecommendedWatcher
+}
+
+impl<T, Format, Lock, Mode> ContainerWatcher<T, Format, Lock, Mode>
+where
+  T: Send + Sync + 'static,
+  Format: FileFormat<T> + Send + Sync + 'static,
+  Format::FormatError: Send + 'static,
+  Lock: Send + Sync + 'static,
+  Mode: Reading + Send + Sync + 'static
+{
+  pub(crate) fn spawn<R, O>(
+    container: ContainerShared<T, FileManager<Format, Lock, Mode>>,
+    mut resolve: R,
+    overwrite: O
+  ) -> io::Result<Self>
+  where
+    R: FnMut(&T, &T) -> Resolution + Send + 'static,
+    O: Fn(&ContainerShared<T, FileManager<Format, Lock, Mode>>) -> Result<(), Error<Format::FormatError>> + Send + 'static
+  {
+    let path = container.access().manager().path().to_owned();
+    let last_error = Arc::new(Mutex::new(None));
+    let watcher = watch_file(&path, {
+      let container = container.clone();
+      let last_error = Arc::clone(&last_error);
+      move |result: io::Result<()>| {
+        let result = result.map_err(Error::Io).and_then(|()| {
+          match container.resolve_external_change(&mut resolve) {
+            Ok(Some(Resolution::Overwrite)) => overwrite(&container),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err)
+          }
+        });
+
+        if let Err(err) = result {
+          *last_error.lock() = Some(err);
+        };
+      }
+    })?;
+
+    Ok(ContainerWatcher { container, last_error, _watcher: watcher })
+  }
+}
+
+impl<T, Format, Lock, Mode> ContainerWatcher<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  /// Gets a reference to the container that this watcher refreshes.
+  #[inline]
+  pub fn container(&self) -> &ContainerShared<T, FileManager<Format, Lock, Mode>> {
+    &self.container
+  }
+
+  /// Takes the latest error that occurred while handling a change to the file, if any.
+  pub fn take_error(&self) -> Option<Error<Format::FormatError>> {
+    self.last_error.lock().take()
+  }
+}
+
+impl<T, Format, Lock, Mode> fmt::Debug for ContainerWatcher<T, Format, Lock, Mode>
+where Format: FileFormat<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ContainerWatcher")
+      .field("watcher", &self._watcher)
+      .finish_non_exhaustive()
+  }
+}
+
+/// Watches the directory of the file at the given path, calling `on_event` from a thread owned by `notify`
+/// whenever the file may have been changed, or with an error if watching the directory failed.
+///
+/// Events that only access or remove the file are ignored, watching stops once the returned watcher is dropped.
+pub(crate) fn watch_file<F>(path: &Path, mut on_event: F) -> io::Result<RecommendedWatcher>
+where F: FnMut(io::Result<()>) + Send + 'static {
+  let file_name = path.file_name().map(ToOwned::to_owned)
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+  let dir = match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new(".")
+  };
+
+  let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+    let event = match result {
+      Ok(event) => event,
+      Err(err) => return on_event(Err(into_io_error(err)))
+    };
+
+    let concerns_file = event.paths.iter().any(|path| path.file_name() == Some(file_name.as_os_str()));
+    if concerns_file && !matches!(event.kind, EventKind::Access(_) | EventKind::Remove(_)) {
+      on_event(Ok(()));
+    };
+  }).map_err(into_io_error)?;
+
+  watcher.watch(dir, RecursiveMode::NonRecursive).map_err(into_io_error)?;
+  Ok(watcher)
+}
+
+fn into_io_error(err: notify::Error) -> io::Error {
+  match err.kind {
+    notify::ErrorKind::Io(err) => err,
+    kind => io::Error::new(io::ErrorKind::Other, notify::Error { kind, paths: err.paths })
+  }
+}
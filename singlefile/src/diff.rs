@@ -0,0 +1,157 @@
+//! Structural diffs between values, used to show what committing or refreshing a container will change.
+//!
+//! This module can be enabled with the `diff` cargo feature.
+//!
+//! Values are compared by converting them into [`serde_json::Value`]s, so any type implementing
+//! [`Serialize`] can be diffed, regardless of the [`FileFormat`] used to store it.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! # use singlefile::diff::DiffError;
+//! # fn main() -> Result<(), DiffError<JsonError>> {
+//! use singlefile::container::ContainerWritable;
+//!
+//! let mut container = ContainerWritable::<Vec<i32>, Json>::create_or_default("numbers.json", Json)?;
+//! container.push(1);
+//!
+//! let diff = container.diff()?;
+//! if !diff.is_empty() {
+//!   // + /0: 1
+//!   println!("unsaved changes:\n{diff}");
+//! };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`FileFormat`]: crate::manager::format::FileFormat
+
+use crate::error::UserError;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use std::fmt;
+
+/// The error type returned by [`Container::diff`], where either value failing to convert
+/// into a [`serde_json::Value`] is a [`UserError::User`].
+///
+/// [`Container::diff`]: crate::container::Container::diff
+pub type DiffError<FE> = UserError<FE, serde_json::Error>;
+
+/// A structural diff between an old and a new value, as a list of [`Change`]s.
+///
+/// The [`Display`][fmt::Display] implementation lists one change per line,
+/// prefixed with `+` for additions, `-` for removals, and `~` for modifications.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Diff {
+  /// Every change between the two values.
+  pub changes: Vec<Change>
+}
+
+impl Diff {
+  /// Computes the diff between two values.
+  pub fn new<T: Serialize>(old: &T, new: &T) -> Result<Self, serde_json::Error> {
+    let (old, new) = (serde_json::to_value(old)?, serde_json::to_value(new)?);
+    let mut changes = Vec::new();
+    diff_value(&mut String::new(), old, new, &mut changes);
+    Ok(Diff { changes })
+  }
+
+  /// Returns `true` if the two values were identical.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.changes.is_empty()
+  }
+}
+
+impl fmt::Display for Diff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.changes.iter().try_for_each(|change| writeln!(f, "{change}"))
+  }
+}
+
+/// A single change found by a [`Diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+  /// The location of the change within the value, as a JSON pointer (see RFC 6901).
+  /// The root of the value is the empty string.
+  pub path: String,
+  /// What changed at that location.
+  pub kind: ChangeKind
+}
+
+impl fmt::Display for Change {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let path = match self.path.as_str() {
+      "" => "/",
+      path => path
+    };
+
+    match &self.kind {
+      ChangeKind::Added(new) => write!(f, "+ {path}: {new}"),
+      ChangeKind::Removed(old) => write!(f, "- {path}: {old}"),
+      ChangeKind::Modified(old, new) => write!(f, "~ {path}: {old} -> {new}")
+    }
+  }
+}
+
+/// The kind of a [`Change`], holding the values involved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+  /// A value is present in the new value, but not the old value.
+  Added(Value),
+  /// A value is present in the old value, but not the new value.
+  Removed(Value),
+  /// A value is present in both, but differs. Holds the old and new values, respectively.
+  Modified(Value, Value)
+}
+
+fn diff_value(path: &mut String, old: Value, new: Value, changes: &mut Vec<Change>) {
+  match (old, new) {
+    (Value::Object(mut old), Value::Object(new)) => {
+      for (key, new) in new {
+        with_segment(path, &key, |path| match old.remove(&key) {
+          Some(old) => diff_value(path, old, new, changes),
+          None => push_change(path, ChangeKind::Added(new), changes)
+        });
+      };
+
+      for (key, old) in old {
+        with_segment(path, &key, |path| push_change(path, ChangeKind::Removed(old), changes));
+      };
+    },
+    (Value::Array(old), Value::Array(new)) => {
+      let mut old = old.into_iter();
+      let mut new = new.into_iter();
+      for i in 0.. {
+        let kind = match (old.next(), new.next()) {
+          (Some(old), Some(new)) => {
+            with_segment(path, &i.to_string(), |path| diff_value(path, old, new, changes));
+            continue;
+          },
+          (None, Some(new)) => ChangeKind::Added(new),
+          (Some(old), None) => ChangeKind::Removed(old),
+          (None, None) => break
+        };
+
+        with_segment(path, &i.to_string(), |path| push_change(path, kind, changes));
+      };
+    },
+    (old, new) => if old != new {
+      push_change(path, ChangeKind::Modified(old, new), changes);
+    }
+  };
+}
+
+fn with_segment<R>(path: &mut String, segment: &str, f: impl FnOnce(&mut String) -> R) -> R {
+  let len = path.len();
+  path.push('/');
+  path.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+  let result = f(path);
+  path.truncate(len);
+  result
+}
+
+fn push_change(path: &str, kind: ChangeKind, changes: &mut Vec<Change>) {
+  changes.push(Change { path: path.to_owned(), kind });
+}
@@ -2,8 +2,11 @@
 
 use thiserror::Error;
 
+use std::any::Any;
 use std::convert::Infallible;
+use std::fmt;
 use std::io;
+use std::sync::Mutex;
 
 /// An error that can occur within `singlefile`.
 #[derive(Debug, Error)]
@@ -15,7 +18,26 @@ pub enum Error<FE> {
   Format(FE),
   /// An error caused by the filesystem.
   #[error(transparent)]
-  Io(#[from] io::Error)
+  Io(io::Error),
+  /// The managed file could not be locked, because it is locked by another handle or process.
+  #[error(transparent)]
+  LockContended(#[from] LockContended),
+  /// Access to a container could not be acquired in time.
+  #[error(transparent)]
+  TimedOut(#[from] TimedOut),
+  /// A container was poisoned by a panic during an earlier operation.
+  #[error(transparent)]
+  Poisoned(#[from] Poisoned),
+  /// The managed file was changed by someone else since it was last read or written through a container.
+  #[error(transparent)]
+  Conflict(#[from] Conflict),
+  /// The managed file was larger than the maximum size allowed for it, so it was not read.
+  #[error(transparent)]
+  TooLarge(#[from] TooLarge),
+  /// A blocking task spawned by an asynchronous container panicked or was cancelled.
+  /// Only returned by asynchronous containers.
+  #[error(transparent)]
+  Task(#[from] TaskFailed)
 }
 
 impl<FE> From<UserError<FE, Infallible>> for Error<FE> {
@@ -23,15 +45,37 @@ impl<FE> From<UserError<FE, Infallible>> for Error<FE> {
     match err {
       UserError::Format(err) => Error::Format(err),
       UserError::Io(err) => Error::Io(err),
+      UserError::LockContended(err) => Error::LockContended(err),
+      UserError::TimedOut(err) => Error::TimedOut(err),
+      UserError::Poisoned(err) => Error::Poisoned(err),
+      UserError::Conflict(err) => Error::Conflict(err),
+      UserError::TooLarge(err) => Error::TooLarge(err),
+      UserError::Task(err) => Error::Task(err),
       UserError::User(i) => match i {}
     }
   }
 }
 
+/// Converts an [`io::Error`] into an [`enum@Error`], as [`Error::LockContended`] if it was caused by lock contention.
+impl<FE> From<io::Error> for Error<FE> {
+  fn from(err: io::Error) -> Self {
+    match LockContended::is_cause_of(&err) {
+      true => Error::LockContended(LockContended),
+      false => Error::Io(err)
+    }
+  }
+}
+
 impl From<Error<io::Error>> for io::Error {
   fn from(err: Error<io::Error>) -> Self {
     match err {
-      Error::Format(err) | Error::Io(err) => err
+      Error::Format(err) | Error::Io(err) => err,
+      Error::LockContended(err) => io::Error::from(err),
+      Error::TimedOut(err) => io::Error::new(io::ErrorKind::TimedOut, err),
+      Error::Poisoned(err) => io::Error::new(io::ErrorKind::Other, err),
+      Error::Conflict(err) => io::Error::new(io::ErrorKind::Other, err),
+      Error::TooLarge(err) => io::Error::new(io::ErrorKind::InvalidData, err),
+      Error::Task(err) => io::Error::new(io::ErrorKind::Other, err)
     }
   }
 }
@@ -46,12 +90,41 @@ pub enum UserError<FE, U> {
   Format(FE),
   /// An error caused by the filesystem.
   #[error(transparent)]
-  Io(#[from] std::io::Error),
+  Io(std::io::Error),
+  /// The managed file could not be locked, because it is locked by another handle or process.
+  #[error(transparent)]
+  LockContended(#[from] LockContended),
+  /// Access to a container could not be acquired in time.
+  #[error(transparent)]
+  TimedOut(#[from] TimedOut),
+  /// A container was poisoned by a panic during an earlier operation.
+  #[error(transparent)]
+  Poisoned(#[from] Poisoned),
+  /// The managed file was changed by someone else since it was last read or written through a container.
+  #[error(transparent)]
+  Conflict(#[from] Conflict),
+  /// The managed file was larger than the maximum size allowed for it, so it was not read.
+  #[error(transparent)]
+  TooLarge(#[from] TooLarge),
+  /// A blocking task spawned by an asynchronous container panicked or was cancelled.
+  /// Only returned by asynchronous containers.
+  #[error(transparent)]
+  Task(#[from] TaskFailed),
   /// An error caused by the user.
   #[error("user error: {0}")]
   User(U)
 }
 
+/// Converts an [`io::Error`] into a [`UserError`], as [`UserError::LockContended`] if it was caused by lock contention.
+impl<FE, U> From<io::Error> for UserError<FE, U> {
+  fn from(err: io::Error) -> Self {
+    match LockContended::is_cause_of(&err) {
+      true => UserError::LockContended(LockContended),
+      false => UserError::Io(err)
+    }
+  }
+}
+
 impl<FE, U> UserError<FE, U> {
   /// Maps this error into another error.
   /// The new error type `E` must implement [`From<Error<FE>>`][enum@Error].
@@ -61,6 +134,12 @@ impl<FE, U> UserError<FE, U> {
     match self {
       UserError::Format(err) => Error::Format(err).into(),
       UserError::Io(err) => Error::Io(err).into(),
+      UserError::LockContended(err) => Error::LockContended(err).into(),
+      UserError::TimedOut(err) => Error::TimedOut(err).into(),
+      UserError::Poisoned(err) => Error::Poisoned(err).into(),
+      UserError::Conflict(err) => Error::Conflict(err).into(),
+      UserError::TooLarge(err) => Error::TooLarge(err).into(),
+      UserError::Task(err) => Error::Task(err).into(),
       UserError::User(err) => f(err)
     }
   }
@@ -71,7 +150,196 @@ impl<FE, U> From<Error<FE>> for UserError<FE, U> {
   fn from(err: Error<FE>) -> Self {
     match err {
       Error::Format(err) => UserError::Format(err),
-      Error::Io(err) => UserError::Io(err)
+      Error::Io(err) => UserError::Io(err),
+      Error::LockContended(err) => UserError::LockContended(err),
+      Error::TimedOut(err) => UserError::TimedOut(err),
+      Error::Poisoned(err) => UserError::Poisoned(err),
+      Error::Conflict(err) => UserError::Conflict(err),
+      Error::TooLarge(err) => UserError::TooLarge(err),
+      Error::Task(err) => UserError::Task(err)
     }
   }
 }
+
+/// An error that can occur while converting a file from one format to another with [`transcode`].
+///
+/// [`transcode`]: crate::utils::transcode
+#[derive(Debug, Error)]
+pub enum TranscodeError<FI, FO> {
+  /// The file could not be read in its original format.
+  #[error("failed to read the original file: {0}")]
+  Read(Error<FI>),
+  /// The file could not be written in its new format.
+  #[error("failed to write the converted file: {0}")]
+  Write(Error<FO>)
+}
+
+/// An error indicating that access to a container could not be acquired before a timeout elapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("timed out while waiting for access to the container")]
+pub struct TimedOut;
+
+/// An error indicating that a container was poisoned, because an operation panicked while it had mutable access to the state.
+/// Only returned by shared containers using the `PanicPolicy::Poison` policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("the container was poisoned by a panic during an earlier operation")]
+pub struct Poisoned;
+
+/// An error indicating that the managed file was changed by someone else since it was last read or written through a container,
+/// so committing would have overwritten those changes. Only returned by optimistic commits, such as [`Container::commit_if_unchanged`].
+///
+/// [`Container::commit_if_unchanged`]: crate::container::Container::commit_if_unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("the file was changed by someone else since it was last read or written")]
+pub struct Conflict;
+
+/// An error indicating that a file was not read because it was larger than the maximum size allowed for it.
+/// Only returned by managers that have a maximum size, see [`FileManager::with_max_size`].
+///
+/// [`FileManager::with_max_size`]: crate::manager::FileManager::with_max_size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("the file is {size} bytes, larger than the maximum of {max_size} bytes")]
+pub struct TooLarge {
+  /// The size of the file in bytes.
+  pub size: u64,
+  /// The maximum size allowed for the file in bytes.
+  pub max_size: u64
+}
+
+/// An error indicating that a blocking task spawned by an asynchronous container did not complete,
+/// because it panicked, or because it was cancelled or dropped before it was run (such as when the runtime is shutting down).
+pub struct TaskFailed {
+  payload: Option<Mutex<Box<dyn Any + Send + 'static>>>
+}
+
+impl TaskFailed {
+  #[cfg(feature = "shared-async")]
+  pub(crate) fn panicked(payload: Box<dyn Any + Send + 'static>) -> Self {
+    TaskFailed { payload: Some(Mutex::new(payload)) }
+  }
+
+  #[cfg(feature = "shared-async")]
+  pub(crate) const fn cancelled() -> Self {
+    TaskFailed { payload: None }
+  }
+
+  /// Returns `true` if the task panicked.
+  #[inline]
+  pub const fn is_panic(&self) -> bool {
+    self.payload.is_some()
+  }
+
+  /// Returns `true` if the task was cancelled or dropped before it could complete.
+  #[inline]
+  pub const fn is_cancelled(&self) -> bool {
+    self.payload.is_none()
+  }
+
+  /// Returns the payload that the task panicked with, or this error if the task was cancelled.
+  /// The payload can be passed to [`std::panic::resume_unwind`] to continue unwinding.
+  pub fn try_into_panic(self) -> Result<Box<dyn Any + Send + 'static>, Self> {
+    match self.payload {
+      Some(payload) => Ok(payload.into_inner().unwrap_or_else(|err| err.into_inner())),
+      None => Err(self)
+    }
+  }
+}
+
+impl fmt::Debug for TaskFailed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.is_panic() {
+      true => f.write_str("TaskFailed::Panic(..)"),
+      false => f.write_str("TaskFailed::Cancelled")
+    }
+  }
+}
+
+impl fmt::Display for TaskFailed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.is_panic() {
+      true => f.write_str("a blocking task panicked"),
+      false => f.write_str("a blocking task was cancelled before it could complete")
+    }
+  }
+}
+
+impl std::error::Error for TaskFailed {}
+
+#[cfg(feature = "shared-async")]
+impl From<tokio::task::JoinError> for TaskFailed {
+  fn from(err: tokio::task::JoinError) -> Self {
+    match err.try_into_panic() {
+      Ok(payload) => TaskFailed::panicked(payload),
+      Err(..) => TaskFailed::cancelled()
+    }
+  }
+}
+
+/// An error indicating that a file could not be locked, because it is locked by another handle or process.
+/// Returned when opening a file with a lock mode that does not wait, or that stopped waiting after a timeout,
+/// so the operation may be retried later. See [`manager::lock`] for the available lock modes.
+///
+/// When converted into an [`io::Error`], its kind matches the one the operating system uses for lock contention.
+///
+/// [`manager::lock`]: crate::manager::lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+#[error("the file is locked by another handle or process")]
+pub struct LockContended;
+
+impl LockContended {
+  /// Returns `true` if the given [`io::Error`] was caused by lock contention.
+  pub fn is_cause_of(err: &io::Error) -> bool {
+    err.get_ref().map_or(false, |inner| inner.is::<LockContended>())
+  }
+}
+
+impl From<LockContended> for io::Error {
+  fn from(err: LockContended) -> Self {
+    io::Error::new(fs4::lock_contended_error().kind(), err)
+  }
+}
+
+/// An error indicating that the lock held on a file could not be upgraded or downgraded,
+/// holding the manager that the lock change was attempted on, so that it can be used further.
+///
+/// See [`FileManager::upgrade_lock`] and [`FileManager::downgrade_lock`].
+///
+/// [`FileManager::upgrade_lock`]: crate::manager::FileManager::upgrade_lock
+/// [`FileManager::downgrade_lock`]: crate::manager::FileManager::downgrade_lock
+#[derive(Debug, Error)]
+#[error("failed to change the lock on the file: {error}")]
+pub struct LockChangeError<M> {
+  manager: Box<M>,
+  #[source]
+  error: io::Error
+}
+
+impl<M> LockChangeError<M> {
+  pub(crate) fn new(manager: M, error: io::Error) -> Self {
+    LockChangeError { manager: Box::new(manager), error }
+  }
+
+  /// Gets the error that changing the lock failed with.
+  #[inline]
+  pub const fn error(&self) -> &io::Error {
+    &self.error
+  }
+
+  /// Returns the manager that the lock change was attempted on.
+  #[inline]
+  pub fn into_manager(self) -> M {
+    *self.manager
+  }
+
+  /// Returns the manager that the lock change was attempted on, and the error that it failed with.
+  #[inline]
+  pub fn into_parts(self) -> (M, io::Error) {
+    (*self.manager, self.error)
+  }
+}
+
+impl<M> From<LockChangeError<M>> for io::Error {
+  fn from(err: LockChangeError<M>) -> Self {
+    err.error
+  }
+}
@@ -0,0 +1,84 @@
+//! Inspection of the file system that managed files live on.
+//!
+//! Different file systems (tmpfs, NFS, FAT32, etc.) make different guarantees,
+//! [`probe_capabilities`] can be used to pick an appropriate file mode and lock mode at runtime.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The capabilities of a file system, as found by [`probe_capabilities`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities {
+  /// Whether a file can be renamed over an existing file, replacing it.
+  /// Modes that commit by renaming a temporary file into place depend on this.
+  pub atomic_rename: bool,
+  /// Whether advisory locks are supported and enforced between separate file handles.
+  pub advisory_locks: bool,
+  /// Whether a directory can be synced, making renames and newly created files within it durable.
+  pub dir_fsync: bool
+}
+
+/// Probes the file system at the given path for the [`Capabilities`] it supports.
+///
+/// If `path` is a directory, the probe is performed within it, otherwise it is performed in the parent directory of `path`.
+/// A few small probe files are created in that directory and removed afterwards.
+pub fn probe_capabilities<P: AsRef<Path>>(path: P) -> io::Result<Capabilities> {
+  let path = path.as_ref();
+  let dir = match path.is_dir() {
+    true => path,
+    false => match path.parent() {
+      Some(parent) if !parent.as_os_str().is_empty() => parent,
+      _ => Path::new(".")
+    }
+  };
+
+  let source = probe_path(dir, "a");
+  let target = probe_path(dir, "b");
+  let result = (|| Ok(Capabilities {
+    atomic_rename: probe_rename(&source, &target)?,
+    advisory_locks: probe_locks(&target)?,
+    dir_fsync: probe_dir_fsync(dir)
+  }))();
+
+  let _ = fs::remove_file(&source);
+  let _ = fs::remove_file(&target);
+  result
+}
+
+fn probe_path(dir: &Path, suffix: &str) -> PathBuf {
+  let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+  dir.join(format!(".singlefile-probe-{}-{nanos}-{suffix}", std::process::id()))
+}
+
+fn probe_rename(source: &Path, target: &Path) -> io::Result<bool> {
+  File::create(source)?.write_all(b"new")?;
+  File::create(target)?.write_all(b"old")?;
+  if fs::rename(source, target).is_err() {
+    return Ok(false);
+  };
+
+  Ok(!source.exists() && fs::read(target)? == b"new")
+}
+
+fn probe_locks(path: &Path) -> io::Result<bool> {
+  let first = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+  let second = OpenOptions::new().read(true).write(true).open(path)?;
+  if fs4::fs_std::FileExt::try_lock_exclusive(&first).is_err() {
+    return Ok(false);
+  };
+
+  let enforced = match fs4::fs_std::FileExt::try_lock_exclusive(&second) {
+    Ok(()) => false,
+    Err(err) => err.kind() == fs4::lock_contended_error().kind()
+  };
+
+  let _ = fs4::fs_std::FileExt::unlock(&first);
+  let _ = fs4::fs_std::FileExt::unlock(&second);
+  Ok(enforced)
+}
+
+fn probe_dir_fsync(dir: &Path) -> bool {
+  File::open(dir).and_then(|dir| dir.sync_all()).is_ok()
+}
@@ -34,6 +34,9 @@
 //! }
 //! ```
 //!
+//! For the common case of one type stored in one file at a fixed path, [`impl_container!`] generates
+//! `load` and `save` functions on the type itself, so that the container type, path and format are only named once.
+//!
 //! ## Shared and async containers
 //! `singlefile` also provides a [`ContainerShared`] type that can be used from multiple threads, as well as
 //! a [`ContainerSharedAsync`] that can be used from multiple threads and spawns its operations asynchronously.
@@ -42,9 +45,14 @@
 //! The shared container types can be enabled with the `shared` cargo feature.
 //! The async container types can be enabled with the `shared-async` cargo feature.
 //!
+//! Both come with the same set of type aliases as [`Container`], so atomic writes are available through
+//! [`ContainerSharedAtomic`] and [`ContainerSharedAsyncAtomic`] (and their `Locked` variants).
+//!
 //! ```no_run
 //! # use singlefile_formats::json_serde::{Json, JsonError};
 //! # use std::convert::Infallible;
+//! # #[cfg(not(feature = "shared"))] fn main() {}
+//! # #[cfg(feature = "shared")] fn main() -> Result<(), singlefile::Error<JsonError>> {
 //! // A readable, writable container with multiple-ownership
 //! use singlefile::container_shared::ContainerSharedWritable;
 //! use serde::{Serialize, Deserialize};
@@ -64,9 +72,41 @@
 //!     Ok::<(), Infallible>(())
 //!   });
 //! });
-//! # Ok::<(), singlefile::Error<JsonError>>(())
+//! # Ok(())
+//! # }
 //! ```
 //!
+//! ## Multi-section containers
+//! When several related but separate values need to be persisted together, [`ContainerMulti`] can store
+//! a tuple of them as individual sections in a single file, rather than requiring one file (and one lock) per value.
+//!
+//! ## Key-value containers
+//! [`ContainerKv`] uses a single map file as a small embedded key-value store, with `get`, `insert` and `remove`
+//! operations, and commits that only write to disk when a key has been modified.
+//!
+//! ## Directory containers
+//! [`ContainerDirectory`] manages a directory with one file per entry of a collection, keyed by file name.
+//! Entries are read lazily as they are accessed, and can be committed individually.
+//!
+//! ## Event log containers
+//! [`EventLogContainer`] persists state as an append-only log of events, so that committing never rewrites the whole file.
+//! Opening it replays the log onto the latest snapshot of the state, and old events can be kept as an audit trail.
+//!
+//! ## Log containers
+//! [`ContainerLog`] is an append-only log of records, each serialized on its own and framed by its length.
+//! Records are streamed back lazily, and the log can be compacted to drop the records that are no longer needed.
+//!
+//! ## Layered containers
+//! [`ContainerLayeredReadonly`] serves defaults embedded in the application (for example with `include_bytes!`)
+//! until a file exists at its path, at which point the file is read instead.
+//! [`ContainerLayered`] additionally applies environment variable overrides, and commits only the values that were modified.
+//! [`ContainerMerged`] merges several files (such as system-wide and per-user configuration) with the [`Merge`] trait,
+//! committing only to the file with the highest precedence.
+//!
+//! ## Partial containers
+//! [`PartialContainer`] deserializes only the part of a larger document found at a JSON pointer,
+//! and writes modifications to that part back into the document, leaving the rest of it untouched.
+//!
 //! ## File formats
 //! `singlefile` is serialization framework-agnostic, so you will need a [`FileFormat`] adapter
 //! before you are able to read and write a given file format to disk.
@@ -102,15 +142,57 @@
 //! ## Features
 //! By default, only the `tokio-parking-lot` feature is enabled.
 //!
-//! - `shared`: Enables [`ContainerShared`], pulling in `parking_lot`.
+//! - `shared`: Enables [`ContainerShared`] and [`ContainerCached`], pulling in `parking_lot`.
 //! - `shared-async`: Enables [`ContainerSharedAsync`], pulling in `tokio` and (by default) `parking_lot`.
+//! - `io-uring`: Enables io_uring reads and writes for [`ContainerSharedAsync`] on Linux, pulling in `tokio-uring`.
+//!   Implies `shared-async`.
+//! - `watch`: Enables the [`container_watcher`] module, refreshing [`ContainerShared`] when its file changes on disk,
+//!   pulling in `notify`. Implies `shared`.
+//! - `axum`: Enables the [`web`] module, providing `axum` extractors for [`ContainerSharedAsync`]. Implies `shared-async`.
+//! - `serde`: Enables `serde::Serialize` for [`Container`], delegating to the contained value.
+//! - `cas`: Enables the [`ContentAddressed`] file mode, pulling in `sha2`.
+//! - `diff`: Enables the [`diff`] module and [`Container::diff`], pulling in `serde_json`. Implies `serde`.
+//! - `layered`: Enables [`ContainerLayered`], merging defaults, a file and environment variables, pulling in `serde_json`.
+//!   Implies `serde`.
+//! - `projection`: Enables the [`container_projection`] module, reading and writing single parts of a larger document,
+//!   pulling in `serde_json`. Implies `serde`.
+//! - `paths`: Enables the [`paths`] module, resolving the platform's directories for an application, pulling in `directories`.
+//! - `log`: Emits the warnings of the [`slow`] module through `log`.
+//! - `metrics`: Enables the [`metrics`] module, measuring the reads, writes and lock waits of file managers.
+//! - `tracing`: Enables a [`metrics`] instrumentation emitting spans and events through `tracing`,
+//!   pulling in `tracing`. Implies `metrics`.
+//! - `test-support`: Enables the [`test_support`] module, providing roundtrip assertions for tests, pulling in `proptest`.
 //! - `deadlock-detection`: Enables `parking_lot`'s `deadlock_detection` feature, if it is present.
 //! - `tokio-parking-lot`: Enables `parking_lot` for use in `tokio`, if it is present. Enabled by default.
 //!
 //! [`Container`]: crate::container::Container
+//! [`impl_container!`]: crate::impl_container
 //! [`ContainerShared`]: crate::container_shared::ContainerShared
+//! [`ContainerCached`]: crate::container_shared::ContainerCached
 //! [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+//! [`ContainerSharedAtomic`]: crate::container_shared::ContainerSharedAtomic
+//! [`ContainerSharedAsyncAtomic`]: crate::container_shared_async::ContainerSharedAsyncAtomic
+//! [`ContainerMulti`]: crate::container_multi::ContainerMulti
+//! [`ContainerKv`]: crate::container_kv::ContainerKv
+//! [`ContainerDirectory`]: crate::container_directory::ContainerDirectory
+//! [`EventLogContainer`]: crate::container_event_log::EventLogContainer
+//! [`ContainerLog`]: crate::container_log::ContainerLog
+//! [`ContainerLayeredReadonly`]: crate::container_layered::ContainerLayeredReadonly
+//! [`ContainerLayered`]: crate::container_layered::ContainerLayered
+//! [`ContainerMerged`]: crate::container_layered::ContainerMerged
+//! [`Merge`]: crate::container_layered::Merge
+//! [`ContentAddressed`]: crate::manager::cas::ContentAddressed
 //! [`FileFormat`]: crate::manager::format::FileFormat
+//! [`test_support`]: crate::test_support
+//! [`diff`]: crate::diff
+//! [`Container::diff`]: crate::container::Container::diff
+//! [`web`]: crate::web
+//! [`container_watcher`]: crate::container_watcher
+//! [`container_projection`]: crate::container_projection
+//! [`PartialContainer`]: crate::container_projection::PartialContainer
+//! [`slow`]: crate::slow
+//! [`metrics`]: crate::metrics
+//! [`paths`]: crate::paths
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![warn(
@@ -123,22 +205,77 @@
 
 extern crate fs4;
 extern crate thiserror;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(feature = "axum")]
+extern crate axum;
+#[cfg(feature = "paths")]
+extern crate directories;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "watch")]
+extern crate notify;
 #[cfg(feature = "shared")]
 extern crate parking_lot;
+#[cfg(feature = "test-support")]
+extern crate proptest;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(any(feature = "diff", feature = "layered", feature = "projection"))]
+extern crate serde_json;
+#[cfg(feature = "cas")]
+extern crate sha2;
 #[cfg(feature = "shared-async")]
 extern crate tokio;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+extern crate tokio_uring;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
 pub mod container;
+pub mod container_directory;
+pub mod container_event_log;
+pub mod container_kv;
+pub mod container_layered;
+pub mod container_log;
+pub mod container_multi;
+#[cfg_attr(docsrs, doc(cfg(feature = "projection")))]
+#[cfg(feature = "projection")]
+pub mod container_projection;
 #[cfg_attr(docsrs, doc(cfg(feature = "shared")))]
 #[cfg(feature = "shared")]
 pub mod container_shared;
 #[cfg_attr(docsrs, doc(cfg(feature = "shared-async")))]
 #[cfg(feature = "shared-async")]
 pub mod container_shared_async;
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+#[cfg(feature = "watch")]
+pub mod container_watcher;
+#[cfg_attr(docsrs, doc(cfg(feature = "diff")))]
+#[cfg(feature = "diff")]
+pub mod diff;
 pub mod error;
+pub mod fs;
+mod macros;
 pub mod manager;
+#[cfg(any(feature = "shared", feature = "shared-async"))]
+mod panic_policy;
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg_attr(docsrs, doc(cfg(feature = "paths")))]
+#[cfg(feature = "paths")]
+pub mod paths;
+pub mod slow;
+#[cfg_attr(docsrs, doc(cfg(feature = "test-support")))]
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod utils;
+#[cfg_attr(docsrs, doc(cfg(feature = "axum")))]
+#[cfg(feature = "axum")]
+pub mod web;
 
-pub use crate::error::{Error, UserError};
+pub use crate::error::{Error, UserError, TimedOut, Poisoned};
 
 #[doc(inline)]
 pub use crate::manager::format::{FileFormat, FileFormatUtf8};
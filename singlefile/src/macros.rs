@@ -0,0 +1,101 @@
+/// Implements loading and saving for a type that is stored in a single file at a fixed path,
+/// removing the boilerplate of naming a container type, path and format everywhere the file is used.
+///
+/// The format is given as a type, optionally followed by `=` and an expression that constructs it.
+/// If the expression is left out, the format is constructed with [`Default`].
+/// The type must implement [`Default`], which is written to the file if it does not exist yet.
+///
+/// The following items are generated as inherent items of the type:
+///
+/// - `PATH`: The path of the file.
+/// - `load()`: Reads the value from the file. See [`read_or_default`].
+/// - `save(&self)`: Writes the value to the file, atomically replacing it. See [`write_atomic`].
+/// - `load_container()`: Opens the file as a [`ContainerWritable`], so that the value can be committed repeatedly.
+/// - `load_shared()`: Opens the file as a [`ContainerSharedWritable`]. Only generated with the `shared` cargo feature.
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use serde::{Serialize, Deserialize};
+///
+/// #[derive(Serialize, Deserialize, Default)]
+/// struct Settings {
+///   volume: u8
+/// }
+///
+/// singlefile::impl_container! {
+///   Settings {
+///     path = "settings.json",
+///     format: Json = Json
+///   }
+/// }
+///
+/// let mut settings = Settings::load()?;
+/// settings.volume += 1;
+/// settings.save()?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+///
+/// [`read_or_default`]: crate::utils::read_or_default
+/// [`write_atomic`]: crate::utils::write_atomic
+/// [`ContainerWritable`]: crate::container::ContainerWritable
+/// [`ContainerSharedWritable`]: crate::container_shared::ContainerSharedWritable
+#[macro_export]
+macro_rules! impl_container {
+  ($Type:ty { path = $path:expr, format: $Format:ty $(,)? }) => (
+    $crate::impl_container!($Type { path = $path, format: $Format = <$Format as ::std::default::Default>::default() });
+  );
+  ($Type:ty { path = $path:expr, format: $Format:ty = $format:expr $(,)? }) => (
+    impl $Type {
+      /// The path of the file that this type is stored in.
+      pub const PATH: &'static str = $path;
+
+      /// Reads a value from the file, writing the default value to it first if it does not exist.
+      pub fn load() -> ::std::result::Result<Self, $crate::Error<<$Format as $crate::FileFormat<Self>>::FormatError>> {
+        let format: $Format = $format;
+        $crate::utils::read_or_default(Self::PATH, &format)
+      }
+
+      /// Writes this value to the file, atomically replacing it.
+      pub fn save(&self) -> ::std::result::Result<(), $crate::Error<<$Format as $crate::FileFormat<Self>>::FormatError>> {
+        let format: $Format = $format;
+        $crate::utils::write_atomic(Self::PATH, &format, self)
+      }
+
+      /// Opens the file as a container, writing the default value to it first if it does not exist.
+      pub fn load_container() -> ::std::result::Result<
+        $crate::container::ContainerWritable<Self, $Format>,
+        $crate::Error<<$Format as $crate::FileFormat<Self>>::FormatError>
+      > {
+        let format: $Format = $format;
+        $crate::container::ContainerWritable::create_or_default(Self::PATH, format)
+      }
+    }
+
+    $crate::__impl_container_shared!($Type, $Format, $format);
+  );
+}
+
+#[cfg(feature = "shared")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_container_shared {
+  ($Type:ty, $Format:ty, $format:expr) => (
+    impl $Type {
+      /// Opens the file as a shared container, writing the default value to it first if it does not exist.
+      pub fn load_shared() -> ::std::result::Result<
+        $crate::container_shared::ContainerSharedWritable<Self, $Format>,
+        $crate::Error<<$Format as $crate::FileFormat<Self>>::FormatError>
+      > {
+        let format: $Format = $format;
+        $crate::container_shared::ContainerSharedWritable::create_or_default(Self::PATH, format)
+      }
+    }
+  );
+}
+
+#[cfg(not(feature = "shared"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_container_shared {
+  ($Type:ty, $Format:ty, $format:expr) => ();
+}
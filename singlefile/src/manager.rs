@@ -7,18 +7,45 @@
 pub mod lock;
 pub mod mode;
 pub mod format;
+pub mod backup;
+pub mod builder;
+pub mod sync;
+pub mod embedded;
+#[cfg_attr(docsrs, doc(cfg(feature = "shared-async")))]
+#[cfg(feature = "shared-async")]
+pub mod async_manager;
+#[cfg_attr(docsrs, doc(cfg(feature = "cas")))]
+#[cfg(feature = "cas")]
+pub mod cas;
 
-use crate::error::Error;
-use self::lock::FileLock;
+use crate::error::{Error, LockChangeError, TooLarge};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Instrumentation, Instruments};
+use crate::slow::{self, Operation};
+use self::lock::{FileLock, Upgradable, Downgradable};
 use self::mode::FileMode;
-pub use self::lock::{NoLock, SharedLock, ExclusiveLock};
-pub use self::mode::{Atomic, Readonly, Writable, Reading, Writing};
+use self::sync::SyncState;
+pub use self::lock::{NoLock, SharedLock, ExclusiveLock, SharedLockBlocking, ExclusiveLockBlocking};
+pub use self::lock::{SharedLockWithTimeout, ExclusiveLockWithTimeout};
+#[cfg(unix)]
+pub use self::lock::{SharedFcntlLock, ExclusiveFcntlLock};
+pub use self::mode::{Atomic, AtomicRename, Chunked, Readonly, Writable, Reading, Writing};
+#[cfg(feature = "shared-async")]
+pub use self::async_manager::AsyncFileManager;
+#[cfg(feature = "cas")]
+pub use self::cas::ContentAddressed;
 pub use self::format::FileFormat;
+pub use self::backup::BackupPolicy;
+pub use self::builder::{CreateOptions, FileManagerBuilder, OpenBehavior};
+pub use self::sync::SyncPolicy;
+pub use self::embedded::StaticManager;
 
-use std::io;
+use std::io::{self, Seek, SeekFrom};
 use std::marker::PhantomData;
-use std::path::Path;
-use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
 
 #[cfg(unix)]
 use std::os::unix::io::{IntoRawFd, AsRawFd, RawFd};
@@ -32,82 +59,377 @@ pub struct FileManager<Format, Lock, Mode> {
   format: Format,
   lock: PhantomData<Lock>,
   mode: PhantomData<Mode>,
-  file: File
+  file: File,
+  path: PathBuf,
+  backup_policy: Option<BackupPolicy>,
+  sync_policy: SyncPolicy,
+  sync_state: SyncState,
+  max_size: Option<u64>,
+  #[cfg(feature = "metrics")]
+  instruments: Option<Instruments>
 }
 
 impl<Format, Lock, Mode> FileManager<Format, Lock, Mode>
 where Lock: FileLock, Mode: FileMode {
   /// Opens a new [`FileManager`], returning an error if the file at the given path does not exist.
   pub fn open<P: AsRef<Path>>(path: P, format: Format) -> io::Result<Self> {
+    let path = path.as_ref().to_owned();
+    let file = Mode::open(&path)?;
+    Self::with_file(file, path, format)
+  }
+
+  /// Creates a new [`FileManager`] from a file handle that has already been opened,
+  /// such as one received from another process, or an anonymous file with no path (like a `memfd` or `O_TMPFILE` file).
+  ///
+  /// The file must have been opened with the access that the file mode needs (for example, write access for [`Writable`]),
+  /// and it is locked and rewound to its start. Since the manager does not know the path of the file,
+  /// [`FileManager::path`] returns an empty path, and anything that needs it (such as backups) fails.
+  /// Returns an error if the file mode replaces the file by its path, such as [`AtomicRename`].
+  pub fn from_file(mut file: File, format: Format) -> io::Result<Self> {
+    if Mode::REPLACES_FILE {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput, "file mode requires a path"));
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    Self::with_file(file, PathBuf::new(), format)
+  }
+
+  /// Opens a new [`FileManager`] like [`FileManager::open`], recording how long it took to lock the file.
+  #[cfg(feature = "metrics")]
+  pub(crate) fn open_instrumented(path: &Path, format: Format, instruments: Instruments) -> io::Result<Self> {
     let file = Mode::open(path)?;
+    instruments.measure_lock(path, || Lock::lock(&file))?;
+    let mut manager = Self::with_locked_file(file, path.to_owned(), format);
+    manager.instruments = Some(instruments);
+    Ok(manager)
+  }
+
+  fn with_file(file: File, path: PathBuf, format: Format) -> io::Result<Self> {
     Lock::lock(&file)?;
-    Ok(FileManager {
+    Ok(Self::with_locked_file(file, path, format))
+  }
+
+  fn with_locked_file(file: File, path: PathBuf, format: Format) -> Self {
+    FileManager {
       format,
       lock: PhantomData,
       mode: PhantomData,
-      file
-    })
+      file,
+      path,
+      backup_policy: None,
+      sync_policy: SyncPolicy::default(),
+      sync_state: SyncState::default(),
+      max_size: None,
+      #[cfg(feature = "metrics")]
+      instruments: None
+    }
   }
 
   /// Opens a new [`FileManager`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
   pub fn create_overwrite<P: AsRef<Path>, T>(path: P, format: Format, value: T) -> Result<(T, Self), Error<Format::FormatError>>
   where Format: FileFormat<T> {
-    overwrite(path.as_ref(), &format, &value)?;
+    overwrite::<_, _, Mode>(path.as_ref(), &format, &value)?;
     Ok((value, Self::open(path, format)?))
   }
 
   /// Opens a new [`FileManager`], writing the given value to the file if it does not exist.
   pub fn create_or<P: AsRef<Path>, T>(path: P, format: Format, value: T) -> Result<(T, Self), Error<Format::FormatError>>
-  where Format: FileFormat<T> {
-    let value = read_or_write(path.as_ref(), &format, || value)?;
+  where Format: FileFormat<T>, Mode: Reading {
+    let value = read_or_write::<_, _, _, Mode>(path.as_ref(), &format, || value)?;
     Ok((value, Self::open(path, format)?))
   }
 
   /// Opens a new [`FileManager`], writing the result of the given closure to the file if it does not exist.
   pub fn create_or_else<P: AsRef<Path>, T, C>(path: P, format: Format, closure: C) -> Result<(T, Self), Error<Format::FormatError>>
-  where Format: FileFormat<T>, C: FnOnce() -> T {
-    let value = read_or_write(path.as_ref(), &format, closure)?;
+  where Format: FileFormat<T>, C: FnOnce() -> T, Mode: Reading {
+    let value = read_or_write::<_, _, _, Mode>(path.as_ref(), &format, closure)?;
     Ok((value, Self::open(path, format)?))
   }
 
   /// Opens a new [`FileManager`], writing the default value of `T` to the file if it does not exist.
   pub fn create_or_default<P: AsRef<Path>, T>(path: P, format: Format) -> Result<(T, Self), Error<Format::FormatError>>
-  where Format: FileFormat<T>, T: Default {
-    let value = read_or_write(path.as_ref(), &format, T::default)?;
+  where Format: FileFormat<T>, T: Default, Mode: Reading {
+    let value = read_or_write::<_, _, _, Mode>(path.as_ref(), &format, T::default)?;
     Ok((value, Self::open(path, format)?))
   }
 }
 
 impl<Format, Lock, Mode> FileManager<Format, Lock, Mode>
-where Lock: FileLock {
-  /// Unlocks and closes this [`FileManager`].
+where Mode: FileMode {
+  /// Sets the [`BackupPolicy`] of this manager, so that every write first takes a backup of the file.
+  #[inline]
+  pub fn with_backup_policy(mut self, backup_policy: BackupPolicy) -> Self {
+    self.backup_policy = Some(backup_policy);
+    self
+  }
+
+  /// Gets the [`BackupPolicy`] of this manager, if it has one.
+  #[inline]
+  pub fn backup_policy(&self) -> Option<&BackupPolicy> {
+    self.backup_policy.as_ref()
+  }
+
+  /// Sets the [`SyncPolicy`] of this manager, which determines when writes are synced to disk.
+  #[inline]
+  pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+    self.sync_policy = sync_policy;
+    self
+  }
+
+  /// Gets the [`SyncPolicy`] of this manager, [`SyncPolicy::Always`] unless configured otherwise.
+  #[inline]
+  pub const fn sync_policy(&self) -> SyncPolicy {
+    self.sync_policy
+  }
+
+  /// Sets the maximum size of the file in bytes, so that reading it fails with [`Error::TooLarge`]
+  /// instead of deserializing it if it is larger, protecting against files that would take too long to parse,
+  /// or too much memory to hold, such as those provided by users.
+  ///
+  /// The size is taken from the metadata of the file before reading it. File modes that store the contents
+  /// elsewhere, such as [`Chunked`] above its threshold, are only checked against the size of the file itself.
+  #[inline]
+  pub fn with_max_size(mut self, max_size: u64) -> Self {
+    self.max_size = Some(max_size);
+    self
+  }
+
+  /// Gets the maximum size of the file in bytes, if this manager has one.
+  #[inline]
+  pub const fn max_size(&self) -> Option<u64> {
+    self.max_size
+  }
+
+  /// Attaches an [`Instrumentation`] to this manager, which measures every read and write from then on.
+  /// See the [`metrics`] module for more information.
+  ///
+  /// Since the file is locked when the manager is opened, lock waits are only measured
+  /// when the instrumentation is given to a builder, such as [`FileManagerBuilder::with_instrumentation`].
+  ///
+  /// [`metrics`]: crate::metrics
+  #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+  #[cfg(feature = "metrics")]
+  #[inline]
+  pub fn with_instrumentation(mut self, instrumentation: Arc<dyn Instrumentation>) -> Self {
+    self.instruments = Some(Instruments(instrumentation));
+    self
+  }
+
+  /// Syncs the file (and any write that has not been synced yet) to disk, regardless of the [`SyncPolicy`].
+  pub fn sync(&self) -> io::Result<()> {
+    if Mode::REPLACES_FILE {
+      // the handle held by this manager may point at a file that has since been replaced
+      File::open(&self.path)?.sync_all()?;
+      mode::sync_dir(&self.path)?;
+    } else {
+      self.file.sync_all()?;
+    };
+
+    self.sync_state.record_sync();
+    Ok(())
+  }
+
+  /// Copies the current contents of the file to a new backup, rotating existing backups according to the given policy.
+  /// Returns the path of the new backup, or `None` if the policy keeps no backups.
+  pub fn backup(&self, backup_policy: &BackupPolicy) -> io::Result<Option<PathBuf>> {
+    let backup_path = match backup_policy.rotate(&self.path)? {
+      Some(backup_path) => backup_path,
+      None => return Ok(None)
+    };
+
+    let mut backup = File::create(&backup_path)?;
+    if Mode::READABLE && !Mode::REPLACES_FILE {
+      // copy through the handle the file is locked with, other handles may be blocked by the lock on windows
+      let mut file = &self.file;
+      file.seek(SeekFrom::Start(0))?;
+      let result = io::copy(&mut file, &mut backup);
+      file.seek(SeekFrom::Start(0))?;
+      result?;
+    } else {
+      io::copy(&mut File::open(&self.path)?, &mut backup)?;
+    };
+
+    backup.sync_all()?;
+    Ok(Some(backup_path))
+  }
+
+  /// Writes a given value to the file managed by this manager, taking a backup according to the given policy
+  /// instead of the policy of this manager.
+  pub fn write_with_backup<T>(&self, value: &T, backup_policy: &BackupPolicy) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T>, Mode: Writing {
+    self.backup(backup_policy)?;
+    self.write_unchecked(value)
+  }
+
+  /// Writes a given value to the file managed by this manager.
+  ///
+  /// If this manager has a [`BackupPolicy`], a backup of the file is taken first.
+  #[inline]
+  pub fn write<T>(&self, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T>, Mode: Writing {
+    if let Some(backup_policy) = &self.backup_policy {
+      self.backup(backup_policy)?;
+    };
+
+    self.write_unchecked(value)
+  }
+
+  #[inline]
+  fn write_unchecked<T>(&self, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T>, Mode: Writing {
+    let sync = self.sync_state.should_sync(self.sync_policy);
+    slow::measure(Operation::Commit, Some(&self.path), || {
+      #[cfg(feature = "metrics")]
+      if let Some(instruments) = &self.instruments {
+        return instruments.measure(Operation::Commit, &self.path, &self.format, |format| {
+          Mode::write(format, &self.file, &self.path, value, sync)
+        }, |success| if success { self.file_len().ok() } else { None });
+      };
+
+      Mode::write(&self.format, &self.file, &self.path, value, sync)
+    })?;
+
+    if sync {
+      self.sync_state.record_sync();
+    };
+
+    Ok(())
+  }
+}
+
+impl<Format, Lock, Mode> FileManager<Format, Lock, Mode>
+where Lock: FileLock, Mode: FileMode {
+  /// Unlocks and closes this [`FileManager`], syncing the file to disk unless its [`SyncPolicy`] is [`SyncPolicy::Never`].
   pub fn close(self) -> io::Result<()> {
     Lock::unlock(&self.file)?;
-    self.file.sync_all()?;
-    Ok(())
+    self.sync_on_close()
   }
 
   /// Unlocks and closes this [`FileManager`], returning the [`FileFormat`] that it uses.
+  /// The file is synced to disk unless its [`SyncPolicy`] is [`SyncPolicy::Never`].
   pub fn into_inner(self) -> io::Result<Format> {
     Lock::unlock(&self.file)?;
-    self.file.sync_all()?;
+    self.sync_on_close()?;
     Ok(self.format)
   }
+
+  fn sync_on_close(&self) -> io::Result<()> {
+    match self.sync_policy {
+      SyncPolicy::Never => Ok(()),
+      _ => self.sync()
+    }
+  }
+}
+
+impl<Format, Lock, Mode> FileManager<Format, Lock, Mode>
+where Lock: Upgradable {
+  /// Upgrades the shared lock held on the file to an exclusive lock, without reopening the file.
+  ///
+  /// If the lock cannot be upgraded (for example, because another process also holds a shared lock),
+  /// the manager is returned inside of the error, holding its shared lock again. See [`Upgradable`] for caveats.
+  pub fn upgrade_lock(self) -> Result<FileManager<Format, Lock::Upgraded, Mode>, LockChangeError<Self>> {
+    match Lock::upgrade(&self.file) {
+      Ok(()) => Ok(self.with_lock()),
+      Err(err) => Err(LockChangeError::new(self, err))
+    }
+  }
+}
+
+impl<Format, Lock, Mode> FileManager<Format, Lock, Mode>
+where Lock: Downgradable {
+  /// Downgrades the exclusive lock held on the file to a shared lock, without reopening the file,
+  /// allowing other processes to lock it for shared access.
+  ///
+  /// If the lock cannot be downgraded, the manager is returned inside of the error. See [`Downgradable`] for caveats.
+  pub fn downgrade_lock(self) -> Result<FileManager<Format, Lock::Downgraded, Mode>, LockChangeError<Self>> {
+    match Lock::downgrade(&self.file) {
+      Ok(()) => Ok(self.with_lock()),
+      Err(err) => Err(LockChangeError::new(self, err))
+    }
+  }
 }
 
 impl<Format, Lock, Mode> FileManager<Format, Lock, Mode> {
-  /// Writes a given value to the file managed by this manager.
+  /// Changes the lock mode of this manager, without locking or unlocking the file.
+  fn with_lock<NewLock>(self) -> FileManager<Format, NewLock, Mode> {
+    FileManager {
+      format: self.format,
+      lock: PhantomData,
+      mode: PhantomData,
+      file: self.file,
+      path: self.path,
+      backup_policy: self.backup_policy,
+      sync_policy: self.sync_policy,
+      sync_state: self.sync_state,
+      max_size: self.max_size,
+      #[cfg(feature = "metrics")]
+      instruments: self.instruments
+    }
+  }
+
+  /// Gets a reference to the [`FileFormat`] used by this manager.
   #[inline]
-  pub fn write<T>(&self, value: &T) -> Result<(), Error<Format::FormatError>>
-  where Format: FileFormat<T>, Mode: Writing {
-    Mode::write(&self.format, &self.file, value)
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets the path that the file managed by this manager was opened from.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  #[inline]
+  pub(crate) const fn file(&self) -> &File {
+    &self.file
   }
 
   /// Reads a value from the file managed by this manager.
   #[inline]
   pub fn read<T>(&self) -> Result<T, Error<Format::FormatError>>
   where Format: FileFormat<T>, Mode: Reading {
-    Mode::read(&self.format, &self.file)
+    self.check_size()?;
+    slow::measure(Operation::Read, Some(&self.path), || {
+      #[cfg(feature = "metrics")]
+      if let Some(instruments) = &self.instruments {
+        let bytes = self.file_len().ok();
+        return instruments.measure(Operation::Read, &self.path, &self.format, |format| {
+          Mode::read(format, &self.file, &self.path)
+        }, |_| bytes);
+      };
+
+      Mode::read(&self.format, &self.file, &self.path)
+    })
+  }
+
+  fn check_size<FE>(&self) -> Result<(), Error<FE>>
+  where Mode: FileMode {
+    let max_size = match self.max_size {
+      Some(max_size) => max_size,
+      None => return Ok(())
+    };
+
+    let size = self.file_len()?;
+    match size > max_size {
+      true => Err(TooLarge { size, max_size }.into()),
+      false => Ok(())
+    }
+  }
+
+  fn file_len(&self) -> io::Result<u64>
+  where Mode: FileMode {
+    self.metadata().map(|metadata| metadata.len())
+  }
+
+  /// Gets the metadata of the managed file, through the handle unless the file mode replaces the file.
+  /// Managers created from a handle have no path, so the handle is the only way to reach their file.
+  pub(crate) fn metadata(&self) -> io::Result<fs::Metadata>
+  where Mode: FileMode {
+    // modes that replace the file read it by its path, so the handle may refer to an older file
+    match Mode::REPLACES_FILE {
+      true => fs::metadata(&self.path),
+      false => self.file.metadata()
+    }
   }
 }
 
@@ -150,6 +472,9 @@ pub type ManagerWritable<Format> = FileManager<Format, NoLock, Writable>;
 /// Type alias to a file manager that is readable and writable (with atomic writes), and has no file lock.
 /// See [`Atomic`] for more information.
 pub type ManagerAtomic<Format> = FileManager<Format, NoLock, Atomic>;
+/// Type alias to a file manager that is readable and writable (with atomic writes through a temporary file),
+/// and has no file lock. See [`AtomicRename`] for more information.
+pub type ManagerAtomicRename<Format> = FileManager<Format, NoLock, AtomicRename>;
 /// Type alias to a file manager that is read-only, and has a shared file lock.
 pub type ManagerReadonlyLocked<Format> = FileManager<Format, SharedLock, Readonly>;
 /// Type alias to a file manager that is readable and writable, and has an exclusive file lock.
@@ -157,26 +482,45 @@ pub type ManagerWritableLocked<Format> = FileManager<Format, ExclusiveLock, Writ
 /// Type alias to a file manager that is readable and writable (with atomic writes), and has an exclusive file lock.
 /// See [`Atomic`] for more information.
 pub type ManagerAtomicLocked<Format> = FileManager<Format, ExclusiveLock, Atomic>;
+/// Type alias to a file manager that is readable and writable (splitting contents larger than `THRESHOLD` bytes
+/// across multiple files), and has an exclusive file lock. See [`Chunked`] for more information.
+pub type ManagerChunkedLocked<Format, const THRESHOLD: u64> = FileManager<Format, ExclusiveLock, Chunked<THRESHOLD>>;
+/// Type alias to a file manager that is readable and writable (keeping every version as a content-addressed object),
+/// and has an exclusive file lock. See [`ContentAddressed`] for more information.
+#[cfg_attr(docsrs, doc(cfg(feature = "cas")))]
+#[cfg(feature = "cas")]
+pub type ManagerContentAddressedLocked<Format> = FileManager<Format, ExclusiveLock, ContentAddressed>;
 
-fn read_or_write<T, C, Format>(path: &Path, format: &Format, closure: C) -> Result<T, Error<Format::FormatError>>
-where Format: FileFormat<T>, C: FnOnce() -> T {
+pub(crate) fn read_or_write<T, C, Format, Mode>(path: &Path, format: &Format, closure: C) -> Result<T, Error<Format::FormatError>>
+where Format: FileFormat<T>, C: FnOnce() -> T, Mode: Reading {
   use std::io::ErrorKind::NotFound;
   match OpenOptions::new().read(true).open(path) {
-    Ok(file) => self::mode::read(format, &file),
+    Ok(file) => Mode::read(format, &file, path),
     Err(err) if err.kind() == NotFound => {
-      let file = OpenOptions::new().write(true).create(true).open(path)?;
+      // the value is produced before the file is created, so that a panicking closure does not leave an empty file behind
       let value = closure();
-      self::mode::write(format, &file, &value)?;
+      // the file may have been created by someone else in the meantime, and not every mode resets it when writing,
+      // so it is truncated to uphold the contract of `FileMode::write_initial`
+      let file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+      Mode::write_initial(format, &file, path, &value)?;
       Ok(value)
     },
     Err(err) => Err(err.into())
   }
 }
 
-fn overwrite<T, Format>(path: &Path, format: &Format, value: &T) -> Result<(), Error<Format::FormatError>>
-where Format: FileFormat<T> {
+/// Creates the parent directories of the given path, if it has any and they do not exist.
+pub(crate) fn create_parent_dirs(path: &Path) -> io::Result<()> {
+  match path.parent() {
+    Some(parent) => fs::create_dir_all(parent),
+    None => Ok(())
+  }
+}
+
+pub(crate) fn overwrite<T, Format, Mode>(path: &Path, format: &Format, value: &T) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T>, Mode: FileMode {
   let file = OpenOptions::new().write(true)
     .create(true).truncate(true).open(path)?;
-  self::mode::write(format, &file, &value)?;
+  Mode::write_initial(format, &file, path, value)?;
   Ok(())
 }
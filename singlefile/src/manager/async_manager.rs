@@ -0,0 +1,221 @@
+//! This module contains the [`AsyncFileManager`] struct, which manages a file through Tokio's asynchronous file API.
+//!
+//! This module can be enabled with the `shared-async` cargo feature.
+//!
+//! Where [`FileManager`] is driven by [`FileFormat`] and has to be moved onto a blocking thread by async containers,
+//! [`AsyncFileManager`] is driven by [`AsyncFileFormat`], so a [`ContainerSharedAsync`] that uses it
+//! can read and write its file without spawning any blocking tasks of its own.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! # async fn run() -> Result<(), singlefile::Error<JsonError>> {
+//! use singlefile::container_shared_async::ContainerSharedAsync;
+//! use singlefile::manager::async_manager::AsyncManagerWritable;
+//! use singlefile::manager::format::async_io::Buffered;
+//!
+//! type Names = ContainerSharedAsync<Vec<String>, AsyncManagerWritable<Buffered<Json>>>;
+//!
+//! let names = Names::create_or_default("names.json", Buffered(Json)).await?;
+//! names.operate_mut(|names| names.push("Scotty".to_owned())).await;
+//! names.commit().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only the [`Readonly`], [`Writable`] and [`Atomic`] file modes are supported, see [`AsyncMode`].
+//!
+//! [`FileManager`]: crate::manager::FileManager
+//! [`FileFormat`]: crate::manager::format::FileFormat
+//! [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+
+use crate::error::Error;
+use crate::manager::format::async_io::AsyncFileFormat;
+use crate::manager::lock::{ExclusiveLock, FileLock, NoLock, SharedLock};
+use crate::manager::mode::{Atomic, FileMode, Readonly, Reading, Writable, Writing};
+use crate::sealed::Sealed;
+
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+use std::io::{self, SeekFrom};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// A file mode that is supported by [`AsyncFileManager`].
+///
+/// This trait is sealed, it is implemented for [`Readonly`], [`Writable`] and [`Atomic`].
+pub trait AsyncMode: FileMode + Sealed {
+  /// Whether the contents are buffered in memory before the file is truncated, like [`Atomic`] does.
+  #[doc(hidden)]
+  const BUFFERED: bool;
+}
+
+impl AsyncMode for Readonly {
+  const BUFFERED: bool = false;
+}
+
+impl AsyncMode for Writable {
+  const BUFFERED: bool = false;
+}
+
+impl AsyncMode for Atomic {
+  const BUFFERED: bool = true;
+}
+
+/// Manages a single file through Tokio's asynchronous file API, see the [module-level documentation][self].
+///
+/// Like [`FileManager`], this is generic over the file format, file locking mode and file access mode.
+/// The file is locked when it is opened, and stays locked until the manager is closed or dropped.
+///
+/// [`FileManager`]: crate::manager::FileManager
+#[derive(Debug)]
+pub struct AsyncFileManager<Format, Lock, Mode> {
+  format: Format,
+  lock: PhantomData<Lock>,
+  mode: PhantomData<Mode>,
+  file: Mutex<File>,
+  path: PathBuf
+}
+
+impl<Format, Lock, Mode> AsyncFileManager<Format, Lock, Mode>
+where Lock: FileLock, Mode: AsyncMode {
+  /// Opens a new [`AsyncFileManager`], returning an error if the file at the given path does not exist.
+  pub async fn open<P: AsRef<Path>>(path: P, format: Format) -> io::Result<Self> {
+    let path = path.as_ref().to_owned();
+    let mut options = OpenOptions::new();
+    options.read(Mode::READABLE).write(Mode::WRITABLE);
+    #[cfg(windows)]
+    options.share_mode(Mode::SHARE_MODE);
+    let file = options.open(&path).await?.into_std().await;
+    // locking never blocks, so it does not have to be moved onto a blocking thread
+    Lock::lock(&file)?;
+    Ok(AsyncFileManager {
+      format,
+      lock: PhantomData,
+      mode: PhantomData,
+      file: Mutex::new(File::from_std(file)),
+      path
+    })
+  }
+
+  /// Opens a new [`AsyncFileManager`], creating a file at the given path if it does not exist, and overwriting its contents if it does.
+  pub async fn create_overwrite<P: AsRef<Path>, T>(path: P, format: Format, value: T) -> Result<(T, Self), Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T> {
+    let mut file = File::create(path.as_ref()).await?;
+    write::<_, _, Mode>(&format, &mut file, &value).await?;
+    Ok((value, Self::open(path, format).await?))
+  }
+
+  /// Opens a new [`AsyncFileManager`], writing the given value to the file if it does not exist.
+  pub async fn create_or<P: AsRef<Path>, T>(path: P, format: Format, value: T) -> Result<(T, Self), Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T>, Mode: Reading {
+    Self::create_or_else(path, format, || value).await
+  }
+
+  /// Opens a new [`AsyncFileManager`], writing the result of the given closure to the file if it does not exist.
+  pub async fn create_or_else<P: AsRef<Path>, T, C>(path: P, format: Format, closure: C) -> Result<(T, Self), Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T>, C: FnOnce() -> T, Mode: Reading {
+    let value = match File::open(path.as_ref()).await {
+      Ok(mut file) => read(&format, &mut file).await?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        let mut file = File::create(path.as_ref()).await?;
+        let value = closure();
+        write::<_, _, Mode>(&format, &mut file, &value).await?;
+        value
+      },
+      Err(err) => return Err(err.into())
+    };
+
+    Ok((value, Self::open(path, format).await?))
+  }
+
+  /// Opens a new [`AsyncFileManager`], writing the default value of `T` to the file if it does not exist.
+  pub async fn create_or_default<P: AsRef<Path>, T>(path: P, format: Format) -> Result<(T, Self), Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T>, T: Default, Mode: Reading {
+    Self::create_or_else(path, format, T::default).await
+  }
+
+  /// Writes a given value to the file managed by this manager.
+  pub async fn write<T>(&self, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T>, Mode: Writing {
+    write::<_, _, Mode>(&self.format, &mut *self.file.lock().await, value).await
+  }
+
+  /// Reads a value from the file managed by this manager.
+  pub async fn read<T>(&self) -> Result<T, Error<Format::FormatError>>
+  where Format: AsyncFileFormat<T>, Mode: Reading {
+    read(&self.format, &mut *self.file.lock().await).await
+  }
+}
+
+impl<Format, Lock, Mode> AsyncFileManager<Format, Lock, Mode>
+where Lock: FileLock {
+  /// Unlocks and closes this [`AsyncFileManager`].
+  pub async fn close(self) -> io::Result<()> {
+    self.into_inner().await.map(drop)
+  }
+
+  /// Unlocks and closes this [`AsyncFileManager`], returning the [`AsyncFileFormat`] that it uses.
+  pub async fn into_inner(self) -> io::Result<Format> {
+    let file = self.file.into_inner();
+    file.sync_all().await?;
+    Lock::unlock(&file.into_std().await)?;
+    Ok(self.format)
+  }
+}
+
+impl<Format, Lock, Mode> AsyncFileManager<Format, Lock, Mode> {
+  /// Gets a reference to the [`AsyncFileFormat`] used by this manager.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets the path that the file managed by this manager was opened from.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+}
+
+/// Type alias to an asynchronous file manager that is read-only, and has no file lock.
+pub type AsyncManagerReadonly<Format> = AsyncFileManager<Format, NoLock, Readonly>;
+/// Type alias to an asynchronous file manager that is readable and writable, and has no file lock.
+pub type AsyncManagerWritable<Format> = AsyncFileManager<Format, NoLock, Writable>;
+/// Type alias to an asynchronous file manager that is readable and writable (with atomic writes), and has no file lock.
+/// See [`Atomic`] for more information.
+pub type AsyncManagerAtomic<Format> = AsyncFileManager<Format, NoLock, Atomic>;
+/// Type alias to an asynchronous file manager that is read-only, and has a shared file lock.
+pub type AsyncManagerReadonlyLocked<Format> = AsyncFileManager<Format, SharedLock, Readonly>;
+/// Type alias to an asynchronous file manager that is readable and writable, and has an exclusive file lock.
+pub type AsyncManagerWritableLocked<Format> = AsyncFileManager<Format, ExclusiveLock, Writable>;
+/// Type alias to an asynchronous file manager that is readable and writable (with atomic writes), and has an exclusive file lock.
+/// See [`Atomic`] for more information.
+pub type AsyncManagerAtomicLocked<Format> = AsyncFileManager<Format, ExclusiveLock, Atomic>;
+
+async fn read<T, Format>(format: &Format, file: &mut File) -> Result<T, Error<Format::FormatError>>
+where Format: AsyncFileFormat<T> {
+  let result = format.from_async_reader(&mut *file).await;
+  // rewind even if the format failed, so that the file can be read again
+  file.seek(SeekFrom::Start(0)).await?;
+  result
+}
+
+async fn write<T, Format, Mode>(format: &Format, file: &mut File, value: &T) -> Result<(), Error<Format::FormatError>>
+where Format: AsyncFileFormat<T>, Mode: AsyncMode {
+  if Mode::BUFFERED {
+    let mut buf = Vec::new();
+    format.to_async_writer(&mut buf, value).await?;
+    file.set_len(0).await?;
+    file.write_all(&buf).await?;
+  } else {
+    file.set_len(0).await?;
+    format.to_async_writer(&mut *file, value).await?;
+  };
+
+  file.flush().await?;
+  file.seek(SeekFrom::Start(0)).await?;
+  file.sync_all().await?;
+  Ok(())
+}
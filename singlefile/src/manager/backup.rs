@@ -0,0 +1,82 @@
+//! Defines how backups of a file are kept when it is committed to.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Determines how many rotated backups of a file are kept, and where.
+///
+/// Before a backup is taken, every existing backup is shifted up by one (`<file name>.bak.1` becomes
+/// `<file name>.bak.2`, and so on), and the oldest one is removed once there are more than [`BackupPolicy::keep`].
+/// The file's current contents are then copied to `<file name>.bak.1`, so lower numbers are always newer.
+///
+/// Only the file itself is copied, so backups of files using [`Chunked`] above its threshold
+/// only hold a manifest that refers to chunks which are not backed up.
+///
+/// See [`FileManager::with_backup_policy`] and [`Container::commit_with_backup`].
+///
+/// [`Chunked`]: crate::manager::mode::Chunked
+/// [`FileManager::with_backup_policy`]: crate::manager::FileManager::with_backup_policy
+/// [`Container::commit_with_backup`]: crate::container::Container::commit_with_backup
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BackupPolicy {
+  keep: usize,
+  dir: Option<PathBuf>
+}
+
+impl BackupPolicy {
+  /// Creates a new policy keeping up to `keep` backups next to the file. A policy keeping `0` backups takes none.
+  #[inline]
+  pub const fn new(keep: usize) -> Self {
+    BackupPolicy { keep, dir: None }
+  }
+
+  /// Keeps backups in the given directory instead of next to the file, creating it if it does not exist.
+  #[inline]
+  pub fn in_dir<P: Into<PathBuf>>(self, dir: P) -> Self {
+    BackupPolicy { dir: Some(dir.into()), ..self }
+  }
+
+  /// Returns the number of backups that are kept.
+  #[inline]
+  pub const fn keep(&self) -> usize {
+    self.keep
+  }
+
+  /// Returns the directory that backups are kept in, or `None` if they are kept next to the file.
+  #[inline]
+  pub fn dir(&self) -> Option<&Path> {
+    self.dir.as_deref()
+  }
+
+  /// Returns the path of the `n`th newest backup of the file at the given path, starting from `1`.
+  pub fn backup_path(&self, path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".bak.{n}"));
+    match &self.dir {
+      Some(dir) => dir.join(name),
+      None => path.with_file_name(name)
+    }
+  }
+
+  /// Shifts every existing backup up by one, removing the oldest one, returning the path that the newest backup
+  /// should be written to, or `None` if no backups are kept.
+  pub(crate) fn rotate(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+    if self.keep == 0 {
+      return Ok(None);
+    };
+
+    if let Some(dir) = &self.dir {
+      fs::create_dir_all(dir)?;
+    };
+
+    for n in (1..self.keep).rev() {
+      match fs::rename(self.backup_path(path, n), self.backup_path(path, n + 1)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => ()
+      };
+    };
+
+    Ok(Some(self.backup_path(path, 1)))
+  }
+}
@@ -0,0 +1,363 @@
+//! Defines [`FileManagerBuilder`], for configuring how a file is opened before opening it.
+
+use crate::error::Error;
+use crate::manager::format::FileFormat;
+use crate::manager::lock::{FileLock, NoLock};
+use crate::manager::mode::{Reading, Writable};
+use crate::manager::{create_parent_dirs, BackupPolicy, FileManager, SyncPolicy};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Instrumentation, Instruments};
+
+use std::fs::{self, OpenOptions, Permissions};
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+
+/// Determines what happens when a file is opened, depending on whether it exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OpenBehavior {
+  /// Reads the file, failing if it does not exist.
+  Open,
+  /// Reads the file, writing an initial value to it first if it does not exist.
+  #[default]
+  Create,
+  /// Writes an initial value to the file, creating it if it does not exist and overwriting it if it does.
+  Overwrite
+}
+
+/// Platform-specific options that are applied to a file as it is created, so that it never exists without them.
+///
+/// Unlike [`FileManagerBuilder::with_permissions`], which is applied after the file has been opened,
+/// these options are given to the operating system when the file is created, and have no effect on a file that already exists.
+/// See [`FileManagerBuilder::with_create_options`].
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use singlefile::manager::{CreateOptions, FileManagerBuilder};
+///
+/// let mut options = CreateOptions::new();
+/// // only the owner may read or write the file
+/// #[cfg(unix)] options.set_mode(0o600);
+///
+/// let (value, manager) = FileManagerBuilder::new("secrets.json", Json::<true>)
+///   .with_create_options(options)
+///   .build_or_default::<Vec<String>>()?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CreateOptions {
+  #[cfg(unix)]
+  mode: Option<u32>,
+  #[cfg(windows)]
+  attributes: Option<u32>,
+  #[cfg(windows)]
+  security_qos_flags: Option<u32>
+}
+
+impl CreateOptions {
+  /// Creates a new [`CreateOptions`], which leaves every option to the operating system's default.
+  #[inline]
+  pub const fn new() -> Self {
+    CreateOptions {
+      #[cfg(unix)]
+      mode: None,
+      #[cfg(windows)]
+      attributes: None,
+      #[cfg(windows)]
+      security_qos_flags: None
+    }
+  }
+
+  /// Sets the mode bits that the file is created with, such as `0o600`, which are masked by the process's umask.
+  /// See [`OpenOptionsExt::mode`].
+  #[cfg_attr(docsrs, doc(cfg(unix)))]
+  #[cfg(unix)]
+  #[inline]
+  pub fn set_mode(&mut self, mode: u32) -> &mut Self {
+    self.mode = Some(mode);
+    self
+  }
+
+  /// Sets the file attributes that the file is created with, such as `FILE_ATTRIBUTE_HIDDEN`.
+  /// See [`OpenOptionsExt::attributes`].
+  #[cfg_attr(docsrs, doc(cfg(windows)))]
+  #[cfg(windows)]
+  #[inline]
+  pub fn set_attributes(&mut self, attributes: u32) -> &mut Self {
+    self.attributes = Some(attributes);
+    self
+  }
+
+  /// Sets the security quality of service flags that the file is created with.
+  /// See [`OpenOptionsExt::security_qos_flags`].
+  #[cfg_attr(docsrs, doc(cfg(windows)))]
+  #[cfg(windows)]
+  #[inline]
+  pub fn set_security_qos_flags(&mut self, flags: u32) -> &mut Self {
+    self.security_qos_flags = Some(flags);
+    self
+  }
+
+  /// Applies these options to the given [`OpenOptions`].
+  pub fn apply(&self, options: &mut OpenOptions) {
+    #[cfg(unix)]
+    if let Some(mode) = self.mode {
+      options.mode(mode);
+    };
+
+    #[cfg(windows)]
+    if let Some(attributes) = self.attributes {
+      options.attributes(attributes);
+    };
+
+    #[cfg(windows)]
+    if let Some(flags) = self.security_qos_flags {
+      options.security_qos_flags(flags);
+    };
+
+    // there are no options to apply on other platforms
+    let _ = options;
+  }
+}
+
+/// Configures the lock mode, file mode, open behavior, permissions, backup policy and sync policy of a [`FileManager`] fluently,
+/// as an alternative to naming the [`FileManager`] type and picking one of its constructors.
+///
+/// Unless configured otherwise, the file is not locked, is written with [`Writable`],
+/// and is created if it does not exist (see [`OpenBehavior::Create`]).
+///
+/// ```no_run
+/// # use singlefile_formats::json_serde::{Json, JsonError};
+/// use singlefile::manager::{Atomic, ExclusiveLock, FileManagerBuilder, OpenBehavior};
+///
+/// let (value, manager) = FileManagerBuilder::new("data.json", Json::<true>)
+///   .with_lock::<ExclusiveLock>()
+///   .with_mode::<Atomic>()
+///   .with_open_behavior(OpenBehavior::Create)
+///   .build_or_default::<Vec<String>>()?;
+/// # Ok::<(), singlefile::Error<JsonError>>(())
+/// ```
+///
+/// See also [`ContainerBuilder`], which opens a [`Container`] instead.
+///
+/// [`ContainerBuilder`]: crate::container::ContainerBuilder
+/// [`Container`]: crate::container::Container
+#[derive(Debug, Clone)]
+pub struct FileManagerBuilder<Format, Lock = NoLock, Mode = Writable> {
+  path: PathBuf,
+  format: Format,
+  lock: PhantomData<Lock>,
+  mode: PhantomData<Mode>,
+  open_behavior: OpenBehavior,
+  permissions: Option<Permissions>,
+  create_options: CreateOptions,
+  create_dirs: bool,
+  backup_policy: Option<BackupPolicy>,
+  sync_policy: SyncPolicy,
+  max_size: Option<u64>,
+  #[cfg(feature = "metrics")]
+  instruments: Option<Instruments>
+}
+
+impl<Format> FileManagerBuilder<Format> {
+  /// Creates a new [`FileManagerBuilder`] for the file at the given path, using the given format.
+  pub fn new<P: AsRef<Path>>(path: P, format: Format) -> Self {
+    FileManagerBuilder {
+      path: path.as_ref().to_owned(),
+      format,
+      lock: PhantomData,
+      mode: PhantomData,
+      open_behavior: OpenBehavior::default(),
+      permissions: None,
+      create_options: CreateOptions::new(),
+      create_dirs: false,
+      backup_policy: None,
+      sync_policy: SyncPolicy::default(),
+      max_size: None,
+      #[cfg(feature = "metrics")]
+      instruments: None
+    }
+  }
+}
+
+impl<Format, Lock, Mode> FileManagerBuilder<Format, Lock, Mode> {
+  /// Sets the lock mode that the file is locked with, see [`manager::lock`][crate::manager::lock].
+  #[inline]
+  pub fn with_lock<NewLock>(self) -> FileManagerBuilder<Format, NewLock, Mode> {
+    self.retype()
+  }
+
+  /// Sets the file mode that the file is read and written with, see [`manager::mode`][crate::manager::mode].
+  #[inline]
+  pub fn with_mode<NewMode>(self) -> FileManagerBuilder<Format, Lock, NewMode> {
+    self.retype()
+  }
+
+  /// Sets what happens when the file is opened, depending on whether it exists.
+  #[inline]
+  pub fn with_open_behavior(self, open_behavior: OpenBehavior) -> Self {
+    FileManagerBuilder { open_behavior, ..self }
+  }
+
+  /// Sets the permissions of the file, which are applied to it when it is opened.
+  /// If the file is created, the permissions are applied before anything is written to it.
+  #[inline]
+  pub fn with_permissions(self, permissions: Permissions) -> Self {
+    FileManagerBuilder { permissions: Some(permissions), ..self }
+  }
+
+  /// Sets the [`CreateOptions`] that the file is created with, if it does not exist.
+  #[inline]
+  pub fn with_create_options(self, create_options: CreateOptions) -> Self {
+    FileManagerBuilder { create_options, ..self }
+  }
+
+  /// Sets whether the parent directories of the file are created if they do not exist, when the file is created.
+  ///
+  /// This is disabled by default, so that a mistyped path fails instead of creating directories.
+  #[inline]
+  pub fn with_create_dirs(self, create_dirs: bool) -> Self {
+    FileManagerBuilder { create_dirs, ..self }
+  }
+
+  /// Sets the [`BackupPolicy`] of the manager, see [`FileManager::with_backup_policy`].
+  #[inline]
+  pub fn with_backup_policy(self, backup_policy: BackupPolicy) -> Self {
+    FileManagerBuilder { backup_policy: Some(backup_policy), ..self }
+  }
+
+  /// Sets the [`SyncPolicy`] of the manager, see [`FileManager::with_sync_policy`].
+  #[inline]
+  pub fn with_sync_policy(self, sync_policy: SyncPolicy) -> Self {
+    FileManagerBuilder { sync_policy, ..self }
+  }
+
+  /// Sets the maximum size of the file, see [`FileManager::with_max_size`].
+  /// The file is never checked against it when it is created, since the initial value is not read from it.
+  #[inline]
+  pub fn with_max_size(self, max_size: u64) -> Self {
+    FileManagerBuilder { max_size: Some(max_size), ..self }
+  }
+
+  /// Sets the [`Instrumentation`] of the manager, see [`FileManager::with_instrumentation`].
+  /// Unlike attaching it to the manager afterwards, this also measures how long it takes to lock the file.
+  #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+  #[cfg(feature = "metrics")]
+  #[inline]
+  pub fn with_instrumentation(self, instrumentation: Arc<dyn Instrumentation>) -> Self {
+    FileManagerBuilder { instruments: Some(Instruments(instrumentation)), ..self }
+  }
+
+  /// Gets the path of the file that will be opened.
+  #[inline]
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Gets the configured open behavior.
+  #[inline]
+  pub const fn open_behavior(&self) -> OpenBehavior {
+    self.open_behavior
+  }
+
+  fn retype<NewLock, NewMode>(self) -> FileManagerBuilder<Format, NewLock, NewMode> {
+    FileManagerBuilder {
+      path: self.path,
+      format: self.format,
+      lock: PhantomData,
+      mode: PhantomData,
+      open_behavior: self.open_behavior,
+      permissions: self.permissions,
+      create_options: self.create_options,
+      create_dirs: self.create_dirs,
+      backup_policy: self.backup_policy,
+      sync_policy: self.sync_policy,
+      max_size: self.max_size,
+      #[cfg(feature = "metrics")]
+      instruments: self.instruments
+    }
+  }
+}
+
+type BuildResult<T, Format, Lock, Mode> = Result<(T, FileManager<Format, Lock, Mode>), Error<<Format as FileFormat<T>>::FormatError>>;
+
+impl<Format, Lock, Mode> FileManagerBuilder<Format, Lock, Mode>
+where Lock: FileLock, Mode: Reading {
+  /// Opens the [`FileManager`], writing the given value to the file if the open behavior calls for it.
+  #[inline]
+  pub fn build_or<T>(self, value: T) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T> {
+    self.build_or_else(|| value)
+  }
+
+  /// Opens the [`FileManager`], writing the default value of `T` to the file if the open behavior calls for it.
+  #[inline]
+  pub fn build_or_default<T>(self) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T>, T: Default {
+    self.build_or_else(T::default)
+  }
+
+  /// Opens the [`FileManager`], writing the result of the given closure to the file if the open behavior calls for it.
+  pub fn build_or_else<T, C>(self, closure: C) -> BuildResult<T, Format, Lock, Mode>
+  where Format: FileFormat<T>, C: FnOnce() -> T {
+    let write_initial = match self.open_behavior {
+      OpenBehavior::Open => false,
+      OpenBehavior::Create => match fs::metadata(&self.path) {
+        Ok(_) => false,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => true,
+        Err(err) => return Err(err.into())
+      },
+      OpenBehavior::Overwrite => true
+    };
+
+    let value = match write_initial {
+      true => {
+        if self.create_dirs {
+          create_parent_dirs(&self.path)?;
+        };
+
+        let mut options = OpenOptions::new();
+        self.create_options.apply(options.write(true).create(true).truncate(true));
+        let file = options.open(&self.path)?;
+        if let Some(permissions) = &self.permissions {
+          file.set_permissions(permissions.clone())?;
+        };
+
+        let value = closure();
+        Mode::write_initial(&self.format, &file, &self.path, &value)?;
+        Some(value)
+      },
+      false => {
+        if let Some(permissions) = &self.permissions {
+          fs::set_permissions(&self.path, permissions.clone())?;
+        };
+
+        None
+      }
+    };
+
+    #[cfg(feature = "metrics")]
+    let manager = match self.instruments {
+      Some(instruments) => FileManager::open_instrumented(&self.path, self.format, instruments)?,
+      None => FileManager::open(&self.path, self.format)?
+    };
+    #[cfg(not(feature = "metrics"))]
+    let manager = FileManager::open(&self.path, self.format)?;
+
+    let mut manager = manager.with_sync_policy(self.sync_policy);
+    manager.backup_policy = self.backup_policy;
+    manager.max_size = self.max_size;
+    let value = match value {
+      Some(value) => value,
+      None => manager.read()?
+    };
+
+    Ok((value, manager))
+  }
+}
@@ -0,0 +1,278 @@
+//! A content-addressed file mode, keeping every committed version of a file until it is garbage collected.
+//!
+//! This module can be enabled with the `cas` cargo feature.
+//!
+//! With the [`ContentAddressed`] mode, every write stores the serialized contents as an immutable object
+//! named after their SHA-256 hash, in a directory next to the managed file (`<file name>.objects/<hash>`).
+//! The managed file itself only holds a history of object hashes, one per line, with the last one being the current version.
+//!
+//! Since objects are never modified, any previous version can be read back with [`FileManager::read_version`],
+//! or made current again with [`FileManager::rollback`]. Objects no longer referenced by the history are
+//! removed with [`FileManager::gc`].
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! # fn main() -> Result<(), singlefile::Error<JsonError>> {
+//! use singlefile::container::ContainerContentAddressedLocked;
+//!
+//! let mut container = ContainerContentAddressedLocked::<Vec<i32>, Json>::create_or_default("numbers.json", Json)?;
+//! container.push(1);
+//! container.commit()?;
+//!
+//! // Undo the last commit
+//! let history = container.manager().history()?;
+//! container.manager().rollback(&history[history.len() - 2])?;
+//! container.refresh()?;
+//! assert!(container.is_empty());
+//!
+//! // Forget everything but the current version
+//! container.manager().gc(1)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::Error;
+use crate::manager::FileManager;
+use crate::manager::format::FileFormat;
+use crate::manager::mode::{sync_dir, FileMode, Reading, Writing};
+use crate::sealed::Sealed;
+
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+
+
+/// A file mode that stores every version of a file as an immutable, content-addressed object.
+/// See the [module-level documentation][self] for more information.
+///
+/// The history file is only ever appended to (except by [`FileManager::gc`], which overwrites it without truncating it first),
+/// and objects are written to a temporary file before being renamed into place, so an interrupted write never corrupts
+/// the current version. A line of the history left incomplete by an interrupted write is ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentAddressed;
+
+impl Sealed for ContentAddressed {}
+
+impl Reading for ContentAddressed {
+  fn read<T, Format>(format: &Format, file: &File, path: &Path) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    let id = read_history(file)?.pop()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "history is empty"))?;
+    read_object(format, path, &id)
+  }
+}
+
+impl Writing for ContentAddressed {
+  /// Always syncs, regardless of `sync`.
+  fn write<T, Format>(format: &Format, file: &File, path: &Path, value: &T, _sync: bool) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    let id = write_object(format, path, value)?;
+    if read_history(file)?.last() != Some(&id) {
+      append_history(file, &id)?;
+    };
+
+    Ok(())
+  }
+}
+
+impl FileMode for ContentAddressed {
+  const READABLE: bool = true;
+  const WRITABLE: bool = true;
+
+  fn write_initial<T, Format>(format: &Format, file: &File, path: &Path, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    let id = write_object(format, path, value)?;
+    append_history(file, &id)?;
+    Ok(())
+  }
+}
+
+impl<Format, Lock> FileManager<Format, Lock, ContentAddressed> {
+  /// Returns the hashes of every version of the file still in the history, from oldest to newest.
+  /// The last hash is that of the current version.
+  pub fn history(&self) -> io::Result<Vec<ObjectId>> {
+    read_history(self.file())
+  }
+
+  /// Reads a specific version of the file, which must not have been removed by [`FileManager::gc`].
+  pub fn read_version<T>(&self, id: &ObjectId) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    read_object(self.format(), self.path(), id)
+  }
+
+  /// Makes a previous version of the file the current version again, by appending it to the history.
+  ///
+  /// This does not modify any container using this manager, it must be refreshed to observe the rolled back version.
+  pub fn rollback(&self, id: &ObjectId) -> io::Result<()> {
+    if !object_path(self.path(), id).is_file() {
+      return Err(io::Error::new(io::ErrorKind::NotFound, "object does not exist"));
+    };
+
+    append_history(self.file(), id)
+  }
+
+  /// Truncates the history to the `keep` most recent versions (always keeping at least the current version),
+  /// then removes every object that is no longer referenced by it, returning the hashes of the removed objects.
+  pub fn gc(&self, keep: usize) -> io::Result<Vec<ObjectId>> {
+    let mut history = read_history(self.file())?;
+    history.drain(..history.len().saturating_sub(keep.max(1)));
+    write_history(self.file(), &history)?;
+
+    let mut removed = Vec::new();
+    let dir = objects_dir(self.path());
+    for entry in fs::read_dir(&dir)? {
+      let entry = entry?;
+      let id = match entry.file_name().to_str().map(ObjectId::from_str) {
+        Some(Ok(id)) => id,
+        // leftover temporary files from interrupted writes are removed as well
+        _ => {
+          fs::remove_file(entry.path())?;
+          continue;
+        }
+      };
+
+      if !history.contains(&id) {
+        fs::remove_file(entry.path())?;
+        removed.push(id);
+      };
+    };
+
+    Ok(removed)
+  }
+}
+
+
+
+/// The SHA-256 hash identifying an object stored by [`ContentAddressed`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub [u8; 32]);
+
+impl ObjectId {
+  /// Computes the [`ObjectId`] of the given contents.
+  pub fn of(contents: &[u8]) -> Self {
+    ObjectId(Sha256::digest(contents).into())
+  }
+}
+
+impl fmt::Display for ObjectId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+  }
+}
+
+impl fmt::Debug for ObjectId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "ObjectId({self})")
+  }
+}
+
+impl FromStr for ObjectId {
+  type Err = InvalidObjectId;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s.len() != 64 || !s.is_ascii() {
+      return Err(InvalidObjectId);
+    };
+
+    let mut id = [0; 32];
+    for (i, b) in id.iter_mut().enumerate() {
+      *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| InvalidObjectId)?;
+    };
+
+    Ok(ObjectId(id))
+  }
+}
+
+/// An error returned when parsing an [`ObjectId`] from a string that is not 64 hexadecimal digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("invalid object id")]
+pub struct InvalidObjectId;
+
+
+
+fn objects_dir(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(".objects");
+  path.with_file_name(name)
+}
+
+fn object_path(path: &Path, id: &ObjectId) -> PathBuf {
+  objects_dir(path).join(id.to_string())
+}
+
+fn read_object<T, Format>(format: &Format, path: &Path, id: &ObjectId) -> Result<T, Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let buf = fs::read(object_path(path, id))?;
+  if ObjectId::of(&buf) != *id {
+    return Err(io::Error::new(io::ErrorKind::InvalidData, "object does not match its hash").into());
+  };
+
+  format.from_buffer(&buf).map_err(Error::Format)
+}
+
+fn write_object<T, Format>(format: &Format, path: &Path, value: &T) -> Result<ObjectId, Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let buf = format.to_buffer(value).map_err(Error::Format)?;
+  let id = ObjectId::of(&buf);
+  let object_path = object_path(path, &id);
+  // objects are immutable, so an existing object already holds these exact contents
+  if !object_path.exists() {
+    fs::create_dir_all(objects_dir(path))?;
+    let temp_path = object_path.with_extension("tmp");
+    let mut temp_file = File::create(&temp_path)?;
+    temp_file.write_all(&buf)?;
+    temp_file.sync_all()?;
+    fs::rename(&temp_path, &object_path)?;
+    // the history must never refer to an object whose rename (or directory) could still be lost
+    sync_dir(&object_path)?;
+    sync_dir(&objects_dir(path))?;
+  };
+
+  Ok(id)
+}
+
+fn read_history(mut file: &File) -> io::Result<Vec<ObjectId>> {
+  let mut buf = String::new();
+  file.seek(SeekFrom::Start(0))?;
+  file.read_to_string(&mut buf)?;
+  file.seek(SeekFrom::Start(0))?;
+
+  // a line that cannot be parsed can only have been left behind by an interrupted append
+  Ok(buf.lines().filter_map(|line| line.parse().ok()).collect())
+}
+
+fn append_history(mut file: &File, id: &ObjectId) -> io::Result<()> {
+  let len = file.seek(SeekFrom::End(0))?;
+  if len > 0 {
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last != *b"\n" {
+      file.write_all(b"\n")?;
+    };
+  };
+
+  writeln!(file, "{id}")?;
+  file.seek(SeekFrom::Start(0))?;
+  file.sync_all()
+}
+
+/// Replaces the history with the given one, which must be a suffix of the current history.
+///
+/// The history is overwritten in place (the file cannot be replaced, since it is locked through its handle),
+/// and only cut to length once the new history has been synced. An interruption leaves the new history followed
+/// by the end of the old one, which still ends with the current version and only refers to objects that still exist.
+fn write_history(mut file: &File, history: &[ObjectId]) -> io::Result<()> {
+  let buf = history.iter().map(|id| format!("{id}\n")).collect::<String>();
+  file.seek(SeekFrom::Start(0))?;
+  file.write_all(buf.as_bytes())?;
+  file.sync_all()?;
+  file.set_len(buf.len() as u64)?;
+  file.seek(SeekFrom::Start(0))?;
+  file.sync_all()
+}
@@ -0,0 +1,50 @@
+//! Defines a manager that reads from bytes embedded in the application, instead of from a file.
+
+use crate::error::Error;
+use crate::manager::format::FileFormat;
+
+/// Manages a static buffer of bytes, such as one embedded with `include_bytes!`, as if it were a read-only file.
+///
+/// This allows bundled defaults to be read through the same [`Container`] interface as files on disk,
+/// without ever touching the filesystem. See [`ContainerStatic`].
+///
+/// [`Container`]: crate::container::Container
+/// [`ContainerStatic`]: crate::container::ContainerStatic
+#[derive(Debug, Clone, Copy)]
+pub struct StaticManager<Format> {
+  format: Format,
+  bytes: &'static [u8]
+}
+
+impl<Format> StaticManager<Format> {
+  /// Creates a new [`StaticManager`] for the given bytes.
+  #[inline]
+  pub const fn new(bytes: &'static [u8], format: Format) -> Self {
+    StaticManager { format, bytes }
+  }
+
+  /// Gets a reference to the [`FileFormat`] used by this manager.
+  #[inline]
+  pub const fn format(&self) -> &Format {
+    &self.format
+  }
+
+  /// Gets the bytes managed by this manager.
+  #[inline]
+  pub const fn bytes(&self) -> &'static [u8] {
+    self.bytes
+  }
+
+  /// Reads a value from the bytes managed by this manager.
+  #[inline]
+  pub fn read<T>(&self) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    self.format.from_buffer(self.bytes).map_err(Error::Format)
+  }
+
+  /// Closes this [`StaticManager`], returning the [`FileFormat`] that it uses.
+  #[inline]
+  pub fn into_inner(self) -> Format {
+    self.format
+  }
+}
@@ -1,9 +1,14 @@
 //! How to interpret the contents of files.
 
 pub mod default_formats;
+pub mod record;
+#[cfg_attr(docsrs, doc(cfg(feature = "shared-async")))]
+#[cfg(feature = "shared-async")]
+pub mod async_io;
 
 pub use self::default_formats::PlainBytes;
 pub use self::default_formats::PlainUtf8;
+pub use self::record::RecordFormat;
 
 use std::io::{Cursor, BufReader, BufWriter, Read, Write};
 
@@ -42,6 +47,7 @@ pub trait FileFormat<T> {
   ///
   /// If you are reading directly from a [`File`][std::fs::File], you should consider
   /// using [`from_reader_buffered`][FileFormat::from_reader_buffered] instead.
+  #[allow(clippy::wrong_self_convention)]
   fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError>;
 
   /// Identical to [`FileFormat::from_reader`], however the provided reader is buffered with [`BufReader`].
@@ -49,12 +55,14 @@ pub trait FileFormat<T> {
   /// You should override this function if your file format reads
   /// to a buffer internally in order to avoid double-buffering.
   #[inline]
+  #[allow(clippy::wrong_self_convention)]
   fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
     self.from_reader(BufReader::new(reader))
   }
 
   /// Deserialize a value from a byte vec.
   #[inline]
+  #[allow(clippy::wrong_self_convention)]
   fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
     self.from_reader(buf)
   }
@@ -80,11 +88,21 @@ pub trait FileFormat<T> {
     self.to_writer(&mut buf, value)?;
     Ok(buf.into_inner())
   }
+
+  /// Finds the byte offset into `buf` at which the given error occurred while deserializing it.
+  ///
+  /// This is only used for diagnostics (see [`fsck`][crate::utils::fsck]),
+  /// so formats that cannot tell where an error occurred may leave this returning `None`.
+  #[inline]
+  fn error_offset(&self, _error: &Self::FormatError, _buf: &[u8]) -> Option<usize> {
+    None
+  }
 }
 
 /// A trait that indicates a file's contents will always be valid UTF-8.
 pub trait FileFormatUtf8<T>: FileFormat<T> {
   /// Deserialize a buffer from a string slice.
+  #[allow(clippy::wrong_self_convention)]
   fn from_string_buffer(&self, buf: &str) -> Result<T, Self::FormatError>;
 
   /// Serialize a value into a string buffer.
@@ -120,6 +138,11 @@ macro_rules! impl_file_format_delegate {
       fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
         $Format::to_buffer(self, value)
       }
+
+      #[inline]
+      fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+        $Format::error_offset(self, error, buf)
+      }
     }
   );
 }
@@ -0,0 +1,192 @@
+//! Bridges between [`FileFormat`] and Tokio's [`AsyncRead`] and [`AsyncWrite`] streams.
+//!
+//! Since [`FileFormat`] is blocking, the entire contents are buffered in memory, and then
+//! deserialized or written out in one go. This makes it possible to send a container's contents
+//! over a socket, or read them from a network stream, without touching disk.
+//!
+//! This module can be enabled with the `shared-async` cargo feature.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! # async fn run<S>(mut stream: S) -> Result<(), singlefile::Error<JsonError>>
+//! # where S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin {
+//! use singlefile::manager::format::async_io;
+//!
+//! let format = Json::<true>;
+//! let value: Vec<i32> = async_io::from_async_reader(&format, &mut stream).await?;
+//! async_io::to_async_writer(&format, &mut stream, &value).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Files using a [`RecordFormat`] can instead be read one record at a time with [`stream_records`],
+//! which only buffers a single record at once.
+//!
+//! Formats that can read and write asynchronous streams directly implement [`AsyncFileFormat`],
+//! which is used by [`AsyncFileManager`] to do file I/O without blocking. Any [`FileFormat`] can be used
+//! as an [`AsyncFileFormat`] by wrapping it in [`Buffered`].
+//!
+//! [`AsyncFileManager`]: crate::manager::async_manager::AsyncFileManager
+
+use crate::error::Error;
+use super::FileFormat;
+use super::record::{RecordFormat, trim_record};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+
+/// A boxed future returned by the functions of [`AsyncFileFormat`].
+pub type FormatFuture<'a, R, FE> = Pin<Box<dyn Future<Output = Result<R, Error<FE>>> + Send + 'a>>;
+
+/// A trait that describes how a file's contents should be interpreted, reading from and writing to
+/// Tokio's [`AsyncRead`] and [`AsyncWrite`] streams instead of blocking ones.
+///
+/// Since traits cannot contain `async` functions, the returned futures are boxed.
+/// Errors from the stream itself are returned as [`Error::Io`].
+///
+/// # Example
+/// ```no_run
+/// use singlefile::Error;
+/// use singlefile::manager::format::async_io::{AsyncFileFormat, FormatFuture};
+/// use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+/// use std::convert::Infallible;
+///
+/// /// Stores a list of lines.
+/// struct Lines;
+///
+/// impl AsyncFileFormat<Vec<String>> for Lines {
+///   type FormatError = Infallible;
+///
+///   fn from_async_reader<'a, R>(&'a self, mut reader: R) -> FormatFuture<'a, Vec<String>, Infallible>
+///   where R: AsyncRead + Unpin + Send + 'a {
+///     Box::pin(async move {
+///       let mut buf = String::new();
+///       reader.read_to_string(&mut buf).await?;
+///       Ok(buf.lines().map(str::to_owned).collect())
+///     })
+///   }
+///
+///   fn to_async_writer<'a, W>(&'a self, mut writer: W, value: &'a Vec<String>) -> FormatFuture<'a, (), Infallible>
+///   where W: AsyncWrite + Unpin + Send + 'a {
+///     Box::pin(async move {
+///       for line in value {
+///         writer.write_all(line.as_bytes()).await?;
+///         writer.write_all(b"\n").await?;
+///       };
+///
+///       writer.flush().await.map_err(Error::from)
+///     })
+///   }
+/// }
+/// ```
+pub trait AsyncFileFormat<T> {
+  /// The type of error to return from `to_async_writer` and `from_async_reader`.
+  type FormatError: std::error::Error;
+
+  /// Deserialize a value from an `AsyncRead` stream.
+  #[allow(clippy::wrong_self_convention)]
+  fn from_async_reader<'a, R>(&'a self, reader: R) -> FormatFuture<'a, T, Self::FormatError>
+  where R: AsyncRead + Unpin + Send + 'a, T: 'a;
+
+  /// Serialize a value into an `AsyncWrite` stream, flushing it afterwards.
+  fn to_async_writer<'a, W>(&'a self, writer: W, value: &'a T) -> FormatFuture<'a, (), Self::FormatError>
+  where W: AsyncWrite + Unpin + Send + 'a, T: 'a;
+}
+
+/// Adapts a [`FileFormat`] into an [`AsyncFileFormat`], using [`from_async_reader`] and [`to_async_writer`].
+///
+/// Values are serialized into a buffer before the returned future is first polled,
+/// so writing does not require `T` to be [`Sync`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Buffered<Format>(pub Format);
+
+impl<T, Format> AsyncFileFormat<T> for Buffered<Format>
+where T: Send, Format: FileFormat<T> + Sync, Format::FormatError: Send {
+  type FormatError = Format::FormatError;
+
+  fn from_async_reader<'a, R>(&'a self, reader: R) -> FormatFuture<'a, T, Self::FormatError>
+  where R: AsyncRead + Unpin + Send + 'a, T: 'a {
+    Box::pin(from_async_reader(&self.0, reader))
+  }
+
+  fn to_async_writer<'a, W>(&'a self, mut writer: W, value: &'a T) -> FormatFuture<'a, (), Self::FormatError>
+  where W: AsyncWrite + Unpin + Send + 'a, T: 'a {
+    let buf = self.0.to_buffer(value);
+    Box::pin(async move {
+      let buf = buf.map_err(Error::Format)?;
+      writer.write_all(&buf).await?;
+      writer.flush().await?;
+      Ok(())
+    })
+  }
+}
+
+/// Reads the whole of `reader` into a buffer, then deserializes a value from it.
+pub async fn from_async_reader<T, Format, R>(format: &Format, mut reader: R) -> Result<T, Error<Format::FormatError>>
+where Format: FileFormat<T>, R: AsyncRead + Unpin {
+  let mut buf = Vec::new();
+  reader.read_to_end(&mut buf).await?;
+  format.from_buffer(&buf).map_err(Error::Format)
+}
+
+/// Serializes a value into a buffer, then writes the whole buffer to `writer` and flushes it.
+pub async fn to_async_writer<T, Format, W>(format: &Format, mut writer: W, value: &T) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T>, W: AsyncWrite + Unpin {
+  let buf = format.to_buffer(value).map_err(Error::Format)?;
+  writer.write_all(&buf).await?;
+  writer.flush().await?;
+  Ok(())
+}
+
+/// Returns a stream that deserializes the records of `reader` one at a time.
+///
+/// The provided reader is buffered with [`BufReader`].
+#[inline]
+pub fn stream_records<T, Format, R>(format: &Format, reader: R) -> AsyncRecords<'_, T, Format, BufReader<R>>
+where Format: RecordFormat<T>, R: AsyncRead + Unpin {
+  AsyncRecords::new(format, BufReader::new(reader))
+}
+
+/// An asynchronous stream over the records of a reader, returned by [`stream_records`].
+///
+/// Iteration ends after the first I/O error, format errors on individual records do not end iteration.
+#[derive(Debug)]
+pub struct AsyncRecords<'f, T, Format, R> {
+  format: &'f Format,
+  reader: Option<R>,
+  buf: Vec<u8>,
+  phantom: PhantomData<fn() -> T>
+}
+
+impl<'f, T, Format, R> AsyncRecords<'f, T, Format, R>
+where Format: RecordFormat<T>, R: AsyncBufRead + Unpin {
+  /// Creates a stream over the records of an already buffered reader.
+  #[inline]
+  pub fn new(format: &'f Format, reader: R) -> Self {
+    AsyncRecords { format, reader: Some(reader), buf: Vec::new(), phantom: PhantomData }
+  }
+
+  /// Reads and deserializes the next record, returning `None` once the end of the reader has been reached.
+  pub async fn next(&mut self) -> Option<Result<T, Error<Format::FormatError>>> {
+    let reader = self.reader.as_mut()?;
+    loop {
+      self.buf.clear();
+      match reader.read_until(b'\n', &mut self.buf).await {
+        Ok(0) => {
+          self.reader = None;
+          return None;
+        },
+        Ok(_) => if let Some(record) = trim_record(&self.buf) {
+          return Some(self.format.from_record(record).map_err(Error::Format));
+        },
+        Err(err) => {
+          self.reader = None;
+          return Some(Err(err.into()));
+        }
+      };
+    }
+  }
+}
@@ -0,0 +1,118 @@
+//! Formats for files consisting of a sequence of newline-delimited records, such as NDJSON or CSV.
+//!
+//! Unlike a [`FileFormat`], which always reads and writes a file's contents as one whole value,
+//! a [`RecordFormat`] can be used to process a file one record at a time with [`RecordFormat::stream`],
+//! without ever holding the whole file in memory.
+//!
+//! [`FileFormat`]: crate::manager::format::FileFormat
+
+use crate::error::Error;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+
+/// A trait that describes how each record of a newline-delimited file should be interpreted.
+///
+/// Records are separated by `\n` (optionally preceded by `\r`), and blank lines are skipped.
+pub trait RecordFormat<T> {
+  /// The type of error to return from `from_record` and `to_record`.
+  type FormatError: std::error::Error;
+
+  /// Deserialize a value from a single record, not including its line terminator.
+  #[allow(clippy::wrong_self_convention)]
+  fn from_record(&self, record: &[u8]) -> Result<T, Self::FormatError>;
+
+  /// Serialize a value as a single record into a `Write` stream, not including its line terminator.
+  ///
+  /// The serialized record must not contain any newlines.
+  fn to_record<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError>;
+
+  /// Returns an iterator that deserializes the records of a `Read` stream one at a time.
+  ///
+  /// The provided reader is buffered with [`BufReader`].
+  #[inline]
+  fn stream<R: Read>(&self, reader: R) -> Records<'_, T, Self, BufReader<R>> where Self: Sized {
+    Records::new(self, BufReader::new(reader))
+  }
+
+  /// Serializes each value as a record into a `Write` stream, terminating every record with `\n`.
+  fn to_writer_records<W, I>(&self, mut writer: W, values: I) -> Result<(), Error<Self::FormatError>>
+  where W: Write, I: IntoIterator<Item = T> {
+    for value in values {
+      self.to_record(&mut writer, &value).map_err(Error::Format)?;
+      writer.write_all(b"\n")?;
+    };
+
+    writer.flush()?;
+    Ok(())
+  }
+}
+
+impl<T, Format: RecordFormat<T>> RecordFormat<T> for &Format {
+  type FormatError = Format::FormatError;
+
+  #[inline]
+  fn from_record(&self, record: &[u8]) -> Result<T, Self::FormatError> {
+    Format::from_record(self, record)
+  }
+
+  #[inline]
+  fn to_record<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+    Format::to_record(self, writer, value)
+  }
+}
+
+/// An iterator over the records of a stream, returned by [`RecordFormat::stream`].
+///
+/// Iteration ends after the first I/O error, format errors on individual records do not end iteration.
+#[derive(Debug)]
+pub struct Records<'f, T, Format, R> {
+  format: &'f Format,
+  reader: Option<R>,
+  buf: Vec<u8>,
+  phantom: PhantomData<fn() -> T>
+}
+
+impl<'f, T, Format, R> Records<'f, T, Format, R>
+where Format: RecordFormat<T>, R: BufRead {
+  /// Creates an iterator over the records of an already buffered reader.
+  #[inline]
+  pub fn new(format: &'f Format, reader: R) -> Self {
+    Records { format, reader: Some(reader), buf: Vec::new(), phantom: PhantomData }
+  }
+}
+
+impl<'f, T, Format, R> Iterator for Records<'f, T, Format, R>
+where Format: RecordFormat<T>, R: BufRead {
+  type Item = Result<T, Error<Format::FormatError>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let reader = self.reader.as_mut()?;
+    loop {
+      self.buf.clear();
+      match reader.read_until(b'\n', &mut self.buf) {
+        Ok(0) => {
+          self.reader = None;
+          return None;
+        },
+        Ok(_) => if let Some(record) = trim_record(&self.buf) {
+          return Some(self.format.from_record(record).map_err(Error::Format));
+        },
+        Err(err) => {
+          self.reader = None;
+          return Some(Err(err.into()));
+        }
+      };
+    }
+  }
+}
+
+/// Strips the line terminator from a record, returning `None` if the record is blank.
+pub(crate) fn trim_record(line: &[u8]) -> Option<&[u8]> {
+  let line = line.strip_suffix(b"\n").unwrap_or(line);
+  let line = line.strip_suffix(b"\r").unwrap_or(line);
+  match line.iter().all(u8::is_ascii_whitespace) {
+    true => None,
+    false => Some(line)
+  }
+}
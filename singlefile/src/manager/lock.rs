@@ -1,9 +1,56 @@
 //! Defines different types of file system locks.
+//!
+//! [`SharedLock`] and [`ExclusiveLock`] use `flock` on Unix (and `LockFileEx` on Windows).
+//! On Unix, [`SharedFcntlLock`] and [`ExclusiveFcntlLock`] are also available, which use POSIX `fcntl` record locks instead.
+//! The two kinds of lock have different semantics:
+//!
+//! - `flock` locks belong to an open file description, so two handles to the same file
+//!   within one process will conflict with each other. They are released when that handle is closed.
+//!   `flock` is not reliably supported on network file systems such as NFS.
+//! - `fcntl` locks belong to a process, so they never conflict with other handles within the same process.
+//!   They are released when the process closes *any* handle to the file, not just the one that locked it.
+//!   `fcntl` locks are forwarded to the server on NFS, making them the right choice there.
+//! - An exclusive `fcntl` lock can only be taken on a file opened for writing,
+//!   so [`ExclusiveFcntlLock`] cannot be used with [`Readonly`][crate::manager::mode::Readonly].
+//!
+//! The two kinds of lock do not interact with each other on most platforms,
+//! so every process accessing a file should agree on which is used.
+//!
+//! [`SharedLock`] and [`ExclusiveLock`] fail immediately if the file is already locked, with an error
+//! that converts into [`Error::LockContended`]. [`SharedLockBlocking`] and [`ExclusiveLockBlocking`]
+//! instead wait for as long as it takes the lock to become available, while [`SharedLockWithTimeout`]
+//! and [`ExclusiveLockWithTimeout`] wait for at most the given number of milliseconds:
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::container::Container;
+//! use singlefile::manager::{FileManager, Writable};
+//! use singlefile::manager::lock::ExclusiveLockWithTimeout;
+//!
+//! // Waits up to 5 seconds for other processes to release the file
+//! type ManagerPatient<Format> = FileManager<Format, ExclusiveLockWithTimeout<5000>, Writable>;
+//! let container = Container::<i32, ManagerPatient<Json>>::create_or_default("data.json", Json)?;
+//! # Ok::<(), singlefile::Error<singlefile_formats::json_serde::JsonError>>(())
+//! ```
+//!
+//! The lock held by a [`FileManager`] can be switched between shared and exclusive access without reopening the file,
+//! with [`FileManager::upgrade_lock`] and [`FileManager::downgrade_lock`]. This suits read-mostly setups
+//! where several processes share a file, and only occasionally need to write to it. See [`Upgradable`].
+//!
+//! [`Error::LockContended`]: crate::error::Error::LockContended
+//! [`FileManager`]: crate::manager::FileManager
+//! [`FileManager::upgrade_lock`]: crate::manager::FileManager::upgrade_lock
+//! [`FileManager::downgrade_lock`]: crate::manager::FileManager::downgrade_lock
 
+use crate::error::LockContended;
 use crate::sealed::Sealed;
 
 use std::fs::File;
 use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
 
 
 
@@ -18,6 +65,66 @@ pub trait FileLock: Sealed + Send + Sync + 'static {
 
 
 
+/// Describes a shared lock mode that can be upgraded to an exclusive lock mode while the file is locked.
+///
+/// Upgrading a `flock` lock (or any lock on Windows) is not atomic: the shared lock is released before
+/// the exclusive lock is taken, so another process may take an exclusive lock in between. Upgrading an `fcntl`
+/// lock is atomic. If upgrading fails, the shared lock is taken again.
+pub trait Upgradable: FileLock {
+  /// The exclusive lock mode that this lock mode is upgraded to.
+  type Upgraded: Downgradable<Downgraded = Self>;
+
+  /// Upgrades the shared lock held on the file to an exclusive lock.
+  fn upgrade(file: &File) -> io::Result<()> {
+    #[cfg(windows)]
+    Self::unlock(file)?;
+    Self::Upgraded::lock(file).map_err(|err| {
+      // the shared lock may have been released before the exclusive lock failed
+      let _ = Self::lock(file);
+      err
+    })
+  }
+}
+
+/// Describes an exclusive lock mode that can be downgraded to a shared lock mode while the file is locked.
+///
+/// Downgrading a `flock` lock is not atomic, so another process may take an exclusive lock in between,
+/// failing the downgrade and leaving the file unlocked.
+pub trait Downgradable: FileLock {
+  /// The shared lock mode that this lock mode is downgraded to.
+  type Downgraded: Upgradable<Upgraded = Self>;
+
+  /// Downgrades the exclusive lock held on the file to a shared lock.
+  fn downgrade(file: &File) -> io::Result<()> {
+    Self::Downgraded::lock(file)?;
+    // on windows, a handle may hold both locks at once, and unlocking releases the exclusive lock first
+    #[cfg(windows)]
+    Self::unlock(file)?;
+    Ok(())
+  }
+}
+
+macro_rules! impl_lock_pair {
+  ($(#[$attr:meta])* $Shared:ty, $Exclusive:ty $(, const $MILLIS:ident)?) => {
+    $(#[$attr])*
+    impl$(<const $MILLIS: u64>)? Upgradable for $Shared {
+      type Upgraded = $Exclusive;
+    }
+
+    $(#[$attr])*
+    impl$(<const $MILLIS: u64>)? Downgradable for $Exclusive {
+      type Downgraded = $Shared;
+    }
+  };
+}
+
+impl_lock_pair!(SharedLock, ExclusiveLock);
+impl_lock_pair!(SharedLockBlocking, ExclusiveLockBlocking);
+impl_lock_pair!(SharedLockWithTimeout<MILLIS>, ExclusiveLockWithTimeout<MILLIS>, const MILLIS);
+impl_lock_pair!(#[cfg(unix)] SharedFcntlLock, ExclusiveFcntlLock);
+
+
+
 /// A file lock mode that does not lock the file.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct NoLock;
@@ -47,7 +154,7 @@ impl Sealed for SharedLock {}
 impl FileLock for SharedLock {
   #[inline(always)]
   fn lock(file: &File) -> io::Result<()> {
-    fs4::fs_std::FileExt::try_lock_shared(file)
+    fs4::fs_std::FileExt::try_lock_shared(file).map_err(contended)
   }
 
   #[inline(always)]
@@ -67,7 +174,89 @@ impl Sealed for ExclusiveLock {}
 impl FileLock for ExclusiveLock {
   #[inline(always)]
   fn lock(file: &File) -> io::Result<()> {
-    fs4::fs_std::FileExt::try_lock_exclusive(file)
+    fs4::fs_std::FileExt::try_lock_exclusive(file).map_err(contended)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::unlock(file)
+  }
+}
+
+
+
+/// A file lock mode that locks the file for shared access, waiting until it is no longer locked for exclusive access.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SharedLockBlocking;
+
+impl Sealed for SharedLockBlocking {}
+
+impl FileLock for SharedLockBlocking {
+  #[inline(always)]
+  fn lock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::lock_shared(file)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::unlock(file)
+  }
+}
+
+
+
+/// A file lock mode that locks the file for exclusive access, waiting until it is no longer locked.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExclusiveLockBlocking;
+
+impl Sealed for ExclusiveLockBlocking {}
+
+impl FileLock for ExclusiveLockBlocking {
+  #[inline(always)]
+  fn lock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::lock_exclusive(file)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::unlock(file)
+  }
+}
+
+
+
+/// A file lock mode that locks the file for shared access, waiting up to `MILLIS` milliseconds
+/// for it to no longer be locked for exclusive access.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SharedLockWithTimeout<const MILLIS: u64>;
+
+impl<const MILLIS: u64> Sealed for SharedLockWithTimeout<MILLIS> {}
+
+impl<const MILLIS: u64> FileLock for SharedLockWithTimeout<MILLIS> {
+  #[inline]
+  fn lock(file: &File) -> io::Result<()> {
+    lock_with_timeout(file, Duration::from_millis(MILLIS), SharedLock::lock)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fs4::fs_std::FileExt::unlock(file)
+  }
+}
+
+
+
+/// A file lock mode that locks the file for exclusive access, waiting up to `MILLIS` milliseconds
+/// for it to no longer be locked.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExclusiveLockWithTimeout<const MILLIS: u64>;
+
+impl<const MILLIS: u64> Sealed for ExclusiveLockWithTimeout<MILLIS> {}
+
+impl<const MILLIS: u64> FileLock for ExclusiveLockWithTimeout<MILLIS> {
+  #[inline]
+  fn lock(file: &File) -> io::Result<()> {
+    lock_with_timeout(file, Duration::from_millis(MILLIS), ExclusiveLock::lock)
   }
 
   #[inline(always)]
@@ -75,3 +264,103 @@ impl FileLock for ExclusiveLock {
     fs4::fs_std::FileExt::unlock(file)
   }
 }
+
+
+
+/// A file lock mode that locks the file for shared access, using a POSIX `fcntl` record lock.
+/// See the [module-level documentation][self] for how this differs from [`SharedLock`].
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[cfg(unix)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SharedFcntlLock;
+
+#[cfg(unix)]
+impl Sealed for SharedFcntlLock {}
+
+#[cfg(unix)]
+impl FileLock for SharedFcntlLock {
+  #[inline(always)]
+  fn lock(file: &File) -> io::Result<()> {
+    fcntl_lock(file, libc::F_RDLCK)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fcntl_lock(file, libc::F_UNLCK)
+  }
+}
+
+
+
+/// A file lock mode that locks the file for exclusive access, using a POSIX `fcntl` record lock.
+/// See the [module-level documentation][self] for how this differs from [`ExclusiveLock`].
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[cfg(unix)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExclusiveFcntlLock;
+
+#[cfg(unix)]
+impl Sealed for ExclusiveFcntlLock {}
+
+#[cfg(unix)]
+impl FileLock for ExclusiveFcntlLock {
+  #[inline(always)]
+  fn lock(file: &File) -> io::Result<()> {
+    fcntl_lock(file, libc::F_WRLCK)
+  }
+
+  #[inline(always)]
+  fn unlock(file: &File) -> io::Result<()> {
+    fcntl_lock(file, libc::F_UNLCK)
+  }
+}
+
+
+
+/// Marks an error from a non-blocking lock as [`LockContended`] if it was caused by lock contention.
+fn contended(err: io::Error) -> io::Error {
+  match err.raw_os_error().is_some() && err.raw_os_error() == fs4::lock_contended_error().raw_os_error() {
+    true => LockContended.into(),
+    false => err
+  }
+}
+
+/// Retries a non-blocking lock until it succeeds, fails for a reason other than contention, or the timeout elapses.
+fn lock_with_timeout(file: &File, timeout: Duration, try_lock: fn(&File) -> io::Result<()>) -> io::Result<()> {
+  // neither `flock` nor `LockFileEx` can wait with a timeout, so the lock is polled with a growing interval instead
+  let deadline = Instant::now() + timeout;
+  let mut interval = Duration::from_millis(1);
+  loop {
+    match try_lock(file) {
+      Err(err) if LockContended::is_cause_of(&err) => {
+        let now = Instant::now();
+        if now >= deadline { return Err(err) };
+        thread::sleep(interval.min(deadline - now));
+        interval = (interval * 2).min(Duration::from_millis(50));
+      },
+      result => return result
+    };
+  };
+}
+
+/// Applies a non-blocking `fcntl` lock of the given type over the whole file.
+#[cfg(unix)]
+fn fcntl_lock(file: &File, lock_type: libc::c_int) -> io::Result<()> {
+  // SAFETY: `flock` is a plain C struct, for which all zeroes is a valid value.
+  let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+  lock.l_type = lock_type as _;
+  lock.l_whence = libc::SEEK_SET as _;
+  // a start and length of zero covers the whole file, however large it grows
+  lock.l_start = 0;
+  lock.l_len = 0;
+
+  // SAFETY: the file descriptor is valid for the lifetime of `file`, and `lock` is a valid `flock`.
+  match unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } {
+    -1 => match io::Error::last_os_error() {
+      // `fcntl` may report contention as either of these, normalize it to match `flock`
+      err if matches!(err.raw_os_error(), Some(libc::EACCES | libc::EAGAIN)) => Err(LockContended.into()),
+      err => Err(err)
+    },
+    _ => Ok(())
+  }
+}
@@ -1,12 +1,13 @@
 //! Defines different modes of accessing/manipulating files.
 
-use crate::error::Error;
+use crate::error::{Error, TooLarge};
 use crate::manager::format::FileFormat;
 use crate::sealed::Sealed;
 
-use std::fs::{File, OpenOptions};
-use std::io::{self, Seek, SeekFrom};
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 
 
 
@@ -16,21 +17,42 @@ pub trait FileMode: Sealed + Send + Sync + 'static {
   const READABLE: bool;
   /// Whether this file mode writes to files.
   const WRITABLE: bool;
+  /// The sharing mode to open files with, made up of [`SHARE_READ`], [`SHARE_WRITE`] and [`SHARE_DELETE`].
+  /// This only has an effect on Windows, and allows all sharing by default.
+  const SHARE_MODE: u32 = SHARE_READ | SHARE_WRITE | SHARE_DELETE;
+  /// Whether writes replace the file at the path rather than modifying it in place,
+  /// leaving the handle that it was opened with pointing at the previous file.
+  const REPLACES_FILE: bool = false;
 
   /// Open a new file with this file mode.
   fn open<P: AsRef<Path>>(path: P) -> io::Result<File> {
-    OpenOptions::new()
-      .read(Self::READABLE)
-      .write(Self::WRITABLE)
-      .open(path)
+    let mut options = OpenOptions::new();
+    options.read(Self::READABLE).write(Self::WRITABLE);
+    #[cfg(windows)]
+    std::os::windows::fs::OpenOptionsExt::share_mode(&mut options, Self::SHARE_MODE);
+    options.open(path)
+  }
+
+  /// Write the initial value to a file that was just created (or truncated) by one of the `create` functions of a manager.
+  #[inline]
+  fn write_initial<T, Format>(format: &Format, file: &File, _path: &Path, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    write(format, file, value, true)
   }
 }
 
+/// Allows other handles to open the file for reading, corresponds to `FILE_SHARE_READ` on Windows.
+pub const SHARE_READ: u32 = 0x1;
+/// Allows other handles to open the file for writing, corresponds to `FILE_SHARE_WRITE` on Windows.
+pub const SHARE_WRITE: u32 = 0x2;
+/// Allows other handles to delete or rename the file, corresponds to `FILE_SHARE_DELETE` on Windows.
+pub const SHARE_DELETE: u32 = 0x4;
+
 /// Extends `FileMode`, adding the ability to read from files.
 pub trait Reading: FileMode {
-  /// Read a value from the file.
+  /// Read a value from the file, which was opened from the given path.
   #[inline]
-  fn read<T, Format>(format: &Format, file: &File) -> Result<T, Error<Format::FormatError>>
+  fn read<T, Format>(format: &Format, file: &File, _path: &Path) -> Result<T, Error<Format::FormatError>>
   where Format: FileFormat<T> {
     read(format, file)
   }
@@ -38,11 +60,14 @@ pub trait Reading: FileMode {
 
 /// Extends `FileMode`, adding the ability to write to files.
 pub trait Writing: FileMode {
-  /// Write a value to the file.
+  /// Write a value to the file, which was opened from the given path,
+  /// syncing it to disk before returning if `sync` is `true` (see [`SyncPolicy`]).
+  ///
+  /// [`SyncPolicy`]: crate::manager::sync::SyncPolicy
   #[inline]
-  fn write<T, Format>(format: &Format, file: &File, value: &T) -> Result<(), Error<Format::FormatError>>
+  fn write<T, Format>(format: &Format, file: &File, _path: &Path, value: &T, sync: bool) -> Result<(), Error<Format::FormatError>>
   where Format: FileFormat<T> {
-    write(format, file, value)
+    write(format, file, value, sync)
   }
 }
 
@@ -94,9 +119,9 @@ impl Reading for Atomic {}
 
 impl Writing for Atomic {
   #[inline]
-  fn write<T, Format>(format: &Format, file: &File, value: &T) -> Result<(), Error<Format::FormatError>>
+  fn write<T, Format>(format: &Format, file: &File, _path: &Path, value: &T, sync: bool) -> Result<(), Error<Format::FormatError>>
   where Format: FileFormat<T> {
-    write_atomic(format, file, value)
+    write_atomic(format, file, value, sync)
   }
 }
 
@@ -107,30 +132,177 @@ impl FileMode for Atomic {
 
 
 
+/// Similar to [`Atomic`], but never modifies the file in place, eliminating the possibility of file corruption
+/// in the case of a crash midway during a write, not just a failing [`FileFormat`].
+///
+/// Contents are written and synced to a temporary file next to the file, which is then moved into place with a rename.
+/// A crash at any point leaves either the previous or the new contents in place. Temporary files left behind
+/// by a crash are named `<file name>.tmp-<process id>-<timestamp>`, and can be dealt with using [`recover`]
+/// or [`clean_stale_temp_files`].
+///
+/// Since every write replaces the file, values are always read from the path rather than through the handle held
+/// by the manager, and file locks only apply to the file that was originally opened.
+/// The file's permissions are carried over to every replacement (the temporary file is created with them),
+/// but other metadata (such as ownership) is not.
+///
+/// [`recover`]: crate::utils::recover
+/// [`clean_stale_temp_files`]: crate::utils::clean_stale_temp_files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AtomicRename;
+
+impl Sealed for AtomicRename {}
+
+impl Reading for AtomicRename {
+  #[inline]
+  fn read<T, Format>(format: &Format, _file: &File, path: &Path) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    read(format, &File::open(path)?)
+  }
+}
+
+impl Writing for AtomicRename {
+  #[inline]
+  fn write<T, Format>(format: &Format, _file: &File, path: &Path, value: &T, sync: bool) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    write_rename(format, path, value, sync)
+  }
+}
+
+impl FileMode for AtomicRename {
+  const READABLE: bool = true;
+  const WRITABLE: bool = true;
+  const REPLACES_FILE: bool = true;
+
+  #[inline]
+  fn write_initial<T, Format>(format: &Format, _file: &File, path: &Path, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    write_rename(format, path, value, true)
+  }
+}
+
+
+
+/// Wraps another file mode, opening files with the given Windows sharing mode instead of allowing all sharing.
+///
+/// This lets a writable container deny other writers at the OS level, without relying on advisory locks:
+///
+/// ```
+/// use singlefile::manager::mode::{ShareMode, Writable, SHARE_READ, SHARE_DELETE};
+/// use singlefile::manager::{FileManager, NoLock};
+///
+/// // Other handles may still read (and rename) the file, but not open it for writing
+/// type ManagerDenyWriters<Format> = FileManager<Format, NoLock, ShareMode<Writable, { SHARE_READ | SHARE_DELETE }>>;
+/// ```
+///
+/// On platforms other than Windows this behaves exactly like the wrapped mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShareMode<Mode, const SHARE: u32>(PhantomData<Mode>);
+
+impl<Mode, const SHARE: u32> Sealed for ShareMode<Mode, SHARE> {}
+
+impl<Mode: Reading, const SHARE: u32> Reading for ShareMode<Mode, SHARE> {
+  #[inline]
+  fn read<T, Format>(format: &Format, file: &File, path: &Path) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    Mode::read(format, file, path)
+  }
+}
+
+impl<Mode: Writing, const SHARE: u32> Writing for ShareMode<Mode, SHARE> {
+  #[inline]
+  fn write<T, Format>(format: &Format, file: &File, path: &Path, value: &T, sync: bool) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    Mode::write(format, file, path, value, sync)
+  }
+}
+
+impl<Mode: FileMode, const SHARE: u32> FileMode for ShareMode<Mode, SHARE> {
+  const READABLE: bool = Mode::READABLE;
+  const WRITABLE: bool = Mode::WRITABLE;
+  const SHARE_MODE: u32 = SHARE;
+  const REPLACES_FILE: bool = Mode::REPLACES_FILE;
+
+  #[inline]
+  fn write_initial<T, Format>(format: &Format, file: &File, path: &Path, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    Mode::write_initial(format, file, path, value)
+  }
+}
+
+
+
+/// Similar to [`Atomic`], but splits the file's contents across multiple numbered chunk files
+/// when they are larger than `THRESHOLD` bytes. This works around file systems with file size limits,
+/// and allows the chunks to be written in parallel.
+///
+/// When the contents are split, the file itself only holds a small manifest, and each chunk of at most `THRESHOLD` bytes
+/// is stored next to it as `<file name>.chunk-<generation>-<index>`. New chunks are written under a new generation
+/// before the manifest is replaced, so an interrupted write never leaves the manifest pointing at partially written chunks.
+/// Contents smaller than `THRESHOLD` are stored in the file directly, as they would be with [`Atomic`].
+///
+/// Chunk files are not locked, they are protected by the lock held on the file that contains the manifest.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Chunked<const THRESHOLD: u64>;
+
+impl<const THRESHOLD: u64> Sealed for Chunked<THRESHOLD> {}
+
+impl<const THRESHOLD: u64> Reading for Chunked<THRESHOLD> {
+  #[inline]
+  fn read<T, Format>(format: &Format, file: &File, path: &Path) -> Result<T, Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    read_chunked(format, file, path)
+  }
+}
+
+impl<const THRESHOLD: u64> Writing for Chunked<THRESHOLD> {
+  #[inline]
+  fn write<T, Format>(format: &Format, file: &File, path: &Path, value: &T, sync: bool) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    write_chunked(format, file, path, value, THRESHOLD, sync)
+  }
+}
+
+impl<const THRESHOLD: u64> FileMode for Chunked<THRESHOLD> {
+  const READABLE: bool = true;
+  const WRITABLE: bool = true;
+
+  #[inline]
+  fn write_initial<T, Format>(format: &Format, file: &File, path: &Path, value: &T) -> Result<(), Error<Format::FormatError>>
+  where Format: FileFormat<T> {
+    write_chunked(format, file, path, value, THRESHOLD, true)
+  }
+}
+
+
+
 pub(crate) fn read<T, Format>(
   format: &Format, mut file: &File
 ) -> Result<T, Error<Format::FormatError>>
 where Format: FileFormat<T> {
-  let value = format.from_reader_buffered(file)
-    .map_err(Error::Format)?;
+  let result = format.from_reader_buffered(file)
+    .map_err(Error::Format);
+  // rewind even if the format failed, so that the file can be read again
   file.seek(SeekFrom::Start(0))?;
-  Ok(value)
+  result
 }
 
 pub(crate) fn write<T, Format>(
-  format: &Format, mut file: &File, value: &T
+  format: &Format, mut file: &File, value: &T, sync: bool
 ) -> Result<(), Error<Format::FormatError>>
 where Format: FileFormat<T> {
   file.set_len(0)?;
   format.to_writer_buffered(file, value)
     .map_err(Error::Format)?;
   file.seek(SeekFrom::Start(0))?;
-  file.sync_all()?;
+  if sync {
+    file.sync_all()?;
+  };
+
   Ok(())
 }
 
 pub(crate) fn write_atomic<T, Format>(
-  format: &Format, mut file: &File, value: &T
+  format: &Format, mut file: &File, value: &T, sync: bool
 ) -> Result<(), Error<Format::FormatError>>
 where Format: FileFormat<T> {
   let buf = format.to_buffer(value)
@@ -138,6 +310,242 @@ where Format: FileFormat<T> {
   file.set_len(0)?;
   io::copy(&mut buf.as_slice(), &mut file)?;
   file.seek(SeekFrom::Start(0))?;
-  file.sync_all()?;
+  if sync {
+    file.sync_all()?;
+  };
+
   Ok(())
 }
+
+pub(crate) fn write_rename<T, Format>(
+  format: &Format, path: &Path, value: &T, sync: bool
+) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let buf = format.to_buffer(value)
+    .map_err(Error::Format)?;
+  let temp_path = crate::utils::temp_path(path);
+  let result = replace_with(path, &temp_path, &buf, sync);
+  if result.is_err() {
+    // the temporary file is useless if it was not moved into place, and it may not exist at all
+    let _ = fs::remove_file(&temp_path);
+  };
+
+  result.map_err(Error::from)
+}
+
+fn replace_with(path: &Path, temp_path: &Path, buf: &[u8], sync: bool) -> io::Result<()> {
+  let permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+  let mut options = OpenOptions::new();
+  options.write(true).create_new(true);
+  // create the temporary file with the permissions it will end up with,
+  // so that a restricted file is never readable by others while it is being replaced
+  #[cfg(unix)]
+  if let Some(permissions) = &permissions {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    options.mode(permissions.mode() & 0o7777);
+  };
+
+  let mut temp_file = options.open(temp_path)?;
+  if let Some(permissions) = permissions {
+    temp_file.set_permissions(permissions)?;
+  };
+
+  temp_file.write_all(buf)?;
+  if sync {
+    temp_file.sync_all()?;
+  };
+
+  drop(temp_file);
+  fs::rename(temp_path, path)?;
+  if sync {
+    sync_dir(path)?;
+  };
+
+  Ok(())
+}
+
+/// Syncs the directory containing the given path, since a rename is only durable once its directory has been synced.
+pub(crate) fn sync_dir(path: &Path) -> io::Result<()> {
+  #[cfg(unix)]
+  match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all()?,
+    _ => File::open(".")?.sync_all()?
+  };
+
+  #[cfg(not(unix))]
+  let _ = path;
+  Ok(())
+}
+
+const CHUNK_MAGIC: &[u8; 8] = b"sfchunk\0";
+const CHUNK_VERSION: u32 = 1;
+/// The most memory that is reserved up front for reading chunks, since the lengths in a manifest cannot be trusted.
+const CHUNK_MAX_PREALLOCATION: usize = 64 * 1024 * 1024;
+
+/// The manifest stored in place of a file's contents when they have been split into chunks.
+struct ChunkManifest {
+  generation: u64,
+  lengths: Vec<u64>
+}
+
+impl ChunkManifest {
+  /// Reads the manifest from the start of the file, returning `None` if the file does not contain one.
+  fn read(mut file: &File) -> io::Result<Option<Self>> {
+    let mut magic = Vec::with_capacity(CHUNK_MAGIC.len());
+    file.take(CHUNK_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    if magic != CHUNK_MAGIC {
+      file.seek(SeekFrom::Start(0))?;
+      return Ok(None);
+    };
+
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid chunk manifest");
+    let (version, rest) = take_u32(&buf).ok_or_else(invalid)?;
+    if version != CHUNK_VERSION {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported chunk manifest version"));
+    };
+
+    let (generation, rest) = take_u64(rest).ok_or_else(invalid)?;
+    let (count, mut rest) = take_u32(rest).ok_or_else(invalid)?;
+    let mut lengths = Vec::new();
+    for _ in 0..count {
+      let (length, next) = take_u64(rest).ok_or_else(invalid)?;
+      lengths.push(length);
+      rest = next;
+    };
+
+    Ok(Some(ChunkManifest { generation, lengths }))
+  }
+
+  fn to_bytes(&self) -> Vec<u8> {
+    let mut buf = CHUNK_MAGIC.to_vec();
+    buf.extend_from_slice(&CHUNK_VERSION.to_le_bytes());
+    buf.extend_from_slice(&self.generation.to_le_bytes());
+    buf.extend_from_slice(&(self.lengths.len() as u32).to_le_bytes());
+    for length in self.lengths.iter() {
+      buf.extend_from_slice(&length.to_le_bytes());
+    };
+
+    buf
+  }
+
+  fn remove_chunks(&self, path: &Path) -> io::Result<()> {
+    for index in 0..self.lengths.len() {
+      match fs::remove_file(chunk_path(path, self.generation, index)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => ()
+      };
+    };
+
+    Ok(())
+  }
+}
+
+fn take_u32(buf: &[u8]) -> Option<(u32, &[u8])> {
+  let bytes = buf.get(..4)?;
+  Some((u32::from_le_bytes(bytes.try_into().ok()?), &buf[4..]))
+}
+
+fn take_u64(buf: &[u8]) -> Option<(u64, &[u8])> {
+  let bytes = buf.get(..8)?;
+  Some((u64::from_le_bytes(bytes.try_into().ok()?), &buf[8..]))
+}
+
+fn chunk_path(path: &Path, generation: u64, index: usize) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(format!(".chunk-{generation}-{index}"));
+  path.with_file_name(name)
+}
+
+pub(crate) fn read_chunked<T, Format>(
+  format: &Format, file: &File, path: &Path
+) -> Result<T, Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let manifest = match ChunkManifest::read(file)? {
+    Some(manifest) => manifest,
+    None => return read(format, file)
+  };
+
+  let mismatch = || io::Error::new(io::ErrorKind::InvalidData, "chunk length does not match manifest");
+  let size = manifest.lengths.iter()
+    .try_fold(0u64, |size, &length| size.checked_add(length))
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "chunk lengths in manifest overflow"))?;
+  let size = usize::try_from(size).map_err(|_| TooLarge { size, max_size: usize::MAX as u64 })?;
+
+  let mut buf = Vec::with_capacity(size.min(CHUNK_MAX_PREALLOCATION));
+  for (index, &length) in manifest.lengths.iter().enumerate() {
+    let chunk_file = File::open(chunk_path(path, manifest.generation, index))?;
+    if chunk_file.metadata()?.len() != length {
+      return Err(mismatch().into());
+    };
+
+    // read at most one byte more than expected, in case the chunk grew since its length was checked
+    let start = buf.len();
+    chunk_file.take(length.saturating_add(1)).read_to_end(&mut buf)?;
+    if (buf.len() - start) as u64 != length {
+      return Err(mismatch().into());
+    };
+  };
+
+  format.from_buffer(&buf).map_err(Error::Format)
+}
+
+pub(crate) fn write_chunked<T, Format>(
+  format: &Format, mut file: &File, path: &Path, value: &T, threshold: u64, sync: bool
+) -> Result<(), Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  let buf = format.to_buffer(value)
+    .map_err(Error::Format)?;
+  // a manifest that cannot be read has no chunks that can be cleaned up
+  let previous = ChunkManifest::read(file).ok().flatten();
+
+  // contents that happen to look like a manifest must be split, or they would be mistaken for one
+  let contents = if buf.len() as u64 <= threshold && !buf.starts_with(CHUNK_MAGIC) {
+    buf
+  } else {
+    let chunk_size = usize::try_from(threshold.max(1)).unwrap_or(usize::MAX);
+    let chunks = buf.chunks(chunk_size).collect::<Vec<&[u8]>>();
+    let generation = previous.as_ref().map_or(0, |previous| previous.generation.wrapping_add(1));
+    write_chunks(path, generation, &chunks, sync)?;
+    ChunkManifest { generation, lengths: chunks.iter().map(|chunk| chunk.len() as u64).collect() }.to_bytes()
+  };
+
+  file.set_len(0)?;
+  io::copy(&mut contents.as_slice(), &mut file)?;
+  file.seek(SeekFrom::Start(0))?;
+  if sync {
+    file.sync_all()?;
+  };
+
+  if let Some(previous) = previous {
+    previous.remove_chunks(path)?;
+  };
+
+  Ok(())
+}
+
+/// Writes (and if `sync` is `true`, syncs) every chunk, spreading them across as many threads as there is available parallelism.
+fn write_chunks(path: &Path, generation: u64, chunks: &[&[u8]], sync: bool) -> io::Result<()> {
+  let workers = std::thread::available_parallelism()
+    .map_or(1, |n| n.get()).min(chunks.len());
+  std::thread::scope(|scope| {
+    let handles = (0..workers).map(|worker| scope.spawn(move || {
+      for index in (worker..chunks.len()).step_by(workers) {
+        let mut chunk_file = File::create(chunk_path(path, generation, index))?;
+        chunk_file.write_all(chunks[index])?;
+        if sync {
+          chunk_file.sync_all()?;
+        };
+      };
+
+      Ok(())
+    })).collect::<Vec<_>>();
+
+    handles.into_iter().try_for_each(|handle| {
+      handle.join().unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+    })
+  })
+}
@@ -0,0 +1,62 @@
+//! Defines when the writes made to a file are synced to disk.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Determines when the writes made by a [`FileManager`] are synced to disk (with [`File::sync_all`]),
+/// trading durability for throughput.
+///
+/// Syncing makes sure that a write survives a crash of the operating system or a loss of power,
+/// it is not needed for other processes to observe the write. Writes that have not been synced
+/// can be synced at any time with [`FileManager::sync`].
+///
+/// [`AtomicRename`] also syncs the directory of the file when it syncs a write. [`ContentAddressed`] always syncs,
+/// since its history must never refer to an object that was not synced.
+///
+/// See [`FileManager::with_sync_policy`].
+///
+/// [`FileManager`]: crate::manager::FileManager
+/// [`FileManager::sync`]: crate::manager::FileManager::sync
+/// [`FileManager::with_sync_policy`]: crate::manager::FileManager::with_sync_policy
+/// [`File::sync_all`]: std::fs::File::sync_all
+/// [`AtomicRename`]: crate::manager::mode::AtomicRename
+/// [`ContentAddressed`]: crate::manager::cas::ContentAddressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SyncPolicy {
+  /// Syncs every write before it returns.
+  ///
+  /// This is the default.
+  #[default]
+  Always,
+  /// Syncs when the manager is closed, but not on every write.
+  OnClose,
+  /// Never syncs, leaving it to the operating system.
+  Never,
+  /// Syncs a write if the last sync was at least the given duration ago, and when the manager is closed.
+  ///
+  /// A write that is not synced stays unsynced until the next synced write, there is no background thread.
+  Interval(Duration)
+}
+
+/// Tracks when a file was last synced, on behalf of a [`SyncPolicy`].
+#[derive(Debug, Default)]
+pub(crate) struct SyncState {
+  last_sync: Mutex<Option<Instant>>
+}
+
+impl SyncState {
+  /// Returns whether the next write should be synced under the given policy.
+  pub(crate) fn should_sync(&self, policy: SyncPolicy) -> bool {
+    match policy {
+      SyncPolicy::Always => true,
+      SyncPolicy::OnClose | SyncPolicy::Never => false,
+      SyncPolicy::Interval(interval) => self.last_sync.lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .map_or(true, |last_sync| last_sync.elapsed() >= interval)
+    }
+  }
+
+  pub(crate) fn record_sync(&self) {
+    *self.last_sync.lock().unwrap_or_else(|err| err.into_inner()) = Some(Instant::now());
+  }
+}
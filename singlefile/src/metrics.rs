@@ -0,0 +1,307 @@
+//! Instrumentation of the operations performed by file managers, for exporting metrics such as counters and histograms.
+//!
+//! An [`Instrumentation`] is attached to a [`FileManager`] with [`FileManager::with_instrumentation`]
+//! (or with the `with_instrumentation` functions of the builders), and is handed a [`Measurement`] after every read,
+//! write and file lock acquisition that the manager performs. Implementations may feed these into any metrics system.
+//! [`Counters`] keeps running totals in memory, and with the `tracing` cargo feature, [`TracingInstrumentation`]
+//! emits a span around every operation and an event once it has finished.
+//!
+//! This module can be enabled with the `metrics` cargo feature. Unlike the [`slow`] module,
+//! which reports operations process-wide, instrumentation is configured for each manager.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! use singlefile::container::ContainerBuilder;
+//! use singlefile::metrics::Counters;
+//! use std::sync::Arc;
+//!
+//! let counters = Arc::new(Counters::new());
+//! let container = ContainerBuilder::new("data.json", Json::<true>)
+//!   .with_instrumentation(counters.clone())
+//!   .build_or_default::<Vec<String>>()?;
+//! container.commit()?;
+//!
+//! let totals = counters.snapshot();
+//! println!("{} reads, {} writes, {} bytes written", totals.reads, totals.writes, totals.bytes_written);
+//! # Ok::<(), singlefile::Error<JsonError>>(())
+//! ```
+//!
+//! [`FileManager`]: crate::manager::FileManager
+//! [`FileManager::with_instrumentation`]: crate::manager::FileManager::with_instrumentation
+//! [`slow`]: crate::slow
+
+pub use crate::slow::Operation;
+use crate::manager::format::FileFormat;
+
+use std::any::Any;
+use std::cell::Cell;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Receives a [`Measurement`] of every operation performed by the managers it is attached to.
+///
+/// Operations may be performed from several threads at once, so implementations must be thread-safe.
+/// See the [module-level documentation][self] for more information.
+pub trait Instrumentation: Send + Sync {
+  /// Called when an operation starts. The returned value is dropped once the operation has finished
+  /// and its measurement has been recorded, allowing implementations to enter a span for the duration of the operation.
+  #[inline]
+  fn enter(&self, _operation: Operation, _path: &Path) -> Option<Box<dyn Any>> {
+    None
+  }
+
+  /// Records the measurement of an operation that has just finished, whether or not it succeeded.
+  fn record(&self, measurement: &Measurement<'_>);
+}
+
+/// The measurement of a single operation performed by a manager, see [`Instrumentation::record`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Measurement<'a> {
+  /// The kind of operation. Writes are reported as [`Operation::Commit`], and acquiring the file lock
+  /// when a manager is opened as [`Operation::LockWait`].
+  pub operation: Operation,
+  /// The path of the file.
+  pub path: &'a Path,
+  /// How long the whole operation took.
+  pub duration: Duration,
+  /// How long was spent in the [`FileFormat`] serializing or deserializing the value, or `None` for lock waits.
+  /// For formats that read from or write to the file as they go, this includes that I/O.
+  pub format_duration: Option<Duration>,
+  /// The size of the file in bytes, taken before reading or after writing it.
+  /// `None` for lock waits, failed writes, or if the size could not be determined.
+  pub bytes: Option<u64>,
+  /// Whether the operation succeeded.
+  pub success: bool
+}
+
+/// An [`Instrumentation`] that keeps running totals of every measurement in memory.
+///
+/// Share it between managers behind an [`Arc`] to collect totals for all of them.
+#[derive(Debug, Default)]
+pub struct Counters {
+  reads: AtomicU64,
+  writes: AtomicU64,
+  lock_waits: AtomicU64,
+  failures: AtomicU64,
+  bytes_read: AtomicU64,
+  bytes_written: AtomicU64,
+  format_nanos: AtomicU64,
+  lock_wait_nanos: AtomicU64
+}
+
+impl Counters {
+  /// Creates a new [`Counters`], with every total at zero.
+  #[inline]
+  pub fn new() -> Self {
+    Counters::default()
+  }
+
+  /// Takes a snapshot of the current totals.
+  pub fn snapshot(&self) -> CountersSnapshot {
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    CountersSnapshot {
+      reads: load(&self.reads),
+      writes: load(&self.writes),
+      lock_waits: load(&self.lock_waits),
+      failures: load(&self.failures),
+      bytes_read: load(&self.bytes_read),
+      bytes_written: load(&self.bytes_written),
+      format_time: Duration::from_nanos(load(&self.format_nanos)),
+      lock_wait_time: Duration::from_nanos(load(&self.lock_wait_nanos))
+    }
+  }
+}
+
+impl Instrumentation for Counters {
+  fn record(&self, measurement: &Measurement<'_>) {
+    let add = |counter: &AtomicU64, value: u64| counter.fetch_add(value, Ordering::Relaxed);
+    let nanos = |duration: Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+    let (count, bytes) = match measurement.operation {
+      Operation::Read => (&self.reads, &self.bytes_read),
+      Operation::Commit => (&self.writes, &self.bytes_written),
+      Operation::LockWait => {
+        add(&self.lock_waits, 1);
+        add(&self.lock_wait_nanos, nanos(measurement.duration));
+        return;
+      }
+    };
+
+    add(count, 1);
+    if !measurement.success {
+      add(&self.failures, 1);
+    };
+
+    add(bytes, measurement.bytes.unwrap_or(0));
+    add(&self.format_nanos, nanos(measurement.format_duration.unwrap_or_default()));
+  }
+}
+
+/// A snapshot of the totals kept by [`Counters`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CountersSnapshot {
+  /// The number of reads, including failed reads.
+  pub reads: u64,
+  /// The number of writes, including failed writes.
+  pub writes: u64,
+  /// The number of times a file lock was acquired (or failed to be).
+  pub lock_waits: u64,
+  /// The number of reads and writes that failed.
+  pub failures: u64,
+  /// The total size of the files read, in bytes.
+  pub bytes_read: u64,
+  /// The total size of the files written, in bytes.
+  pub bytes_written: u64,
+  /// The total time spent serializing and deserializing values.
+  pub format_time: Duration,
+  /// The total time spent acquiring file locks.
+  pub lock_wait_time: Duration
+}
+
+/// An [`Instrumentation`] that emits a `tracing` span around every operation, and a `DEBUG` event with
+/// the measurement once it has finished, both under the `singlefile` target.
+///
+/// Spans are named after the operation (`read`, `commit` or `lock_wait`) and carry the path of the file.
+/// This is enabled with the `tracing` cargo feature.
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingInstrumentation;
+
+#[cfg(feature = "tracing")]
+impl Instrumentation for TracingInstrumentation {
+  fn enter(&self, operation: Operation, path: &Path) -> Option<Box<dyn Any>> {
+    let path = path.display();
+    let span = match operation {
+      Operation::Read => tracing::debug_span!(target: "singlefile", "read", path = %path),
+      Operation::Commit => tracing::debug_span!(target: "singlefile", "commit", path = %path),
+      Operation::LockWait => tracing::debug_span!(target: "singlefile", "lock_wait", path = %path)
+    };
+
+    Some(Box::new(span.entered()))
+  }
+
+  fn record(&self, measurement: &Measurement<'_>) {
+    tracing::debug!(
+      target: "singlefile",
+      operation = %measurement.operation,
+      duration = ?measurement.duration,
+      format_duration = ?measurement.format_duration,
+      bytes = ?measurement.bytes,
+      success = measurement.success,
+      "{} of {} finished", measurement.operation, measurement.path.display()
+    );
+  }
+}
+
+/// The [`Instrumentation`] attached to a manager.
+#[derive(Clone)]
+pub(crate) struct Instruments(pub(crate) Arc<dyn Instrumentation>);
+
+impl Instruments {
+  /// Runs the given operation, recording how long it took, and how long was spent in the format given to it.
+  pub(crate) fn measure<Format, R, E>(
+    &self, operation: Operation, path: &Path, format: &Format,
+    f: impl FnOnce(&Timed<'_, Format>) -> Result<R, E>,
+    bytes: impl FnOnce(bool) -> Option<u64>
+  ) -> Result<R, E> {
+    let guard = self.0.enter(operation, path);
+    let timed = Timed { format, elapsed: Cell::new(Duration::ZERO) };
+    let start = Instant::now();
+    let result = f(&timed);
+    let duration = start.elapsed();
+    self.0.record(&Measurement {
+      operation,
+      path,
+      duration,
+      format_duration: Some(timed.elapsed.get()),
+      bytes: bytes(result.is_ok()),
+      success: result.is_ok()
+    });
+
+    drop(guard);
+    result
+  }
+
+  /// Runs the given lock acquisition, recording how long it took.
+  pub(crate) fn measure_lock<R, E>(&self, path: &Path, f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    let guard = self.0.enter(Operation::LockWait, path);
+    let start = Instant::now();
+    let result = f();
+    self.0.record(&Measurement {
+      operation: Operation::LockWait,
+      path,
+      duration: start.elapsed(),
+      format_duration: None,
+      bytes: None,
+      success: result.is_ok()
+    });
+
+    drop(guard);
+    result
+  }
+}
+
+impl fmt::Debug for Instruments {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Instruments").finish_non_exhaustive()
+  }
+}
+
+/// Wraps a [`FileFormat`], adding up the time spent in it.
+pub(crate) struct Timed<'f, Format> {
+  format: &'f Format,
+  elapsed: Cell<Duration>
+}
+
+impl<'f, Format> Timed<'f, Format> {
+  fn time<R>(&self, f: impl FnOnce(&'f Format) -> R) -> R {
+    let start = Instant::now();
+    let result = f(self.format);
+    self.elapsed.set(self.elapsed.get() + start.elapsed());
+    result
+  }
+}
+
+impl<'f, T, Format: FileFormat<T>> FileFormat<T> for Timed<'f, Format> {
+  type FormatError = Format::FormatError;
+
+  #[inline]
+  fn from_reader<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+    self.time(|format| format.from_reader(reader))
+  }
+
+  #[inline]
+  fn from_reader_buffered<R: Read>(&self, reader: R) -> Result<T, Self::FormatError> {
+    self.time(|format| format.from_reader_buffered(reader))
+  }
+
+  #[inline]
+  fn from_buffer(&self, buf: &[u8]) -> Result<T, Self::FormatError> {
+    self.time(|format| format.from_buffer(buf))
+  }
+
+  #[inline]
+  fn to_writer<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+    self.time(|format| format.to_writer(writer, value))
+  }
+
+  #[inline]
+  fn to_writer_buffered<W: Write>(&self, writer: W, value: &T) -> Result<(), Self::FormatError> {
+    self.time(|format| format.to_writer_buffered(writer, value))
+  }
+
+  #[inline]
+  fn to_buffer(&self, value: &T) -> Result<Vec<u8>, Self::FormatError> {
+    self.time(|format| format.to_buffer(value))
+  }
+
+  #[inline]
+  fn error_offset(&self, error: &Self::FormatError, buf: &[u8]) -> Option<usize> {
+    self.format.error_offset(error, buf)
+  }
+}
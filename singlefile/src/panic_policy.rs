@@ -0,0 +1,143 @@
+//! Panic handling shared by [`ContainerShared`] and [`ContainerSharedAsync`].
+//!
+//! [`ContainerShared`]: crate::container_shared::ContainerShared
+//! [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+
+use crate::error::Poisoned;
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+/// Determines what happens to the state of a [`ContainerShared`] or [`ContainerSharedAsync`]
+/// when an operation panics while it has mutable access to that state.
+///
+/// The policy applies to the `operate_mut` and `operate_mut_commit` families of methods,
+/// it is shared between every handle to the same container, and can be changed at any time
+/// with `set_panic_policy`. Panics are always propagated to the caller after the policy has been applied
+/// (for the non-blocking methods of [`ContainerSharedAsync`], as a [`TaskFailed`] error).
+///
+/// [`ContainerShared`]: crate::container_shared::ContainerShared
+/// [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+/// [`TaskFailed`]: crate::error::TaskFailed
+pub enum PanicPolicy<T> {
+  /// The state is left exactly as the operation left it, possibly half-modified.
+  ///
+  /// This is the default policy.
+  Propagate,
+  /// The container is marked as poisoned, and any commit made through the container will fail with
+  /// [`Error::Poisoned`] until the state is replaced through `refresh` or `overwrite`, or the poison is
+  /// explicitly cleared with `clear_poison`.
+  ///
+  /// [`Error::Poisoned`]: crate::error::Error::Poisoned
+  Poison,
+  /// A snapshot of the state is taken with the given function before every operation,
+  /// and the state is restored from it if the operation panics.
+  /// See [`PanicPolicy::restore`].
+  Restore(fn(&T) -> T)
+}
+
+impl<T: Clone> PanicPolicy<T> {
+  /// Creates a [`PanicPolicy::Restore`] policy that takes snapshots by cloning the state.
+  #[inline]
+  pub fn restore() -> Self {
+    PanicPolicy::Restore(T::clone)
+  }
+}
+
+impl<T> Default for PanicPolicy<T> {
+  #[inline]
+  fn default() -> Self {
+    PanicPolicy::Propagate
+  }
+}
+
+impl<T> Clone for PanicPolicy<T> {
+  #[inline]
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for PanicPolicy<T> {}
+
+impl<T> fmt::Debug for PanicPolicy<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PanicPolicy::Propagate => f.write_str("Propagate"),
+      PanicPolicy::Poison => f.write_str("Poison"),
+      PanicPolicy::Restore(_) => f.write_str("Restore")
+    }
+  }
+}
+
+
+
+/// Tracks the panic policy and poison status of a shared container.
+#[derive(Debug)]
+pub(crate) struct Panics<T> {
+  state: Mutex<PanicState<T>>
+}
+
+#[derive(Debug)]
+struct PanicState<T> {
+  policy: PanicPolicy<T>,
+  poisoned: bool
+}
+
+impl<T> Panics<T> {
+  fn lock(&self) -> MutexGuard<'_, PanicState<T>> {
+    self.state.lock().unwrap_or_else(|err| err.into_inner())
+  }
+
+  pub(crate) fn policy(&self) -> PanicPolicy<T> {
+    self.lock().policy
+  }
+
+  pub(crate) fn set_policy(&self, policy: PanicPolicy<T>) {
+    self.lock().policy = policy;
+  }
+
+  pub(crate) fn is_poisoned(&self) -> bool {
+    self.lock().poisoned
+  }
+
+  pub(crate) fn clear(&self) {
+    self.lock().poisoned = false;
+  }
+
+  pub(crate) fn check(&self) -> Result<(), Poisoned> {
+    if self.is_poisoned() { Err(Poisoned) } else { Ok(()) }
+  }
+
+  /// Runs the operation on the given state, applying the current policy if it panics.
+  pub(crate) fn operate<F, R>(&self, value: &mut T, operation: F) -> R
+  where F: FnOnce(&mut T) -> R {
+    match self.policy() {
+      PanicPolicy::Propagate => operation(value),
+      PanicPolicy::Poison => match panic::catch_unwind(AssertUnwindSafe(|| operation(value))) {
+        Ok(ret) => ret,
+        Err(payload) => {
+          self.lock().poisoned = true;
+          panic::resume_unwind(payload)
+        }
+      },
+      PanicPolicy::Restore(snapshot) => {
+        let previous = snapshot(value);
+        match panic::catch_unwind(AssertUnwindSafe(|| operation(value))) {
+          Ok(ret) => ret,
+          Err(payload) => {
+            *value = previous;
+            panic::resume_unwind(payload)
+          }
+        }
+      }
+    }
+  }
+}
+
+impl<T> Default for Panics<T> {
+  fn default() -> Self {
+    Panics { state: Mutex::new(PanicState { policy: PanicPolicy::Propagate, poisoned: false }) }
+  }
+}
@@ -0,0 +1,107 @@
+//! Resolution of the standard directories that the platform sets aside for an application,
+//! such as `~/.config/<app>` on Linux, `~/Library/Application Support/<bundle id>` on macOS,
+//! or `%APPDATA%\<org>\<app>\config` on Windows.
+//!
+//! This module can be enabled with the `paths` cargo feature.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! use singlefile::container::ContainerWritable;
+//! use singlefile::paths::Project;
+//!
+//! const PROJECT: Project<'static> = Project::new("org", "Example Corp", "Example App");
+//!
+//! // opens `settings.json` in the platform's configuration directory for the application
+//! let settings = ContainerWritable::<Vec<String>, Json>::create_or_default_in(&PROJECT, "settings.json", Json)?;
+//! # Ok::<(), singlefile::Error<JsonError>>(())
+//! ```
+
+pub use directories::ProjectDirs;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Identifies an application, so that its directories can be resolved. See [`ProjectDirs::from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Project<'a> {
+  /// The reverse domain name notation of the application, excluding the organization and application name,
+  /// such as `"com"` or `"org"`. Only used on macOS.
+  pub qualifier: &'a str,
+  /// The name of the organization that develops the application. Not used on Linux.
+  pub organization: &'a str,
+  /// The name of the application.
+  pub application: &'a str
+}
+
+impl<'a> Project<'a> {
+  /// Creates a new [`Project`].
+  #[inline]
+  pub const fn new(qualifier: &'a str, organization: &'a str, application: &'a str) -> Self {
+    Project { qualifier, organization, application }
+  }
+
+  /// Resolves the directories of this project.
+  ///
+  /// Returns an error of kind [`NotFound`] if no home directory could be found for the current user.
+  ///
+  /// [`NotFound`]: std::io::ErrorKind::NotFound
+  pub fn dirs(&self) -> io::Result<ProjectDirs> {
+    ProjectDirs::from(self.qualifier, self.organization, self.application)
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory found"))
+  }
+
+  /// Resolves the path of a file within one of the directories of this project,
+  /// creating the directory (and any directories between it and the file) if it does not exist.
+  ///
+  /// Returns an error of kind [`NotFound`] if the platform has no such directory.
+  ///
+  /// [`NotFound`]: std::io::ErrorKind::NotFound
+  pub fn path<P: AsRef<Path>>(&self, directory: Directory, file: P) -> io::Result<PathBuf> {
+    let path = directory.resolve(&self.dirs()?)?.join(file);
+    crate::manager::create_parent_dirs(&path)?;
+    Ok(path)
+  }
+}
+
+/// One of the directories that the platform sets aside for an application. See [`ProjectDirs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Directory {
+  /// The directory for configuration files, see [`ProjectDirs::config_dir`].
+  ///
+  /// This is the default.
+  #[default]
+  Config,
+  /// The directory for configuration files that are not synchronized across machines,
+  /// see [`ProjectDirs::config_local_dir`].
+  ConfigLocal,
+  /// The directory for data files, see [`ProjectDirs::data_dir`].
+  Data,
+  /// The directory for data files that are not synchronized across machines, see [`ProjectDirs::data_local_dir`].
+  DataLocal,
+  /// The directory for cached files, which may be deleted at any time, see [`ProjectDirs::cache_dir`].
+  Cache,
+  /// The directory for preference files, see [`ProjectDirs::preference_dir`].
+  Preference,
+  /// The directory for state files, such as logs and history, see [`ProjectDirs::state_dir`].
+  /// Only Linux has such a directory.
+  State
+}
+
+impl Directory {
+  /// Gets this directory from the given [`ProjectDirs`],
+  /// returning an error of kind [`NotFound`] if the platform has no such directory.
+  ///
+  /// [`NotFound`]: std::io::ErrorKind::NotFound
+  pub fn resolve(self, dirs: &ProjectDirs) -> io::Result<&Path> {
+    match self {
+      Directory::Config => Ok(dirs.config_dir()),
+      Directory::ConfigLocal => Ok(dirs.config_local_dir()),
+      Directory::Data => Ok(dirs.data_dir()),
+      Directory::DataLocal => Ok(dirs.data_local_dir()),
+      Directory::Cache => Ok(dirs.cache_dir()),
+      Directory::Preference => Ok(dirs.preference_dir()),
+      Directory::State => dirs.state_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no state directory on this platform"))
+    }
+  }
+}
@@ -0,0 +1,184 @@
+//! Warnings for slow file operations and lock waits.
+//!
+//! Thresholds are configured process-wide with [`set_thresholds`]. Whenever a commit, read or lock wait
+//! takes longer than its threshold, the callback registered with [`set_callback`] is invoked with a [`SlowOperation`],
+//! and (with the `log` cargo feature) a warning is emitted through the `log` crate under the `singlefile` target.
+//!
+//! This helps catch pathological serialization or heavily contended containers in production.
+//! Nothing is measured until a threshold has been set.
+//!
+//! ```no_run
+//! use singlefile::slow::{self, Thresholds};
+//! use std::time::Duration;
+//!
+//! slow::set_thresholds(Thresholds {
+//!   commit: Some(Duration::from_millis(50)),
+//!   read: Some(Duration::from_millis(50)),
+//!   lock_wait: Some(Duration::from_millis(200))
+//! });
+//!
+//! slow::set_callback(|slow| eprintln!("{slow}"));
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+type Callback = Arc<dyn Fn(&SlowOperation<'_>) + Send + Sync>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CONFIG: RwLock<Config> = RwLock::new(Config {
+  thresholds: Thresholds { commit: None, read: None, lock_wait: None },
+  callback: None
+});
+
+struct Config {
+  thresholds: Thresholds,
+  callback: Option<Callback>
+}
+
+/// The kind of operation reported by a [`SlowOperation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+  /// Serializing and writing a value to a file.
+  Commit,
+  /// Reading and deserializing a value from a file.
+  Read,
+  /// Waiting to get access to a shared container.
+  LockWait
+}
+
+impl fmt::Display for Operation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Operation::Commit => "commit",
+      Operation::Read => "read",
+      Operation::LockWait => "lock wait"
+    })
+  }
+}
+
+/// The thresholds above which operations are reported as slow.
+/// A threshold of `None` disables reporting for that kind of operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Thresholds {
+  /// The threshold for [`Operation::Commit`].
+  pub commit: Option<Duration>,
+  /// The threshold for [`Operation::Read`].
+  pub read: Option<Duration>,
+  /// The threshold for [`Operation::LockWait`].
+  pub lock_wait: Option<Duration>
+}
+
+impl Thresholds {
+  /// Returns the threshold for the given kind of operation.
+  pub const fn get(&self, operation: Operation) -> Option<Duration> {
+    match operation {
+      Operation::Commit => self.commit,
+      Operation::Read => self.read,
+      Operation::LockWait => self.lock_wait
+    }
+  }
+
+  const fn is_enabled(&self) -> bool {
+    self.commit.is_some() || self.read.is_some() || self.lock_wait.is_some()
+  }
+}
+
+/// An operation that took longer than its threshold.
+#[derive(Debug, Clone, Copy)]
+pub struct SlowOperation<'a> {
+  /// The kind of operation.
+  pub operation: Operation,
+  /// The path of the file, if the operation concerned one.
+  /// Lock waits do not have a path, since the file is behind the lock.
+  pub path: Option<&'a Path>,
+  /// How long the operation took.
+  pub duration: Duration,
+  /// The threshold that was exceeded.
+  pub threshold: Duration
+}
+
+impl fmt::Display for SlowOperation<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "slow {}", self.operation)?;
+    if let Some(path) = self.path {
+      write!(f, " of {}", path.display())?;
+    };
+
+    write!(f, " took {:?} (threshold {:?})", self.duration, self.threshold)
+  }
+}
+
+/// Sets the thresholds above which operations are reported as slow, for the whole process.
+pub fn set_thresholds(thresholds: Thresholds) {
+  let mut config = CONFIG.write().unwrap_or_else(|err| err.into_inner());
+  config.thresholds = thresholds;
+  ENABLED.store(thresholds.is_enabled(), Ordering::Release);
+}
+
+/// Returns the thresholds above which operations are reported as slow.
+pub fn thresholds() -> Thresholds {
+  CONFIG.read().unwrap_or_else(|err| err.into_inner()).thresholds
+}
+
+/// Registers a callback to be invoked with every slow operation, replacing any previous callback.
+///
+/// The callback is invoked on the thread that performed the operation, possibly while holding access to a container,
+/// so it must not attempt to access the container that the operation concerned.
+pub fn set_callback<F>(callback: F)
+where F: Fn(&SlowOperation<'_>) + Send + Sync + 'static {
+  CONFIG.write().unwrap_or_else(|err| err.into_inner()).callback = Some(Arc::new(callback));
+}
+
+/// Removes the callback registered with [`set_callback`], if any.
+pub fn clear_callback() {
+  CONFIG.write().unwrap_or_else(|err| err.into_inner()).callback = None;
+}
+
+/// Measures an operation, reporting it if it exceeds its threshold.
+/// Does not read the clock at all if no thresholds are set.
+#[derive(Debug)]
+pub(crate) struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+  #[inline]
+  pub(crate) fn start() -> Self {
+    Stopwatch(ENABLED.load(Ordering::Acquire).then(Instant::now))
+  }
+
+  #[inline]
+  pub(crate) fn finish(self, operation: Operation, path: Option<&Path>) {
+    if let Some(start) = self.0 {
+      report(operation, path, start.elapsed());
+    };
+  }
+}
+
+/// Runs the given closure, reporting it if it exceeds the threshold of the given operation.
+#[inline]
+pub(crate) fn measure<R>(operation: Operation, path: Option<&Path>, f: impl FnOnce() -> R) -> R {
+  let stopwatch = Stopwatch::start();
+  let result = f();
+  stopwatch.finish(operation, path);
+  result
+}
+
+fn report(operation: Operation, path: Option<&Path>, duration: Duration) {
+  let (threshold, callback) = {
+    let config = CONFIG.read().unwrap_or_else(|err| err.into_inner());
+    match config.thresholds.get(operation) {
+      Some(threshold) if duration > threshold => (threshold, config.callback.clone()),
+      _ => return
+    }
+  };
+
+  let slow = SlowOperation { operation, path, duration, threshold };
+  #[cfg(feature = "log")]
+  log::warn!(target: "singlefile", "{slow}");
+  if let Some(callback) = callback {
+    callback(&slow);
+  };
+}
@@ -0,0 +1,124 @@
+//! Helpers for testing [`FileFormat`] implementations and the types stored with them.
+//!
+//! This module can be enabled with the `test-support` cargo feature, and is intended to be used from tests only.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use singlefile::test_support::{assert_format_roundtrip, assert_container_roundtrip};
+//!
+//! let format = Json::<true>;
+//! assert_format_roundtrip(&format, &vec![1, 2, 3]);
+//! assert_container_roundtrip(format, vec![1, 2, 3]);
+//! ```
+//!
+//! With [`check_roundtrip`], values can instead be generated by a `proptest` strategy:
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::Json;
+//! use proptest::prelude::*;
+//! use singlefile::test_support::check_roundtrip;
+//!
+//! let format = Json::<true>;
+//! check_roundtrip(format, any::<Vec<i32>>()).unwrap();
+//! ```
+//!
+//! [`FileFormat`]: crate::manager::format::FileFormat
+
+use crate::container::ContainerWritable;
+use crate::manager::format::FileFormat;
+
+use proptest::strategy::Strategy;
+use proptest::test_runner::{TestCaseError, TestError, TestRunner};
+
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Asserts that a value survives being written to and read back from a buffer and a stream with the given format.
+///
+/// # Panics
+/// Panics if the format fails to serialize or deserialize the value, or if the value read back is not equal to the original.
+#[track_caller]
+pub fn assert_format_roundtrip<T, Format>(format: &Format, value: &T)
+where Format: FileFormat<T>, T: PartialEq + Debug {
+  if let Err(message) = format_roundtrip(format, value) {
+    panic!("{message}");
+  };
+}
+
+/// Asserts that a value survives being committed to and refreshed from a file by a [`ContainerWritable`],
+/// using a temporary file that is removed afterwards.
+///
+/// # Panics
+/// Panics if any operation on the container fails, or if the value read back is not equal to the original.
+///
+/// [`ContainerWritable`]: crate::container::ContainerWritable
+#[track_caller]
+pub fn assert_container_roundtrip<T, Format>(format: Format, value: T)
+where Format: FileFormat<T>, T: PartialEq + Debug + Clone {
+  if let Err(message) = container_roundtrip(format, value) {
+    panic!("{message}");
+  };
+}
+
+/// Runs every value generated by `strategy` through both [`assert_format_roundtrip`]
+/// and [`assert_container_roundtrip`], with the default `proptest` configuration.
+///
+/// Returns the minimal failing value if any roundtrip fails.
+pub fn check_roundtrip<T, Format, S>(format: Format, strategy: S) -> Result<(), TestError<T>>
+where
+  Format: FileFormat<T> + Clone,
+  T: PartialEq + Debug + Clone,
+  S: Strategy<Value = T>
+{
+  TestRunner::default().run(&strategy, |value| {
+    format_roundtrip(&format, &value).map_err(TestCaseError::fail)?;
+    container_roundtrip(format.clone(), value).map_err(TestCaseError::fail)
+  })
+}
+
+fn format_roundtrip<T, Format>(format: &Format, value: &T) -> Result<(), String>
+where Format: FileFormat<T>, T: PartialEq + Debug {
+  let buf = format.to_buffer(value)
+    .map_err(|err| format!("failed to serialize {value:?} to a buffer: {err}"))?;
+  let from_buffer = format.from_buffer(&buf)
+    .map_err(|err| format!("failed to deserialize {value:?} from a buffer: {err}"))?;
+  check_eq(value, &from_buffer, "buffer")?;
+
+  let mut stream = Vec::new();
+  format.to_writer(&mut stream, value)
+    .map_err(|err| format!("failed to serialize {value:?} to a writer: {err}"))?;
+  let from_reader = format.from_reader(stream.as_slice())
+    .map_err(|err| format!("failed to deserialize {value:?} from a reader: {err}"))?;
+  check_eq(value, &from_reader, "reader")
+}
+
+fn container_roundtrip<T, Format>(format: Format, value: T) -> Result<(), String>
+where Format: FileFormat<T>, T: PartialEq + Debug + Clone {
+  let path = temp_path();
+  let result = (|| {
+    let mut container = ContainerWritable::create_overwrite(&path, format, value.clone())
+      .map_err(|err| format!("failed to create container for {value:?}: {err}"))?;
+    container.commit()
+      .map_err(|err| format!("failed to commit {value:?}: {err}"))?;
+    container.refresh()
+      .map_err(|err| format!("failed to refresh {value:?}: {err}"))?;
+    check_eq(&value, container.get(), "container")
+  })();
+
+  let _ = std::fs::remove_file(&path);
+  result
+}
+
+fn check_eq<T: PartialEq + Debug>(expected: &T, found: &T, source: &str) -> Result<(), String> {
+  match expected == found {
+    true => Ok(()),
+    false => Err(format!("value read back from {source} differs: expected {expected:?}, found {found:?}"))
+  }
+}
+
+fn temp_path() -> PathBuf {
+  static COUNTER: AtomicUsize = AtomicUsize::new(0);
+  let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+  std::env::temp_dir().join(format!("singlefile-roundtrip-{}-{n}", std::process::id()))
+}
@@ -0,0 +1,337 @@
+//! Utilities for working with files outside of a container, such as diagnosing and repairing them,
+//! reading or writing them in a single step, or reading them from standard input.
+//!
+//! The diagnostics are intended for operators investigating bad state files, [`fsck`] never modifies the file it inspects.
+
+use crate::container::ContainerMemoryOnly;
+use crate::error::{Error, LockContended, TranscodeError};
+use crate::manager::format::FileFormat;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The path that refers to standard input (or output) by convention.
+pub const STDIO_PATH: &str = "-";
+
+/// The marker separating a file's name from the unique suffix of its temporary files.
+const TEMP_MARKER: &str = ".tmp-";
+
+/// A report on the state of a file, produced by [`fsck`].
+#[derive(Debug)]
+pub struct FsckReport<FE> {
+  /// The length of the file in bytes, or `None` if the file does not exist.
+  pub len: Option<u64>,
+  /// Whether the file is currently locked by another file handle.
+  pub locked: bool,
+  /// The error produced by the format while parsing the file, if any.
+  pub parse_error: Option<ParseError<FE>>
+}
+
+impl<FE> FsckReport<FE> {
+  /// Returns `true` if the file exists and could be parsed.
+  #[inline]
+  pub fn is_healthy(&self) -> bool {
+    self.len.is_some() && self.parse_error.is_none()
+  }
+}
+
+/// A parse error found by [`fsck`], along with where in the file it occurred.
+#[derive(Debug)]
+pub struct ParseError<FE> {
+  /// The error produced by the format.
+  pub error: FE,
+  /// The byte offset at which the error occurred, if the format was able to tell.
+  /// See [`FileFormat::error_offset`].
+  pub offset: Option<usize>
+}
+
+/// The action taken by [`repair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairOutcome {
+  /// The file was healthy, nothing was changed.
+  Healthy,
+  /// The file did not exist, and was created from the fallback value.
+  Created,
+  /// The file could not be parsed, it was moved to `backup` and replaced with the fallback value.
+  Replaced {
+    /// The path that the unparseable file was moved to.
+    backup: PathBuf
+  }
+}
+
+/// The actions taken by [`recover`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RecoveryReport {
+  /// Whether the file was found to be empty, as left behind by a write that was interrupted after truncating it.
+  pub was_empty: bool,
+  /// The temporary file that was rolled forward into place, if any.
+  pub rolled_forward: Option<PathBuf>,
+  /// The temporary files that were rolled back (removed) because they were incomplete or outdated.
+  pub rolled_back: Vec<PathBuf>
+}
+
+impl RecoveryReport {
+  /// Returns `true` if no evidence of an interrupted commit was found.
+  #[inline]
+  pub fn is_clean(&self) -> bool {
+    !self.was_empty && self.rolled_forward.is_none() && self.rolled_back.is_empty()
+  }
+}
+
+/// Inspects the file at the given path, reporting whether it exists, is locked, and can be parsed as a `T`.
+///
+/// Returns an error only if the file exists but could not be read.
+pub fn fsck<T, P, Format>(path: P, format: &Format) -> io::Result<FsckReport<Format::FormatError>>
+where P: AsRef<Path>, Format: FileFormat<T> {
+  let mut file = match File::open(path) {
+    Ok(file) => file,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+      return Ok(FsckReport { len: None, locked: false, parse_error: None });
+    },
+    Err(err) => return Err(err)
+  };
+
+  let locked = is_locked(&file)?;
+  let mut buf = Vec::new();
+  file.read_to_end(&mut buf)?;
+
+  let parse_error = format.from_buffer(&buf).err().map(|error| {
+    let offset = format.error_offset(&error, &buf);
+    ParseError { error, offset }
+  });
+
+  Ok(FsckReport { len: Some(buf.len() as u64), locked, parse_error })
+}
+
+/// Applies safe fixes to the file at the given path.
+///
+/// If the file does not exist, it is created from `fallback`. If it cannot be parsed, it is moved aside
+/// (to the same path with a `.corrupt` extension appended) before being replaced with `fallback`,
+/// so that no data is ever discarded. Files that are currently locked are left untouched and an error is returned.
+pub fn repair<T, P, Format>(path: P, format: &Format, fallback: T) -> Result<RepairOutcome, Error<Format::FormatError>>
+where P: AsRef<Path>, Format: FileFormat<T> {
+  let path = path.as_ref();
+  let report = fsck::<T, _, _>(path, format)?;
+  if report.locked {
+    return Err(LockContended.into());
+  };
+
+  let outcome = match report {
+    FsckReport { len: None, .. } => RepairOutcome::Created,
+    FsckReport { parse_error: None, .. } => return Ok(RepairOutcome::Healthy),
+    FsckReport { parse_error: Some(_), .. } => {
+      let backup = aside_path(path, ".corrupt");
+      fs::rename(path, &backup)?;
+      RepairOutcome::Replaced { backup }
+    }
+  };
+
+  crate::manager::overwrite::<_, _, crate::manager::Writable>(path, format, &fallback)?;
+  Ok(outcome)
+}
+
+/// Reads a value from the file at the given path, writing the default value of `T` to it first if it does not exist.
+///
+/// No container is kept, and the file is not locked. See [`write_atomic`] for the counterpart.
+pub fn read_or_default<T, P, Format>(path: P, format: &Format) -> Result<T, Error<Format::FormatError>>
+where P: AsRef<Path>, Format: FileFormat<T>, T: Default {
+  crate::manager::read_or_write::<_, _, _, crate::manager::Writable>(path.as_ref(), format, T::default)
+}
+
+/// Writes a value to the file at the given path through a temporary file that is renamed into place,
+/// creating the file if it does not exist. See [`AtomicRename`] for more information.
+///
+/// No container is kept, and the file is not locked.
+///
+/// [`AtomicRename`]: crate::manager::mode::AtomicRename
+pub fn write_atomic<T, P, Format>(path: P, format: &Format, value: &T) -> Result<(), Error<Format::FormatError>>
+where P: AsRef<Path>, Format: FileFormat<T> {
+  crate::manager::mode::write_rename(format, path.as_ref(), value, true)
+}
+
+/// Reads a value from the file at `path_in` in one format, and writes it to the file at `path_out` in another,
+/// creating it if it does not exist. The value is written atomically, as with [`write_atomic`].
+///
+/// This migrates a file to a new format without opening it as a container, see [`Container::convert_format`]
+/// to convert the file managed by a container. Both paths may be the same, to convert a file in place.
+///
+/// [`Container::convert_format`]: crate::container::Container::convert_format
+pub fn transcode<T, PI, PO, FormatIn, FormatOut>(
+  path_in: PI, format_in: &FormatIn,
+  path_out: PO, format_out: &FormatOut
+) -> Result<(), TranscodeError<FormatIn::FormatError, FormatOut::FormatError>>
+where PI: AsRef<Path>, PO: AsRef<Path>, FormatIn: FileFormat<T>, FormatOut: FileFormat<T> {
+  let file = File::open(path_in).map_err(|err| TranscodeError::Read(err.into()))?;
+  let value = format_in.from_reader_buffered(file).map_err(|err| TranscodeError::Read(Error::Format(err)))?;
+  write_atomic(path_out, format_out, &value).map_err(TranscodeError::Write)
+}
+
+/// Reads a value from standard input using the given format, reading until the end of input.
+pub fn read_stdin<T, Format>(format: &Format) -> Result<T, Error<Format::FormatError>>
+where Format: FileFormat<T> {
+  format.from_reader(io::stdin().lock()).map_err(Error::Format)
+}
+
+/// Reads a value from the file at the given path into a memory-only [`Container`],
+/// or from standard input if the path is `-`, as is conventional for command line tools.
+///
+/// [`Container`]: crate::container::Container
+pub fn read_path_or_stdin<T, P, Format>(path: P, format: &Format) -> Result<ContainerMemoryOnly<T>, Error<Format::FormatError>>
+where P: AsRef<Path>, Format: FileFormat<T> {
+  let path = path.as_ref();
+  match path == Path::new(STDIO_PATH) {
+    true => read_stdin(format).map(ContainerMemoryOnly::from),
+    false => ContainerMemoryOnly::from_reader_only(File::open(path)?, format)
+  }
+}
+
+/// Recovers the file at the given path from a commit that was interrupted by a crash.
+///
+/// Every temporary file belonging to this file (see [`clean_stale_temp_files`] for the naming scheme) is inspected.
+/// The newest temporary file that can be parsed as a `T` is rolled forward (moved into place) if it is newer than the file,
+/// or if the file is empty or missing. All other temporary files are rolled back (removed).
+///
+/// This must not be called while another process may be committing to the file.
+pub fn recover<T, P, Format>(path: P, format: &Format) -> io::Result<RecoveryReport>
+where P: AsRef<Path>, Format: FileFormat<T> {
+  let path = path.as_ref();
+  let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+  let dir = match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new(".")
+  };
+
+  let mut prefix = name.to_owned();
+  prefix.push(TEMP_MARKER);
+  let prefix = prefix.to_string_lossy().into_owned();
+  let mut temp_files = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    let file_name = entry.file_name();
+    let is_ours = file_name.to_str().map_or(false, |name| name.starts_with(&prefix))
+      && is_temp_file_name(&file_name);
+
+    if is_ours && entry.file_type()?.is_file() {
+      temp_files.push((entry.metadata()?.modified()?, entry.path()));
+    };
+  };
+
+  // newest first
+  temp_files.sort_by(|a, b| b.cmp(a));
+
+  let main_modified = match fs::metadata(path) {
+    Ok(metadata) => Some((metadata.len(), metadata.modified()?)),
+    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+    Err(err) => return Err(err)
+  };
+
+  let mut report = RecoveryReport {
+    was_empty: matches!(main_modified, Some((0, _))),
+    ..RecoveryReport::default()
+  };
+
+  for (modified, temp_path) in temp_files {
+    let supersedes = match main_modified {
+      Some((len, main_modified)) => len == 0 || modified > main_modified,
+      None => true
+    };
+
+    if report.rolled_forward.is_none() && supersedes && is_parseable(&temp_path, format)? {
+      fs::rename(&temp_path, path)?;
+      report.rolled_forward = Some(temp_path);
+    } else {
+      fs::remove_file(&temp_path)?;
+      report.rolled_back.push(temp_path);
+    };
+  };
+
+  Ok(report)
+}
+
+fn is_parseable<T, Format>(path: &Path, format: &Format) -> io::Result<bool>
+where Format: FileFormat<T> {
+  let buf = fs::read(path)?;
+  Ok(format.from_buffer(&buf).is_ok())
+}
+
+/// Returns a unique path for a temporary file belonging to the file at the given path,
+/// following the naming scheme recognized by [`recover`] and [`clean_stale_temp_files`].
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+  let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_nanos());
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(format!("{TEMP_MARKER}{}-{nanos}", std::process::id()));
+  path.with_file_name(name)
+}
+
+/// Removes leftover temporary files in the given directory, such as those left behind by a commit that was
+/// interrupted by a crash, returning the paths of the files that were removed.
+///
+/// Temporary files are named `<file name>.tmp-<process id>-<timestamp>`, only files matching
+/// that scheme which have not been modified for at least `max_age` are removed.
+pub fn clean_stale_temp_files<P: AsRef<Path>>(dir: P, max_age: Duration) -> io::Result<Vec<PathBuf>> {
+  let now = SystemTime::now();
+  let mut removed = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    if !entry.file_type()?.is_file() || !is_temp_file_name(&entry.file_name()) {
+      continue;
+    };
+
+    let modified = entry.metadata()?.modified()?;
+    if now.duration_since(modified).map_or(false, |age| age >= max_age) {
+      let path = entry.path();
+      match fs::remove_file(&path) {
+        Ok(()) => removed.push(path),
+        // another process may have cleaned it up already
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err)
+      };
+    };
+  };
+
+  Ok(removed)
+}
+
+fn is_temp_file_name(name: &std::ffi::OsStr) -> bool {
+  let name = match name.to_str() {
+    Some(name) => name,
+    None => return false
+  };
+
+  let suffix = match name.rfind(TEMP_MARKER) {
+    Some(i) if i > 0 => &name[i + TEMP_MARKER.len()..],
+    _ => return false
+  };
+
+  let mut parts = suffix.split('-');
+  let is_number = |part: Option<&str>| part.map_or(false, |part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+  is_number(parts.next()) && is_number(parts.next()) && parts.next().is_none()
+}
+
+fn is_locked(file: &File) -> io::Result<bool> {
+  match fs4::fs_std::FileExt::try_lock_exclusive(file) {
+    Ok(()) => fs4::fs_std::FileExt::unlock(file).map(|()| false),
+    Err(err) if err.kind() == fs4::lock_contended_error().kind() => Ok(true),
+    Err(err) => Err(err)
+  }
+}
+
+/// Returns the path of the given file with the given suffix appended to its name,
+/// followed by a number if something already exists at that path.
+pub(crate) fn aside_path(path: &Path, suffix: &str) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(suffix);
+  let mut backup = path.with_file_name(&name);
+  let mut n = 1;
+  while backup.exists() {
+    let mut numbered = name.clone();
+    numbered.push(format!(".{n}"));
+    backup = path.with_file_name(numbered);
+    n += 1;
+  };
+
+  backup
+}
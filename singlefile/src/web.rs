@@ -0,0 +1,151 @@
+//! Integration for using a [`ContainerSharedAsync`] as `axum` application state.
+//!
+//! This module can be enabled with the `axum` cargo feature.
+//!
+//! A [`ContainerSharedAsync`] is cheaply cloneable, so it can be used as router state directly,
+//! or as a field of a larger state struct that implements (or derives) [`FromRef`].
+//! The [`Access`] and [`AccessMut`] extractors then lock the container for the duration of a handler.
+//!
+//! ```no_run
+//! # use singlefile_formats::json_serde::{Json, JsonError};
+//! use axum::{Router, routing::{get, post}};
+//! use singlefile::container_shared_async::ContainerSharedAsyncWritable;
+//! use singlefile::manager::ManagerWritable;
+//! use singlefile::web::{Access, AccessMut};
+//!
+//! type Store = ContainerSharedAsyncWritable<Vec<String>, Json>;
+//!
+//! async fn list(Access(names): Access<Vec<String>, ManagerWritable<Json>>) -> String {
+//!   names.join(", ")
+//! }
+//!
+//! async fn add(
+//!   AccessMut(mut names): AccessMut<Vec<String>, ManagerWritable<Json>>,
+//!   name: String
+//! ) -> Result<(), singlefile::Error<JsonError>> {
+//!   names.push(name);
+//!   // Writes the new state to disk before the lock is released
+//!   names.commit().await?;
+//!   Ok(())
+//! }
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let store = Store::create_or_default("names.json", Json).await?;
+//! let app: Router = Router::new()
+//!   .route("/names", get(list))
+//!   .route("/names", post(add))
+//!   .with_state(store);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+//! [`FromRef`]: axum::extract::FromRef
+
+use crate::container_shared_async::{ContainerSharedAsync, OwnedAccessGuard, OwnedAccessGuardMut};
+use crate::error::Error;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+
+use std::convert::Infallible;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+
+
+/// An extractor that yields read-only access to a [`ContainerSharedAsync`] from the router state.
+///
+/// The container is locked for reading until this extractor is dropped.
+///
+/// [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+#[derive(Debug)]
+pub struct Access<T, Manager>(pub OwnedAccessGuard<T, Manager>);
+
+#[async_trait]
+impl<S, T, Manager> FromRequestParts<S> for Access<T, Manager>
+where
+  ContainerSharedAsync<T, Manager>: FromRef<S>,
+  S: Send + Sync,
+  T: Send + Sync + 'static,
+  Manager: Send + Sync + 'static
+{
+  type Rejection = Infallible;
+
+  async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    let container = ContainerSharedAsync::<T, Manager>::from_ref(state);
+    Ok(Access(container.access_owned().await))
+  }
+}
+
+impl<T, Manager> Deref for Access<T, Manager> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+
+
+/// An extractor that yields mutable access to a [`ContainerSharedAsync`] from the router state.
+///
+/// The container is locked for writing until this extractor is dropped, changes can be written to disk
+/// before that happens with [`OwnedAccessGuardMut::commit`].
+///
+/// [`ContainerSharedAsync`]: crate::container_shared_async::ContainerSharedAsync
+#[derive(Debug)]
+pub struct AccessMut<T, Manager>(pub OwnedAccessGuardMut<T, Manager>);
+
+#[async_trait]
+impl<S, T, Manager> FromRequestParts<S> for AccessMut<T, Manager>
+where
+  ContainerSharedAsync<T, Manager>: FromRef<S>,
+  S: Send + Sync,
+  T: Send + Sync + 'static,
+  Manager: Send + Sync + 'static
+{
+  type Rejection = Infallible;
+
+  async fn from_request_parts(_parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+    let container = ContainerSharedAsync::<T, Manager>::from_ref(state);
+    Ok(AccessMut(container.access_owned_mut().await))
+  }
+}
+
+impl<T, Manager> Deref for AccessMut<T, Manager> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl<T, Manager> DerefMut for AccessMut<T, Manager> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+
+
+/// Responds with `503 Service Unavailable` for timeouts and lock contention, `409 Conflict` for conflicts,
+/// `413 Payload Too Large` for files above their maximum size, and `500 Internal Server Error` otherwise.
+impl<FE: fmt::Display> IntoResponse for Error<FE> {
+  fn into_response(self) -> Response {
+    let status = match self {
+      Error::TimedOut(_) | Error::LockContended(_) => StatusCode::SERVICE_UNAVAILABLE,
+      Error::Conflict(_) => StatusCode::CONFLICT,
+      Error::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+      _ => StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    (status, self.to_string()).into_response()
+  }
+}
@@ -2,7 +2,7 @@
 extern crate serde;
 extern crate singlefile;
 
-use singlefile_formats::json_serde::Json;
+use singlefile_formats::json_serde::{Json, RegularJson};
 
 use std::{fs, mem};
 
@@ -31,6 +31,339 @@ fn container_writable() {
   temp_dir.close().unwrap();
 }
 
+#[test]
+fn container_export_import() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.number = 1;
+
+  let mut backup = Vec::new();
+  container.export_to(&mut backup).unwrap();
+  assert_eq!(serde_json::from_slice::<Data>(&backup).unwrap().number, 1);
+
+  container.number = 5;
+  assert_eq!(container.import_from(backup.as_slice()).unwrap().number, 5);
+  assert_eq!(container.number, 1);
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_commit_dry_run() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  let written = fs::read(&path).unwrap();
+
+  container.number = 1234;
+  let len = container.commit_dry_run().unwrap();
+  assert!(len as u64 > written.len() as u64);
+  assert_eq!(fs::read(&path).unwrap(), written);
+  assert_eq!(container.commit_count(), 0);
+
+  container.commit().unwrap();
+  assert_eq!(fs::metadata(&path).unwrap().len(), len as u64);
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_commit_count() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  assert_eq!(container.commit_count(), 0);
+  assert!(container.last_commit_at().is_none());
+
+  container.number += 1;
+  container.commit().unwrap();
+  assert_eq!(container.commit_count(), 1);
+  assert!(container.last_commit_at().is_some());
+  assert!(container.last_refresh_at().is_none());
+
+  container.refresh().unwrap();
+  assert_eq!(container.commit_count(), 1);
+  assert!(container.last_refresh_at().is_some());
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_refresh_if_changed() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+
+  assert_eq!(container.refresh_if_changed().unwrap(), None);
+  assert!(container.last_refresh_at().is_none());
+
+  container.number = 1;
+  container.commit().unwrap();
+  assert_eq!(container.refresh_if_changed().unwrap(), None);
+
+  fs::write(&path, r#"{ "number": 1234 }"#).unwrap();
+  assert_eq!(container.refresh_if_changed().unwrap(), Some(Data { number: 1 }));
+  assert_eq!(container.number, 1234);
+  assert_eq!(container.refresh_if_changed().unwrap(), None);
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_commit_if_dirty() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  assert!(!container.is_dirty());
+  assert!(!container.commit_if_dirty().unwrap());
+  assert_eq!(container.commit_count(), 0);
+
+  container.number = 1;
+  assert!(container.is_dirty());
+  assert!(container.commit_if_dirty().unwrap());
+  assert!(!container.commit_if_dirty().unwrap());
+  assert_eq!(container.commit_count(), 1);
+
+  container.mark_dirty();
+  assert!(container.commit_if_dirty().unwrap());
+  container.number = 2;
+  container.refresh().unwrap();
+  assert!(!container.is_dirty());
+  assert_eq!(container.number, 1);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_commit_if_unchanged() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::error::Error;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut first = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  let mut second = ContainerWritable::<Data, Json>::open(&path, Json)
+    .expect("failed to open container for data.json");
+
+  first.number = 10;
+  first.commit_if_unchanged().expect("failed to commit state to disk");
+  second.number = 20;
+  assert!(matches!(second.commit_if_unchanged(), Err(Error::Conflict(_))));
+
+  second.refresh().unwrap();
+  assert_eq!(second.number, 10);
+  second.number += 100;
+  second.commit_if_unchanged().expect("failed to commit state to disk");
+  assert!(matches!(first.commit_if_unchanged(), Err(Error::Conflict(_))));
+  mem::drop((first, second));
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_slow_operations() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::slow::{self, Operation, Thresholds};
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let reported = Arc::new(Mutex::new(Vec::new()));
+  slow::set_callback({
+    let reported = Arc::clone(&reported);
+    let path = path.clone();
+    move |slow| if slow.path == Some(path.as_path()) {
+      reported.lock().unwrap().push(slow.operation);
+    }
+  });
+
+  slow::set_thresholds(Thresholds { commit: Some(Duration::ZERO), ..Thresholds::default() });
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.refresh().unwrap();
+  container.commit().unwrap();
+  slow::set_thresholds(Thresholds::default());
+  container.commit().unwrap();
+  slow::clear_callback();
+
+  assert_eq!(*reported.lock().unwrap(), [Operation::Commit]);
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_memory_only() {
+  use singlefile::container::ContainerMemoryOnly;
+
+  let mut container = ContainerMemoryOnly::<Data>::default();
+
+  container.number += 1;
+  container.commit().unwrap();
+  container.refresh().unwrap();
+  assert_eq!(container.number, 1);
+
+  container.overwrite(Data { number: 5 }).unwrap();
+  assert_eq!(container.close().unwrap().number, 5);
+}
+
+#[test]
+#[cfg(all(feature = "shared", feature = "shared-async"))]
+fn container_shared_attach_manager() {
+  use singlefile::container_shared::ContainerSharedMemoryOnly;
+  use singlefile::container_shared_async::ContainerSharedAsyncMemoryOnly;
+  use singlefile::manager::ManagerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, "").unwrap();
+
+  let container = ContainerSharedMemoryOnly::from(Data { number: 1 });
+  let other = container.clone();
+  let (container, manager) = container.attach_manager(ManagerWritable::open(&path, Json::<true>).unwrap())
+    .expect_err("a shared container cannot have a manager attached");
+  mem::drop(other);
+  let container = container.attach_manager(manager).unwrap();
+  container.commit().unwrap();
+  assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 1"));
+  mem::drop(container);
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncMemoryOnly::from(Data { number: 2 });
+    let container = container.attach_manager(ManagerWritable::open(&path, Json::<true>).unwrap()).unwrap();
+    container.commit().await.unwrap();
+  });
+
+  assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 2"));
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_static() {
+  use singlefile::container::ContainerStatic;
+
+  static DEFAULTS: &[u8] = br#"{ "number": 3 }"#;
+
+  let mut container = ContainerStatic::<Data, Json>::from_static(DEFAULTS, Json)
+    .expect("failed to read static bytes");
+  assert_eq!(container.number, 3);
+  assert_eq!(container.manager().bytes(), DEFAULTS);
+
+  // refreshing discards modifications, since the bytes never change
+  container.number += 1;
+  assert_eq!(container.refresh().unwrap().number, 4);
+  assert_eq!(container.close().unwrap().number, 3);
+
+  assert!(ContainerStatic::<Data, Json>::from_static(b"not json", Json).is_err());
+}
+
+#[test]
+fn container_multi() {
+  use singlefile::container_multi::{ContainerMulti, MultiFormat};
+
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.bin");
+
+  let mut container = ContainerMulti::<(Data, Vec<String>), Json>::create_or_default(&path, MultiFormat(Json))
+    .expect("failed to create container for data.bin");
+
+  container.0.number += 1;
+  container.1.push("hello".to_owned());
+  container.commit()
+    .expect("failed to commit state to disk");
+  mem::drop(container);
+
+  let container = ContainerMulti::<(Data, Vec<String>), Json>::open(&path, MultiFormat(Json))
+    .expect("failed to reopen container for data.bin");
+  assert_eq!(container.0.number, 1);
+  assert_eq!(container.1, ["hello"]);
+  mem::drop(container);
+
+  let mut container = ContainerMulti::<(Data, Vec<String>), Json>::open(&path, MultiFormat(Json)).unwrap();
+  let hooks = Arc::new(AtomicUsize::new(0));
+  container.on_before_commit({
+    let hooks = Arc::clone(&hooks);
+    move |_| { hooks.fetch_add(1, Ordering::SeqCst); }
+  });
+  container.on_after_commit({
+    let hooks = Arc::clone(&hooks);
+    move |_| { hooks.fetch_add(10, Ordering::SeqCst); }
+  });
+
+  let len = fs::metadata(&path).unwrap().len();
+  container.0.number = 2;
+  container.commit_section(0)
+    .expect("failed to commit section to disk");
+  assert_eq!(fs::metadata(&path).unwrap().len(), len);
+  assert_eq!(hooks.load(Ordering::SeqCst), 11);
+
+  container.1 = vec!["a much longer string that cannot fit".to_owned(); 8];
+  container.commit_section(1)
+    .expect("failed to commit section to disk");
+  assert_eq!(container.commit_count(), 2);
+  assert_eq!(hooks.load(Ordering::SeqCst), 22);
+  mem::drop(container);
+
+  let container = ContainerMulti::<(Data, Vec<String>), Json>::open(&path, MultiFormat(Json)).unwrap();
+  assert_eq!(container.0.number, 2);
+  assert_eq!(container.1.len(), 8);
+  mem::drop(container);
+
+  ContainerMulti::<(Data,), Json>::open(&path, MultiFormat(Json))
+    .expect_err("section count mismatch should be rejected");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
 #[test]
 #[cfg(feature = "shared")]
 fn container_shared_writable() {
@@ -85,13 +418,2683 @@ fn container_shared_writable() {
   temp_dir.close().unwrap();
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Data {
-  number: i32
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_atomic() {
+  use singlefile::container_shared::ContainerSharedAtomic;
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedAtomic::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+
+  container.operate_mut_commit(|data| {
+    data.number = 42;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+
+  mem::drop(container);
+
+  let container = ContainerSharedAtomic::<Data, Json>::open(&path, Json)
+    .expect("failed to open container for data.json");
+  assert_eq!(container.operate(|data| data.number), 42);
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
 }
 
-impl Default for Data {
-  fn default() -> Self {
-    Data { number: 0 }
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_wait_for_change() {
+  use singlefile::container_shared::ContainerSharedMemoryOnly;
+
+  use std::thread;
+  use std::time::Duration;
+
+  let container = ContainerSharedMemoryOnly::<Data>::default();
+  assert!(!container.wait_for_change(Duration::from_millis(10)));
+
+  let container1 = container.clone();
+  let waiter = thread::spawn(move || container1.wait_for_change(Duration::from_secs(10)));
+
+  while !waiter.is_finished() {
+    container.overwrite(Data { number: 1 }).unwrap();
+    thread::sleep(Duration::from_millis(1));
   }
+
+  assert!(waiter.join().unwrap());
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_subscribe() {
+  use singlefile::container::ChangeEvent;
+  use singlefile::container_shared::ContainerSharedWritable;
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json).unwrap();
+  let receiver = container.subscribe();
+  let dropped = container.subscribe();
+  mem::drop(dropped);
+
+  container.operate_mut_commit(|data| {
+    data.number = 1;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+  container.overwrite(Data { number: 2 }).unwrap();
+  container.refresh().unwrap();
+  // mutable access alone does not change the persisted state
+  container.operate_mut(|data| data.number = 3);
+
+  let events = receiver.try_iter().collect::<Vec<ChangeEvent>>();
+  assert_eq!(events, [ChangeEvent::Commit, ChangeEvent::Overwrite, ChangeEvent::Refresh]);
+
+  mem::drop(container);
+  assert!(receiver.recv().is_err());
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_cached() {
+  use singlefile::container_shared::ContainerCached;
+
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{ "number": 1 }"#).unwrap();
+
+  let container = ContainerCached::<Data, Json>::open(&path, Json, Duration::from_secs(3600)).unwrap();
+  fs::write(&path, r#"{ "number": 22 }"#).unwrap();
+  assert!(!container.is_expired());
+  assert_eq!(container.operate(|data| data.number).unwrap(), 1);
+  container.refresh().unwrap();
+  assert_eq!(container.operate(|data| data.number).unwrap(), 22);
+
+  // with no time-to-live, every access reads the file again if it has changed
+  let container = ContainerCached::new(container.into_container(), Duration::ZERO);
+  fs::write(&path, r#"{ "number": 333 }"#).unwrap();
+  assert!(container.is_expired());
+  assert_eq!(container.operate(|data| data.number).unwrap(), 333);
+
+  fs::write(&path, "not json").unwrap();
+  assert!(container.access().is_err());
+  assert_eq!(container.container().operate(|data| data.number), 333);
+
+  mem::drop(container);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_operate_mut_cas() {
+  use singlefile::container_shared::ContainerSharedWritable;
+  use singlefile::error::UserError;
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.operate_mut_cas(|data| {
+    data.number = 1;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+
+  // another process changes the file
+  fs::write(&path, r#"{ "number": 50 }"#).unwrap();
+  let result = container.operate_mut_cas(|data| {
+    data.number += 1;
+    Ok::<(), Infallible>(())
+  });
+  assert!(matches!(result, Err(UserError::Conflict(_))));
+  assert_eq!(container.operate(|data| data.number), 1);
+
+  container.refresh().unwrap();
+  container.operate_mut_cas(|data| {
+    data.number += 1;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+  assert_eq!(container.refresh().unwrap().number, 51);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_panic_policy() {
+  use singlefile::container_shared::{ContainerSharedWritable, PanicPolicy};
+  use singlefile::{Error, UserError};
+
+  use std::convert::Infallible;
+  use std::panic::{self, AssertUnwindSafe};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json).unwrap();
+  let half_mutate = |data: &mut Data| -> Result<(), Infallible> {
+    data.number += 1;
+    panic!("operation failed halfway");
+  };
+
+  container.set_panic_policy(PanicPolicy::restore());
+  panic::catch_unwind(AssertUnwindSafe(|| container.operate_mut_commit(half_mutate))).unwrap_err();
+  assert_eq!(container.operate(Clone::clone), Data { number: 0 });
+
+  container.set_panic_policy(PanicPolicy::Poison);
+  panic::catch_unwind(AssertUnwindSafe(|| container.operate_mut_commit(half_mutate))).unwrap_err();
+  assert!(container.is_poisoned());
+  assert!(matches!(container.commit(), Err(Error::Poisoned(_))));
+  assert!(matches!(container.operate_mut_commit(|_| Ok::<(), Infallible>(())), Err(UserError::Poisoned(_))));
+  assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 0"));
+
+  container.refresh().unwrap();
+  assert!(!container.is_poisoned());
+  assert_eq!(container.operate(Clone::clone), Data { number: 0 });
+  container.operate_mut_commit(|data| {
+    data.number = 2;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+  assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 2"));
+
+  container.set_panic_policy(PanicPolicy::Propagate);
+  panic::catch_unwind(AssertUnwindSafe(|| container.operate_mut_commit_staged(half_mutate))).unwrap_err();
+  assert_eq!(container.operate(Clone::clone), Data { number: 2 });
+  container.operate_mut_commit_staged(|data| {
+    data.number = 3;
+    Err::<(), _>("rejected")
+  }).unwrap_err();
+  assert_eq!(container.operate(Clone::clone), Data { number: 2 });
+  container.operate_mut_commit_staged(|data| {
+    data.number = 4;
+    Ok::<(), Infallible>(())
+  }).unwrap();
+  assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 4"));
+
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_background_writer() {
+  use singlefile::container_shared::{ContainerSharedWritable, WriteOrder};
+
+  use std::thread;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json).unwrap();
+  for order in [WriteOrder::LatestWins, WriteOrder::Ordered] {
+    let writer = container.spawn_writer(order).unwrap();
+    thread::scope(|scope| {
+      for _ in 0..8 {
+        scope.spawn(|| {
+          container.operate_mut(|data| data.number += 1);
+          writer.commit();
+        });
+      };
+    });
+
+    container.operate_mut(|data| data.number += 1);
+    let version = writer.commit();
+    writer.wait_durable(version).unwrap();
+    assert!(writer.durable_version() >= version);
+
+    writer.flush().unwrap();
+    assert_eq!(writer.pending(), 0);
+    let on_disk = ContainerSharedWritable::<Data, Json>::open(&path, Json).unwrap();
+    assert_eq!(on_disk.operate(Clone::clone), container.operate(Clone::clone));
+    writer.close().unwrap();
+  };
+
+  assert_eq!(container.operate(|data| data.number), 18);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_autosave() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::container_shared::ContainerSharedWritable;
+
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  assert!(!container.flush().unwrap());
+
+  container.enable_autosave(Duration::from_millis(10)).unwrap();
+  assert!(container.is_autosave_enabled());
+  container.operate_mut(|data| data.number = 1);
+  assert!(container.wait_for_change(Duration::from_secs(5)));
+  assert!(!container.access().container().is_dirty());
+  assert_eq!(container.commit_count(), 1);
+
+  container.disable_autosave();
+  assert!(!container.is_autosave_enabled());
+  container.operate_mut(|data| data.number = 2);
+  assert!(container.flush().unwrap());
+  assert!(!container.flush().unwrap());
+  assert_eq!(container.commit_count(), 2);
+
+  // dropping the last handle commits the dirty state one final time
+  container.enable_autosave(Duration::from_secs(60)).unwrap();
+  container.operate_mut(|data| data.number = 3);
+  mem::drop(container);
+  let container = ContainerWritable::<Data, Json>::open(&path, Json)
+    .expect("failed to open container for data.json");
+  assert_eq!(container.number, 3);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_autosave() {
+  use singlefile::container_shared_async::ContainerSharedAsyncWritable;
+
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+  let value = runtime.block_on(async {
+    let container = ContainerSharedAsyncWritable::<Data, Json>::create_or_default(&path, Json).await
+      .expect("failed to create container for data.json");
+
+    container.enable_autosave(Duration::from_millis(10));
+    container.operate_mut(|data| data.number = 1).await;
+    while container.commit_count().await == 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    assert!(!container.flush().await.unwrap());
+    container.disable_autosave().await;
+    container.operate_mut(|data| data.number = 2).await;
+    assert!(container.flush().await.unwrap());
+
+    // shutting down commits the dirty state without waiting for the interval
+    container.enable_autosave(Duration::from_secs(60));
+    container.operate_mut(|data| data.number = 3).await;
+    container.shutdown_autosave().await.unwrap();
+    assert!(!container.is_autosave_enabled());
+    assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 3"));
+
+    container.enable_autosave(Duration::from_secs(60));
+    container.into_inner_graceful().await.unwrap()
+  });
+
+  assert_eq!(value, Data { number: 3 });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_blocking_pool() {
+  use singlefile::container_shared_async::{BlockingPool, ContainerSharedAsyncWritable};
+
+  use std::convert::Infallible;
+  use std::thread;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  runtime.block_on(async {
+    let pool = BlockingPool::dedicated(1).unwrap();
+    let container = ContainerSharedAsyncWritable::<Data, Json>::create_or_default(&path, Json).await
+      .expect("failed to create container for data.json")
+      .with_blocking_pool(pool);
+    assert!(!container.blocking_pool().is_global());
+
+    let thread_name = container.operate_nonblocking(|_| thread::current().name().map(str::to_owned)).await.unwrap();
+    assert_eq!(thread_name.as_deref(), Some("singlefile-io"));
+
+    container.operate_mut_commit(|data| {
+      data.number = 7;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert!(container.operate_nonblocking(|_| panic!("operation failed")).await.unwrap_err().is_panic());
+    assert_eq!(container.refresh().await.unwrap().number, 7);
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_spawner() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::container_shared_async::{BlockingPool, ContainerSharedAsyncWritable, Spawner};
+  use singlefile::error::Error;
+
+  use std::convert::Infallible;
+  use std::future::Future;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::task::{Context, Poll, Wake};
+  use std::thread::{self, Thread};
+
+  struct ThreadSpawner(Arc<AtomicUsize>);
+
+  impl Spawner for ThreadSpawner {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send + 'static>) {
+      self.0.fetch_add(1, Ordering::Relaxed);
+      thread::spawn(task);
+    }
+  }
+
+  struct DroppingSpawner;
+
+  impl Spawner for DroppingSpawner {
+    fn spawn_blocking(&self, task: Box<dyn FnOnce() + Send + 'static>) {
+      mem::drop(task);
+    }
+  }
+
+  // a minimal executor, to show that no tokio runtime is needed
+  fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+      fn wake(self: Arc<Self>) {
+        self.0.unpark();
+      }
+    }
+
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+      if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+        return output;
+      };
+
+      thread::park();
+    };
+  }
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let spawned = Arc::new(AtomicUsize::new(0));
+  let container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  let container = ContainerSharedAsyncWritable::from(container)
+    .with_blocking_pool(BlockingPool::from_spawner(ThreadSpawner(Arc::clone(&spawned))));
+
+  block_on(async {
+    container.operate_mut_commit(|data| {
+      data.number = 7;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert_eq!(container.refresh().await.unwrap().number, 7);
+    assert!(container.operate_nonblocking(|_| panic!("operation failed")).await.unwrap_err().is_panic());
+  });
+
+  assert_eq!(spawned.load(Ordering::Relaxed), 3);
+  let container = container.with_blocking_pool(BlockingPool::from_spawner(DroppingSpawner));
+  match block_on(container.refresh()) {
+    Err(Error::Task(err)) => assert!(err.is_cancelled()),
+    result => panic!("expected a cancelled task, got {result:?}")
+  };
+
+  mem::drop(container);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_auto_refresh() {
+  use singlefile::container_shared_async::ContainerSharedAsyncReadonly;
+
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{ "number": 1 }"#).unwrap();
+
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncReadonly::<Data, Json>::open(&path, Json).await.unwrap();
+    let auto_refresh = container.spawn_auto_refresh(Duration::from_millis(10));
+    let mut refreshes = auto_refresh.subscribe();
+
+    fs::write(&path, r#"{ "number": 22 }"#).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), refreshes.changed()).await.unwrap().unwrap();
+    assert_eq!(*refreshes.borrow(), 1);
+    assert_eq!(container.operate(|data| data.number).await, 22);
+    assert!(auto_refresh.take_error().is_none());
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_subscribe() {
+  use singlefile::container::ChangeEvent;
+  use singlefile::container_shared_async::ContainerSharedAsyncWritable;
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncWritable::<Data, Json>::create_or_default(&path, Json).await.unwrap();
+    let mut receiver = container.subscribe();
+
+    container.operate_mut_commit(|data| {
+      data.number = 1;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    container.overwrite(Data { number: 2 }).await.unwrap();
+    container.refresh().await.unwrap();
+    assert!(container.refresh_if_changed().await.unwrap().is_none());
+
+    assert_eq!(receiver.recv().await.unwrap(), ChangeEvent::Commit);
+    assert_eq!(receiver.recv().await.unwrap(), ChangeEvent::Overwrite);
+    assert_eq!(receiver.recv().await.unwrap(), ChangeEvent::Refresh);
+    assert!(receiver.try_recv().is_err());
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_commit_coalescer() {
+  use singlefile::container::ChangeEvent;
+  use singlefile::container_shared_async::ContainerSharedAsyncWritable;
+
+  use std::convert::Infallible;
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncWritable::<Data, Json>::create_or_default(&path, Json).await.unwrap();
+    let coalescer = container.commit_coalescer(Duration::from_millis(50));
+    let mut receiver = container.subscribe();
+
+    let mut handles = Vec::new();
+    for _ in 0..10 {
+      let ((), handle) = coalescer.operate_mut_commit(|data| {
+        data.number += 1;
+        Ok::<(), Infallible>(())
+      }).await.unwrap();
+      handles.push(handle);
+    };
+
+    assert!(!handles[0].is_committed());
+    assert_eq!(handles.iter().map(|handle| handle.version()).collect::<Vec<u64>>(), (1..=10).collect::<Vec<u64>>());
+    assert_eq!(coalescer.durable_version(), 0);
+    coalescer.await_durable(4).await.expect("failed to commit batch");
+    assert_eq!(coalescer.durable_version(), 10);
+    for handle in handles {
+      handle.wait().await.expect("failed to commit batch");
+    };
+
+    // every change was written by a single commit
+    assert_eq!(receiver.recv().await.unwrap(), ChangeEvent::Commit);
+    assert!(receiver.try_recv().is_err());
+    let on_disk: Data = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.number, 10);
+
+    let ((), handle) = coalescer.operate_mut_commit(|data| {
+      data.number += 1;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert_eq!(handle.version(), 11);
+    mem::drop(handle);
+    coalescer.await_durable(u64::MAX).await.expect("failed to commit batch");
+    assert_eq!(coalescer.durable_version(), 11);
+
+    // shutting down commits the open batch without waiting for the window
+    let coalescer = container.commit_coalescer(Duration::from_secs(60));
+    let ((), handle) = coalescer.operate_mut_commit(|data| {
+      data.number += 1;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(10), coalescer.shutdown()).await
+      .expect("shutdown waited for the window").expect("failed to commit batch");
+    assert!(handle.is_committed());
+    let on_disk: Data = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(on_disk.number, 12);
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_timeout() {
+  use singlefile::container_shared_async::ContainerSharedAsyncMemoryOnly;
+  use singlefile::error::UserError;
+
+  use std::convert::Infallible;
+  use std::time::Duration;
+
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncMemoryOnly::<Data>::default();
+    let timeout = Duration::from_millis(20);
+
+    // a held guard stands in for a wedged writer
+    let guard = container.access_mut().await;
+    assert!(container.operate_timeout(timeout, |data| data.number).await.is_err());
+    assert!(container.operate_mut_timeout(timeout, |data| data.number += 1).await.is_err());
+    let result = container.operate_mut_commit_timeout(timeout, |_| Ok::<(), Infallible>(())).await;
+    assert!(matches!(result, Err(UserError::TimedOut(_))));
+    drop(guard);
+
+    container.operate_mut_commit_timeout(timeout, |data| {
+      data.number = 1;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert_eq!(container.operate_timeout(timeout, |data| data.number).await.unwrap(), 1);
+  });
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_panic_policy() {
+  use singlefile::container_shared_async::{ContainerSharedAsyncWritable, PanicPolicy};
+  use singlefile::{Error, UserError};
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncWritable::<Data, Json>::create_or_default(&path, Json).await.unwrap();
+    let half_mutate = |data: &mut Data| {
+      data.number += 1;
+      panic!("operation failed halfway");
+    };
+
+    container.set_panic_policy(PanicPolicy::restore());
+    assert!(container.operate_mut_nonblocking(half_mutate).await.unwrap_err().is_panic());
+    assert_eq!(container.operate(Clone::clone).await, Data { number: 0 });
+
+    container.set_panic_policy(PanicPolicy::Poison);
+    assert!(container.operate_mut_nonblocking(half_mutate).await.unwrap_err().is_panic());
+    assert!(container.is_poisoned());
+    assert!(matches!(container.commit().await, Err(Error::Poisoned(_))));
+    let result = container.operate_mut_commit(|_| Ok::<(), Infallible>(())).await;
+    assert!(matches!(result, Err(UserError::Poisoned(_))));
+    assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 0"));
+
+    container.refresh().await.unwrap();
+    assert!(!container.is_poisoned());
+    assert_eq!(container.operate(Clone::clone).await, Data { number: 0 });
+    container.operate_mut_commit(|data| {
+      data.number = 2;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert!(fs::read_to_string(&path).unwrap().contains("\"number\": 2"));
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_max_readers() {
+  use singlefile::container::ContainerMemoryOnly;
+  use singlefile::container_shared_async::ContainerSharedAsyncMemoryOnly;
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsyncMemoryOnly::with_max_readers(ContainerMemoryOnly::from(Data::default()), 2);
+
+    let first = container.access().await;
+    let second = container.access().await;
+    assert!(container.try_access().is_none());
+    drop(first);
+
+    let third = container.try_access().expect("a reader slot should be free");
+    assert!(container.try_access_mut().is_none());
+    drop((second, third));
+
+    container.access_mut().await.number = 1;
+    assert_eq!(container.access().await.number, 1);
+  });
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_into_inner_graceful() {
+  use singlefile::container::ContainerWritableLocked;
+  use singlefile::container_shared_async::ContainerSharedAsyncWritableLocked;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+  let value = runtime.block_on(async {
+    let container = ContainerSharedAsyncWritableLocked::<Data, Json>::create_or_default(&path, Json).await
+      .expect("failed to create container for data.json");
+    container.operate_mut(|data| data.number = 5).await;
+
+    assert!(container.clone().into_inner_graceful().await.is_err());
+    container.into_inner_graceful().await.unwrap()
+  });
+
+  assert_eq!(value, Data { number: 5 });
+  let container = ContainerWritableLocked::<Data, Json>::open(&path, Json)
+    .expect("file was not unlocked");
+  assert_eq!(container.number, 5);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared-async")]
+fn container_shared_async_native_io() {
+  use singlefile::container_shared_async::ContainerSharedAsync;
+  use singlefile::manager::async_manager::{AsyncManagerAtomicLocked, AsyncManagerWritable};
+  use singlefile::manager::format::async_io::Buffered;
+
+  use std::convert::Infallible;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+  runtime.block_on(async {
+    let container = ContainerSharedAsync::<Data, AsyncManagerAtomicLocked<Buffered<Json>>>::create_or_default(&path, Buffered(Json)).await
+      .expect("failed to create container for data.json");
+    container.operate_mut_commit(|data| {
+      data.number = 4;
+      Ok::<(), Infallible>(())
+    }).await.unwrap();
+    assert_eq!(container.commit_count().await, 1);
+
+    fs::write(&path, r#"{ "number": 5 }"#).unwrap();
+    assert_eq!(container.refresh().await.unwrap().number, 4);
+    assert_eq!(container.operate(|data| data.number).await, 5);
+    container.overwrite(Data { number: 6 }).await.unwrap();
+    mem::drop(container);
+
+    let container = ContainerSharedAsync::<Data, AsyncManagerWritable<Buffered<Json>>>::open(&path, Buffered(Json)).await
+      .expect("file was not unlocked");
+    assert_eq!(container.operate(|data| data.number).await, 6);
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn container_shared_async_uring() {
+  use singlefile::container_shared_async::ContainerSharedAsyncAtomic;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  tokio_uring::start(async {
+    let container = ContainerSharedAsyncAtomic::<Data, Json>::create_or_default(&path, Json).await
+      .expect("failed to create container for data.json");
+
+    container.operate_mut(|data| data.number = 1234567).await;
+    container.commit_uring().await.unwrap();
+    container.operate_mut(|data| data.number = 1).await;
+    container.commit_uring().await.unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{\n  \"number\": 1\n}");
+
+    container.operate_mut(|data| data.number = 5).await;
+    assert_eq!(container.refresh_uring().await.unwrap().number, 5);
+    assert_eq!(container.operate(|data| data.number).await, 1);
+    assert_eq!(container.commit_count().await, 2);
+  });
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Data {
+  number: i32
+}
+
+impl singlefile::container_event_log::Apply<i32> for Data {
+  fn apply(&mut self, event: &i32) {
+    self.number += event;
+  }
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_shared_auto_refresh() {
+  use singlefile::container_shared::ContainerSharedReadonly;
+
+  use std::time::{Duration, Instant};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{ "number": 1 }"#).unwrap();
+
+  let mut container = ContainerSharedReadonly::<Data, Json>::open(&path, Json).unwrap();
+  let auto_refresh = container.spawn_auto_refresh(Duration::from_millis(10)).unwrap();
+  assert!(container.get_mut().is_none());
+
+  fs::write(&path, r#"{ "number": 22 }"#).unwrap();
+  let deadline = Instant::now() + Duration::from_secs(10);
+  while container.operate(|data| data.number) != 22 && Instant::now() < deadline {
+    container.wait_for_change(Duration::from_millis(100));
+  };
+
+  assert_eq!(container.operate(|data| data.number), 22);
+  assert!(auto_refresh.take_error().is_none());
+
+  fs::write(&path, "not json").unwrap();
+  while auto_refresh.take_error().is_none() && Instant::now() < deadline {
+    std::thread::sleep(Duration::from_millis(10));
+  };
+
+  assert_eq!(container.operate(|data| data.number), 22);
+  mem::drop(auto_refresh);
+  assert!(container.get_mut().is_some());
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "watch")]
+fn container_shared_watch() {
+  use singlefile::container_shared::ContainerSharedWritable;
+  use singlefile::container_watcher::Resolution;
+
+  use std::time::{Duration, Instant};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerSharedWritable::<Data, Json>::create_or_default(&path, Json).unwrap();
+  let watcher = container.watch().unwrap();
+
+  // changes made through the container itself do not cause a refresh
+  container.operate_mut_commit(|data| {
+    data.number = 1;
+    Ok::<(), std::convert::Infallible>(())
+  }).unwrap();
+
+  fs::write(&path, r#"{ "number": 2 }"#).unwrap();
+  let deadline = Instant::now() + Duration::from_secs(10);
+  while container.operate(|data| data.number) != 2 && Instant::now() < deadline {
+    container.wait_for_change(Duration::from_millis(100));
+  };
+
+  assert_eq!(container.operate(|data| data.number), 2);
+  mem::drop(watcher);
+
+  let watcher = container.watch_with(|_, _| Resolution::Overwrite).unwrap();
+  fs::write(&path, r#"{ "number": 3 }"#).unwrap();
+  // the file may be read while it is being overwritten, so only a complete write counts
+  let read_on_disk = || serde_json::from_str::<Data>(&fs::read_to_string(&path).unwrap()).ok();
+  while read_on_disk().map_or(true, |on_disk| on_disk.number != 2) && Instant::now() < deadline {
+    std::thread::sleep(Duration::from_millis(20));
+  };
+
+  assert_eq!(container.operate(|data| data.number), 2);
+  assert_eq!(read_on_disk().map(|on_disk| on_disk.number), Some(2));
+  mem::drop(watcher);
+  mem::drop(container);
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn config_reload() {
+  use singlefile::container_shared::Config;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicI32, Ordering};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("config.json");
+  fs::write(&path, r#"{ "number": 1 }"#).unwrap();
+
+  let config = Config::<Data, Json>::open(&path, Json, |data: &Data| match data.number >= 0 {
+    true => Ok(()),
+    false => Err("number must not be negative".into())
+  }).expect("failed to open config.json");
+
+  let last_old = Arc::new(AtomicI32::new(0));
+  let last_old_clone = Arc::clone(&last_old);
+  config.on_change(move |old, _new| last_old_clone.store(old.number, Ordering::SeqCst));
+
+  fs::write(&path, r#"{ "number": 2 }"#).unwrap();
+  config.reload().expect("failed to reload config.json");
+  assert_eq!(config.get().number, 2);
+  assert_eq!(last_old.load(Ordering::SeqCst), 1);
+
+  fs::write(&path, r#"{ "number": -1 }"#).unwrap();
+  config.reload().expect_err("invalid config should be rejected");
+  assert_eq!(config.get().number, 2);
+
+  mem::drop(config);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "watch")]
+fn config_watch() {
+  use singlefile::container_shared::Config;
+
+  use std::time::{Duration, Instant};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("config.json");
+  fs::write(&path, r#"{ "number": 1 }"#).unwrap();
+
+  let config = Config::<Data, Json>::watch(&path, Json, |data: &Data| match data.number >= 0 {
+    true => Ok(()),
+    false => Err("number must not be negative".into())
+  }).expect("failed to watch config.json");
+
+  let deadline = Instant::now() + Duration::from_secs(10);
+  fs::write(&path, r#"{ "number": -1 }"#).unwrap();
+  fs::write(&path, r#"{ "number": 2 }"#).unwrap();
+  while config.get().number != 2 && Instant::now() < deadline {
+    config.container().wait_for_change(Duration::from_millis(100));
+  };
+
+  assert_eq!(config.get().number, 2);
+
+  // rejected contents never replace the current value
+  fs::write(&path, r#"{ "number": -1 }"#).unwrap();
+  std::thread::sleep(Duration::from_millis(200));
+  assert_eq!(config.get().number, 2);
+
+  mem::drop(config);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn utils_fsck_repair() {
+  use singlefile::utils::{self, RepairOutcome};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  let format = Json::<true>;
+
+  let report = utils::fsck::<Data, _, _>(&path, &format).unwrap();
+  assert_eq!(report.len, None);
+
+  fs::write(&path, "{\n  \"number\": oops\n}").unwrap();
+  let report = utils::fsck::<Data, _, _>(&path, &format).unwrap();
+  assert!(!report.is_healthy());
+  assert_eq!(report.parse_error.unwrap().offset, Some(14));
+
+  let outcome = utils::repair(&path, &format, Data::default()).unwrap();
+  let backup = temp_dir.path().join("data.json.corrupt");
+  assert_eq!(outcome, RepairOutcome::Replaced { backup: backup.clone() });
+  assert!(utils::fsck::<Data, _, _>(&path, &format).unwrap().is_healthy());
+  assert_eq!(utils::repair(&path, &format, Data::default()).unwrap(), RepairOutcome::Healthy);
+
+  fs::remove_file(backup).unwrap();
+  fs::remove_file(path).unwrap();
+
+  let stale = temp_dir.path().join("data.json.tmp-1234-5678");
+  let unrelated = temp_dir.path().join("data.json.tmp-notes");
+  fs::write(&stale, "").unwrap();
+  fs::write(&unrelated, "").unwrap();
+  let removed = utils::clean_stale_temp_files(temp_dir.path(), std::time::Duration::ZERO).unwrap();
+  assert_eq!(removed, [stale]);
+  assert!(unrelated.exists());
+
+  fs::remove_file(unrelated).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "test-support")]
+fn test_support_roundtrip() {
+  use proptest::prelude::*;
+  use singlefile::test_support::{assert_format_roundtrip, check_roundtrip};
+
+  let format = Json::<true>;
+  assert_format_roundtrip(&format, &Data { number: 5 });
+  check_roundtrip(format, any::<i32>().prop_map(|number| Data { number }))
+    .expect("roundtrip failed");
+}
+
+#[test]
+fn container_snapshot_rollback() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or(&path, Json, Data { number: 1 })
+    .expect("failed to create container for data.json");
+  let snapshot = container.snapshot().expect("failed to take snapshot");
+
+  // rolling back uncommitted changes does not write to the file
+  container.number = 2;
+  let commit_count = container.commit_count();
+  assert_eq!(container.rollback(snapshot.clone()).unwrap().number, 2);
+  assert_eq!(container.number, 1);
+  assert_eq!(container.commit_count(), commit_count);
+  assert!(!container.is_dirty());
+
+  container.number = 3;
+  container.commit().expect("failed to commit state to disk");
+  assert_eq!(container.rollback(snapshot).unwrap().number, 3);
+  assert_eq!(container.number, 1);
+  assert_eq!(container.refresh().unwrap().number, 1);
+
+  mem::drop(container);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_sync_policy() {
+  use singlefile::container::ContainerBuilder;
+  use singlefile::manager::{AtomicRename, SyncPolicy};
+
+  use std::time::Duration;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  for sync_policy in [SyncPolicy::Always, SyncPolicy::OnClose, SyncPolicy::Never, SyncPolicy::Interval(Duration::from_secs(60))] {
+    let mut container = ContainerBuilder::new(&path, Json::<true>)
+      .with_mode::<AtomicRename>()
+      .with_sync_policy(sync_policy)
+      .build_or_default::<Data>()
+      .expect("failed to create container for data.json");
+    assert_eq!(container.manager().sync_policy(), sync_policy);
+
+    // writes are visible whether or not they have been synced
+    container.number += 1;
+    container.commit().expect("failed to commit state to disk");
+    container.manager().sync().expect("failed to sync data.json");
+    assert_eq!(container.refresh().unwrap().number, container.number);
+    container.close().unwrap();
+  };
+
+  let container = ContainerBuilder::new(&path, Json::<true>).build_or_default::<Data>().unwrap();
+  assert_eq!(container.number, 4);
+
+  mem::drop(container);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_open_first() {
+  use singlefile::container::ContainerReadonly;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let missing = temp_dir.path().join("missing.json");
+  let user = temp_dir.path().join("user.json");
+  let system = temp_dir.path().join("system.json");
+  fs::write(&user, r#"{ "number": 1 }"#).unwrap();
+  fs::write(&system, r#"{ "number": 2 }"#).unwrap();
+
+  let container = ContainerReadonly::<Data, Json>::open_first([&missing, &user, &system], Json)
+    .expect("failed to open any candidate path");
+  assert_eq!(container.number, 1);
+  assert_eq!(container.manager().path(), user);
+  mem::drop(container);
+
+  // a file that exists but fails to parse is not skipped
+  fs::write(&user, "not json").unwrap();
+  assert!(ContainerReadonly::<Data, Json>::open_first([&user, &system], Json).is_err());
+
+  let err = ContainerReadonly::<Data, Json>::open_first([&missing], Json).unwrap_err();
+  assert!(matches!(err, singlefile::Error::Io(err) if err.kind() == std::io::ErrorKind::NotFound));
+
+  fs::remove_file(user).unwrap();
+  fs::remove_file(system).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_from_file() {
+  use singlefile::container::{ContainerWritable, ContainerAtomicRename};
+  use std::io::Write;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path).unwrap();
+  file.write_all(br#"{ "number": 1 }"#).unwrap();
+  let mut container = ContainerWritable::<Data, Json>::from_file(file, Json)
+    .expect("failed to create container from file handle");
+  assert_eq!(container.number, 1);
+  assert_eq!(container.manager().path(), std::path::Path::new(""));
+  container.number = 2;
+  container.commit().expect("failed to commit state to disk");
+  container.close().unwrap();
+  let on_disk: Data = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+  assert_eq!(on_disk.number, 2);
+
+  // an anonymous file with no path works just as well, and is read from its start
+  let mut file = tempfile::tempfile().unwrap();
+  file.write_all(br#"{ "number": 3 }"#).unwrap();
+  let container = ContainerWritable::<Data, Json>::from_file(file, Json).unwrap();
+  assert_eq!(container.number, 3);
+  container.close().unwrap();
+
+  let file = fs::File::open(&path).unwrap();
+  assert!(ContainerAtomicRename::<Data, Json>::from_file(file, Json).is_err());
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_from_file_change_detection() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::error::Error;
+  use std::io::{Seek, SeekFrom, Write};
+
+  // a container created from a handle has no path, so changes are detected through the handle
+  let mut file = tempfile::tempfile().unwrap();
+  file.write_all(br#"{ "number": 1 }"#).unwrap();
+  let mut other = file.try_clone().unwrap();
+  let mut container = ContainerWritable::<Data, Json>::from_file(file, Json).unwrap();
+  assert!(container.refresh_if_changed().unwrap().is_none());
+  container.number = 2;
+  container.commit_if_unchanged().expect("unchanged file should be committed");
+
+  other.set_len(0).unwrap();
+  other.seek(SeekFrom::Start(0)).unwrap();
+  other.write_all(br#"{ "number": 300 }"#).unwrap();
+  // the cloned handle shares its position with the one held by the container
+  other.seek(SeekFrom::Start(0)).unwrap();
+  assert!(matches!(container.commit_if_unchanged(), Err(Error::Conflict(_))));
+  assert_eq!(container.refresh_if_changed().unwrap().unwrap().number, 2);
+  assert_eq!(container.number, 300);
+  container.close().unwrap();
+}
+
+#[test]
+fn container_transaction() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::error::UserError;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<Data, Json>::create_or(&path, Json, Data { number: 1 })
+    .expect("failed to create container for data.json");
+
+  let result = container.transaction(|data| {
+    data.number = 2;
+    Err::<(), &str>("rejected")
+  });
+  assert!(matches!(result, Err(UserError::User("rejected"))));
+  assert_eq!(container.number, 1);
+  assert!(!container.is_dirty());
+
+  container.transaction(|data| {
+    data.number += 1;
+    Ok::<(), &str>(())
+  }).unwrap();
+  assert_eq!(container.number, 2);
+  assert_eq!(container.refresh().unwrap().number, 2);
+
+  mem::drop(container);
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "shared")]
+fn container_commit_hooks() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::container_shared::ContainerSharedWritable;
+  use std::sync::{Arc, Mutex};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let calls = Arc::new(Mutex::new(Vec::new()));
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.on_before_commit({
+    let calls = Arc::clone(&calls);
+    move |data| calls.lock().unwrap().push(("before", data.number))
+  });
+  container.on_after_commit({
+    let calls = Arc::clone(&calls);
+    let path = path.clone();
+    move |data| {
+      // the write has completed by the time this is invoked
+      let written: Data = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+      assert_eq!(written, *data);
+      calls.lock().unwrap().push(("after", data.number));
+    }
+  });
+
+  container.number = 1;
+  container.commit().expect("failed to commit state to disk");
+  container.overwrite(Data { number: 2 }).expect("failed to overwrite state");
+  assert_eq!(*calls.lock().unwrap(), [("before", 1), ("after", 1), ("before", 2), ("after", 2)]);
+  calls.lock().unwrap().clear();
+
+  // callbacks carry over to the shared container, and are invoked for every kind of commit
+  let container = ContainerSharedWritable::from(container);
+  container.operate_mut_commit(|data| {
+    data.number = 3;
+    Ok::<(), ()>(())
+  }).expect("failed to commit state to disk");
+  container.transaction(|data| {
+    data.number = 4;
+    Ok::<(), ()>(())
+  }).expect("failed to commit state to disk");
+  assert_eq!(*calls.lock().unwrap(), [("before", 3), ("after", 3), ("before", 4), ("after", 4)]);
+
+  container.on_after_commit(|data| assert_eq!(data.number, 4));
+  container.commit().expect("failed to commit state to disk");
+  assert_eq!(calls.lock().unwrap().len(), 6);
+
+  container.try_unwrap().unwrap().close().expect("failed to close container");
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_impl_container() {
+  #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+  struct Settings {
+    volume: u8
+  }
+
+  singlefile::impl_container! {
+    Settings {
+      path = concat!(env!("CARGO_TARGET_TMPDIR"), "/impl_container.json"),
+      format: RegularJson = Json::<false>
+    }
+  }
+
+  let _ = fs::remove_file(Settings::PATH);
+  let mut settings = Settings::load().expect("failed to load settings");
+  assert_eq!(settings, Settings::default());
+  assert_eq!(fs::read_to_string(Settings::PATH).unwrap(), r#"{"volume":0}"#);
+
+  settings.volume = 5;
+  settings.save().expect("failed to save settings");
+  assert_eq!(Settings::load().unwrap(), settings);
+
+  let mut container = Settings::load_container().expect("failed to open settings");
+  container.volume += 1;
+  container.commit().expect("failed to commit settings");
+  container.close().expect("failed to close container");
+
+  #[cfg(feature = "shared")]
+  assert_eq!(Settings::load_shared().unwrap().operate(|settings| settings.volume), 6);
+
+  fs::remove_file(Settings::PATH).unwrap();
+}
+
+#[test]
+#[cfg(all(feature = "paths", target_os = "linux"))]
+fn container_create_or_default_in() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::paths::{Directory, Project};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  // on linux, the configuration directory of a project is a lowercase directory within `XDG_CONFIG_HOME`
+  std::env::set_var("XDG_CONFIG_HOME", temp_dir.path().join("config"));
+  std::env::set_var("XDG_CACHE_HOME", temp_dir.path().join("cache"));
+  let project = Project::new("org", "Singlefile", "Singlefile Test");
+
+  let container = ContainerWritable::<Data, Json>::create_or_default_in(&project, "data.json", Json)
+    .expect("failed to create container in the configuration directory");
+  let path = temp_dir.path().join("config/singlefiletest/data.json");
+  assert!(path.exists());
+  container.close().expect("failed to close container");
+
+  let path = project.path(Directory::Cache, "nested/data.json").expect("failed to resolve cache path");
+  assert_eq!(path, temp_dir.path().join("cache/singlefiletest/nested/data.json"));
+  assert!(path.parent().unwrap().is_dir());
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_create_or_default_with_dirs() {
+  use singlefile::container::{ContainerBuilder, ContainerWritable};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("a/b/data.json");
+
+  let result = ContainerWritable::<Data, Json>::create_or_default(&path, Json);
+  assert!(matches!(result, Err(singlefile::Error::Io(_))));
+
+  let container = ContainerWritable::<Data, Json>::create_or_default_with_dirs(&path, Json)
+    .expect("failed to create container and its parent directories");
+  assert!(path.exists());
+  container.close().expect("failed to close container");
+
+  let path = temp_dir.path().join("c/data.json");
+  let container = ContainerBuilder::new(&path, Json::<true>)
+    .with_create_dirs(true)
+    .build_or_default::<Data>()
+    .expect("failed to build container and its parent directories");
+  assert!(path.exists());
+  container.close().expect("failed to close container");
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn container_create_options() {
+  use singlefile::container::ContainerBuilder;
+  use singlefile::manager::CreateOptions;
+  use std::os::unix::fs::PermissionsExt;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut options = CreateOptions::new();
+  options.set_mode(0o600);
+  let container = ContainerBuilder::new(&path, Json::<true>)
+    .with_create_options(options)
+    .build_or_default::<Data>()
+    .expect("failed to create container for data.json");
+  assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+  container.close().expect("failed to close container");
+
+  // the options have no effect on a file that already exists
+  fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+  let container = ContainerBuilder::new(&path, Json::<true>)
+    .with_create_options(options)
+    .build_or_default::<Data>()
+    .expect("failed to open container for data.json");
+  assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o644);
+  container.close().expect("failed to close container");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_detect_format() {
+  use singlefile::container::{ContainerReadonly, ContainerWritable};
+  use singlefile_formats::detect::{DetectFormat, FormatKind};
+
+  let dir = tempfile::tempdir().unwrap();
+  let value = Data { number: 12 };
+  fs::write(dir.path().join("data.json"), "{ \"number\": 12 }").unwrap();
+  fs::write(dir.path().join("data.toml"), "number = 12\n").unwrap();
+  let mut cbor = Vec::new();
+  singlefile::FileFormat::<Data>::to_writer(&singlefile_formats::cbor_serde::Cbor, &mut cbor, &value).unwrap();
+  fs::write(dir.path().join("data.cbor"), cbor).unwrap();
+
+  for (file, kind) in [("data.json", FormatKind::Json), ("data.toml", FormatKind::Toml), ("data.cbor", FormatKind::Cbor)] {
+    let path = dir.path().join(file);
+    // detected from the contents, then written back in the same format
+    let mut container = ContainerWritable::<Data, _>::open(&path, DetectFormat::new()).unwrap();
+    assert_eq!(*container, value);
+    assert_eq!(container.manager().format().kind(), Some(kind));
+    container.number = 13;
+    container.commit().unwrap();
+    drop(container);
+
+    let container = ContainerReadonly::<Data, _>::open(&path, DetectFormat::for_path(&path)).unwrap();
+    assert_eq!(container.manager().format().kind(), Some(kind));
+    assert_eq!(container.number, 13);
+  };
+
+  // nothing to detect the format of a new file from
+  let path = dir.path().join("new.txt");
+  assert!(ContainerWritable::<Data, _>::create_or_default(&path, DetectFormat::new()).is_err());
+  let path = dir.path().join("data.txt");
+  ContainerWritable::<Data, _>::create_or_default(&path, DetectFormat::with_kind(FormatKind::Toml)).unwrap();
+  assert_eq!(fs::read_to_string(&path).unwrap(), "number = 0\n");
+}
+
+#[test]
+fn container_convert_format() {
+  use singlefile::container::{ContainerReadonly, ContainerWritable};
+  use singlefile::error::TranscodeError;
+  use singlefile_formats::cbor_serde::Cbor;
+
+  let dir = tempfile::tempdir().unwrap();
+  let json_path = dir.path().join("data.json");
+  let cbor_path = dir.path().join("data.cbor");
+  let container = ContainerWritable::<Data, Json>::create_or(&json_path, Json, Data { number: 5 }).unwrap();
+  let converted = container.convert_format(&cbor_path, Cbor).unwrap();
+  assert_eq!(*converted, Data { number: 5 });
+  drop((container, converted));
+  let container = ContainerReadonly::<Data, Cbor>::open(&cbor_path, Cbor).unwrap();
+  assert_eq!(*container, Data { number: 5 });
+  drop(container);
+
+  // converting in place, and back again
+  singlefile::utils::transcode::<Data, _, _, _, _>(&cbor_path, &Cbor, &cbor_path, &Json::<false>).unwrap();
+  assert_eq!(fs::read_to_string(&cbor_path).unwrap(), r#"{"number":5}"#);
+  let err = singlefile::utils::transcode::<Data, _, _, _, _>(&cbor_path, &Cbor, &json_path, &Json::<true>).unwrap_err();
+  assert!(matches!(err, TranscodeError::Read(singlefile::Error::Format(_))));
+}
+
+#[test]
+fn container_open_migrating() {
+  use singlefile::container::{Container, ContainerReadonly, ContainerWritable};
+  use singlefile::manager::{AtomicRename, ExclusiveLock, FileManager};
+  use singlefile_formats::cbor_serde::Cbor;
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("data");
+  fs::write(&path, "{ \"number\": 8 }").unwrap();
+
+  let (mut container, migrated) = ContainerWritable::<Data, Cbor>::open_migrating(&path, Json::<true>, Cbor).unwrap();
+  assert!(migrated);
+  assert_eq!(*container, Data { number: 8 });
+  container.number = 9;
+  container.commit().unwrap();
+  drop(container);
+
+  let (container, migrated) = ContainerWritable::<Data, Cbor>::open_migrating(&path, Json::<true>, Cbor).unwrap();
+  assert!(!migrated);
+  assert_eq!(*container, Data { number: 9 });
+  drop(container);
+
+  fs::write(&path, "not json").unwrap();
+  let err = ContainerWritable::<Data, Cbor>::open_migrating(&path, Json::<true>, Cbor).unwrap_err();
+  assert!(matches!(err, singlefile::Error::Format(_)));
+  assert_eq!(fs::read_to_string(&path).unwrap(), "not json");
+
+  // modes that replace the file migrate it through their own writes, without giving up the lock
+  fs::write(&path, "{ \"number\": 10 }").unwrap();
+  let (container, migrated) = Container::<Data, FileManager<Cbor, ExclusiveLock, AtomicRename>>::open_migrating(&path, Json::<true>, Cbor)
+    .unwrap();
+  assert!(migrated);
+  assert_eq!(*container, Data { number: 10 });
+  drop(container);
+
+  let container = ContainerReadonly::<Data, Cbor>::open(&path, Cbor).unwrap();
+  assert_eq!(*container, Data { number: 10 });
+}
+
+#[test]
+fn container_max_size() {
+  use singlefile::container::ContainerBuilder;
+  use singlefile::error::TooLarge;
+  use singlefile::manager::ManagerWritable;
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("data.json");
+  fs::write(&path, format!("{{ \"number\": 1 }}{}", " ".repeat(64))).unwrap();
+
+  let err = ContainerBuilder::new(&path, Json::<true>).with_max_size(32).build_or_default::<Data>().unwrap_err();
+  assert!(matches!(err, singlefile::Error::TooLarge(TooLarge { size: 79, max_size: 32 })));
+
+  let manager = ManagerWritable::open(&path, Json::<true>).unwrap().with_max_size(79);
+  assert_eq!(manager.read::<Data>().unwrap(), Data { number: 1 });
+  manager.write(&Data { number: 2 }).unwrap();
+  let manager = manager.with_max_size(8);
+  assert!(matches!(manager.read::<Data>(), Err(singlefile::Error::TooLarge(_))));
+}
+
+#[test]
+#[cfg(feature = "metrics")]
+fn container_metrics() {
+  use singlefile::container::ContainerBuilder;
+  use singlefile::metrics::{Counters, CountersSnapshot};
+  use std::sync::Arc;
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("data.json");
+  let counters = Arc::new(Counters::new());
+  let mut container = ContainerBuilder::new(&path, Json::<false>)
+    .with_instrumentation(counters.clone())
+    .build_or_default::<Data>()
+    .unwrap();
+  // the initial value is written before the manager is opened, so only the lock is measured
+  assert_eq!(counters.snapshot(), CountersSnapshot { lock_waits: 1, lock_wait_time: counters.snapshot().lock_wait_time, ..Default::default() });
+
+  container.number = 10;
+  container.commit().unwrap();
+  container.refresh().unwrap();
+  let snapshot = counters.snapshot();
+  assert_eq!((snapshot.reads, snapshot.writes, snapshot.failures), (1, 1, 0));
+  assert_eq!(snapshot.bytes_written, r#"{"number":10}"#.len() as u64);
+  assert_eq!(snapshot.bytes_read, snapshot.bytes_written);
+
+  fs::write(&path, "not json").unwrap();
+  assert!(container.refresh().is_err());
+  assert_eq!((counters.snapshot().reads, counters.snapshot().failures), (2, 1));
+  drop(container);
+
+  #[cfg(feature = "tracing")] {
+    use singlefile::metrics::TracingInstrumentation;
+
+    let manager = singlefile::manager::ManagerWritable::open(&path, Json::<false>).unwrap()
+      .with_instrumentation(Arc::new(TracingInstrumentation));
+    manager.write(&Data::default()).unwrap();
+    assert_eq!(manager.read::<Data>().unwrap(), Data::default());
+  };
+}
+
+#[test]
+fn container_builder() {
+  use singlefile::container::ContainerBuilder;
+  use singlefile::manager::{Atomic, BackupPolicy, ExclusiveLock, OpenBehavior};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  ContainerBuilder::new(&path, Json::<true>)
+    .with_open_behavior(OpenBehavior::Open)
+    .build_or_default::<Data>()
+    .expect_err("missing file should not be created");
+  assert!(!path.exists());
+
+  let mut permissions = fs::metadata(temp_dir.path()).unwrap().permissions();
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.set_mode(0o600);
+  }
+
+  let mut container = ContainerBuilder::new(&path, Json::<true>)
+    .with_lock::<ExclusiveLock>()
+    .with_mode::<Atomic>()
+    .with_permissions(permissions)
+    .with_backup_policy(BackupPolicy::new(1))
+    .build_or(Data { number: 1 })
+    .expect("failed to create container for data.json");
+  assert_eq!(container.number, 1);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+  }
+
+  container.number = 2;
+  container.commit().expect("failed to commit state to disk");
+  assert!(temp_dir.path().join("data.json.bak.1").exists());
+  container.close().unwrap();
+
+  let container = ContainerBuilder::new(&path, Json::<true>)
+    .build_or(Data { number: 3 })
+    .unwrap();
+  assert_eq!(container.number, 2);
+  mem::drop(container);
+
+  let mut container = ContainerBuilder::new(&path, Json::<true>)
+    .with_open_behavior(OpenBehavior::Overwrite)
+    .build_or(Data { number: 3 })
+    .unwrap();
+  assert_eq!(container.number, 3);
+  assert_eq!(container.refresh().unwrap().number, 3);
+
+  mem::drop(container);
+  fs::remove_file(temp_dir.path().join("data.json.bak.1")).unwrap();
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_lock_contended() {
+  use singlefile::container::{Container, ContainerWritableLocked};
+  use singlefile::manager::{FileManager, ExclusiveLockBlocking, ExclusiveLockWithTimeout, Writable};
+  use singlefile::Error;
+  use std::time::{Duration, Instant};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerWritableLocked::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  let result = ContainerWritableLocked::<Data, Json>::open(&path, Json);
+  assert!(matches!(result, Err(Error::LockContended(_))));
+
+  let start = Instant::now();
+  let result = Container::<Data, FileManager<Json, ExclusiveLockWithTimeout<50>, Writable>>::open(&path, Json);
+  assert!(matches!(result, Err(Error::LockContended(_))));
+  assert!(start.elapsed() >= Duration::from_millis(50));
+
+  let waiter = std::thread::spawn({
+    let path = path.clone();
+    move || Container::<Data, FileManager<Json, ExclusiveLockBlocking, Writable>>::open(&path, Json)
+      .expect("failed to open container once it was unlocked")
+  });
+
+  std::thread::sleep(Duration::from_millis(50));
+  container.close().expect("failed to close container");
+  waiter.join().unwrap().close().expect("failed to close container");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_lock_upgrade() {
+  use singlefile::container::Container;
+  use singlefile::manager::{FileManager, SharedLock, ExclusiveLock, Writable};
+  use singlefile::Error;
+
+  type ContainerShared = Container<Data, FileManager<Json, SharedLock, Writable>>;
+  type ContainerExclusive = Container<Data, FileManager<Json, ExclusiveLock, Writable>>;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let container = ContainerShared::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  let other = ContainerShared::open(&path, Json)
+    .expect("failed to open second shared container");
+
+  // another shared lock is held, so the upgrade fails and hands the container back
+  let container = container.upgrade_lock().expect_err("upgraded while another shared lock was held").into_manager();
+  other.close().expect("failed to close container");
+
+  let mut container = container.upgrade_lock().expect("failed to upgrade lock");
+  let result = ContainerShared::open(&path, Json);
+  assert!(matches!(result, Err(Error::LockContended(_))));
+  container.number = 1;
+  container.commit().expect("failed to commit state to disk");
+
+  let container = container.downgrade_lock().expect("failed to downgrade lock");
+  let other = ContainerShared::open(&path, Json)
+    .expect("failed to open shared container after downgrade");
+  assert_eq!(other.number, 1);
+  let result = ContainerExclusive::open(&path, Json);
+  assert!(matches!(result, Err(Error::LockContended(_))));
+
+  other.close().expect("failed to close container");
+  container.close().expect("failed to close container");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn container_fcntl_locked() {
+  use singlefile::container::Container;
+  use singlefile::manager::{FileManager, ExclusiveFcntlLock, Writable};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = Container::<Data, FileManager<Json, ExclusiveFcntlLock, Writable>>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.number += 1;
+  container.commit().expect("failed to commit state to disk");
+  container.close().expect("failed to close container");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_atomic_rename() {
+  use singlefile::container::ContainerAtomicRename;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerAtomicRename::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+  }
+
+  container.number = 3;
+  container.commit().expect("failed to commit state to disk");
+  container.number = 4;
+  container.commit().expect("failed to commit state to disk");
+  assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+  }
+
+  // the file has been replaced, so it has to be read from its path again
+  assert_eq!(container.refresh().unwrap().number, 4);
+  assert_eq!(container.number, 4);
+  fs::write(&path, r#"{ "number": 5 }"#).unwrap();
+  assert!(container.refresh_if_changed().unwrap().is_some());
+  assert_eq!(container.number, 5);
+  container.close().expect("failed to close container");
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_backup_rotation() {
+  use singlefile::container::{ContainerAtomicRename, ContainerWritable};
+  use singlefile::manager::BackupPolicy;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  let backup_dir = temp_dir.path().join("backups");
+  let policy = BackupPolicy::new(2).in_dir(&backup_dir);
+
+  let mut container = ContainerWritable::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json")
+    .with_backup_policy(policy.clone());
+
+  let mut versions = Vec::new();
+  for number in 1..=3 {
+    versions.push(fs::read(&path).unwrap());
+    container.number = number;
+    container.commit().expect("failed to commit state to disk");
+  };
+
+  // only the two most recent versions are kept, newest first
+  assert_eq!(fs::read(policy.backup_path(&path, 1)).unwrap(), versions[2]);
+  assert_eq!(fs::read(policy.backup_path(&path, 2)).unwrap(), versions[1]);
+  assert!(!policy.backup_path(&path, 3).exists());
+  assert_eq!(fs::read_dir(&backup_dir).unwrap().count(), 2);
+  container.close().expect("failed to close container");
+
+  // a one-off policy, for a mode that replaces the file
+  let container = ContainerAtomicRename::<Data, Json>::open(&path, Json)
+    .expect("failed to open container for data.json");
+  let current = fs::read(&path).unwrap();
+  let one_off = BackupPolicy::new(1);
+  container.commit_with_backup(&one_off).expect("failed to commit state to disk");
+  assert_eq!(fs::read(one_off.backup_path(&path, 1)).unwrap(), current);
+  assert_eq!(one_off.backup_path(&path, 1), temp_dir.path().join("data.json.bak.1"));
+  container.close().expect("failed to close container");
+
+  fs::remove_dir_all(backup_dir).unwrap();
+  fs::remove_file(one_off.backup_path(&path, 1)).unwrap();
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_secret() {
+  use singlefile::error::Error;
+  use singlefile_formats::secret::{self, ChaCha20Poly1305, Encrypted};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("secret.bin");
+  let key = Encrypted::<Json>::generate_key();
+
+  let mut container = secret::create_or_default::<Data, _, _>(&path, Encrypted::new(Json::<true>, ChaCha20Poly1305, key))
+    .expect("failed to create container for secret.bin");
+  container.number = 42;
+  container.commit().expect("failed to commit state to disk");
+  // the lock must still cover the file at the path after a commit
+  let result = secret::open::<Data, _, _>(&path, Encrypted::new(Json::<true>, ChaCha20Poly1305, key));
+  assert!(matches!(result, Err(Error::LockContended(_))));
+  container.close().expect("failed to close container");
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+  }
+
+  assert!(!fs::read_to_string(&path).unwrap_or_default().contains("42"));
+  let container = secret::open::<Data, _, _>(&path, Encrypted::new(Json::<true>, ChaCha20Poly1305, key)).unwrap();
+  assert_eq!(container.number, 42);
+  container.close().unwrap();
+
+  // locking may fail under a restrictive memory lock limit, which must not affect reading
+  let format = Encrypted::new(Json::<true>, ChaCha20Poly1305, key).with_locked_memory(true);
+  let container = secret::open::<Data, _, _>(&path, format).unwrap();
+  let _lock = secret::lock_memory(&*container);
+  assert_eq!(container.number, 42);
+  container.close().unwrap();
+
+  secret::open::<Data, _, _>(&path, Encrypted::new(Json::<true>, ChaCha20Poly1305, [0; 32]))
+    .expect_err("wrong key should be rejected");
+
+  // json maps must have string keys, so this value fails to serialize
+  let failing_path = temp_dir.path().join("failing.bin");
+  let value = std::collections::HashMap::from([((1, 2), 3)]);
+  secret::create_or(&failing_path, Encrypted::new(Json::<true>, ChaCha20Poly1305, key), value)
+    .expect_err("value should fail to serialize");
+  assert!(!failing_path.exists());
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_checksummed() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::Error;
+  use singlefile_formats::checksum::{Checksummed, ChecksummedError};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  for format in [Checksummed::crc32(Json::<true>), Checksummed::sha256(Json::<true>)] {
+    let mut container = ContainerWritable::<Data, _>::create_or_default(&path, format)
+      .expect("failed to create container for data.json");
+    container.number = 42;
+    container.commit().expect("failed to commit state to disk");
+    container.close().expect("failed to close container");
+
+    let container = ContainerWritable::<Data, _>::open(&path, format).unwrap();
+    assert_eq!(container.number, 42);
+    container.close().unwrap();
+
+    // flipping a digit still parses as json, but no longer matches the checksum
+    let mut contents = fs::read(&path).unwrap();
+    let digit = contents.windows(2).position(|window| window == b"42").unwrap();
+    contents[digit + 1] = b'3';
+    fs::write(&path, &contents).unwrap();
+    let result = ContainerWritable::<Data, _>::open(&path, format);
+    assert!(matches!(result, Err(Error::Format(ChecksummedError::Corrupted))));
+
+    fs::write(&path, &contents[..2]).unwrap();
+    let result = ContainerWritable::<Data, _>::open(&path, format);
+    assert!(matches!(result, Err(Error::Format(ChecksummedError::Corrupted))));
+    fs::remove_file(&path).unwrap();
+  };
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_encrypted() {
+  use singlefile::container::ContainerWritable;
+  use singlefile_formats::encryption::{Aes256Gcm, ChaCha20Poly1305, Encrypted, EncryptionFormat, XChaCha20Poly1305};
+
+  fn round_trip<E: EncryptionFormat + Copy + std::fmt::Debug>(path: &std::path::Path, encryption: E) {
+    let key = Encrypted::<E, Json>::generate_key();
+    let format = Encrypted::new(Json::<true>, encryption, key);
+    let mut container = ContainerWritable::<Data, _>::create_or_default(path, format.clone())
+      .expect("failed to create container for data.bin");
+    container.number = 42;
+    container.commit().expect("failed to commit state to disk");
+    container.close().expect("failed to close container");
+
+    assert!(!fs::read(path).unwrap().windows(2).any(|window| window == b"42"));
+    let container = ContainerWritable::<Data, _>::open(path, format).unwrap();
+    assert_eq!(container.number, 42);
+    container.close().unwrap();
+
+    ContainerWritable::<Data, _>::open(path, Encrypted::new(Json::<true>, encryption, [0; 32]))
+      .expect_err("wrong key should be rejected");
+    fs::remove_file(path).unwrap();
+
+    let format = Encrypted::with_passphrase(Json::<true>, encryption, "correct horse battery staple");
+    let mut container = ContainerWritable::<Data, _>::create_or_default(path, format)
+      .expect("failed to create container for data.bin");
+    container.number = 42;
+    container.commit().expect("failed to commit state to disk");
+    container.number = 43;
+    container.commit().expect("failed to commit state to disk");
+    container.close().expect("failed to close container");
+
+    let format = Encrypted::with_passphrase(Json::<true>, encryption, "correct horse battery staple");
+    let container = ContainerWritable::<Data, _>::open(path, format).unwrap();
+    assert_eq!(container.number, 43);
+    container.close().unwrap();
+
+    let format = Encrypted::with_passphrase(Json::<true>, encryption, "incorrect horse battery staple");
+    ContainerWritable::<Data, _>::open(path, format)
+      .expect_err("wrong passphrase should be rejected");
+    fs::remove_file(path).unwrap();
+  }
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.bin");
+  round_trip(&path, Aes256Gcm);
+  round_trip(&path, ChaCha20Poly1305);
+  round_trip(&path, XChaCha20Poly1305);
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_directory() {
+  use singlefile::container_directory::ContainerDirectory;
+  use std::collections::BTreeSet;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let dir = temp_dir.path().join("entries");
+
+  let mut container = ContainerDirectory::<String, Data, Json>::open(&dir, "json", Json)
+    .expect("failed to open container for entries");
+  assert!(container.get(&"a".to_owned()).unwrap().is_none());
+  container.get_or_insert_with("a".to_owned(), Data::default).unwrap().number = 1;
+  container.insert("b".to_owned(), Data { number: 2 });
+  container.commit("a").expect("failed to commit entry to disk");
+  assert!(dir.join("a.json").exists());
+  assert!(!dir.join("b.json").exists());
+  container.commit_all().expect("failed to commit entries to disk");
+  assert!(!container.is_dirty());
+
+  // other files in the directory are not entries
+  fs::write(dir.join("notes.txt"), "").unwrap();
+  assert!(container.path_of("../escape").is_err());
+  drop(container);
+
+  let mut container = ContainerDirectory::<String, Data, Json>::open(&dir, "json", Json).unwrap();
+  assert_eq!(container.keys().unwrap(), BTreeSet::from(["a".to_owned(), "b".to_owned()]));
+  assert!(!container.is_loaded("b"));
+  assert_eq!(container.get(&"b".to_owned()).unwrap().unwrap().number, 2);
+  assert!(container.is_loaded("b"));
+
+  container.get_mut(&"a".to_owned()).unwrap().unwrap().number = 10;
+  container.refresh(&"a".to_owned()).unwrap();
+  assert_eq!(container.get(&"a".to_owned()).unwrap().unwrap().number, 1);
+
+  container.remove("a".to_owned());
+  assert!(container.get(&"a".to_owned()).unwrap().is_none());
+  assert_eq!(container.keys().unwrap(), BTreeSet::from(["b".to_owned()]));
+  assert!(dir.join("a.json").exists());
+  container.commit_all().unwrap();
+  assert!(!dir.join("a.json").exists());
+
+  // bulk operations carry on past entries that fail
+  container.insert("bad/key".to_owned(), Data { number: 3 });
+  container.insert("c".to_owned(), Data { number: 4 });
+  let err = container.commit_all().expect_err("invalid key should fail to commit");
+  assert_eq!(err.errors().len(), 1);
+  assert_eq!(err.errors()[0].0, "bad/key");
+  assert!(dir.join("c.json").exists());
+  container.unload("bad/key");
+
+  container.insert("ba".to_owned(), Data { number: 5 });
+  container.commit_all().unwrap();
+  assert_eq!(container.select("b*").unwrap(), BTreeSet::from(["b".to_owned(), "ba".to_owned()]));
+  assert_eq!(container.select("?").unwrap(), BTreeSet::from(["b".to_owned(), "c".to_owned()]));
+  assert!(container.select("*x*").unwrap().is_empty());
+
+  container.get_mut(&"b".to_owned()).unwrap().unwrap().number = 20;
+  container.remove("c".to_owned());
+  fs::write(dir.join("ba.json"), "not json").unwrap();
+  let err = container.refresh_all().expect_err("corrupt entry should fail to refresh");
+  assert_eq!(err.into_errors().into_iter().map(|(key, _)| key).collect::<Vec<String>>(), ["ba"]);
+  assert_eq!(container.get(&"b".to_owned()).unwrap().unwrap().number, 2);
+  assert_eq!(container.get(&"c".to_owned()).unwrap().unwrap().number, 4);
+  assert!(!container.is_loaded("ba"));
+
+  // scanning reads the files on disk without loading them
+  container.unload("b");
+  let mut scan = container.scan().unwrap();
+  let mut scanned = scan.by_ref()
+    .map(|(key, value)| (key, value.ok().map(|data| data.number)))
+    .collect::<Vec<(String, Option<i32>)>>();
+  scanned.sort();
+  assert_eq!(scanned, [("b".to_owned(), Some(2)), ("ba".to_owned(), None), ("c".to_owned(), Some(4))]);
+  assert!(scan.take_error().is_none());
+  assert!(!container.is_loaded("b"));
+
+  // entries are locked independently of each other
+  let locks = container.lock_many(["c", "b", "c"]).unwrap();
+  assert_eq!(locks.len(), 2);
+  assert!(container.lock_many(["bad/key"]).is_err());
+  let other = ContainerDirectory::<String, Data, Json>::open(&dir, "json", Json).unwrap();
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let waiter = std::thread::spawn(move || {
+    sender.send(other.lock_many(["ba"]).unwrap().len()).unwrap();
+    let locks = other.lock_many_shared(["b"]).unwrap();
+    sender.send(locks.len()).unwrap();
+  });
+  assert_eq!(receiver.recv().unwrap(), 1);
+  assert!(receiver.recv_timeout(std::time::Duration::from_millis(100)).is_err());
+  mem::drop(locks);
+  assert_eq!(receiver.recv().unwrap(), 1);
+  waiter.join().unwrap();
+  let shared = container.lock_many_shared(["b"]).unwrap();
+  assert_eq!(container.lock_many_shared(["b"]).unwrap().len(), 1);
+  mem::drop(shared);
+  assert_eq!(container.keys().unwrap(), BTreeSet::from(["b".to_owned(), "ba".to_owned(), "c".to_owned()]));
+
+  mem::drop(container);
+  fs::remove_dir_all(dir).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_create_or_recover() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let (container, error) = ContainerWritable::<Data, Json>::create_or_recover(&path, Json, Data { number: 1 })
+    .expect("failed to create container for data.json");
+  assert!(error.is_none());
+  assert_eq!(container.number, 1);
+  mem::drop(container);
+
+  let (container, error) = ContainerWritable::<Data, Json>::create_or_recover(&path, Json, Data { number: 2 }).unwrap();
+  assert!(error.is_none());
+  assert_eq!(container.number, 1);
+  mem::drop(container);
+
+  fs::write(&path, "{ \"number\": ").unwrap();
+  let (container, error) = ContainerWritable::<Data, Json>::create_or_recover(&path, Json, Data { number: 2 }).unwrap();
+  assert!(error.is_some());
+  assert_eq!(container.number, 2);
+  mem::drop(container);
+
+  let corrupt = fs::read_dir(temp_dir.path()).unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|entry| entry.to_string_lossy().contains(".corrupt-"))
+    .expect("corrupt file should be moved aside");
+  assert_eq!(fs::read_to_string(&corrupt).unwrap(), "{ \"number\": ");
+
+  // a lenient open may overwrite the corrupt file instead
+  fs::write(&path, "{ \"number\": ").unwrap();
+  let (container, error) = ContainerWritable::<Data, Json>::create_or_default_lenient(&path, Json, false).unwrap();
+  assert!(error.is_some());
+  assert_eq!(container.number, 0);
+  mem::drop(container);
+  assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+  fs::remove_file(corrupt).unwrap();
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_open_with_recovery() {
+  use singlefile::container::ContainerWritable;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  let complete = temp_dir.path().join("data.json.tmp-1-2");
+  let partial = temp_dir.path().join("data.json.tmp-1-1");
+
+  // an interrupted write left the file empty, with one complete and one partial temporary file
+  fs::write(&path, "").unwrap();
+  fs::write(&partial, r#"{ "numb"#).unwrap();
+  fs::write(&complete, r#"{ "number": 3 }"#).unwrap();
+
+  let (container, report) = ContainerWritable::<Data, Json>::open_with_recovery(&path, Json)
+    .expect("failed to recover data.json");
+  assert_eq!(container.number, 3);
+  assert!(report.was_empty);
+  assert_eq!(report.rolled_forward, Some(complete));
+  assert_eq!(report.rolled_back, [partial]);
+  mem::drop(container);
+
+  let (_, report) = ContainerWritable::<Data, Json>::open_with_recovery(&path, Json).unwrap();
+  assert!(report.is_clean());
+
+  fs::remove_file(path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn fs_probe_capabilities() {
+  let temp_dir = tempfile::tempdir().unwrap();
+  let capabilities = singlefile::fs::probe_capabilities(temp_dir.path())
+    .expect("failed to probe capabilities");
+  assert!(capabilities.atomic_rename);
+  assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn record_format_stream() {
+  use singlefile::manager::format::RecordFormat;
+  use singlefile_formats::json_serde::JsonLines;
+
+  let values = vec![Data { number: 1 }, Data { number: 2 }, Data { number: 3 }];
+  let mut buf = Vec::new();
+  JsonLines.to_writer_records(&mut buf, values.clone()).unwrap();
+  buf.extend_from_slice(b"\r\n{ \"numb\n");
+
+  let mut records = JsonLines.stream(buf.as_slice());
+  for expected in &values {
+    let found: Data = records.next().unwrap().expect("failed to read record");
+    assert_eq!(&found, expected);
+  };
+
+  assert!(matches!(records.next(), Some(Err(singlefile::Error::Format(_)))));
+  assert!(records.next().is_none());
+}
+
+#[test]
+fn container_chunked() {
+  use singlefile::container::ContainerChunkedLocked;
+  use singlefile::manager::format::PlainBytes;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("blob.bin");
+  let chunk_count = || fs::read_dir(temp_dir.path()).unwrap().count() - 1;
+
+  let mut container = ContainerChunkedLocked::<Vec<u8>, PlainBytes, 16>::create_or_default(&path, PlainBytes)
+    .expect("failed to create container for blob.bin");
+  assert_eq!(chunk_count(), 0);
+
+  container.extend(0..40);
+  container.commit().unwrap();
+  assert_eq!(chunk_count(), 3);
+  container.refresh().unwrap();
+  assert_eq!(*container, (0..40).collect::<Vec<u8>>());
+
+  // rewriting with a new generation removes the chunks of the previous one
+  container.truncate(20);
+  container.commit().unwrap();
+  assert_eq!(chunk_count(), 2);
+  container.refresh().unwrap();
+  assert_eq!(*container, (0..20).collect::<Vec<u8>>());
+
+  container.truncate(4);
+  container.commit().unwrap();
+  assert_eq!(chunk_count(), 0);
+  assert_eq!(fs::read(&path).unwrap(), [0, 1, 2, 3]);
+  mem::drop(container);
+
+  // a manifest whose lengths overflow, or do not match its chunks, is rejected instead of being trusted
+  let manifest = |lengths: &[u64]| {
+    let mut manifest = b"sfchunk\0".to_vec();
+    manifest.extend_from_slice(&1u32.to_le_bytes());
+    manifest.extend_from_slice(&0u64.to_le_bytes());
+    manifest.extend_from_slice(&(lengths.len() as u32).to_le_bytes());
+    lengths.iter().for_each(|length| manifest.extend_from_slice(&length.to_le_bytes()));
+    manifest
+  };
+
+  fs::write(&path, manifest(&[u64::MAX, u64::MAX])).unwrap();
+  assert!(ContainerChunkedLocked::<Vec<u8>, PlainBytes, 16>::open(&path, PlainBytes).is_err());
+  fs::write(temp_dir.path().join("blob.bin.chunk-0-0"), [0; 4]).unwrap();
+  fs::write(&path, manifest(&[u64::MAX >> 1])).unwrap();
+  assert!(ContainerChunkedLocked::<Vec<u8>, PlainBytes, 16>::open(&path, PlainBytes).is_err());
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "cas")]
+fn manager_create_truncates_raced_file() {
+  use singlefile::manager::{ContentAddressed, FileManager, NoLock};
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let (_, manager) = FileManager::<Json, NoLock, ContentAddressed>::create_or_else(&path, Json, || {
+    // another process creates the file after it was found missing, but before it is written
+    fs::write(&path, format!("{}\n", "0".repeat(64))).unwrap();
+    Data { number: 1 }
+  }).unwrap();
+
+  // the history only holds the initial version, rather than being appended to what was there
+  assert_eq!(manager.history().unwrap().len(), 1);
+  assert_eq!(manager.read::<Data>().unwrap(), Data { number: 1 });
+  mem::drop(manager);
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "cas")]
+fn container_content_addressed() {
+  use singlefile::container::ContainerContentAddressedLocked;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  let objects = temp_dir.path().join("data.json.objects");
+
+  let mut container = ContainerContentAddressedLocked::<Data, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  for number in 1..=3 {
+    container.number = number;
+    container.commit().unwrap();
+  };
+
+  // committing unchanged contents does not add a version
+  container.commit().unwrap();
+  let history = container.manager().history().unwrap();
+  assert_eq!(history.len(), 4);
+  assert_eq!(fs::read_dir(&objects).unwrap().count(), 4);
+  assert_eq!(container.manager().read_version::<Data>(&history[1]).unwrap(), Data { number: 1 });
+
+  container.manager().rollback(&history[1]).unwrap();
+  container.refresh().unwrap();
+  assert_eq!(container.number, 1);
+
+  let removed = container.manager().gc(2).unwrap();
+  assert_eq!(removed.len(), 2);
+  assert_eq!(container.manager().history().unwrap(), [history[3], history[1]]);
+  mem::drop(container);
+
+  let container = ContainerContentAddressedLocked::<Data, Json>::open(&path, Json).unwrap();
+  assert_eq!(container.number, 1);
+  mem::drop(container);
+
+  // a collection interrupted before the history was cut to length still ends with the current version
+  let interrupted = [history[3], history[1], history[2], history[3], history[1]];
+  fs::write(&path, interrupted.iter().map(|id| format!("{id}\n")).collect::<String>()).unwrap();
+  let container = ContainerContentAddressedLocked::<Data, Json>::open(&path, Json).unwrap();
+  assert_eq!(container.number, 1);
+  mem::drop(container);
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "diff")]
+fn container_diff() {
+  use singlefile::container::ContainerWritable;
+  use singlefile::diff::{Change, ChangeKind};
+  use std::collections::BTreeMap;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerWritable::<BTreeMap<String, Vec<i32>>, Json>::create_or_default(&path, Json)
+    .expect("failed to create container for data.json");
+  container.insert("a/b".to_owned(), vec![1, 2]);
+  container.commit().unwrap();
+  assert!(container.diff().unwrap().is_empty());
+
+  container.get_mut().get_mut("a/b").unwrap()[1] = 3;
+  container.insert("c".to_owned(), Vec::new());
+  let diff = container.diff().unwrap();
+  assert_eq!(diff.changes, [
+    Change { path: "/a~1b/1".to_owned(), kind: ChangeKind::Modified(2.into(), 3.into()) },
+    Change { path: "/c".to_owned(), kind: ChangeKind::Added(Vec::<i32>::new().into()) }
+  ]);
+  assert_eq!(diff.to_string(), "~ /a~1b/1: 2 -> 3\n+ /c: []\n");
+
+  mem::drop(container);
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_from_reader_only() {
+  use singlefile::container::ContainerMemoryOnly;
+
+  let format = Json::<true>;
+  let container = ContainerMemoryOnly::<Data>::from_reader_only(&br#"{ "number": 4 }"#[..], &format)
+    .expect("failed to read data");
+  assert_eq!(container.number, 4);
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{ "number": 5 }"#).unwrap();
+  let container = singlefile::utils::read_path_or_stdin::<Data, _, _>(&path, &format).unwrap();
+  assert_eq!(container.number, 5);
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_layered_readonly() {
+  use singlefile::container_layered::{ContainerLayeredReadonly, Layer};
+
+  static DEFAULTS: &[u8] = br#"{ "number": 7 }"#;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  let mut container = ContainerLayeredReadonly::<Data, Json>::open(&path, Json, DEFAULTS)
+    .expect("failed to read defaults");
+  assert_eq!(container.layer(), Layer::Defaults);
+  assert_eq!(container.number, 7);
+  assert!(!path.exists());
+
+  fs::write(&path, r#"{ "number": 8 }"#).unwrap();
+  container.refresh().unwrap();
+  assert_eq!(container.layer(), Layer::File);
+  assert_eq!(container.number, 8);
+
+  fs::remove_file(&path).unwrap();
+  container.refresh().unwrap();
+  assert_eq!(container.layer(), Layer::Defaults);
+  assert_eq!(container.number, 7);
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_merged() {
+  use singlefile::container_layered::{ContainerMerged, Merge};
+
+  #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+  struct Settings { port: Option<u16>, verbose: Option<bool> }
+
+  impl Merge for Settings {
+    fn merge(&mut self, overlay: &Self) {
+      self.port.merge(&overlay.port);
+      self.verbose.merge(&overlay.verbose);
+    }
+  }
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let system = temp_dir.path().join("system.json");
+  let user = temp_dir.path().join("user.json");
+  fs::write(&system, r#"{ "port": 8080 }"#).unwrap();
+
+  let defaults = Settings { port: Some(80), verbose: Some(false) };
+  let mut container = ContainerMerged::<Settings, Json>::open([&system, &user], Json, defaults)
+    .expect("failed to open layers");
+  assert_eq!(*container, Settings { port: Some(8080), verbose: Some(false) });
+  assert_eq!(container.writable_layer(), None);
+  assert_eq!(container.writable_path(), user);
+
+  container.writable_layer_mut().verbose = Some(true);
+  container.commit().expect("failed to commit writable layer");
+  assert_eq!(*container, Settings { port: Some(8080), verbose: Some(true) });
+
+  // values from the other layers are not copied into the writable layer
+  let on_disk: Settings = serde_json::from_str(&fs::read_to_string(&user).unwrap()).unwrap();
+  assert_eq!(on_disk, Settings { port: None, verbose: Some(true) });
+
+  fs::write(&user, r#"{ "port": 9000 }"#).unwrap();
+  container.refresh().unwrap();
+  assert_eq!(*container, Settings { port: Some(9000), verbose: Some(false) });
+
+  fs::remove_file(system).unwrap();
+  fs::remove_file(user).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+#[cfg(feature = "projection")]
+fn container_projection() {
+  use singlefile::container_projection::{read_projection, PartialContainer};
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("state.json");
+  fs::write(&path, r#"{ "log": [1, 2, 3], "items": [{ "data": { "number": 1 } }] }"#).unwrap();
+
+  let mut container = PartialContainer::<Data, RegularJson>::open(&path, Json::<false>, "/items/0/data").unwrap();
+  assert_eq!(*container, Data { number: 1 });
+  container.number = 2;
+  container.commit().unwrap();
+  drop(container);
+  assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"items":[{"data":{"number":2}}],"log":[1,2,3]}"#);
+
+  // missing keys are created on commit
+  let container = PartialContainer::<Data, RegularJson>::open_or_default(&path, Json::<false>, "/new/a~1b").unwrap();
+  assert_eq!(*container, Data::default());
+  container.commit().unwrap();
+  assert_eq!(read_projection::<Data, _, _>(&path, &Json::<false>, "/new/a~1b").unwrap(), Some(Data::default()));
+  assert_eq!(read_projection::<Data, _, _>(&path, &Json::<false>, "/missing").unwrap(), None);
+
+  assert!(PartialContainer::<Data, RegularJson>::open(&path, Json::<false>, "/missing").is_err());
+  assert!(PartialContainer::<Data, RegularJson>::open(&path, Json::<false>, "items").is_err());
+  let container = PartialContainer::<Data, RegularJson>::open_or_default(&path, Json::<false>, "/log/3").unwrap();
+  assert!(container.commit().is_err());
+}
+
+#[test]
+#[cfg(feature = "layered")]
+fn container_layered() {
+  use singlefile::container_layered::ContainerLayered;
+
+  #[derive(Serialize, Deserialize)]
+  struct Settings { name: String, port: u16, verbose: bool }
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("settings.json");
+  let defaults = || Settings { name: "server".to_owned(), port: 80, verbose: false };
+  std::env::set_var("SINGLEFILE_TEST_LAYERED_PORT", "8080");
+  std::env::set_var("SINGLEFILE_TEST_LAYERED_NAME", "123");
+
+  let format = Json::<true>;
+  let mut container = ContainerLayered::open(&path, format, defaults(), Some("SINGLEFILE_TEST_LAYERED"))
+    .expect("failed to open settings.json");
+  assert_eq!(container.port, 8080);
+  assert_eq!(container.name, "123");
+  assert!(!container.verbose);
+
+  // only the modified value is written, not the environment overrides
+  container.verbose = true;
+  container.commit().unwrap();
+  assert_eq!(container.file_layer(), &serde_json::json!({ "verbose": true }));
+
+  std::env::remove_var("SINGLEFILE_TEST_LAYERED_PORT");
+  std::env::remove_var("SINGLEFILE_TEST_LAYERED_NAME");
+  container.refresh().unwrap();
+  assert_eq!(container.port, 80);
+  assert_eq!(container.name, "server");
+  assert!(container.verbose);
+
+  container.close().unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_kv() {
+  use singlefile::container_kv::ContainerKv;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("store.json");
+
+  let format = Json::<true>;
+  let mut store = ContainerKv::<String, Data, _>::open(&path, format).expect("failed to open store.json");
+  assert!(store.is_empty());
+  store.insert("a".to_owned(), Data { number: 1 });
+  store.insert("b".to_owned(), Data { number: 2 });
+  store.get_mut("b").unwrap().number += 1;
+  assert!(store.remove("missing").is_none());
+  assert_eq!(store.dirty_keys().collect::<Vec<_>>(), ["a", "b"]);
+  store.commit().expect("failed to commit store.json");
+  assert!(!store.is_dirty());
+
+  // committing an unmodified store does not touch the file
+  fs::write(&path, "{}").unwrap();
+  store.commit().unwrap();
+  assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+  store.insert("c".to_owned(), Data { number: 4 });
+  assert_eq!(store.remove("a"), Some(Data { number: 1 }));
+  store.commit().unwrap();
+  store.close().unwrap();
+
+  let store = ContainerKv::<String, Data, _>::open(&path, format).unwrap();
+  let entries = store.iter().map(|(key, data)| (key.as_str(), data.number)).collect::<Vec<_>>();
+  assert_eq!(entries, [("b", 3), ("c", 4)]);
+  store.close().unwrap();
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_log() {
+  use singlefile::container_log::ContainerLog;
+  use std::io::Write;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("records.log");
+  let open = || ContainerLog::<Data, RegularJson>::open(&path, Json::<false>);
+
+  let mut container = open().expect("failed to open records.log");
+  assert!(container.is_empty());
+  container.append(&Data { number: 1 }).expect("failed to append record");
+  container.extend(&[Data { number: 2 }, Data { number: 3 }, Data { number: 4 }]).unwrap();
+  let mut iter = container.iter();
+  assert_eq!(iter.next().unwrap().unwrap(), Data { number: 1 });
+  assert_eq!(iter.size_hint(), (3, Some(3)));
+  container.close().unwrap();
+
+  // an interrupted append is discarded
+  fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[10, 0, 0]).unwrap();
+  let mut container = open().unwrap();
+  assert_eq!(container.len(), 4);
+  let records = container.iter().collect::<Result<Vec<_>, _>>().unwrap();
+  assert_eq!(records, [1, 2, 3, 4].map(|number| Data { number }));
+
+  assert_eq!(container.compact(|record| record.number % 2 == 0).unwrap(), 2);
+  container.append(&Data { number: 5 }).unwrap();
+  container.close().unwrap();
+
+  let container = open().unwrap();
+  let numbers = container.iter().map(|record| record.unwrap().number).collect::<Vec<_>>();
+  assert_eq!(numbers, [2, 4, 5]);
+  container.close().unwrap();
+
+  // an append that failed partway must not shift the frames of later appends
+  #[cfg(unix)]
+  {
+    let mut container = open().unwrap();
+    fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[10, 0, 0]).unwrap();
+    container.append(&Data { number: 6 }).unwrap();
+    container.close().unwrap();
+
+    let container = open().unwrap();
+    let numbers = container.iter().map(|record| record.unwrap().number).collect::<Vec<_>>();
+    assert_eq!(numbers, [2, 4, 5, 6]);
+    container.close().unwrap();
+  }
+
+  fs::write(&path, b"not a log").unwrap();
+  assert!(open().is_err());
+
+  fs::remove_file(&path).unwrap();
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_event_log() {
+  use singlefile::container_event_log::{EventLogContainer, Retention};
+  use singlefile_formats::json_serde::JsonLines;
+  use std::io::Write;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("events.log");
+  let open = || EventLogContainer::<i32, Data, _, Json>::open(&path, JsonLines, Json);
+
+  let mut container = open().expect("failed to open events.log");
+  container.extend([1, 2, 3]);
+  assert_eq!(container.number, 6);
+  assert_eq!(container.pending(), [1, 2, 3]);
+  container.commit().expect("failed to commit events");
+  container.push(4);
+  container.close().unwrap();
+
+  // uncommitted events are lost, and an interrupted append is discarded
+  fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"10").unwrap();
+  let mut container = open().unwrap();
+  assert_eq!((container.number, container.sequence()), (6, 3));
+
+  container.set_retention(Retention { snapshot_every: Some(2), keep_segments: 1 });
+  container.push(4);
+  container.commit().unwrap();
+  assert_eq!(container.log_len(), 0);
+  assert_eq!(fs::read_to_string(&path).unwrap(), "");
+  assert_eq!(fs::read_to_string(temp_dir.path().join("events.log.segment-0")).unwrap(), "sfevents 0\n1\n2\n3\n4\n");
+  container.extend([5, 6]);
+  container.snapshot_now().unwrap();
+  assert!(!temp_dir.path().join("events.log.segment-0").exists());
+  assert!(temp_dir.path().join("events.log.segment-4").exists());
+  container.push(7);
+  container.commit().unwrap();
+  container.close().unwrap();
+
+  let container = open().unwrap();
+  assert_eq!((container.number, container.sequence(), container.log_len()), (28, 7, 1));
+  container.close().unwrap();
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_json_pretty_toggle() {
+  use singlefile::container::ContainerWritable;
+  use singlefile_formats::json_serde::RuntimeJson;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+
+  // the choice of formatting does not change the type of the container
+  let open = |pretty: bool| ContainerWritable::<Data, RuntimeJson>::create_or_default(&path, RuntimeJson::new(pretty));
+  let container = open(false).expect("failed to create data.json");
+  assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"number":0}"#);
+  container.close().unwrap();
+
+  let container = open(true).unwrap();
+  container.commit().unwrap();
+  assert!(fs::read_to_string(&path).unwrap().contains('\n'));
+  container.close().unwrap();
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_open_normalized() {
+  use singlefile::container::ContainerWritable;
+  use singlefile_formats::Compressed;
+  use singlefile_formats::flate::Gz;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{"number":3}"#).unwrap();
+
+  let (container, rewritten) = ContainerWritable::<Data, _>::open_normalized(&path, Json::<true>)
+    .expect("failed to normalize data.json");
+  assert!(rewritten);
+  assert_eq!(fs::read_to_string(&path).unwrap(), "{\n  \"number\": 3\n}");
+  container.close().unwrap();
+
+  let (container, rewritten) = ContainerWritable::<Data, _>::open_normalized(&path, Json::<true>).unwrap();
+  assert!(!rewritten);
+  container.close().unwrap();
+
+  // uncompressed to compressed, which the compressed format cannot read by itself
+  let format = Compressed::new(Json::<false>, Gz);
+  ContainerWritable::<Data, _>::open_normalized(&path, format).unwrap_err();
+  let (container, rewritten) = ContainerWritable::<Data, _>::open_migrating(&path, Json::<true>, format).unwrap();
+  assert!(rewritten);
+  assert_eq!(container.number, 3);
+  container.close().unwrap();
+
+  let (container, rewritten) = ContainerWritable::<Data, _>::open_migrating(&path, Json::<true>, format).unwrap();
+  assert!(!rewritten);
+  assert_eq!(container.number, 3);
+  container.close().unwrap();
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_compressed() {
+  use singlefile::container::ContainerWritable;
+  use singlefile_formats::{Compressed, DecompressionLimitExceeded};
+  use singlefile_formats::flate::Gz;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json.gz");
+  let format = Compressed::new(Json::<false>, Gz).with_threshold(64);
+  assert_eq!(format.threshold(), Some(64));
+  assert_eq!(format.limit(), None);
+
+  // small contents are stored as-is after the flag byte
+  let mut container = ContainerWritable::<Vec<i32>, _>::create_or_default(&path, format)
+    .expect("failed to create data.json.gz");
+  assert_eq!(fs::read(&path).unwrap(), b"\x00[]");
+
+  container.extend(0..100);
+  container.commit().unwrap();
+  assert_eq!(fs::read(&path).unwrap()[0], 1);
+  container.close().unwrap();
+
+  let container = ContainerWritable::<Vec<i32>, _>::open(&path, format).unwrap();
+  assert_eq!(*container, (0..100).collect::<Vec<i32>>());
+  container.close().unwrap();
+
+  // contents of exactly the limit are accepted, anything larger is rejected
+  let len = serde_json::to_vec(&(0..100).collect::<Vec<i32>>()).unwrap().len() as u64;
+  let container = ContainerWritable::<Vec<i32>, _>::open(&path, format.with_limit(len)).unwrap();
+  container.close().unwrap();
+  let err = ContainerWritable::<Vec<i32>, _>::open(&path, format.with_limit(len - 1)).unwrap_err();
+  let singlefile::Error::Format(err) = err else { panic!("expected a format error") };
+  assert_eq!(DecompressionLimitExceeded::find(&err), Some(&DecompressionLimitExceeded { limit: len - 1 }));
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_tracked() {
+  use singlefile::container::ContainerReadonly;
+  use singlefile_formats::tracked::Tracked;
+  use std::collections::BTreeMap;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("data.json");
+  fs::write(&path, r#"{ "first": { "number": 1 }, "second": { "number": "2" } }"#).unwrap();
+
+  let format = Tracked(Json::<true>);
+  let err = ContainerReadonly::<BTreeMap<String, Data>, _>::open(&path, format).unwrap_err();
+  let singlefile::Error::Format(err) = err else { panic!("expected a format error") };
+  assert_eq!(err.path().to_string(), "second.number");
+  assert!(err.to_string().starts_with("second.number: invalid type"));
+
+  fs::write(&path, r#"{ "first": { "number": 1 } } trailing"#).unwrap();
+  let err = ContainerReadonly::<BTreeMap<String, Data>, _>::open(&path, format).unwrap_err();
+  let singlefile::Error::Format(err) = err else { panic!("expected a format error") };
+  assert!(err.inner().is_syntax());
+
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn container_interpolated() {
+  use singlefile::container::ContainerReadonly;
+  use singlefile_formats::interpolate::{Interpolated, InterpolatedError, MissingVariable};
+  use std::collections::BTreeMap;
+
+  let temp_dir = tempfile::tempdir().unwrap();
+  let path = temp_dir.path().join("config.json");
+  std::env::set_var("SINGLEFILE_TEST_INTERPOLATED", "secret");
+  fs::write(&path, r#"{
+    "set": "${SINGLEFILE_TEST_INTERPOLATED}",
+    "default": "${SINGLEFILE_TEST_UNSET:-fallback}",
+    "escaped": "$${SINGLEFILE_TEST_INTERPOLATED}",
+    "plain": "$5"
+  }"#).unwrap();
+
+  let format: Interpolated<Json> = Interpolated::new(Json);
+  let container = ContainerReadonly::<BTreeMap<String, String>, _>::open(&path, format)
+    .expect("failed to read config.json");
+  assert_eq!(container["set"], "secret");
+  assert_eq!(container["default"], "fallback");
+  assert_eq!(container["escaped"], "${SINGLEFILE_TEST_INTERPOLATED}");
+  assert_eq!(container["plain"], "$5");
+  mem::drop(container);
+
+  fs::write(&path, r#"{ "missing": "${SINGLEFILE_TEST_UNSET}" }"#).unwrap();
+  let result = ContainerReadonly::<BTreeMap<String, String>, _>::open(&path, format);
+  assert!(matches!(result, Err(singlefile::Error::Format(InterpolatedError::MissingVariable(_)))));
+
+  let format: Interpolated<Json> = Interpolated::with_missing(Json, MissingVariable::Keep);
+  let container = ContainerReadonly::<BTreeMap<String, String>, _>::open(&path, format).unwrap();
+  assert_eq!(container["missing"], "${SINGLEFILE_TEST_UNSET}");
+  mem::drop(container);
+
+  temp_dir.close().unwrap();
 }